@@ -1,17 +1,30 @@
 use rayon;
 use request::Request;
 use serde_json::json;
-use std::{env, fs::{self, DirEntry, ReadDir}, io::Write, net::TcpListener, ops::{Deref, DerefMut}, sync::{Arc, Mutex, MutexGuard}};
-use obj_db::{database::{self, Database}, endpoint::{self, Endpoint, runnable}};
+use std::{env, fs::{self, DirEntry, ReadDir}, io::Write, net::TcpListener, ops::{Deref, DerefMut}, sync::{Arc, Mutex}};
+use obj_db::{database::{self, access::{self, Permission, User}, Database}, endpoint::{self, Endpoint, runnable}};
 
 mod request;
 
 // WORK ON PART.RS RECORD CREATION
 
 fn main() {
-    let admin_db = Database::new("admin".to_owned(), None, "ADMIN".to_owned(), Some("admin_database".to_owned()));
-    let mut databases: Arc<Mutex<Vec<Arc<Mutex<Database<'static>>>>>> = Arc::new(Mutex::new(vec![Arc::clone(&admin_db)]));
-    let mut endpoints: Arc<Mutex<Vec<Endpoint<'static>>>> = Arc::new(Mutex::new(Endpoint::new_server(admin_db, "ADMIN".to_owned())));
+    let admin_db = match Database::exists(access::ADMIN_DATABASE, Some("admin_database".to_owned())) {
+        true => match Database::build_from_dir(access::ADMIN_DATABASE.to_owned(), None, Some("admin_database".to_owned())) {
+            Ok(admin_db) => admin_db,
+            Err(e) => panic!("{}", ["admin database could not be built from dir ".to_owned(), e].concat())
+        },
+        false => Database::new(access::ADMIN_DATABASE.to_owned(), None, access::ADMIN_ROLE.to_owned(), Some("admin_database".to_owned()))
+    };
+    match admin_db.try_lock() {
+        Ok(mut admin) => match admin.init_access() {
+            Ok(e) => println!("{e}"),
+            Err(e) => panic!("{}", ["users and grants could not be initialised ".to_owned(), e].concat())
+        },
+        Err(e) => panic!("{}", ["shits fucked ".to_owned(), e.to_string()].concat())
+    }
+    let mut databases: Databases = Arc::new(Mutex::new(vec![(access::ADMIN_DATABASE.to_owned(), Arc::clone(&admin_db))]));
+    let mut endpoints: Arc<Mutex<Vec<Arc<Mutex<Endpoint<'static>>>>>> = Arc::new(Mutex::new(Endpoint::new_server(admin_db, "ADMIN".to_owned()).into_iter().map(|endpoint| Arc::new(Mutex::new(endpoint))).collect()));
 
     build_from_dir(Arc::clone(&databases), Arc::clone(&endpoints));
 
@@ -31,18 +44,14 @@ fn main() {
         tpool.install(|| {
             let request = request::Request::parse_stream(&mut stream);
             println!("\n{}\n", request);
-            let mut msg = "message not replaced".to_string();
-            match endpoints.try_lock() {
-                Ok(endpoints) => msg = match_endpoint(request, databases.clone(), endpoints),
-                Err(e) => msg = e.to_string()
-            }
+            let msg = match_endpoint(request, Arc::clone(&databases), Arc::clone(&endpoints));
             stream.write(format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}", msg.len(), msg).as_bytes()).unwrap();
             stream.flush().unwrap();
         });
     }
 }
 
-fn build_from_dir(databases: Arc<Mutex<Vec<Arc<Mutex<Database<'static>>>>>>, mut endpoints: Arc<Mutex<Vec<Endpoint<'static>>>>) -> Result<String, String>  {
+fn build_from_dir(databases: Databases, mut endpoints: Arc<Mutex<Vec<Arc<Mutex<Endpoint<'static>>>>>>) -> Result<String, String>  {
     let curr_dir_res = env::current_dir();
     match curr_dir_res {
         Ok(ref path_buf) => match path_buf.to_str() {
@@ -56,8 +65,8 @@ fn build_from_dir(databases: Arc<Mutex<Vec<Arc<Mutex<Database<'static>>>>>>, mut
                                         let y = de.path().to_str().unwrap().to_owned();
                                         let mut pieces = y.rsplit("\\");
                                         match pieces.next() {
-                                            Some(p) => match Database::build_from_dir(p.to_owned(), Some(Arc::clone(dbl.first().unwrap())), None) {
-                                                Ok(ndb) => dbl.push(ndb),
+                                            Some(p) => match Database::build_from_dir(p.to_owned(), Some(Arc::clone(&dbl.first().unwrap().1)), None) {
+                                                Ok(ndb) => dbl.push((p.to_owned(), ndb)),
                                                 Err(e) => panic!("db could not be built from dir")
                                             },
                                             None => panic!("iterator over path could not get end of path"),
@@ -80,44 +89,164 @@ fn build_from_dir(databases: Arc<Mutex<Vec<Arc<Mutex<Database<'static>>>>>>, mut
     }
 }
 
-fn match_endpoint(request: Request, databases: Arc<Mutex<Vec<Arc<Mutex<Database<'static>>>>>>, mut endpoints: MutexGuard<Vec<Endpoint<'static>>>) -> String {
+/* 
+ * MARK: lookup
+ * the global vectors are only locked long enough to copy out the pointers they hold, so a request running
+ * against one database never holds up a request for another, databases are kept next to their names
+ * so finding one never locks the others
+ */
+type Databases = Arc<Mutex<Vec<(String, Arc<Mutex<Database<'static>>>)>>>;
+
+fn snapshot<T: Clone>(shared: &Arc<Mutex<Vec<T>>>) -> Vec<T> {
+    match shared.lock() {
+        Ok(items) => items.clone(),
+        Err(e) => panic!("{}", ["shits fucked ".to_owned(), e.to_string()].concat())
+    }
+}
+
+fn database_named(dbs: &[(String, Arc<Mutex<Database<'static>>>)], name: &str) -> Option<Arc<Mutex<Database<'static>>>> {
+    dbs.iter().find(|(db_name, _)| db_name == name).map(|(_, db)| Arc::clone(db))
+}
+
+fn server_endpoint(endpoints: &Arc<Mutex<Vec<Arc<Mutex<Endpoint<'static>>>>>>, name: &str) -> Option<Arc<Mutex<Endpoint<'static>>>> {
+    snapshot(endpoints).into_iter().find(|endpoint| match endpoint.lock() { Ok(endpoint) => endpoint.name == name, Err(_) => false })
+}
+
+/* 
+ * run the server query named by the method on the database named in the body and bring the databases
+ * vector in line with what it did to the directories, the admin database is always first and left alone
+ */
+fn alter_database(request: Request, user: &User, databases: &Databases, endpoints: &Arc<Mutex<Vec<Arc<Mutex<Endpoint<'static>>>>>>) -> String {
+    let endpoint_name = request.method.to_lowercase();
+    let db_name = match request.body["database_name"].as_str() {
+        Some(db_name) => db_name.to_owned(),
+        None => return "could not parse database_name".to_owned()
+    };
+    let dbs = snapshot(databases);
+    let db = match database_named(dbs.get(1..).unwrap_or_default(), &db_name) {
+        Some(db) => db,
+        None => return ["database ", &db_name, " not found"].concat()
+    };
+    let new_name = request.body["new_name"].as_str().map(str::to_owned);
+    if let Some(new_name) = &new_name {
+        if database_named(&dbs, new_name).is_some() {
+            return ["database ", new_name, " already exists"].concat();
+        }
+    }
+    let endpoint = match server_endpoint(endpoints, &endpoint_name) {
+        Some(endpoint) => endpoint,
+        None => return [&endpoint_name[..], " endpoint not found"].concat()
+    };
+    let mut endpoint = match endpoint.lock() {
+        Ok(endpoint) => endpoint,
+        Err(e) => panic!("{}", ["shits fucked ".to_owned(), e.to_string()].concat())
+    };
+    let result = match db.lock() {
+        Ok(mut dbmg) => {
+            endpoint.run(user, Some(&mut dbmg), request.body, None);
+            endpoint.result()
+        },
+        Err(_) => return "matching database could not be accessed do to multithreading blocking".to_owned()
+    };
+    let mut dbs = match databases.lock() {
+        Ok(dbs) => dbs,
+        Err(e) => panic!("{}", ["shits fucked ".to_owned(), e.to_string()].concat())
+    };
+    match (result, &request.method[..], new_name) {
+        (Ok(e), "DROP_DATABASE", _) => {
+            dbs.retain(|(_, other)| !Arc::ptr_eq(other, &db));
+            e
+        },
+        (Ok(e), "RENAME_DATABASE", Some(new_name)) => match endpoint.database() {
+            Ok(ndb) => {
+                dbs.iter_mut().filter(|(_, other)| Arc::ptr_eq(other, &db)).for_each(|other| *other = (new_name.clone(), Arc::clone(&ndb)));
+                e
+            },
+            Err(err) => [e, err].concat()
+        },
+        (Ok(e), _, Some(new_name)) => match endpoint.database() {
+            Ok(ndb) => {
+                dbs.push((new_name, ndb));
+                e
+            },
+            Err(err) => [e, err].concat()
+        },
+        (Ok(e), _, None) => e,
+        (Err(e), _, _) => e
+    }
+}
+
+/* 
+ * the user sending the request from its Authorization header, users are kept in the admin database which is always first
+ */
+fn authenticate(request: &Request, databases: &Databases) -> Result<User, String> {
+    let authorization = request.headers.iter().find(|(header, _)| header.eq_ignore_ascii_case("Authorization")).map(|(_, value)| &value[..]);
+    match snapshot(databases).first().map(|(_, admin_db)| admin_db.lock()) {
+        Some(Ok(admin_db)) => admin_db.authenticate(authorization),
+        Some(Err(_)) => Err("admin database could not be accessed do to multithreading blocking".to_owned()),
+        None => Err("admin database not initialised".to_owned())
+    }
+}
+
+fn match_endpoint(request: Request, databases: Databases, endpoints: Arc<Mutex<Vec<Arc<Mutex<Endpoint<'static>>>>>>) -> String {
     println!("matching endpoint");
+    // writes sent with a transaction id are buffered in that transaction until it is committed
+    let transaction = match request.headers.iter().find(|(header, _)| header.eq_ignore_ascii_case("Transaction-Id")).map(|(_, id)| id.parse::<u64>()) {
+        Some(Ok(id)) => Some(id),
+        Some(Err(_)) => return "transaction id header is not a number".to_owned(),
+        None => None
+    };
+    let user = match authenticate(&request, &databases) {
+        Ok(user) => user,
+        Err(e) => return e
+    };
     match &request.method[..] {
-        "CREATE_DATABASE" => match endpoints.iter_mut().find(|endpoint| endpoint.name == "create_database") {
-            Some(mut new_db_endpoint) => {
-                new_db_endpoint.run(None, request.body, None);
-                match new_db_endpoint.result() {
-                    Ok(mut e) => {
-                        match new_db_endpoint.runnable.try_lock() { 
-                            Ok(mut runnable) => match &mut *runnable {  
-                                runnable::Runnable::Query(q) => match q { 
-                                    endpoint::query::Query::QueryNewDatabase(qnd) => match &qnd.result { 
-                                        Ok(ndb) => match databases.try_lock() { 
-                                            Ok(mut dbs) => dbs.push(Arc::clone(&ndb)), 
-                                            Err(err) => e = [e, err.to_string()].concat() 
+        "CREATE_DATABASE" => match server_endpoint(&endpoints, "create_database") {
+            Some(new_db_endpoint) => match new_db_endpoint.lock() {
+                Ok(mut new_db_endpoint) => {
+                    let database_name = request.body["database_name"].as_str().unwrap_or_default().to_owned();
+                    new_db_endpoint.run(&user, None, request.body, None);
+                    match new_db_endpoint.result() {
+                        Ok(mut e) => {
+                            match new_db_endpoint.runnable.try_lock() { 
+                                Ok(mut runnable) => match &mut *runnable {  
+                                    runnable::Runnable::Query(q) => match q { 
+                                        endpoint::query::Query::QueryNewDatabase(qnd) => match &qnd.result { 
+                                            Ok(ndb) => match databases.lock() { 
+                                                Ok(mut dbs) => dbs.push((database_name.clone(), Arc::clone(&ndb))), 
+                                                Err(err) => e = [e, err.to_string()].concat() 
+                                            }, 
+                                            Err(err) => e = [e, err.to_string()].concat() }, 
+                                            _ => e = [e, "query is not a querynewdatabase".to_owned()].concat() 
                                         }, 
-                                        Err(err) => e = [e, err.to_string()].concat() }, 
-                                        _ => e = [e, "query is not a querynewdatabase".to_owned()].concat() 
-                                    }, 
-                                    _ => e = [e, "runnable is not a query".to_owned()].concat(),
-                                runnable::Runnable::Script(_) => e = "scripts not yet implemented".to_owned()
-                            },
-                            Err(err) => e = err.to_string()
-                        };
-                        e
-                    },
-                    Err(e) => e
-                }
+                                        _ => e = [e, "runnable is not a query".to_owned()].concat(),
+                                    runnable::Runnable::Script(_) => e = "scripts not yet implemented".to_owned()
+                                },
+                                Err(err) => e = err.to_string()
+                            };
+                            e
+                        },
+                        Err(e) => e
+                    }
+                },
+                Err(e) => panic!("{}", ["shits fucked ".to_owned(), e.to_string()].concat())
             },
             None => "new database endpoint not found".to_owned()
         },
-        _ => match databases.try_lock() { 
-            Ok(dbs) => {
-                println!("fdsfds");
-                match request.path.len() {
-                    1 => match dbs.iter().find(|a| match a.try_lock() { Ok(e) => { println!("matching db name {} == {}", e.name, request.path[0]); e.name == request.path[0] }, Err(_) => false} ) {
-                        Some(dba) => match dba.try_lock() {
-                            Ok(mut dbmg) => match &request.method[..] {
+        "LIST_DATABASES" => json!({ "databases": snapshot(&databases).iter().filter_map(|(_, db)| match db.lock() {
+            Ok(db) => user.accesses(&db.role(), &db.name).then(|| db.summary(&user)),
+            Err(_) => Some(json!({ "error": "database could not be accessed do to multithreading blocking" }))
+        }).collect::<Vec<serde_json::Value>>() }).to_string(),
+        "DROP_DATABASE" | "RENAME_DATABASE" | "CLONE_DATABASE" => alter_database(request, &user, &databases, &endpoints),
+        _ => {
+            let dbs = snapshot(&databases);
+            println!("fdsfds");
+            match request.path.len() {
+                1 => match database_named(&dbs, &request.path[0]) {
+                    Some(dba) => match dba.lock() {
+                        Ok(mut dbmg) => {
+                            dbmg.transaction = transaction;
+                            match &request.method[..] {
                                 "CREATE_TABLE" => {
                                     println!("CREATE_TABLE");
                                     let endp = match dbmg.endpoints.iter_mut().find(|a| match a.try_lock() { Ok(a) => a.name == "create_table", Err(_) => false}) {
@@ -127,7 +256,7 @@ fn match_endpoint(request: Request, databases: Arc<Mutex<Vec<Arc<Mutex<Database<
                                     match endp {
                                         Ok(e) => match e.try_lock() {
                                             Ok(mut e) => {
-                                                e.run(Some(&mut dbmg), request.body, None);
+                                                e.run(&user, Some(&mut dbmg), request.body, None);
                                                 match e.result() { Ok(e) => e, Err(e) => e }
                                             },
                                             Err(e) => e.to_string()
@@ -144,7 +273,7 @@ fn match_endpoint(request: Request, databases: Arc<Mutex<Vec<Arc<Mutex<Database<
                                     match endp {
                                         Ok(e) => match e.try_lock() {
                                             Ok(mut e) => {
-                                                e.run(Some(&mut dbmg), request.body, None);
+                                                e.run(&user, Some(&mut dbmg), request.body, None);
                                                 match e.result() { Ok(e) => e, Err(e) => e }
                                             },
                                             Err(e) => e.to_string()
@@ -161,7 +290,77 @@ fn match_endpoint(request: Request, databases: Arc<Mutex<Vec<Arc<Mutex<Database<
                                     match endp {
                                         Ok(e) => match e.try_lock() {
                                             Ok(mut e) => {
-                                                e.run(Some(&mut dbmg), request.body, None);
+                                                e.run(&user, Some(&mut dbmg), request.body, None);
+                                                match e.result() { Ok(e) => e, Err(e) => e }
+                                            },
+                                            Err(e) => e.to_string()
+                                        }
+                                        Err(e) => e
+                                    }
+                                },
+                                "CREATE_INDEX" => {
+                                    println!("CREATE_INDEX");
+                                    let endp = match dbmg.endpoints.iter_mut().find(|a| match a.try_lock() { Ok(a) => a.name == "create_index", Err(_) => false}) {
+                                        Some(e) => Ok(Arc::clone(e)),
+                                        None => Err("index creation endpoint not found".to_owned())
+                                    };
+                                    match endp {
+                                        Ok(e) => match e.try_lock() {
+                                            Ok(mut e) => {
+                                                e.run(&user, Some(&mut dbmg), request.body, None);
+                                                match e.result() { Ok(e) => e, Err(e) => e }
+                                            },
+                                            Err(e) => e.to_string()
+                                        }
+                                        Err(e) => e
+                                    }
+                                },
+                                "DROP_INDEX" => {
+                                    println!("DROP_INDEX");
+                                    let endp = match dbmg.endpoints.iter_mut().find(|a| match a.try_lock() { Ok(a) => a.name == "drop_index", Err(_) => false}) {
+                                        Some(e) => Ok(Arc::clone(e)),
+                                        None => Err("index drop endpoint not found".to_owned())
+                                    };
+                                    match endp {
+                                        Ok(e) => match e.try_lock() {
+                                            Ok(mut e) => {
+                                                e.run(&user, Some(&mut dbmg), request.body, None);
+                                                match e.result() { Ok(e) => e, Err(e) => e }
+                                            },
+                                            Err(e) => e.to_string()
+                                        }
+                                        Err(e) => e
+                                    }
+                                },
+                                "JOIN" => {
+                                    println!("JOIN");
+                                    let endp = match dbmg.endpoints.iter_mut().find(|a| match a.try_lock() { Ok(a) => a.name == "join", Err(_) => false}) {
+                                        Some(e) => Ok(Arc::clone(e)),
+                                        None => Err("join endpoint not found".to_owned())
+                                    };
+                                    match endp {
+                                        Ok(e) => match e.try_lock() {
+                                            Ok(mut e) => {
+                                                e.run(&user, Some(&mut dbmg), request.body, None);
+                                                match e.result() { Ok(e) => e, Err(e) => e }
+                                            },
+                                            Err(e) => e.to_string()
+                                        }
+                                        Err(e) => e
+                                    }
+                                },
+                                "SQL" => {
+                                    println!("SQL");
+                                    let endp = match dbmg.endpoints.iter_mut().find(|a| match a.try_lock() { Ok(a) => a.name == "sql", Err(_) => false}) {
+                                        Some(e) => Ok(Arc::clone(e)),
+                                        None => Err("sql endpoint not found".to_owned())
+                                    };
+                                    match endp {
+                                        Ok(e) => match e.try_lock() {
+                                            Ok(mut e) => {
+                                                e.run(&user, Some(&mut dbmg), request.body, None);
+                                                // a read only takes its snapshot while running, the snapshot is scanned once the database is let go of
+                                                drop(dbmg);
                                                 match e.result() { Ok(e) => e, Err(e) => e }
                                             },
                                             Err(e) => e.to_string()
@@ -169,6 +368,81 @@ fn match_endpoint(request: Request, databases: Arc<Mutex<Vec<Arc<Mutex<Database<
                                         Err(e) => e
                                     }
                                 },
+                                "BEGIN" => {
+                                    println!("BEGIN");
+                                    let endp = match dbmg.endpoints.iter_mut().find(|a| match a.try_lock() { Ok(a) => a.name == "begin", Err(_) => false}) {
+                                        Some(e) => Ok(Arc::clone(e)),
+                                        None => Err("transaction begin endpoint not found".to_owned())
+                                    };
+                                    match endp {
+                                        Ok(e) => match e.try_lock() {
+                                            Ok(mut e) => {
+                                                e.run(&user, Some(&mut dbmg), request.body, None);
+                                                match e.result() { Ok(e) => e, Err(e) => e }
+                                            },
+                                            Err(e) => e.to_string()
+                                        }
+                                        Err(e) => e
+                                    }
+                                },
+                                "COMMIT" => {
+                                    println!("COMMIT");
+                                    let endp = match dbmg.endpoints.iter_mut().find(|a| match a.try_lock() { Ok(a) => a.name == "commit", Err(_) => false}) {
+                                        Some(e) => Ok(Arc::clone(e)),
+                                        None => Err("transaction commit endpoint not found".to_owned())
+                                    };
+                                    match endp {
+                                        Ok(e) => match e.try_lock() {
+                                            Ok(mut e) => {
+                                                e.run(&user, Some(&mut dbmg), request.body, None);
+                                                match e.result() { Ok(e) => e, Err(e) => e }
+                                            },
+                                            Err(e) => e.to_string()
+                                        }
+                                        Err(e) => e
+                                    }
+                                },
+                                "ROLLBACK" => {
+                                    println!("ROLLBACK");
+                                    let endp = match dbmg.endpoints.iter_mut().find(|a| match a.try_lock() { Ok(a) => a.name == "rollback", Err(_) => false}) {
+                                        Some(e) => Ok(Arc::clone(e)),
+                                        None => Err("transaction rollback endpoint not found".to_owned())
+                                    };
+                                    match endp {
+                                        Ok(e) => match e.try_lock() {
+                                            Ok(mut e) => {
+                                                e.run(&user, Some(&mut dbmg), request.body, None);
+                                                match e.result() { Ok(e) => e, Err(e) => e }
+                                            },
+                                            Err(e) => e.to_string()
+                                        }
+                                        Err(e) => e
+                                    }
+                                },
+                                "RESET" => {
+                                    println!("RESET");
+                                    let endp = match dbmg.endpoints.iter_mut().find(|a| match a.try_lock() { Ok(a) => a.name == "reset", Err(_) => false}) {
+                                        Some(e) => Ok(Arc::clone(e)),
+                                        None => Err("database reset endpoint not found".to_owned())
+                                    };
+                                    match endp {
+                                        Ok(e) => match e.try_lock() {
+                                            Ok(mut e) => {
+                                                e.run(&user, Some(&mut dbmg), request.body, None);
+                                                match e.result() { Ok(e) => e, Err(e) => e }
+                                            },
+                                            Err(e) => e.to_string()
+                                        }
+                                        Err(e) => e
+                                    }
+                                },
+                                "LIST_TABLES" => {
+                                    println!("LIST_TABLES");
+                                    match user.accesses(&dbmg.role(), &dbmg.name) {
+                                        true => dbmg.describe(&user).to_string(),
+                                        false => ["user ", &user.name, " has no access to database ", &dbmg.name].concat()
+                                    }
+                                },
                                 "INDEV_TOGGLE" => {
                                     println!("INDEV_TOGGLE");
                                     let endp = match dbmg.endpoints.iter_mut().find(|a| match a.try_lock() { Ok(a) => a.name == "indev_toggle", Err(_) => false}) {
@@ -178,7 +452,75 @@ fn match_endpoint(request: Request, databases: Arc<Mutex<Vec<Arc<Mutex<Database<
                                     match endp {
                                         Ok(e) => match e.try_lock() {
                                             Ok(mut e) => {
-                                                e.run(Some(&mut dbmg), request.body, None);
+                                                e.run(&user, Some(&mut dbmg), request.body, None);
+                                                match e.result() { Ok(e) => e, Err(e) => e }
+                                            },
+                                            Err(e) => e.to_string()
+                                        }
+                                        Err(e) => e
+                                    }
+                                },
+                                "CREATE_USER" => {
+                                    println!("CREATE_USER");
+                                    let endp = match dbmg.endpoints.iter_mut().find(|a| match a.try_lock() { Ok(a) => a.name == "create_user", Err(_) => false}) {
+                                        Some(e) => Ok(Arc::clone(e)),
+                                        None => Err("user creation endpoint not found, users are managed on the admin database".to_owned())
+                                    };
+                                    match endp {
+                                        Ok(e) => match e.try_lock() {
+                                            Ok(mut e) => {
+                                                e.run(&user, Some(&mut dbmg), request.body, None);
+                                                match e.result() { Ok(e) => e, Err(e) => e }
+                                            },
+                                            Err(e) => e.to_string()
+                                        }
+                                        Err(e) => e
+                                    }
+                                },
+                                "DROP_USER" => {
+                                    println!("DROP_USER");
+                                    let endp = match dbmg.endpoints.iter_mut().find(|a| match a.try_lock() { Ok(a) => a.name == "drop_user", Err(_) => false}) {
+                                        Some(e) => Ok(Arc::clone(e)),
+                                        None => Err("user drop endpoint not found, users are managed on the admin database".to_owned())
+                                    };
+                                    match endp {
+                                        Ok(e) => match e.try_lock() {
+                                            Ok(mut e) => {
+                                                e.run(&user, Some(&mut dbmg), request.body, None);
+                                                match e.result() { Ok(e) => e, Err(e) => e }
+                                            },
+                                            Err(e) => e.to_string()
+                                        }
+                                        Err(e) => e
+                                    }
+                                },
+                                "GRANT" => {
+                                    println!("GRANT");
+                                    let endp = match dbmg.endpoints.iter_mut().find(|a| match a.try_lock() { Ok(a) => a.name == "grant", Err(_) => false}) {
+                                        Some(e) => Ok(Arc::clone(e)),
+                                        None => Err("grant endpoint not found, users are managed on the admin database".to_owned())
+                                    };
+                                    match endp {
+                                        Ok(e) => match e.try_lock() {
+                                            Ok(mut e) => {
+                                                e.run(&user, Some(&mut dbmg), request.body, None);
+                                                match e.result() { Ok(e) => e, Err(e) => e }
+                                            },
+                                            Err(e) => e.to_string()
+                                        }
+                                        Err(e) => e
+                                    }
+                                },
+                                "REVOKE" => {
+                                    println!("REVOKE");
+                                    let endp = match dbmg.endpoints.iter_mut().find(|a| match a.try_lock() { Ok(a) => a.name == "revoke", Err(_) => false}) {
+                                        Some(e) => Ok(Arc::clone(e)),
+                                        None => Err("revoke endpoint not found, users are managed on the admin database".to_owned())
+                                    };
+                                    match endp {
+                                        Ok(e) => match e.try_lock() {
+                                            Ok(mut e) => {
+                                                e.run(&user, Some(&mut dbmg), request.body, None);
                                                 match e.result() { Ok(e) => e, Err(e) => e }
                                             },
                                             Err(e) => e.to_string()
@@ -187,14 +529,24 @@ fn match_endpoint(request: Request, databases: Arc<Mutex<Vec<Arc<Mutex<Database<
                                     }
                                 },
                                 _ => "request method not recognised".to_owned()
-                            },
-                            Err(e) => "matching database could not be accessed do to multithreading blocking".to_owned()
+                            }
                         },
-                        None => "database not found".to_owned()
+                        Err(e) => "matching database could not be accessed do to multithreading blocking".to_owned()
                     },
-                    2 => match dbs.iter().find(|a| match a.try_lock() { Ok(e) => e.name == request.path[0], Err(_) => false} ) {
-                        Some(dba) => match dba.try_lock() {
-                            Ok(mut dbmg) => match &request.method[..] {
+                    None => "database not found".to_owned()
+                },
+                2 => match database_named(&dbs, &request.path[0]) {
+                    Some(dba) => match dba.lock() {
+                        Ok(mut dbmg) => {
+                            dbmg.transaction = transaction;
+                            match &request.method[..] {
+                                "DESCRIBE_TABLE" => {
+                                    println!("DESCRIBE_TABLE");
+                                    match user.allows(&dbmg.role(), &dbmg.name, Some(&request.path[1]), Permission::Read) {
+                                        true => match dbmg.describe_table(&request.path[1], &user) { Ok(e) => e.to_string(), Err(e) => e },
+                                        false => ["user ", &user.name, " is not granted read on ", &dbmg.name, ".", &request.path[1]].concat()
+                                    }
+                                },
                                 "CREATE_RECORD" => {
                                     println!("CREATE_RECORD {}", request.body);
                                     let endp = match dbmg.endpoints.iter_mut().find(|a| match a.try_lock() { Ok(a) => a.name == "create_record" && match a.table() { Ok(e) => match e.try_lock() { Ok(e) => e.name == request.path[1], Err(_) => false }, Err(_) => false }, Err(_) => false } ) {
@@ -204,7 +556,7 @@ fn match_endpoint(request: Request, databases: Arc<Mutex<Vec<Arc<Mutex<Database<
                                     match endp {
                                         Ok(e) => match e.try_lock() {
                                             Ok(mut e) => {
-                                                e.run(Some(&mut dbmg), request.body, None);
+                                                e.run(&user, Some(&mut dbmg), request.body, None);
                                                 match e.result() { Ok(e) => e, Err(e) => e }
                                             },
                                             Err(e) => e.to_string()
@@ -221,7 +573,9 @@ fn match_endpoint(request: Request, databases: Arc<Mutex<Vec<Arc<Mutex<Database<
                                     match endp {
                                         Ok(e) => match e.try_lock() {
                                             Ok(mut e) => {
-                                                e.run(Some(&mut dbmg), request.body, None);
+                                                e.run(&user, Some(&mut dbmg), request.body, None);
+                                                // a read only takes its snapshot while running, the snapshot is scanned once the database is let go of
+                                                drop(dbmg);
                                                 match e.result() { Ok(e) => e, Err(e) => e }
                                             },
                                             Err(e) => e.to_string()
@@ -238,7 +592,7 @@ fn match_endpoint(request: Request, databases: Arc<Mutex<Vec<Arc<Mutex<Database<
                                     match endp {
                                         Ok(e) => match e.try_lock() {
                                             Ok(mut e) => {
-                                                e.run(Some(&mut dbmg), request.body, None);
+                                                e.run(&user, Some(&mut dbmg), request.body, None);
                                                 match e.result() { Ok(e) => e, Err(e) => e }
                                             },
                                             Err(e) => e.to_string()
@@ -255,7 +609,7 @@ fn match_endpoint(request: Request, databases: Arc<Mutex<Vec<Arc<Mutex<Database<
                                     match endp {
                                         Ok(e) => match e.try_lock() {
                                             Ok(mut e) => {
-                                                e.run(Some(&mut dbmg), request.body, None);
+                                                e.run(&user, Some(&mut dbmg), request.body, None);
                                                 match e.result() { Ok(e) => e, Err(e) => e }
                                             },
                                             Err(e) => e.to_string()
@@ -264,14 +618,14 @@ fn match_endpoint(request: Request, databases: Arc<Mutex<Vec<Arc<Mutex<Database<
                                     }
                                 },
                                 _ => "request method not recognised".to_owned()
-                            },
-                            Err(e) => "matching database could not be accessed do to multithreading blocking".to_owned()
+                            }
                         },
-                        None => "database not found".to_owned()
+                        Err(e) => "matching database could not be accessed do to multithreading blocking".to_owned()
                     },
-                    _ => "requires path to database".to_owned()
-                }
-            }, Err(e) => e.to_string()
+                    None => "database not found".to_owned()
+                },
+                _ => "requires path to database".to_owned()
+            }
         }
     }
 }
\ No newline at end of file
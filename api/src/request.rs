@@ -54,17 +54,40 @@ impl Request {
     }
 }
 
+/*
+ * a copy of the body with every password field hidden
+ */
+fn redact(body: &Value) -> Value {
+    match body {
+        Value::Object(fields) => Value::Object(fields.iter().map(|(key, value)| match &key.to_lowercase()[..] {
+            "password" => (key.clone(), json!("[redacted]")),
+            _ => (key.clone(), redact(value))
+        }).collect()),
+        Value::Array(values) => Value::Array(values.iter().map(redact).collect()),
+        _ => body.clone()
+    }
+}
+
+/*
+ * written without the Authorization header or any password so requests can be logged
+ */
 impl fmt::Display for Request {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let headers = self.headers.iter()
+            .map(|(name, value)| match name.eq_ignore_ascii_case("Authorization") {
+                true => (name.clone(), "[redacted]".to_owned()),
+                false => (name.clone(), value.clone())
+            })
+            .collect::<HashMap<String, String>>();
         fmt.write_str(&self.method)?;
         fmt.write_str("-/")?;
         fmt.write_str(&self.path.join("/"))?;
         fmt.write_str("-")?;
         fmt.write_str(&self.version)?;
         fmt.write_str("-")?;
-        fmt.write_str(&serde_json::to_string(&self.headers).unwrap())?;
+        fmt.write_str(&serde_json::to_string(&headers).unwrap())?;
         fmt.write_str("-")?;
-        fmt.write_str(&self.body.to_string())?;
+        fmt.write_str(&redact(&self.body).to_string())?;
         Ok(())
     }
 }
@@ -1,4 +1,4 @@
-use std::{borrow::Borrow, collections::HashMap, env, fs::{self, File}, io::{Read, Write}, ops::Deref, path::Path, sync::{Arc, Mutex, MutexGuard}};
+use std::{borrow::Borrow, collections::{HashMap, HashSet}, env, fs::{self, File}, io::{Read, Write}, ops::Deref, path::Path, sync::{Arc, Mutex, MutexGuard}, time::{SystemTime, UNIX_EPOCH}};
 use serde_json::{Value, json};
 
 use crate::endpoint::Endpoint;
@@ -7,17 +7,34 @@ use self::table::Table;
 
 use super::endpoint;
 mod part;
+pub(crate) mod aggregate;
+pub(crate) mod index;
+pub(crate) mod join;
+pub(crate) mod plan;
+pub(crate) mod read;
+pub(crate) mod wal;
+pub(crate) mod zone;
 pub(crate) mod conditional;
+pub(crate) mod constraint;
 pub(crate) mod cell;
 pub(crate) mod record;
 pub(crate) mod table;
+pub(crate) mod transaction;
+pub(crate) mod snapshot;
+pub(crate) mod migration;
+pub mod access;
 
 pub struct Database<'a> {
     pub name: String,
     pub indev: bool,
     directory: String,
+    wal: Option<Arc<Mutex<wal::WriteAheadLog>>>,
     pub tables: Vec<Arc<Mutex<table::Table>>>,
     pub endpoints: Vec<Arc<Mutex<endpoint::Endpoint<'a>>>>,
+    pub transaction: Option<u64>,
+    pub user: Option<String>,
+    transactions: HashMap<u64, transaction::Transaction>,
+    next_transaction: u64,
 }
 
 impl<'a> Database<'a> {
@@ -26,7 +43,7 @@ impl<'a> Database<'a> {
      */
     pub fn new(name: String, admin_db: Option<Arc<Mutex<Database<'a>>>>, role: String, dir_override: Option<String>) -> Arc<Mutex<Self>> {
         println!("building a new database called {}", name);
-        let mut new_db: Arc<Mutex<Database<'a>>> = Arc::new(Mutex::new(Database { name: name.clone(), indev: true, directory: "".to_string(), tables: vec![], endpoints: vec![] }));
+        let mut new_db: Arc<Mutex<Database<'a>>> = Arc::new(Mutex::new(Database { name: name.clone(), indev: true, directory: "".to_string(), wal: None, tables: vec![], endpoints: vec![], transaction: None, user: None, transactions: HashMap::new(), next_transaction: Database::first_transaction() }));
         match new_db.try_lock() {
             Ok(mut e) => {
                 match e.init_dir(dir_override, role.clone()) {
@@ -46,18 +63,7 @@ impl<'a> Database<'a> {
      * MARK: build self from directory
      */
     pub fn build_from_dir(db_name: String, admin_db: Option<Arc<Mutex<Database<'a>>>>, dir_override: Option<String>) -> Result<Arc<Mutex<Self>>, String> {
-        let curr_dir_res = env::current_dir();
-        let curr_dir = match curr_dir_res {
-            Ok(ref path_buf) => match path_buf.to_str() {
-                Some(path_str) => path_str,
-                None => return Err( "unable to parse path as str".to_string())
-            },
-            Err(e) => return Err( ["unable to find current directory\n".to_string(), e.to_string()].concat()),
-        };
-        let db_dir = match dir_override {
-            Some(dir) => [curr_dir, &dir[..], &db_name].join("\\"),
-            None => [curr_dir, "databases", &db_name].join("\\")
-        };
+        let db_dir = Database::database_dir(&db_name, dir_override)?;
         println!("building db from dir {db_dir}");
         let table_dirs = match fs::read_dir(db_dir.clone()) {
             Ok(e) => e.into_iter()
@@ -72,28 +78,41 @@ impl<'a> Database<'a> {
                 .collect::<Vec<String>>(),
             Err(e) => return Err("unable to find database directory".to_string()),
         };
-        let db_definition: Result<Value, String> = match File::open([&db_dir, "/.def"].concat()) {
-            Ok(mut e) => {
-                let mut buf = "".to_owned();
-                e.read_to_string(&mut buf);
-                Ok(serde_json::from_str(&buf).unwrap())
+        let db_definition = Database::read_def(&db_dir);
+        let wal = match wal::WriteAheadLog::open([&db_dir, "/.log"].concat()) {
+            Ok(mut wal) => match wal.replay() {
+                Ok(replayed) => {
+                    println!("replayed {replayed} part writes from the database log");
+                    Arc::new(Mutex::new(wal))
+                },
+                Err(e) => return Err(["unable to replay database log\n".to_string(), e].concat())
             },
-            Err(e) => Err("unable to open database defintion file".to_string()),
+            Err(e) => return Err(e)
         };
+        // a table is only built without a part when that part was corrupt, anything else stops the database loading
+        let tables = table_dirs.into_iter()
+            .map(|table_dir| match Table::build_from_dir(table_dir.clone()) {
+                Ok((table, quarantined)) => {
+                    quarantined.iter().for_each(|e| println!("table in {table_dir} was built without a part: {e}"));
+                    Ok(table)
+                },
+                Err(e) => Err(["table in ", &table_dir, " could not be built: ", &e].concat())
+            })
+            .collect::<Result<Vec<Table>, String>>()?;
         let new_db = Arc::new(Mutex::new(Database { 
             name: db_name.clone(), 
-            indev: false, 
+            indev: match &db_definition { Ok(def) => def["indev"].as_bool().unwrap_or(false), Err(_) => false }, 
             directory: db_dir.clone(), 
-            tables:  table_dirs.into_iter()
-                                .map(|table_dir| match Table::build_from_dir(table_dir.clone()) {
-                                    Ok(b) => Some(b), 
-                                    Err(_) => None
-                                })
-                                .filter(|c| c.is_some())
-                                .map(|d| match d { Some(d) => d, _ => panic!("None exists after filtering out all Nones") })
+            wal: Some(Arc::clone(&wal)),
+            tables:  tables.into_iter()
+                                .map(|mut e| { e.wal = Some(Arc::clone(&wal)); e })
                                 .map(|e| Arc::new(Mutex::new(e)))
                                 .collect::<Vec<Arc<Mutex<Table>>>>(), 
-            endpoints: vec![]
+            endpoints: vec![],
+            transaction: None,
+            user: None,
+            transactions: HashMap::new(),
+            next_transaction: Database::first_transaction(),
         }));
         match new_db.try_lock() {
             Ok(mut e) => {
@@ -105,7 +124,17 @@ impl<'a> Database<'a> {
                             e.endpoints.append(&mut Endpoint::new_table(Arc::clone(&table), Arc::clone(&admin_db), match &db_definition { Ok(y) => match y.get("role") { Some(y) => match y.as_str() { Some(y) => y.to_owned(), _ => "admin".to_owned() }, _ => "admin".to_owned() }, _ => "admin".to_owned()}));
                         });
                     },
-                    None => e.endpoints.append(&mut Endpoint::admin_db(Arc::clone(&new_db), match db_definition { Ok(e) => match e.get("role") { Some(e) => match e.as_str() { Some(e) => e.to_owned(), _ => "admin".to_owned() }, _ => "admin".to_owned() }, _ => "admin".to_owned()}))
+                    None => {
+                        let role = match db_definition { Ok(e) => match e.get("role") { Some(e) => match e.as_str() { Some(e) => e.to_owned(), _ => "admin".to_owned() }, _ => "admin".to_owned() }, _ => "admin".to_owned()};
+                        e.endpoints.append(&mut Endpoint::admin_db(Arc::clone(&new_db), role.clone()));
+                        let tables = e.tables.iter()
+                            .filter(|table| match table.try_lock() { Ok(table) => !access::is_access_table(&table.name), Err(e) => panic!("{}", ["shits fucked ".to_owned(), e.to_string()].concat())})
+                            .map(Arc::clone)
+                            .collect::<Vec<Arc<Mutex<Table>>>>();
+                        tables.iter().for_each(|table| {
+                            e.endpoints.append(&mut Endpoint::new_table(Arc::clone(table), Arc::clone(&new_db), role.clone()));
+                        });
+                    }
                 }
             },
             Err(e) => panic!("{}", ["shits fucked ".to_owned(), e.to_string()].concat())
@@ -115,61 +144,266 @@ impl<'a> Database<'a> {
     }
 
     /* 
-     * MARK: initialise directory
+     * MARK: database directory
+     * where a database of the given name lives, under databases in the current directory unless overridden
      */
-    fn init_dir(&mut self, dir_override: Option<String>, role: String) -> Result<String, String> {
-        println!("initialising directory");
+    fn database_dir(db_name: &str, dir_override: Option<String>) -> Result<String, String> {
         let curr_dir_res = env::current_dir();
         let curr_dir = match curr_dir_res {
             Ok(ref path_buf) => match path_buf.to_str() {
                 Some(path_str) => path_str,
-                None => return Err(("unable to parse path as str".to_string()))
+                None => return Err("unable to parse path as str".to_string())
             },
-            Err(e) => return Err((["unable to find current directory\n".to_string(), e.to_string()].concat())),
-        };
-        let db_dir = match dir_override {
-            Some(dir) => [curr_dir, &dir[..], &self.name].join("\\"),
-            None => [curr_dir, "databases", &self.name].join("\\")
+            Err(e) => return Err(["unable to find current directory\n".to_string(), e.to_string()].concat()),
         };
+        Ok(match dir_override {
+            Some(dir) => [curr_dir, &dir[..], db_name].join("\\"),
+            None => [curr_dir, "databases", db_name].join("\\")
+        })
+    }
+
+    /* 
+     * MARK: initialise directory
+     */
+    fn init_dir(&mut self, dir_override: Option<String>, role: String) -> Result<String, String> {
+        println!("initialising directory");
+        let db_dir = Database::database_dir(&self.name, dir_override)?;
         println!("directory: {}", db_dir);
         match fs::create_dir_all(&db_dir) {
             Ok(_) => self.directory = db_dir.to_string(),
             Err(e) => return Err((["unable to create specified directories\n".to_string(), e.to_string()].concat())),
         };
-        match File::create([&db_dir, ".def"].join("\\")) {
-            Ok(mut e) => {write!(e, "{{ \"role\":\"{role}\" }}").unwrap();},
-            Err(e) => return Err((["unable to create database definition file\n".to_string(), e.to_string()].concat()))
-        };
+        Database::write_def(&db_dir, &json!({ "role": role, "indev": self.indev }))?;
         match File::create([&db_dir, "/.log"].concat()) {
-            Ok(_) => {}
+            Ok(_) => self.wal = Some(Arc::new(Mutex::new(wal::WriteAheadLog::open([&db_dir, "/.log"].concat())?))),
             Err(e) => return Err((["unable to create database log file\n".to_string(), e.to_string()].concat()))
         };
         Ok("directory initialisation successful".to_owned())
     }
 
     /* 
-     * MARK: build a new table
-     *                                                                                                        cname   dtype   default value   nullable unique   foreign key
+     * MARK: close
+     * let go of the log held by the database and its tables so the directory can be moved or removed,
+     * the database is not written to again and is replaced by one built from the new directory
      */
-    pub fn build_table(&mut self, admin_db: Arc<Mutex<Database<'a>>>, table_name: String, table_columns: Vec<(String, String, Option<String>, bool, bool, Option<(String, String)>)>) /* -> Result<String, String> */ {
-        println!("building a new table {table_name}");
-        let db_definition = match File::open([&self.directory, "/.def"].concat()) {
-            Ok(mut e) => {
-                let mut buf = "".to_owned();
-                e.read_to_string(&mut buf);
-                Ok(json!(buf))
+    fn close(&mut self) -> Result<(), String> {
+        if !self.transactions.is_empty() {
+            return Err(["database ", &self.name, " has ", &self.transactions.len().to_string(), " open transactions"].concat());
+        }
+        self.tables.iter().for_each(|table| match table.try_lock() {
+            Ok(mut table) => table.wal = None,
+            Err(e) => panic!("{}", ["shits fucked ".to_owned(), e.to_string()].concat())
+        });
+        self.wal = None;
+        Ok(())
+    }
+
+    /* 
+     * open the log again after the directory could not be moved or removed
+     */
+    fn reopen(&mut self) -> Result<(), String> {
+        let wal = Arc::new(Mutex::new(wal::WriteAheadLog::open([&self.directory, "/.log"].concat())?));
+        self.tables.iter().for_each(|table| match table.try_lock() {
+            Ok(mut table) => table.wal = Some(Arc::clone(&wal)),
+            Err(e) => panic!("{}", ["shits fucked ".to_owned(), e.to_string()].concat())
+        });
+        self.wal = Some(wal);
+        Ok(())
+    }
+
+    /* 
+     * MARK: drop database
+     * delete the directory of the database along with every table in it
+     */
+    pub fn drop_dir(&mut self) -> Result<String, String> {
+        self.check_indev("drop_database")?;
+        self.close()?;
+        match fs::remove_dir_all(&self.directory) {
+            Ok(_) => {
+                self.tables = vec![];
+                self.endpoints = vec![];
+                Ok(json!({ "dropped": self.name }).to_string())
+            },
+            Err(e) => {
+                self.reopen()?;
+                Err(["unable to delete database directory\n".to_string(), e.to_string()].concat())
+            }
+        }
+    }
+
+    /* 
+     * MARK: rename database
+     * move the directory of the database to the new name, the moved database is built again from its new directory
+     */
+    pub fn rename_dir(&mut self, new_name: &str, dir_override: Option<String>) -> Result<(), String> {
+        self.check_indev("rename_database")?;
+        let new_dir = Database::unused_dir(new_name, dir_override)?;
+        self.close()?;
+        match fs::rename(&self.directory, &new_dir) {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                self.reopen()?;
+                Err(["unable to move database directory\n".to_string(), e.to_string()].concat())
+            }
+        }
+    }
+
+    /* 
+     * MARK: clone database
+     * copy every file of the database to a directory under the new name, the copy is built from that directory
+     */
+    pub fn clone_dir(&self, new_name: &str, dir_override: Option<String>) -> Result<(), String> {
+        if !self.transactions.is_empty() {
+            return Err(["database ", &self.name, " has ", &self.transactions.len().to_string(), " open transactions"].concat());
+        }
+        let new_dir = Database::unused_dir(new_name, dir_override)?;
+        match Database::copy_dir(Path::new(&self.directory), Path::new(&new_dir)) {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                let _ = fs::remove_dir_all(&new_dir);
+                Err(["unable to copy database directory\n".to_string(), e.to_string()].concat())
+            }
+        }
+    }
+
+    fn unused_dir(db_name: &str, dir_override: Option<String>) -> Result<String, String> {
+        if db_name.is_empty() || db_name.contains(['\\', '/', '.']) {
+            return Err(["database name ", db_name, " is not a valid directory name"].concat());
+        }
+        let db_dir = Database::database_dir(db_name, dir_override)?;
+        match Path::new(&db_dir).exists() {
+            true => Err(["database ", db_name, " already exists"].concat()),
+            false => Ok(db_dir)
+        }
+    }
+
+    fn copy_dir(from: &Path, to: &Path) -> std::io::Result<()> {
+        fs::create_dir_all(to)?;
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            match entry.file_type()?.is_dir() {
+                true => Database::copy_dir(&entry.path(), &to.join(entry.file_name()))?,
+                false => { fs::copy(entry.path(), to.join(entry.file_name()))?; }
+            }
+        }
+        Ok(())
+    }
+
+    /* 
+     * MARK: database definition
+     * the role and indev flag of the database, kept as json in the .def file of its directory
+     */
+    fn def_path(db_dir: &str) -> String {
+        [db_dir, ".def"].join("\\")
+    }
+
+    fn read_def(db_dir: &str) -> Result<Value, String> {
+        let mut buf = "".to_owned();
+        match File::open(Database::def_path(db_dir)) {
+            Ok(mut e) => match e.read_to_string(&mut buf) {
+                Ok(_) => serde_json::from_str(&buf).map_err(|e| ["database definition file is corrupt ".to_string(), e.to_string()].concat()),
+                Err(e) => Err(["unable to read database definition file\n".to_string(), e.to_string()].concat())
             },
             Err(e) => Err("unable to open database defintion file".to_string()),
+        }
+    }
+
+    /* 
+     * the definition is written to a temporary file and renamed over the old one like the table definitions
+     */
+    fn write_def(db_dir: &str, def: &Value) -> Result<(), String> {
+        let def_path = Database::def_path(db_dir);
+        let tmp_path = [&def_path, ".tmp"].concat();
+        match File::create(&tmp_path) {
+            Ok(mut e) => match e.write_all(def.to_string().as_bytes()).and_then(|_| e.sync_all()) {
+                Ok(_) => {},
+                Err(e) => return Err(["unable to write database definition file\n".to_string(), e.to_string()].concat())
+            },
+            Err(e) => return Err(["unable to create database definition file\n".to_string(), e.to_string()].concat())
         };
-        let new_table = Arc::new(Mutex::new(Table::new(self.name.clone(), table_name, table_columns, true)));
-        self.tables.push(Arc::clone(&new_table));
+        match fs::rename(&tmp_path, &def_path) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(["unable to replace database definition file\n".to_string(), e.to_string()].concat())
+        }
+    }
+
+    /* 
+     * the role the database was created for, the role its endpoints carry
+     */
+    pub fn role(&self) -> String {
+        match Database::read_def(&self.directory) {
+            Ok(def) => def["role"].as_str().unwrap_or("admin").to_owned(),
+            Err(_) => "admin".to_owned()
+        }
+    }
+
+    pub fn exists(db_name: &str, dir_override: Option<String>) -> bool {
+        Database::database_dir(db_name, dir_override).is_ok_and(|db_dir| Path::new(&db_dir).exists())
+    }
+
+    /* 
+     * MARK: set indev
+     * the flag is only changed in memory once the definition holding it has been saved
+     */
+    pub fn set_indev(&mut self, indev: bool) -> Result<String, String> {
+        let mut def = Database::read_def(&self.directory)?;
+        def["indev"] = json!(indev);
+        Database::write_def(&self.directory, &def)?;
+        self.indev = indev;
+        Ok(json!({ "database": self.name, "indev": self.indev }).to_string())
+    }
+
+    /* 
+     * MARK: check indev
+     * schema changes only run while the database is in development, production databases refuse them
+     */
+    pub fn check_indev(&self, operation: &str) -> Result<(), String> {
+        match self.indev {
+            true => Ok(()),
+            false => Err(["database ", &self.name, " is in production, ", operation, " only runs while indev is set"].concat())
+        }
+    }
+
+    /* 
+     * MARK: check access table
+     * the users and grants tables of the admin database are only reached through the user and grant endpoints,
+     * a table of the same name in any other database is an ordinary table
+     */
+    pub fn check_access_table(&self, table_name: &str) -> Result<(), String> {
+        match self.name == access::ADMIN_DATABASE && access::is_access_table(table_name) {
+            true => Err(["table ", table_name, " of the ", access::ADMIN_DATABASE, " database is only reached through the user and grant endpoints"].concat()),
+            false => Ok(())
+        }
+    }
+
+    /* 
+     * MARK: build a new table
+     *                                                                                                        cname   dtype   default value   nullable unique   foreign key               on delete
+     */
+    pub fn build_table(&mut self, admin_db: Arc<Mutex<Database<'a>>>, table_name: String, table_columns: Vec<(String, String, Option<String>, bool, bool, Option<(String, String)>, cell::ReferentialAction)>) /* -> Result<String, String> */ {
+        let db_definition = Database::read_def(&self.directory);
+        let new_table = self.add_table(table_name, table_columns);
         self.endpoints.append(&mut Endpoint::new_table(Arc::clone(&new_table), admin_db, match db_definition { Ok(e) => match e.get("role") { Some(e) => match e.as_str() { Some(e) => e.to_owned(), _ => "admin".to_owned() }, _ => "admin".to_owned() }, _ => "admin".to_owned()}));
     }
 
+    /* 
+     * a new table without any endpoints
+     */
+    fn add_table(&mut self, table_name: String, table_columns: Vec<cell::ColumnDef>) -> Arc<Mutex<Table>> {
+        println!("building a new table {table_name}");
+        let mut new_table = Table::new(self.name.clone(), table_name, table_columns, true);
+        new_table.wal = self.wal.clone();
+        let new_table = Arc::new(Mutex::new(new_table));
+        self.tables.push(Arc::clone(&new_table));
+        new_table
+    }
+
     /* 
      * MARK: delete a table
      */
     pub fn delete_table(&mut self, table_name: String) -> Result<String, String> {
+        self.check_indev("delete_table")?;
+        self.check_access_table(&table_name)?;
         let table_index = match self.tables.iter_mut().enumerate().find(|(i, table)| match table.try_lock() { Ok(table) => table.name == table_name, Err(e) => panic!("{}", ["shits fucked ".to_owned(), e.to_string()].concat())}) {
             Some((i, table)) => match table.try_lock() {
                 Ok(mut table) => match table.query_delete_table() {
@@ -188,4 +422,764 @@ impl<'a> Database<'a> {
             Err(e) => Err(e)
         }
     }
-}
\ No newline at end of file
+
+    /* 
+     * MARK: reset tables
+     * empty every table of the database and restart their keys, returns how many records each table lost,
+     * destructive so it only runs while the database is in development, the users and grants of the admin database are left alone
+     */
+    pub fn reset_tables(&mut self) -> Result<Value, String> {
+        self.check_indev("reset")?;
+        let mut truncated = serde_json::Map::new();
+        for table in self.tables.iter() {
+            match table.try_lock() {
+                Ok(table) if self.check_access_table(&table.name).is_err() => {},
+                Ok(mut table) => {
+                    let count = table.truncate()?;
+                    truncated.insert(table.name.clone(), json!(count));
+                },
+                Err(e) => panic!("{}", ["shits fucked ".to_owned(), e.to_string()].concat())
+            }
+        }
+        Ok(Value::Object(truncated))
+    }
+
+    /* 
+     * MARK: update a table
+     * migrations rewrite every stored record of the table so they only run while the database is in development
+     */
+    pub fn update_table(&mut self, table_name: &str, migration: &migration::Migration) -> Result<String, String> {
+        self.check_indev("update_table")?;
+        self.check_access_table(table_name)?;
+        let tables = self.tables.clone();
+        match self.table(table_name) {
+            Some(table) => match table.try_lock() {
+                Ok(mut locked) => locked.migrate(&table, migration, &tables),
+                Err(e) => panic!("{}", ["shits fucked ".to_owned(), e.to_string()].concat())
+            },
+            None => Err(["table ", table_name, " does not exist in database"].concat())
+        }
+    }
+
+    pub fn migration_history(&self, table_name: &str) -> Result<String, String> {
+        match self.table(table_name) {
+            Some(table) => match table.try_lock() {
+                Ok(table) => table.migration_history(),
+                Err(e) => panic!("{}", ["shits fucked ".to_owned(), e.to_string()].concat())
+            },
+            None => Err(["table ", table_name, " does not exist in database"].concat())
+        }
+    }
+
+    /* 
+     * MARK: describe
+     * the database with only the names of its tables, as it is listed alongside the other databases
+     */
+    pub fn summary(&self, user: &access::User) -> Value {
+        let tables = self.tables.iter().filter_map(|table| match table.try_lock() {
+            Ok(table) => self.lists(&table.name, user).then(|| table.name.clone()),
+            Err(e) => panic!("{}", ["shits fucked ".to_owned(), e.to_string()].concat())
+        }).collect::<Vec<String>>();
+        json!({ "name": self.name, "indev": self.indev, "tables": tables, "endpoints": self.describe_endpoints(None) })
+    }
+
+    /* 
+     * the database with the columns of every table
+     */
+    pub fn describe(&self, user: &access::User) -> Value {
+        let tables = self.tables.iter().filter_map(|table| match table.try_lock() {
+            Ok(table) => self.lists(&table.name, user).then(|| table.describe()),
+            Err(e) => panic!("{}", ["shits fucked ".to_owned(), e.to_string()].concat())
+        }).collect::<Vec<Value>>();
+        json!({ "name": self.name, "indev": self.indev, "tables": tables, "endpoints": self.describe_endpoints(None) })
+    }
+
+    pub fn describe_table(&self, table_name: &str, user: &access::User) -> Result<Value, String> {
+        let mut description = match self.table(table_name).filter(|_| self.lists(table_name, user)) {
+            Some(table) => match table.try_lock() {
+                Ok(table) => table.describe(),
+                Err(e) => panic!("{}", ["shits fucked ".to_owned(), e.to_string()].concat())
+            },
+            None => return Err(["table ", table_name, " does not exist in database"].concat())
+        };
+        description["endpoints"] = json!(self.describe_endpoints(Some(table_name)));
+        Ok(description)
+    }
+
+    /* 
+     * the users and grants of the admin database are only listed to the admin role, any other user
+     * granted something on the admin database is told they do not exist
+     */
+    fn lists(&self, table_name: &str, user: &access::User) -> bool {
+        user.role == access::ADMIN_ROLE || self.check_access_table(table_name).is_ok()
+    }
+
+    /* 
+     * the name and role of each endpoint and the table it runs on, only the endpoints of one table when given
+     */
+    fn describe_endpoints(&self, table_name: Option<&str>) -> Vec<Value> {
+        self.endpoints.iter()
+            .filter_map(|endpoint| endpoint.try_lock().ok().map(|endpoint| {
+                let table = endpoint.table().ok().and_then(|table| table.try_lock().ok().map(|table| table.name.clone()));
+                (endpoint.name.clone(), endpoint.role.clone(), table)
+            }))
+            .filter(|(_, _, table)| table_name.is_none_or(|table_name| table.as_deref() == Some(table_name)))
+            .map(|(name, role, table)| json!({ "name": name, "role": role, "table": table }))
+            .collect::<Vec<Value>>()
+    }
+
+    /* 
+     * MARK: access
+     * the users and grants tables of the admin database, created on first start along with an admin user
+     * named by OBJ_DB_ADMIN_USER whose password is OBJ_DB_ADMIN_PASSWORD, which has to be set on that first start
+     */
+    pub fn init_access(&mut self) -> Result<String, String> {
+        for (table_name, columns) in [(access::USERS, access::users_columns()), (access::GRANTS, access::grants_columns())] {
+            if self.table(table_name).is_none() {
+                self.add_table(table_name.to_owned(), columns);
+            }
+        }
+        let users = self.access_table(access::USERS)?;
+        let empty = match users.try_lock() {
+            Ok(users) => access::rows(&users).is_empty(),
+            Err(e) => panic!("{}", ["shits fucked ".to_owned(), e.to_string()].concat())
+        };
+        if !empty {
+            return Ok("access tables loaded".to_owned());
+        }
+        let username = env::var("OBJ_DB_ADMIN_USER").unwrap_or("admin".to_owned());
+        let password = match env::var("OBJ_DB_ADMIN_PASSWORD") {
+            Ok(password) if !password.is_empty() => password,
+            _ => return Err("OBJ_DB_ADMIN_PASSWORD must be set to create the first admin user".to_owned())
+        };
+        self.create_user(&username, &password, access::ADMIN_ROLE)
+    }
+
+    fn access_table(&self, table_name: &str) -> Result<Arc<Mutex<Table>>, String> {
+        self.table(table_name).ok_or(["database ", &self.name, " has no ", table_name, " table, users and grants are kept in the admin database"].concat())
+    }
+
+    /* 
+     * the user sending a request, from the value of its Authorization header
+     */
+    pub fn authenticate(&self, authorization: Option<&str>) -> Result<access::User, String> {
+        let (users, grants) = (self.access_table(access::USERS)?, self.access_table(access::GRANTS)?);
+        let (users, grants) = match (users.try_lock(), grants.try_lock()) {
+            (Ok(users), Ok(grants)) => (users, grants),
+            (Err(e), _) | (_, Err(e)) => panic!("{}", ["shits fucked ".to_owned(), e.to_string()].concat())
+        };
+        access::authenticate(&users, &grants, authorization)
+    }
+
+    pub fn create_user(&mut self, username: &str, password: &str, role: &str) -> Result<String, String> {
+        if username.is_empty() || password.is_empty() || role.is_empty() {
+            return Err("a user needs a username, password and role".to_owned());
+        }
+        let tables = self.tables.clone();
+        match self.access_table(access::USERS)?.try_lock() {
+            Ok(mut users) => users.query_create(vec![access::user_record(username, role, password)?], &tables).map(|_| json!({ "user": username, "role": role }).to_string()),
+            Err(e) => panic!("{}", ["shits fucked ".to_owned(), e.to_string()].concat())
+        }
+    }
+
+    /* 
+     * the last user with the admin role is kept so the server can still be managed
+     */
+    pub fn drop_user(&mut self, username: &str) -> Result<String, String> {
+        let tables = self.tables.clone();
+        let table = self.access_table(access::USERS)?;
+        let dropped = match table.try_lock() {
+            Ok(mut users) => {
+                let rows = access::rows(&users);
+                let admins = rows.iter().filter(|record| access::column(record, "role").as_deref() == Some(access::ADMIN_ROLE)).count();
+                match rows.iter().find(|record| access::column(record, "username").as_deref() == Some(username)) {
+                    Some(user) if admins == 1 && access::column(user, "role").as_deref() == Some(access::ADMIN_ROLE) => Err(["user ", username, " is the last ", access::ADMIN_ROLE, " user and can not be dropped"].concat()),
+                    Some(_) => users.query_delete_records(&table, &access::matching(&[("username", username)]), &tables).map(|_| json!({ "dropped": username }).to_string()),
+                    None => Err(["user ", username, " does not exist"].concat())
+                }
+            },
+            Err(e) => panic!("{}", ["shits fucked ".to_owned(), e.to_string()].concat())
+        };
+        dropped
+    }
+
+    /* 
+     * grants already held are left alone so each grant is only stored once
+     */
+    pub fn grant(&mut self, grants: &[access::Grant]) -> Result<String, String> {
+        if grants.iter().any(|grant| grant.database == access::ADMIN_DATABASE) {
+            return Err(["the ", access::ADMIN_DATABASE, " database holds the users and grants, only the ", access::ADMIN_ROLE, " role can use it"].concat());
+        }
+        let tables = self.tables.clone();
+        match self.access_table(access::GRANTS)?.try_lock() {
+            Ok(mut table) => {
+                let held = access::rows(&table);
+                let records = grants.iter()
+                    .filter(|grant| !held.iter().any(|record| grant.filter().evaluate(record)))
+                    .map(|grant| grant.to_record())
+                    .collect::<Vec<record::Record>>();
+                let granted = records.len();
+                if granted > 0 {
+                    table.query_create(records, &tables)?;
+                }
+                Ok(json!({ "granted": granted }).to_string())
+            },
+            Err(e) => panic!("{}", ["shits fucked ".to_owned(), e.to_string()].concat())
+        }
+    }
+
+    pub fn revoke(&mut self, grants: &[access::Grant]) -> Result<String, String> {
+        let tables = self.tables.clone();
+        let own = self.access_table(access::GRANTS)?;
+        let revoked = match own.try_lock() {
+            Ok(mut table) => {
+                let held = access::rows(&table).len();
+                let filter = conditional::Filter::Or(grants.iter().map(|grant| grant.filter()).collect());
+                table.query_delete_records(&own, &filter, &tables)?;
+                Ok(json!({ "revoked": held - access::rows(&table).len() }).to_string())
+            },
+            Err(e) => panic!("{}", ["shits fucked ".to_owned(), e.to_string()].concat())
+        };
+        revoked
+    }
+
+    /* 
+     * MARK: find a table
+     */
+    pub fn table(&self, table_name: &str) -> Option<Arc<Mutex<Table>>> {
+        self.tables.iter().find(|table| match table.try_lock() { Ok(table) => table.name == table_name, Err(e) => panic!("{}", ["shits fucked ".to_owned(), e.to_string()].concat())}).map(Arc::clone)
+    }
+
+    /* 
+     * MARK: join tables
+     * rows start as every record of the first table and each join extends them in turn, through an index
+     * lookup per row when the joined column is indexed and a hash join otherwise, the conditions and
+     * projection are applied to the joined rows using table.column names
+     */
+    pub fn join(&self, table_name: &str, joins: &[join::Join], filter: &conditional::Filter, projection: Option<&[(String, String)]>) -> Result<String, String> {
+        for table_name in joins.iter().map(|join| &join.table[..]).chain([table_name]) {
+            self.check_access_table(table_name)?;
+        }
+        let mut rows = match self.table(table_name) {
+            Some(table) => match table.try_lock() {
+                Ok(mut table) => table.query_qualified()?,
+                Err(e) => panic!("{}", ["shits fucked ".to_owned(), e.to_string()].concat())
+            },
+            None => return Err(["table ", table_name, " does not exist in database"].concat())
+        };
+        for join in joins {
+            let table = match self.table(&join.table) {
+                Some(table) => table,
+                None => return Err(["table ", &join.table, " does not exist in database"].concat())
+            };
+            let mut table = match table.try_lock() {
+                Ok(table) => table,
+                Err(e) => panic!("{}", ["shits fucked ".to_owned(), e.to_string()].concat())
+            };
+            let nulls = record::Record::new(table.qualified_columns().into_iter().map(|(_, cell)| cell).collect::<Vec<cell::CellValue>>());
+            rows = match table.indexed(&join.column) {
+                true => {
+                    let mut joined = vec![];
+                    for row in rows {
+                        let matches = match row.columns.iter().find(|col| col.name() == join.outer).filter(|cell| !cell.is_null()) {
+                            Some(cell) => table.query_qualified_equal(&join.column, cell)?,
+                            None => vec![]
+                        };
+                        joined.extend(join::matched(row, &matches, join.kind, &nulls));
+                    }
+                    joined
+                },
+                false => join::hash_join(rows, join, table.query_qualified()?, &nulls)
+            };
+        }
+        let rows = rows.into_iter()
+            .filter(|row| filter.evaluate(row))
+            .map(|row| match projection { Some(projection) => row.project(projection), None => row })
+            .collect::<Vec<record::Record>>();
+        Ok(format!("{{{}}}", rows.iter().map(|record| record.to_string()).collect::<Vec<String>>().join(", ")))
+    }
+
+    /* 
+     * MARK: explain a join
+     * the plan of the first table and, for each join, whether it probes an index per row or hashes the joined table,
+     * estimated rows assumes every row finds one match and a left join keeps every row
+     */
+    pub fn explain_join(&self, table_name: &str, joins: &[join::Join], filter: &conditional::Filter, projection: Option<&[(String, String)]>) -> Value {
+        let all = conditional::Filter::And(vec![]);
+        let mut estimated_rows = 0;
+        let mut access = Value::Null;
+        if let Some(table) = self.table(table_name) {
+            match table.try_lock() {
+                Ok(table) => {
+                    let plan = table.plan(&all);
+                    estimated_rows = plan.estimated_rows;
+                    access = plan.access.to_json();
+                },
+                Err(e) => panic!("{}", ["shits fucked ".to_owned(), e.to_string()].concat())
+            }
+        }
+        let joins = joins.iter().map(|join| {
+            let (strategy, table_rows) = match self.table(&join.table) {
+                Some(table) => match table.try_lock() {
+                    Ok(table) => (match table.indexed(&join.column) { true => "index_nested_loop", false => "hash_join" }, table.plan(&all).estimated_rows),
+                    Err(e) => panic!("{}", ["shits fucked ".to_owned(), e.to_string()].concat())
+                },
+                None => ("missing_table", 0)
+            };
+            json!({
+                "table": join.table,
+                "type": match join.kind { join::JoinKind::Inner => "inner", join::JoinKind::Left => "left" },
+                "on": [join.outer, join::qualified(&join.table, &join.column)],
+                "strategy": strategy,
+                "table_rows": table_rows,
+            })
+        }).collect::<Vec<Value>>();
+        json!({ "plan": {
+            "table": table_name,
+            "access": access,
+            "joins": joins,
+            "filter": filter.to_json(),
+            "columns": projection.map(|projection| projection.iter().map(|(column, alias)| json!([column, alias])).collect::<Vec<Value>>()),
+            "estimated_rows": estimated_rows,
+        }})
+    }
+
+    /* 
+     * MARK: create an index
+     */
+    pub fn create_index(&mut self, table_name: String, index_name: String, columns: Vec<String>) -> Result<String, String> {
+        match self.tables.iter().find(|table| match table.try_lock() { Ok(table) => table.name == table_name, Err(e) => panic!("{}", ["shits fucked ".to_owned(), e.to_string()].concat())}) {
+            Some(table) => match table.try_lock() {
+                Ok(mut table) => table.create_index(index_name, columns),
+                Err(e) => panic!("{}", ["shits fucked ".to_owned(), e.to_string()].concat())
+            },
+            None => Err("table does not exist in database".to_owned())
+        }
+    }
+
+    /* 
+     * MARK: drop an index
+     */
+    pub fn drop_index(&mut self, table_name: String, index_name: String) -> Result<String, String> {
+        match self.tables.iter().find(|table| match table.try_lock() { Ok(table) => table.name == table_name, Err(e) => panic!("{}", ["shits fucked ".to_owned(), e.to_string()].concat())}) {
+            Some(table) => match table.try_lock() {
+                Ok(mut table) => table.drop_index(&index_name),
+                Err(e) => panic!("{}", ["shits fucked ".to_owned(), e.to_string()].concat())
+            },
+            None => Err("table does not exist in database".to_owned())
+        }
+    }
+
+    /* 
+     * MARK: transactions
+     * ids start from the time the database was loaded so an id handed out before a restart is not reused,
+     * transactions only live in memory and anything buffered in them is lost if the server stops
+     */
+    fn first_transaction() -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0) << 8
+    }
+
+    /* 
+     * the transaction belongs to the user sending the request
+     */
+    pub fn begin_transaction(&mut self) -> u64 {
+        self.next_transaction += 1;
+        self.transactions.insert(self.next_transaction, transaction::Transaction::new(self.next_transaction, self.user.clone().unwrap_or_default()));
+        self.next_transaction
+    }
+
+    /* 
+     * the transaction named by the request being run, writes are buffered in it instead of being applied
+     */
+    pub fn active_transaction(&mut self) -> Result<Option<&mut transaction::Transaction>, String> {
+        match self.transaction {
+            Some(id) => self.owned_transaction(id).map(Some),
+            None => Ok(None)
+        }
+    }
+
+    /* 
+     * a transaction of another user is reported as missing so ids can not be probed
+     */
+    fn owned_transaction(&mut self, id: u64) -> Result<&mut transaction::Transaction, String> {
+        let user = self.user.clone().unwrap_or_default();
+        match self.transactions.get_mut(&id) {
+            Some(transaction) if transaction.owner == user => Ok(transaction),
+            _ => Err(["transaction ", &id.to_string(), " does not exist on database ", &self.name].concat())
+        }
+    }
+
+    pub fn rollback_transaction(&mut self, id: u64) -> Result<String, String> {
+        self.owned_transaction(id)?;
+        match self.transactions.remove(&id) {
+            Some(transaction) => Ok(json!({ "transaction": id, "rolled_back": transaction.writes.len() }).to_string()),
+            None => Err(["transaction ", &id.to_string(), " does not exist on database ", &self.name].concat())
+        }
+    }
+
+    /* 
+     * MARK: commit a transaction
+     * the buffered writes are applied in order through the usual table queries with the database log deferring
+     * every part rewrite, so each write is checked against the constraints as it would be on its own,
+     * once all of them succeed the part images are logged as one group and only then written to the part files,
+     * if any write fails the parts are reloaded from their untouched files and nothing of the transaction remains,
+     * snapshots taken while it commits see none of its writes as each table only publishes them at the end
+     */
+    pub fn commit_transaction(&mut self, id: u64) -> Result<String, String> {
+        self.owned_transaction(id)?;
+        let transaction = match self.transactions.remove(&id) {
+            Some(transaction) => transaction,
+            None => return Err(["transaction ", &id.to_string(), " does not exist on database ", &self.name].concat())
+        };
+        let wal = match &self.wal {
+            Some(wal) => Arc::clone(wal),
+            None => return Err(["database ", &self.name, " has no log to commit a transaction through"].concat())
+        };
+        match wal.lock() {
+            Ok(mut wal) => wal.defer(),
+            Err(e) => panic!("{}", ["shits fucked ".to_owned(), e.to_string()].concat())
+        }
+
+        let mut results = vec![];
+        let mut failure = None;
+        for write in transaction.writes.iter() {
+            match self.apply_write(write) {
+                Ok(result) => results.push(serde_json::from_str::<Value>(&result).unwrap_or(json!(result))),
+                Err(e) => {
+                    failure = Some(e);
+                    break;
+                }
+            }
+        }
+        let (writes, logged) = match wal.lock() {
+            Ok(mut wal) => {
+                let writes = wal.take_deferred();
+                let logged = match (&failure, writes.is_empty()) {
+                    (None, false) => wal.log_writes(writes.clone()).map(Some),
+                    _ => Ok(None)
+                };
+                (writes, logged)
+            },
+            Err(e) => panic!("{}", ["shits fucked ".to_owned(), e.to_string()].concat())
+        };
+        let parts = writes.iter().map(|write| write.part.clone()).collect::<HashSet<String>>();
+
+        let sequence = match (failure, logged) {
+            (None, Ok(sequence)) => sequence,
+            (Some(e), _) | (None, Err(e)) => {
+                for table in self.tables.iter() {
+                    match table.try_lock() {
+                        Ok(mut table) => {
+                            table.revert_parts(&parts)?;
+                            table.publish();
+                        },
+                        Err(e) => panic!("{}", ["shits fucked ".to_owned(), e.to_string()].concat())
+                    }
+                }
+                return Err(["transaction ", &id.to_string(), " rolled back: ", &e].concat());
+            }
+        };
+        for table in self.tables.iter() {
+            match table.try_lock() {
+                Ok(mut table) => {
+                    table.publish();
+                    if let Err(e) = table.save_parts(&parts) {
+                        return Err(["transaction ", &id.to_string(), " is committed to the log but a part could not be written and will be replayed on restart: ", &e].concat());
+                    }
+                },
+                Err(e) => panic!("{}", ["shits fucked ".to_owned(), e.to_string()].concat())
+            }
+        }
+        if let Some(sequence) = sequence {
+            match wal.lock() {
+                Ok(mut wal) => wal.log_applied(sequence)?,
+                Err(e) => panic!("{}", ["shits fucked ".to_owned(), e.to_string()].concat())
+            }
+        }
+        Ok(json!({ "transaction": id, "committed": results.len(), "results": results }).to_string())
+    }
+
+    fn apply_write(&self, write: &transaction::TransactionWrite) -> Result<String, String> {
+        let tables = self.tables.clone();
+        let own = match self.table(write.table()) {
+            Some(table) => table,
+            None => return Err(["table ", write.table(), " does not exist in database"].concat())
+        };
+        let mut table = match own.try_lock() {
+            Ok(table) => table,
+            Err(e) => panic!("{}", ["shits fucked ".to_owned(), e.to_string()].concat())
+        };
+        match write {
+            transaction::TransactionWrite::Create { records, .. } => table.query_create(records.clone(), &tables),
+            transaction::TransactionWrite::Update { filter, assignments, .. } => table.query_update_records(filter, assignments, &tables),
+            transaction::TransactionWrite::Delete { filter, .. } => table.query_delete_records(&own, filter, &tables),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cell::ReferentialAction;
+    use transaction::TransactionWrite;
+
+    fn database(name: &str) -> Arc<Mutex<Database<'static>>> {
+        crate::test_dir();
+        let db = Database::new(name.to_owned(), None, "OWNER".to_owned(), None);
+        let columns = vec![
+            ("id".to_owned(), "ULong".to_owned(), None, false, false, None, ReferentialAction::Restrict),
+            ("name".to_owned(), "String".to_owned(), None, false, true, None, ReferentialAction::Restrict),
+        ];
+        let admin_db = Arc::clone(&db);
+        db.lock().unwrap().build_table(admin_db, "items".to_owned(), columns);
+        db
+    }
+
+    fn create(name: &str) -> TransactionWrite {
+        TransactionWrite::Create { table: "items".to_owned(), records: vec![record::Record::new(vec![cell::CellValue::String { name: "name".to_owned(), data: Some(name.to_owned()) }])] }
+    }
+
+    fn names(db: &Database) -> Vec<String> {
+        let table = db.table("items").unwrap();
+        let mut table = table.lock().unwrap();
+        table.records.iter_mut().for_each(|part| { part.reload().unwrap(); });
+        table.records.iter().flat_map(|part| part.records.iter()).filter_map(|record| access::column(record, "name")).collect()
+    }
+
+    fn sql(db: &Arc<Mutex<Database<'static>>>, statement: &str) -> Result<String, String> {
+        let mut query = crate::endpoint::query::QueryDatabaseSql::new("sql".to_owned());
+        query.parse(Arc::clone(db), &mut db.lock().unwrap(), json!(statement));
+        query.finish()
+    }
+
+    #[test]
+    fn access_tables_of_the_admin_database_are_refused_outside_their_endpoints() {
+        crate::test_dir();
+        let admin = Database::new(access::ADMIN_DATABASE.to_owned(), None, access::ADMIN_ROLE.to_owned(), None);
+        {
+            let mut db = admin.lock().unwrap();
+            db.add_table(access::USERS.to_owned(), access::users_columns());
+            db.add_table(access::GRANTS.to_owned(), access::grants_columns());
+            db.add_table("notes".to_owned(), vec![("id".to_owned(), "ULong".to_owned(), None, false, false, None, ReferentialAction::Restrict)]);
+            db.create_user("root", "password", access::ADMIN_ROLE).unwrap();
+        }
+        let refused = |result: Result<String, String>| assert!(result.unwrap_err().contains("only reached through the user and grant endpoints"));
+        refused(sql(&admin, "SELECT * FROM users WHERE id >= 0"));
+        refused(sql(&admin, "INSERT INTO users (username, role) VALUES ('mallory', 'ADMIN')"));
+        refused(sql(&admin, "UPDATE users SET role = 'ADMIN' WHERE id >= 0"));
+        refused(sql(&admin, "DROP TABLE grants"));
+        assert!(sql(&admin, "SELECT * FROM notes WHERE id >= 0").is_ok());
+
+        let mut db = admin.lock().unwrap();
+        let every = conditional::Filter::Condition(conditional::Condition { target_column: "notes.id".to_owned(), conditional: conditional::Conditional::EqualGreater, value: cell::CellValue::ULong { name: "notes.id".to_owned(), data: Some(0) }, relational: None });
+        let grants = join::Join { kind: join::JoinKind::Inner, table: access::GRANTS.to_owned(), column: "id".to_owned(), outer: "notes.id".to_owned() };
+        refused(db.join("notes", &[grants], &every, None));
+        refused(db.delete_table(access::USERS.to_owned()));
+        refused(db.update_table(access::GRANTS, &migration::Migration::Drop { column: "table".to_owned() }));
+        let reset = db.reset_tables().unwrap();
+        assert!(reset.get(access::USERS).is_none() && reset.get("notes").is_some());
+        assert_eq!(access::rows(&db.table(access::USERS).unwrap().lock().unwrap()).len(), 1);
+    }
+
+    #[test]
+    fn only_the_user_who_began_a_transaction_can_use_it() {
+        let db = database("owned");
+        let mut db = db.lock().unwrap();
+        db.user = Some("alice".to_owned());
+        let id = db.begin_transaction();
+        db.transaction = Some(id);
+        db.active_transaction().unwrap().unwrap().buffer(create("a"));
+
+        db.user = Some("mallory".to_owned());
+        assert!(db.active_transaction().is_err());
+        assert!(db.commit_transaction(id).is_err());
+        assert!(db.rollback_transaction(id).is_err());
+
+        db.user = Some("alice".to_owned());
+        assert!(db.commit_transaction(id).unwrap().contains("\"committed\":1"));
+        assert_eq!(names(&db), vec!["a"]);
+    }
+
+    #[test]
+    fn a_failed_write_rolls_back_the_whole_commit() {
+        let db = database("rollback");
+        let mut db = db.lock().unwrap();
+        db.user = Some("alice".to_owned());
+        let id = db.begin_transaction();
+        db.transaction = Some(id);
+        let transaction = db.active_transaction().unwrap().unwrap();
+        transaction.buffer(create("a"));
+        transaction.buffer(create("b"));
+        transaction.buffer(create("a"));
+        assert!(db.commit_transaction(id).unwrap_err().contains("rolled back"));
+        assert!(names(&db).is_empty());
+        assert!(db.commit_transaction(id).is_err());
+    }
+
+    #[test]
+    fn a_left_join_keeps_unmatched_rows_that_an_inner_join_drops() {
+        crate::test_dir();
+        let library = Database::new("joins".to_owned(), None, "OWNER".to_owned(), None);
+        let mut db = library.lock().unwrap();
+        let id = || ("id".to_owned(), "ULong".to_owned(), None, false, false, None, ReferentialAction::Restrict);
+        let text = |name: &str| (name.to_owned(), "String".to_owned(), None, true, false, None, ReferentialAction::Restrict);
+        let authors = db.add_table("authors".to_owned(), vec![id(), text("name")]);
+        let books = db.add_table("books".to_owned(), vec![id(), text("title"), ("author_id".to_owned(), "ULong".to_owned(), None, true, false, None, ReferentialAction::Restrict)]);
+        let row = |columns: Vec<cell::CellValue>| record::Record::new([vec![cell::CellValue::ULong { name: "id".to_owned(), data: None }], columns].concat());
+        let name = |column: &str, data: &str| cell::CellValue::String { name: column.to_owned(), data: Some(data.to_owned()) };
+        let author = |data: Option<u128>| cell::CellValue::ULong { name: "author_id".to_owned(), data };
+        authors.lock().unwrap().query_create(vec![row(vec![name("name", "ann")]), row(vec![name("name", "bob")])], &db.tables).unwrap();
+        books.lock().unwrap().query_create(vec![row(vec![name("title", "a"), author(Some(1))]), row(vec![name("title", "b"), author(Some(1))]), row(vec![name("title", "c"), author(None)])], &db.tables).unwrap();
+
+        let every = conditional::Filter::And(vec![]);
+        let projection = [("authors.name".to_owned(), "author".to_owned()), ("books.title".to_owned(), "title".to_owned())];
+        let joined = |db: &Database, table_name: &str, kind: join::JoinKind, joined: &str, column: &str, outer: &str| db.join(table_name, &[join::Join { kind, table: joined.to_owned(), column: column.to_owned(), outer: outer.to_owned() }], &every, Some(&projection)).unwrap();
+        let both = "{ \"author\": \"ann\", \"title\": \"a\" }, { \"author\": \"ann\", \"title\": \"b\" }";
+        // the same rows whether the joined column is hashed or looked up in an index
+        for indexed in [false, true] {
+            if indexed {
+                books.lock().unwrap().create_index("by_author".to_owned(), vec!["author_id".to_owned()]).unwrap();
+            }
+            assert_eq!(joined(&db, "authors", join::JoinKind::Inner, "books", "author_id", "authors.id"), ["{", both, "}"].concat());
+            assert_eq!(joined(&db, "authors", join::JoinKind::Left, "books", "author_id", "authors.id"), ["{", both, ", { \"author\": \"bob\", \"title\": \"null\" }}"].concat());
+        }
+        assert_eq!(joined(&db, "books", join::JoinKind::Inner, "authors", "id", "books.author_id"), ["{", both, "}"].concat());
+        assert!(joined(&db, "books", join::JoinKind::Left, "authors", "id", "books.author_id").ends_with("{ \"author\": \"null\", \"title\": \"c\" }}"));
+    }
+
+    #[test]
+    fn indev_is_read_back_from_the_directory_and_production_refuses_schema_changes() {
+        let built = database("production");
+        let refused = |result: Result<String, String>, operation: &str| assert_eq!(result.unwrap_err(), ["database production is in production, ", operation, " only runs while indev is set"].concat());
+        assert!(Database::build_from_dir("production".to_owned(), None, None).unwrap().lock().unwrap().indev);
+        built.lock().unwrap().set_indev(false).unwrap();
+        // records are still written and read in production
+        sql(&built, "INSERT INTO items (name) VALUES ('a')").unwrap();
+        assert!(sql(&built, "SELECT name FROM items WHERE id >= 0").unwrap().contains("\"a\""));
+        refused(sql(&built, "DROP TABLE items"), "delete_table");
+
+        let production = Database::build_from_dir("production".to_owned(), None, None).unwrap();
+        assert!(!production.lock().unwrap().indev);
+        refused(sql(&production, "CREATE TABLE tags (id ULong)"), "create_table");
+        refused(sql(&production, "DROP TABLE items"), "delete_table");
+        let mut db = production.lock().unwrap();
+        refused(db.update_table("items", &migration::Migration::Drop { column: "name".to_owned() }), "update_table");
+        assert!(db.reset_tables().unwrap_err().contains("reset only runs while indev is set"));
+        assert!(db.drop_dir().unwrap_err().contains("drop_database only runs while indev is set"));
+        drop(db);
+
+        production.lock().unwrap().set_indev(true).unwrap();
+        let development = Database::build_from_dir("production".to_owned(), None, None).unwrap();
+        assert!(development.lock().unwrap().indev);
+        assert!(sql(&development, "CREATE TABLE tags (id ULong)").is_ok());
+    }
+
+    fn exists(db_name: &str) -> bool {
+        Path::new(&Database::database_dir(db_name, None).unwrap()).exists()
+    }
+
+    #[test]
+    fn databases_are_dropped_renamed_and_cloned_with_their_directories() {
+        let original = database("moved_from");
+        let mut rename = crate::endpoint::query::QueryRenameDatabase::new("rename_database".to_owned());
+        rename.parse(None, &mut original.lock().unwrap(), json!({ "new_name": "moved_to" }), None);
+        let moved = rename.result.unwrap();
+        assert!(!exists("moved_from") && exists("moved_to"));
+        assert_eq!(moved.lock().unwrap().name, "moved_to");
+
+        let mut clone = crate::endpoint::query::QueryCloneDatabase::new("clone_database".to_owned());
+        clone.parse(None, &mut moved.lock().unwrap(), json!({ "new_name": "moved_copy" }), None);
+        let copy = clone.result.unwrap();
+        assert!(exists("moved_to") && exists("moved_copy"));
+        assert_eq!(copy.lock().unwrap().name, "moved_copy");
+
+        let dropped = database("dropped");
+        let mut db = dropped.lock().unwrap();
+        let id = db.begin_transaction();
+        assert!(db.drop_dir().unwrap_err().contains("has 1 open transactions"));
+        assert!(db.clone_dir("dropped_copy", None).unwrap_err().contains("has 1 open transactions"));
+        assert!(exists("dropped") && !exists("dropped_copy") && db.wal.is_some());
+        db.rollback_transaction(id).unwrap();
+        assert!(db.drop_dir().unwrap().contains("\"dropped\":\"dropped\""));
+        assert!(!exists("dropped") && db.tables.is_empty());
+    }
+
+    #[test]
+    fn databases_are_only_moved_to_an_unused_directory_name() {
+        let taken = database("taken");
+        let mut db = taken.lock().unwrap();
+        for name in ["", "a/b", "a\\b", "..", "a.b"] {
+            assert_eq!(db.rename_dir(name, None).unwrap_err(), ["database name ", name, " is not a valid directory name"].concat());
+            assert_eq!(db.clone_dir(name, None).unwrap_err(), ["database name ", name, " is not a valid directory name"].concat());
+        }
+        assert_eq!(db.rename_dir("taken", None).unwrap_err(), "database taken already exists");
+        assert_eq!(db.clone_dir("taken", None).unwrap_err(), "database taken already exists");
+        assert!(exists("taken") && db.wal.is_some());
+    }
+
+    #[test]
+    fn a_database_that_could_not_be_moved_is_left_open_where_it_was() {
+        let stuck = database("stuck");
+        // a name longer than a file name can be makes the move and the copy fail after the name was checked
+        let too_long = "x".repeat(300);
+        let mut db = stuck.lock().unwrap();
+        assert!(db.rename_dir(&too_long, None).unwrap_err().contains("unable to move database directory"));
+        assert!(db.clone_dir(&too_long, None).unwrap_err().contains("unable to copy database directory"));
+        assert!(exists("stuck") && db.wal.is_some());
+        assert!(db.tables.iter().all(|table| table.lock().unwrap().wal.is_some()));
+        drop(db);
+        sql(&stuck, "INSERT INTO items (name) VALUES ('a')").unwrap();
+        assert_eq!(names(&stuck.lock().unwrap()), vec!["a"]);
+    }
+
+    fn user(role: &str, grants: Vec<access::Grant>) -> access::User {
+        access::User { name: role.to_lowercase(), role: role.to_owned(), grants }
+    }
+
+    #[test]
+    fn tables_are_described_with_their_columns_indexes_and_endpoints() {
+        let described = database("described");
+        let db = described.lock().unwrap();
+        db.table("items").unwrap().lock().unwrap().create_index("by_name".to_owned(), vec!["name".to_owned()]).unwrap();
+        let owner = user("OWNER", vec![]);
+        let items = db.describe_table("items", &owner).unwrap();
+        assert_eq!(items["columns"], json!([
+            { "name": "id", "index": 0, "type": "ULong", "default": null, "nullable": false, "unique": false, "primary_key": true, "foreign_key": null, "auto_increment": true },
+            { "name": "name", "index": 1, "type": "String", "default": null, "nullable": false, "unique": true, "primary_key": false, "foreign_key": null, "auto_increment": false }
+        ]));
+        assert_eq!(items["indexes"], json!([{ "name": "by_name", "columns": ["name"] }]));
+        let endpoints = items["endpoints"].as_array().unwrap();
+        assert!(!endpoints.is_empty() && endpoints.iter().all(|endpoint| endpoint["table"] == "items" && endpoint["role"] == "OWNER"));
+        assert!(endpoints.iter().any(|endpoint| endpoint["name"] == "read_record"));
+
+        let tables = db.describe(&owner)["tables"].clone();
+        assert_eq!((tables.as_array().unwrap().len(), &tables[0]["name"], &tables[0]["columns"]), (1, &json!("items"), &items["columns"]));
+        let summary = db.summary(&owner);
+        assert_eq!((&summary["name"], &summary["indev"], &summary["tables"]), (&json!("described"), &json!(true), &json!(["items"])));
+        assert_eq!(db.describe_table("missing", &owner).unwrap_err(), "table missing does not exist in database");
+    }
+
+    #[test]
+    fn the_users_and_grants_tables_are_only_listed_to_the_admin_role() {
+        crate::test_dir();
+        let admin = Database::new(access::ADMIN_DATABASE.to_owned(), None, access::ADMIN_ROLE.to_owned(), Some("listed".to_owned()));
+        let mut db = admin.lock().unwrap();
+        db.add_table(access::USERS.to_owned(), access::users_columns());
+        db.add_table(access::GRANTS.to_owned(), access::grants_columns());
+        db.add_table("notes".to_owned(), vec![("id".to_owned(), "ULong".to_owned(), None, false, false, None, ReferentialAction::Restrict)]);
+        let grant = |table: &str| access::Grant { role: "ANALYST".to_owned(), database: access::ADMIN_DATABASE.to_owned(), table: table.to_owned(), permission: access::Permission::Read };
+        let analyst = user("ANALYST", vec![grant("notes"), grant(access::USERS)]);
+        let names = |tables: &Value| tables.as_array().unwrap().iter().map(|table| table["name"].as_str().unwrap().to_owned()).collect::<Vec<String>>();
+
+        assert_eq!(db.summary(&analyst)["tables"], json!(["notes"]));
+        assert_eq!(names(&db.describe(&analyst)["tables"]), vec!["notes"]);
+        assert_eq!(db.describe_table(access::USERS, &analyst).unwrap_err(), "table users does not exist in database");
+        assert!(db.describe_table("notes", &analyst).is_ok());
+
+        let root = user(access::ADMIN_ROLE, vec![]);
+        assert_eq!(db.summary(&root)["tables"], json!(["users", "grants", "notes"]));
+        assert_eq!(names(&db.describe(&root)["tables"]), vec!["users", "grants", "notes"]);
+        assert!(db.describe_table(access::GRANTS, &root).is_ok());
+    }
+}
+
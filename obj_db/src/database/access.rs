@@ -0,0 +1,330 @@
+use base64::Engine;
+use sha2::Sha256;
+use super::cell::{CellValue, ColumnDef, ReferentialAction};
+use super::conditional::{Condition, Conditional, Filter};
+use super::record::Record;
+use super::table::Table;
+
+pub const USERS: &str = "users";
+pub const GRANTS: &str = "grants";
+pub const ADMIN_ROLE: &str = "ADMIN";
+pub const ANY: &str = "*";
+pub const ADMIN_DATABASE: &str = "admin";
+const HASH_ROUNDS: u32 = 100_000;
+
+/*
+ * MARK: Permission
+ * what a grant lets a role do, schema covers creating, changing and dropping tables and indexes
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Permission {
+    Create,
+    Read,
+    Update,
+    Delete,
+    Schema,
+}
+
+impl Permission {
+    pub fn parse(a: &str) -> Result<Self, String> {
+        match &a.to_lowercase()[..] {
+            "create" => Ok(Permission::Create),
+            "read" => Ok(Permission::Read),
+            "update" => Ok(Permission::Update),
+            "delete" => Ok(Permission::Delete),
+            "schema" => Ok(Permission::Schema),
+            _ => Err(["permission ", a, " not recognised, expected create, read, update, delete or schema"].concat())
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            Permission::Create => "create",
+            Permission::Read => "read",
+            Permission::Update => "update",
+            Permission::Delete => "delete",
+            Permission::Schema => "schema",
+        }
+    }
+}
+
+/*
+ * MARK: Grant
+ * a permission held by a role on one table of a database, a table of * covers every table of the database
+ * and a database of * covers every database but the admin database
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Grant {
+    pub role: String,
+    pub database: String,
+    pub table: String,
+    pub permission: Permission,
+}
+
+impl Grant {
+    fn covers(&self, database: &str, table: Option<&str>, permission: Permission) -> bool {
+        self.permission == permission
+            && self.holds(database)
+            && (self.table == ANY || table == Some(&self.table[..]))
+    }
+
+    /*
+     * the users and grants live in the admin database so it is left out of *, only the admin role manages it
+     */
+    fn holds(&self, database: &str) -> bool {
+        self.database == database || (self.database == ANY && database != ADMIN_DATABASE)
+    }
+
+    pub fn to_record(&self) -> Record {
+        Record::new(vec![
+            CellValue::String { name: "role".to_owned(), data: Some(self.role.clone()) },
+            CellValue::String { name: "database".to_owned(), data: Some(self.database.clone()) },
+            CellValue::String { name: "table".to_owned(), data: Some(self.table.clone()) },
+            CellValue::String { name: "permission".to_owned(), data: Some(self.permission.name().to_owned()) },
+        ])
+    }
+
+    /*
+     * the grants table rows holding exactly this grant
+     */
+    pub fn filter(&self) -> Filter {
+        matching(&[("role", &self.role), ("database", &self.database), ("table", &self.table), ("permission", self.permission.name())])
+    }
+
+    fn from_record(record: &Record) -> Option<Grant> {
+        Some(Grant {
+            role: column(record, "role")?,
+            database: column(record, "database")?,
+            table: column(record, "table")?,
+            permission: Permission::parse(&column(record, "permission")?).ok()?,
+        })
+    }
+}
+
+/*
+ * MARK: User
+ * who sent a request, the grants of their role are loaded when they are authenticated
+ */
+#[derive(Debug, Clone)]
+pub struct User {
+    pub name: String,
+    pub role: String,
+    pub grants: Vec<Grant>,
+}
+
+impl User {
+    /*
+     * the admin role and the role a database was created for can do anything to it, every other role
+     * needs a grant, a check with no table is on the database as a whole and needs a grant on every table
+     */
+    pub fn allows(&self, owner: &str, database: &str, table: Option<&str>, permission: Permission) -> bool {
+        self.role == ADMIN_ROLE || self.role == owner || self.grants.iter().any(|grant| grant.covers(database, table, permission))
+    }
+
+    /*
+     * whether the user holds anything at all on the database
+     */
+    pub fn accesses(&self, owner: &str, database: &str) -> bool {
+        self.role == ADMIN_ROLE || self.role == owner || self.grants.iter().any(|grant| grant.holds(database))
+    }
+}
+
+/*
+ * MARK: Tables
+ * the users and grants tables kept in the admin database, they get no record endpoints and are only
+ * touched through the user and grant endpoints
+ */
+pub fn is_access_table(table_name: &str) -> bool {
+    table_name == USERS || table_name == GRANTS
+}
+
+pub fn users_columns() -> Vec<ColumnDef> {
+    vec![
+        ("id".to_owned(), "ULong".to_owned(), None, false, false, None, ReferentialAction::Restrict),
+        ("username".to_owned(), "String".to_owned(), None, false, true, None, ReferentialAction::Restrict),
+        ("role".to_owned(), "String".to_owned(), None, false, false, None, ReferentialAction::Restrict),
+        ("salt".to_owned(), "String".to_owned(), None, false, false, None, ReferentialAction::Restrict),
+        ("password".to_owned(), "String".to_owned(), None, false, false, None, ReferentialAction::Restrict),
+    ]
+}
+
+pub fn grants_columns() -> Vec<ColumnDef> {
+    vec![
+        ("id".to_owned(), "ULong".to_owned(), None, false, false, None, ReferentialAction::Restrict),
+        ("role".to_owned(), "String".to_owned(), None, false, false, None, ReferentialAction::Restrict),
+        ("database".to_owned(), "String".to_owned(), None, false, false, None, ReferentialAction::Restrict),
+        ("table".to_owned(), "String".to_owned(), None, false, false, None, ReferentialAction::Restrict),
+        ("permission".to_owned(), "String".to_owned(), None, false, false, None, ReferentialAction::Restrict),
+    ]
+}
+
+/*
+ * a users row for a new user, the password is only kept as a salted hash
+ */
+pub fn user_record(username: &str, role: &str, password: &str) -> Result<Record, String> {
+    let salt = token()?;
+    Ok(Record::new(vec![
+        CellValue::String { name: "username".to_owned(), data: Some(username.to_owned()) },
+        CellValue::String { name: "role".to_owned(), data: Some(role.to_owned()) },
+        CellValue::String { name: "password".to_owned(), data: Some(hash_password(&salt, password)) },
+        CellValue::String { name: "salt".to_owned(), data: Some(salt) },
+    ]))
+}
+
+/*
+ * every committed record of a table
+ */
+pub fn rows(table: &Table) -> Vec<Record> {
+    let all = Filter::And(vec![]);
+    let mut rows = vec![];
+    table.snapshot(&all).read(&all, None, |records| {
+        rows.extend(records);
+        true
+    });
+    rows
+}
+
+pub fn column(record: &Record, name: &str) -> Option<String> {
+    record.columns.iter().find(|col| col.name() == name).filter(|col| !col.is_null()).map(|col| col.data_str())
+}
+
+/*
+ * a filter matching the records whose string columns hold every one of the given values
+ */
+pub fn matching(values: &[(&str, &str)]) -> Filter {
+    Filter::And(values.iter().map(|(column, value)| Filter::Condition(Condition {
+        target_column: column.to_string(),
+        conditional: Conditional::Equal,
+        value: CellValue::String { name: column.to_string(), data: Some(value.to_string()) },
+        relational: None,
+    })).collect())
+}
+
+/*
+ * MARK: Authenticate
+ * find the user named by a basic Authorization header, check their password and load the grants of their role,
+ * the same error is given for an unknown user and a wrong password
+ */
+pub fn authenticate(users: &Table, grants: &Table, authorization: Option<&str>) -> Result<User, String> {
+    let (username, password) = match authorization {
+        Some(authorization) => credentials(authorization)?,
+        None => return Err("no Authorization header submitted".to_owned())
+    };
+    let incorrect = "username or password is incorrect".to_owned();
+    let user = rows(users).into_iter().find(|record| column(record, "username").as_deref() == Some(&username[..])).ok_or(incorrect.clone())?;
+    let (role, salt, hash) = match (column(&user, "role"), column(&user, "salt"), column(&user, "password")) {
+        (Some(role), Some(salt), Some(hash)) => (role, salt, hash),
+        _ => return Err(incorrect)
+    };
+    if !equal(hash_password(&salt, &password).as_bytes(), hash.as_bytes()) {
+        return Err(incorrect);
+    }
+    let grants = rows(grants).iter()
+        .filter_map(Grant::from_record)
+        .filter(|grant| grant.role == role)
+        .collect::<Vec<Grant>>();
+    Ok(User { name: username, role, grants })
+}
+
+/*
+ * the username and password of a basic Authorization header
+ */
+fn credentials(authorization: &str) -> Result<(String, String), String> {
+    let encoded = match authorization.trim().split_once(' ') {
+        Some((scheme, encoded)) if scheme.eq_ignore_ascii_case("Basic") => encoded.trim(),
+        _ => return Err("Authorization header must use the Basic scheme".to_owned())
+    };
+    let decoded = match base64::engine::general_purpose::STANDARD.decode(encoded) {
+        Ok(decoded) => String::from_utf8(decoded).map_err(|_| "Authorization header credentials are not utf-8".to_owned())?,
+        Err(e) => return Err(["Authorization header credentials are not base64 ", &e.to_string()].concat())
+    };
+    match decoded.split_once(':') {
+        Some((username, password)) => Ok((username.to_owned(), password.to_owned())),
+        None => Err("Authorization header credentials must be username:password".to_owned())
+    }
+}
+
+/*
+ * MARK: Password hashing
+ * pbkdf2 with hmac sha256 so the users table is slow to brute force if it is ever read, every request is
+ * authenticated so the rounds are kept to around 20ms a hash in a release build
+ */
+pub fn hash_password(salt: &str, password: &str) -> String {
+    let mut hash = [0u8; 32];
+    pbkdf2::pbkdf2_hmac::<Sha256>(password.as_bytes(), salt.as_bytes(), HASH_ROUNDS, &mut hash);
+    hex(&hash)
+}
+
+fn equal(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b.iter()).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+/*
+ * a random hex string from the os random number generator, used for salts
+ */
+pub fn token() -> Result<String, String> {
+    let mut bytes = [0u8; 16];
+    getrandom::getrandom(&mut bytes).map_err(|e| ["unable to generate a random token ", &e.to_string()].concat())?;
+    Ok(hex(&bytes))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grant(database: &str, table: &str, permission: Permission) -> Grant {
+        Grant { role: "ANALYST".to_owned(), database: database.to_owned(), table: table.to_owned(), permission }
+    }
+
+    #[test]
+    fn grant_covers_its_table_and_permission() {
+        let grant = grant("shop", "items", Permission::Read);
+        assert!(grant.covers("shop", Some("items"), Permission::Read));
+        assert!(!grant.covers("shop", Some("items"), Permission::Create));
+        assert!(!grant.covers("shop", Some("secrets"), Permission::Read));
+        assert!(!grant.covers("other", Some("items"), Permission::Read));
+        assert!(!grant.covers("shop", None, Permission::Read));
+    }
+
+    #[test]
+    fn grant_on_any_table_covers_the_database() {
+        let grant = grant("shop", ANY, Permission::Schema);
+        assert!(grant.covers("shop", Some("items"), Permission::Schema));
+        assert!(grant.covers("shop", None, Permission::Schema));
+        assert!(!grant.covers("other", None, Permission::Schema));
+    }
+
+    #[test]
+    fn grant_on_any_database_leaves_out_the_admin_database() {
+        let grant = grant(ANY, ANY, Permission::Read);
+        assert!(grant.covers("shop", Some("items"), Permission::Read));
+        assert!(!grant.covers(ADMIN_DATABASE, Some(USERS), Permission::Read));
+        let user = User { name: "bob".to_owned(), role: "ANALYST".to_owned(), grants: vec![grant] };
+        assert!(user.accesses("OWNER", "shop"));
+        assert!(!user.accesses(ADMIN_ROLE, ADMIN_DATABASE));
+        assert!(!user.allows(ADMIN_ROLE, ADMIN_DATABASE, Some(GRANTS), Permission::Read));
+    }
+
+    #[test]
+    fn passwords_are_hashed_with_their_salt() {
+        let (salt, other) = (token().unwrap(), token().unwrap());
+        assert_eq!(salt.len(), 32);
+        assert_ne!(salt, other);
+        let hash = hash_password(&salt, "password");
+        assert!(equal(hash.as_bytes(), hash_password(&salt, "password").as_bytes()));
+        assert!(!equal(hash.as_bytes(), hash_password(&other, "password").as_bytes()));
+    }
+
+    #[test]
+    fn admin_and_owner_roles_need_no_grant() {
+        let admin = User { name: "root".to_owned(), role: ADMIN_ROLE.to_owned(), grants: vec![] };
+        let owner = User { name: "olive".to_owned(), role: "OWNER".to_owned(), grants: vec![] };
+        assert!(admin.allows("OWNER", "shop", None, Permission::Schema));
+        assert!(owner.allows("OWNER", "shop", Some("items"), Permission::Delete));
+        assert!(!owner.accesses("OTHER", "other"));
+    }
+}
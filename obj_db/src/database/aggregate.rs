@@ -0,0 +1,174 @@
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use serde_json::{Map, Value};
+use super::cell::CellValue;
+use super::record::Record;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AggregateFn {
+    Count,
+    Sum,
+    Avg,
+    Min,
+    Max,
+}
+
+impl AggregateFn {
+    pub fn parse(a: &str) -> Result<Self, String> {
+        match &a.to_uppercase()[..] {
+            "COUNT" => Ok(AggregateFn::Count),
+            "SUM" => Ok(AggregateFn::Sum),
+            "AVG" => Ok(AggregateFn::Avg),
+            "MIN" => Ok(AggregateFn::Min),
+            "MAX" => Ok(AggregateFn::Max),
+            _ => Err(["aggregate function ", a, " not recognised"].concat())
+        }
+    }
+
+    fn name(&self) -> &str {
+        match self {
+            AggregateFn::Count => "count",
+            AggregateFn::Sum => "sum",
+            AggregateFn::Avg => "avg",
+            AggregateFn::Min => "min",
+            AggregateFn::Max => "max",
+        }
+    }
+}
+
+/*
+ * MARK: Aggregate
+ * one aggregate column of the result, a count with no column counts rows and any other
+ * aggregate skips null values, the alias defaults to fn(column)
+ */
+#[derive(Debug, Clone)]
+pub struct Aggregate {
+    pub function: AggregateFn,
+    pub column: Option<String>,
+    pub alias: String,
+}
+
+impl Aggregate {
+    pub fn new(function: AggregateFn, column: Option<String>, alias: Option<String>) -> Result<Self, String> {
+        if function != AggregateFn::Count && column.is_none() {
+            return Err(["aggregate ", function.name(), " needs a column"].concat());
+        }
+        let alias = alias.unwrap_or_else(|| [function.name(), "(", column.as_deref().unwrap_or("*"), ")"].concat());
+        Ok(Aggregate { function, column, alias })
+    }
+}
+
+/*
+ * running state of one aggregate within one group, integer sums stay exact until they overflow or meet a float
+ */
+#[derive(Debug, Clone)]
+enum Accumulator {
+    Count(u64),
+    Sum(Option<Result<i128, f64>>),
+    Avg(f64, u64),
+    Min(Option<CellValue>),
+    Max(Option<CellValue>),
+}
+
+impl Accumulator {
+    fn new(function: AggregateFn) -> Self {
+        match function {
+            AggregateFn::Count => Accumulator::Count(0),
+            AggregateFn::Sum => Accumulator::Sum(None),
+            AggregateFn::Avg => Accumulator::Avg(0.0, 0),
+            AggregateFn::Min => Accumulator::Min(None),
+            AggregateFn::Max => Accumulator::Max(None),
+        }
+    }
+
+    fn add(&mut self, cell: Option<&CellValue>) {
+        let cell = match (self, cell) {
+            (Accumulator::Count(count), None) => return *count += 1,
+            (_, None) => return,
+            (_, Some(cell)) if cell.is_null() => return,
+            (accumulator, Some(cell)) => (accumulator, cell)
+        };
+        match cell {
+            (Accumulator::Count(count), _) => *count += 1,
+            (Accumulator::Sum(total), cell) => if let Some(number) = cell.as_number() {
+                *total = Some(match (total.take(), number) {
+                    (None, number) => number,
+                    (Some(Ok(a)), Ok(b)) => a.checked_add(b).ok_or(a as f64 + b as f64),
+                    (Some(Ok(a)), Err(b)) | (Some(Err(b)), Ok(a)) => Err(a as f64 + b),
+                    (Some(Err(a)), Err(b)) => Err(a + b),
+                })
+            },
+            (Accumulator::Avg(total, count), cell) => if let Some(number) = cell.as_number() {
+                *total += match number { Ok(a) => a as f64, Err(a) => a };
+                *count += 1;
+            },
+            (Accumulator::Min(least), cell) => if least.as_ref().is_none_or(|least| cell.order_data(least) == Ordering::Less) {
+                *least = Some(cell.clone());
+            },
+            (Accumulator::Max(most), cell) => if most.as_ref().is_none_or(|most| cell.order_data(most) == Ordering::Greater) {
+                *most = Some(cell.clone());
+            },
+        }
+    }
+
+    fn result(&self) -> Value {
+        match self {
+            Accumulator::Count(count) => Value::from(*count),
+            Accumulator::Sum(None) => Value::Null,
+            Accumulator::Sum(Some(Ok(total))) => i64::try_from(*total).map(Value::from).unwrap_or_else(|_| Value::from(total.to_string())),
+            Accumulator::Sum(Some(Err(total))) => Value::from(*total),
+            Accumulator::Avg(_, 0) => Value::Null,
+            Accumulator::Avg(total, count) => Value::from(total / *count as f64),
+            Accumulator::Min(cell) | Accumulator::Max(cell) => cell.as_ref().map_or(Value::Null, |cell| cell.to_json()),
+        }
+    }
+}
+
+// the group by values of a record, null values are kept apart from strings that happen to read the same
+type GroupKey = Vec<(bool, String)>;
+
+/*
+ * MARK: Grouping
+ * accumulators for every group seen so far, records are fed in one part at a time so only the
+ * group values and running totals are held in memory
+ */
+pub struct Grouping<'a> {
+    group_by: &'a [String],
+    aggregates: &'a [Aggregate],
+    groups: BTreeMap<GroupKey, (Vec<Value>, Vec<Accumulator>)>,
+}
+
+impl<'a> Grouping<'a> {
+    pub fn new(group_by: &'a [String], aggregates: &'a [Aggregate]) -> Self {
+        Grouping { group_by, aggregates, groups: BTreeMap::new() }
+    }
+
+    pub fn add(&mut self, record: &Record) {
+        let cells = self.group_by.iter().map(|column| record.columns.iter().find(|col| col.name() == column)).collect::<Vec<Option<&CellValue>>>();
+        let key = cells.iter().map(|cell| match cell { Some(cell) if !cell.is_null() => (true, cell.data_str()), _ => (false, "".to_owned()) }).collect::<GroupKey>();
+        let aggregates = self.aggregates;
+        let (_, accumulators) = self.groups.entry(key).or_insert_with(|| (
+            cells.iter().map(|cell| cell.map_or(Value::Null, |cell| cell.to_json())).collect::<Vec<Value>>(),
+            aggregates.iter().map(|aggregate| Accumulator::new(aggregate.function)).collect::<Vec<Accumulator>>()
+        ));
+        accumulators.iter_mut().zip(aggregates.iter()).for_each(|(accumulator, aggregate)| match &aggregate.column {
+            Some(column) => accumulator.add(Some(record.columns.iter().find(|col| col.name() == column).unwrap_or(&CellValue::default()))),
+            None => accumulator.add(None)
+        });
+    }
+
+    /*
+     * one row per group, with no group by there is always exactly one row even when nothing matched
+     */
+    pub fn rows(mut self) -> Vec<Value> {
+        if self.group_by.is_empty() && self.groups.is_empty() {
+            self.groups.insert(vec![], (vec![], self.aggregates.iter().map(|aggregate| Accumulator::new(aggregate.function)).collect::<Vec<Accumulator>>()));
+        }
+        self.groups.into_values().map(|(values, accumulators)| {
+            let mut row = Map::new();
+            self.group_by.iter().zip(values).for_each(|(column, value)| { row.insert(column.clone(), value); });
+            self.aggregates.iter().zip(accumulators.iter()).for_each(|(aggregate, accumulator)| { row.insert(aggregate.alias.clone(), accumulator.result()); });
+            Value::Object(row)
+        }).collect::<Vec<Value>>()
+    }
+}
@@ -1,7 +1,8 @@
-use std::{default, fmt::Display};
+use std::{cmp::Ordering, default, fmt::Display};
 
 use get_size::GetSize;
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 
 #[derive(Debug, GetSize, Clone, Serialize, Deserialize)]
 pub enum Cell {
@@ -15,9 +16,98 @@ pub enum Cell {
         unique: bool,
         primary_key: bool,
         foreign_key: Option<(String, String)>,
+        on_delete: ReferentialAction,
     },
 }
 
+/* 
+ * MARK: LegacyCell
+ * the layout cells were stored in before columns held an on delete action, only read from old table
+ * definitions and turned into cells that restrict deletes like those tables always did
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum LegacyCell {
+    CellValue(CellValue),
+    CellDef {
+        name: String,
+        index: u16,
+        ctype: CellValue,
+        default: bool,
+        not_null: bool,
+        unique: bool,
+        primary_key: bool,
+        foreign_key: Option<(String, String)>,
+    },
+}
+
+impl From<LegacyCell> for Cell {
+    fn from(cell: LegacyCell) -> Cell {
+        match cell {
+            LegacyCell::CellValue(value) => Cell::CellValue(value),
+            LegacyCell::CellDef { name, index, ctype, default, not_null, unique, primary_key, foreign_key } => Cell::CellDef { name, index, ctype, default, not_null, unique, primary_key, foreign_key, on_delete: ReferentialAction::Restrict }
+        }
+    }
+}
+
+/* 
+ * a column as it is submitted to create or add it
+ * name    type    default value   nullable unique foreign key      on delete
+ */
+pub type ColumnDef = (String, String, Option<String>, bool, bool, Option<(String, String)>, ReferentialAction);
+
+/* 
+ * what happens to records referencing a row through a foreign key when that row is deleted
+ */
+#[derive(Debug, GetSize, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum ReferentialAction {
+    #[default]
+    Restrict,
+    Cascade,
+    SetNull,
+}
+
+impl ReferentialAction {
+    pub fn parse(a: &str) -> Result<Self, String> {
+        match &a.to_uppercase().replace('_', " ")[..] {
+            "" | "RESTRICT" | "NO ACTION" => Ok(ReferentialAction::Restrict),
+            "CASCADE" => Ok(ReferentialAction::Cascade),
+            "SET NULL" => Ok(ReferentialAction::SetNull),
+            _ => Err(["referential action ", a, " not recognised"].concat())
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            ReferentialAction::Restrict => "restrict",
+            ReferentialAction::Cascade => "cascade",
+            ReferentialAction::SetNull => "set_null",
+        }
+    }
+}
+
+impl Cell {
+    /* 
+     * MARK: describe
+     * a column definition as json for introspection, the table says whether its keys are assigned automatically
+     */
+    pub fn describe(&self, auto_increment: bool) -> Value {
+        match self {
+            Cell::CellDef { name, index, ctype, default, not_null, unique, primary_key, foreign_key, on_delete } => json!({
+                "name": name,
+                "index": index,
+                "type": ctype.type_name(),
+                "default": match default { true => ctype.to_json(), false => Value::Null },
+                "nullable": !not_null,
+                "unique": unique,
+                "primary_key": primary_key,
+                "foreign_key": match foreign_key { Some((table, column)) => json!({ "table": table, "column": column, "on_delete": on_delete.name() }), None => Value::Null },
+                "auto_increment": auto_increment && *primary_key && matches!(ctype, CellValue::ULong { .. }),
+            }),
+            Cell::CellValue(_) => Value::Null
+        }
+    }
+}
+
 #[derive(Debug, GetSize, Clone, PartialEq, PartialOrd, Serialize, Deserialize)]
 pub enum CellValue {
     String {
@@ -81,6 +171,145 @@ impl CellValue {
         }
     }
 
+    /* 
+     * parse a json value into a cell of the same type as self, named after self
+     * a json null gives a cell with no data, any other value that does not fit the type is an error
+     */
+    pub fn parse_json(&self, value: &Value) -> Result<CellValue, String> {
+        let name = self.name().to_owned();
+        let parsed = match self {
+            CellValue::String { .. } => CellValue::String { name, data: match value { Value::String(val) => Some(val.to_owned()), Value::Number(val) => Some(val.to_string()), Value::Bool(val) => Some(val.to_string()), _ => None } },
+            CellValue::Bool   { .. } => CellValue::Bool   { name, data: match value { Value::Bool(val) => Some(*val), Value::String(val) => val.parse::<bool>().ok(), _ => None } },
+            CellValue::UInt   { .. } => CellValue::UInt   { name, data: match value { Value::String(val) => val.parse::<u32>().ok(),  Value::Number(val) => val.as_u64().and_then(|a| u32::try_from(a).ok()), _ => None } },
+            CellValue::ULong  { .. } => CellValue::ULong  { name, data: match value { Value::String(val) => val.parse::<u128>().ok(), Value::Number(val) => val.as_u64().map(|a| a as u128), _ => None } },
+            CellValue::IInt   { .. } => CellValue::IInt   { name, data: match value { Value::String(val) => val.parse::<i32>().ok(),  Value::Number(val) => val.as_i64().and_then(|a| i32::try_from(a).ok()), _ => None } },
+            CellValue::ILong  { .. } => CellValue::ILong  { name, data: match value { Value::String(val) => val.parse::<i128>().ok(), Value::Number(val) => val.as_i64().map(|a| a as i128), _ => None } },
+            CellValue::Float  { .. } => CellValue::Float  { name, data: match value { Value::String(val) => val.parse::<f64>().ok(),  Value::Number(val) => val.as_f64(), _ => None } },
+            CellValue::Bytes  { .. } => CellValue::Bytes  { name, data: match value { Value::String(val) if val.len() % 2 == 0 => Some(<String as AsRef<[u8]>>::as_ref(&val.to_owned()).chunks(2).map(|pair| match pair[0] { b'A'..=b'F' => pair[0]-b'A'+10, _ => pair[0] } << 4 |  match pair[1] { b'A'..=b'F' => pair[1]-b'A'+10, _ => pair[1] }).collect::<Vec<u8>>()), _ => None } },
+        };
+        match (value.is_null(), parsed.is_null()) {
+            (false, true) => Err(["value ", &value.to_string(), " is not valid for column ", self.name(), " of type ", self.type_name()].concat()),
+            _ => Ok(parsed)
+        }
+    }
+
+    pub fn renamed(&self, name: &str) -> CellValue {
+        let name = name.to_owned();
+        match self.clone() {
+            CellValue::String { data, .. } => CellValue::String { name, data },
+            CellValue::Bool   { data, .. } => CellValue::Bool   { name, data },
+            CellValue::UInt   { data, .. } => CellValue::UInt   { name, data },
+            CellValue::ULong  { data, .. } => CellValue::ULong  { name, data },
+            CellValue::IInt   { data, .. } => CellValue::IInt   { name, data },
+            CellValue::ILong  { data, .. } => CellValue::ILong  { name, data },
+            CellValue::Float  { data, .. } => CellValue::Float  { name, data },
+            CellValue::Bytes  { data, .. } => CellValue::Bytes  { name, data },
+        }
+    }
+
+    /* 
+     * the data of the cell as a json value, 128 bit integers that do not fit a json number are given as strings
+     */
+    pub fn to_json(&self) -> Value {
+        match self {
+            CellValue::String { data: Some(data), .. } => Value::from(data.clone()),
+            CellValue::Bool   { data: Some(data), .. } => Value::from(*data),
+            CellValue::UInt   { data: Some(data), .. } => Value::from(*data),
+            CellValue::ULong  { data: Some(data), .. } => u64::try_from(*data).map(Value::from).unwrap_or_else(|_| Value::from(data.to_string())),
+            CellValue::IInt   { data: Some(data), .. } => Value::from(*data),
+            CellValue::ILong  { data: Some(data), .. } => i64::try_from(*data).map(Value::from).unwrap_or_else(|_| Value::from(data.to_string())),
+            CellValue::Float  { data: Some(data), .. } => Value::from(*data),
+            CellValue::Bytes  { data: Some(_), .. }    => Value::from(self.data_str()),
+            _ => Value::Null
+        }
+    }
+
+    /* 
+     * numeric data widened for aggregation, integers as i128 and floats as f64, None for null or non numeric cells
+     */
+    pub fn as_number(&self) -> Option<Result<i128, f64>> {
+        match self {
+            CellValue::UInt   { data: Some(data), .. } => Some(Ok(*data as i128)),
+            CellValue::ULong  { data: Some(data), .. } => Some(i128::try_from(*data).map_err(|_| *data as f64)),
+            CellValue::IInt   { data: Some(data), .. } => Some(Ok(*data as i128)),
+            CellValue::ILong  { data: Some(data), .. } => Some(Ok(*data)),
+            CellValue::Float  { data: Some(data), .. } => Some(Err(*data)),
+            _ => None
+        }
+    }
+
+    pub fn is_numeric(&self) -> bool {
+        matches!(self, CellValue::UInt { .. } | CellValue::ULong { .. } | CellValue::IInt { .. } | CellValue::ILong { .. } | CellValue::Float { .. })
+    }
+
+    pub fn as_null(&self) -> CellValue {
+        let name = self.name().to_owned();
+        match self {
+            CellValue::String { .. } => CellValue::String { name, data: None },
+            CellValue::Bool   { .. } => CellValue::Bool   { name, data: None },
+            CellValue::UInt   { .. } => CellValue::UInt   { name, data: None },
+            CellValue::ULong  { .. } => CellValue::ULong  { name, data: None },
+            CellValue::IInt   { .. } => CellValue::IInt   { name, data: None },
+            CellValue::ILong  { .. } => CellValue::ILong  { name, data: None },
+            CellValue::Float  { .. } => CellValue::Float  { name, data: None },
+            CellValue::Bytes  { .. } => CellValue::Bytes  { name, data: None },
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        match self {
+            CellValue::String { data, .. } => data.is_none(),
+            CellValue::Bool   { data, .. } => data.is_none(),
+            CellValue::UInt   { data, .. } => data.is_none(),
+            CellValue::ULong  { data, .. } => data.is_none(),
+            CellValue::IInt   { data, .. } => data.is_none(),
+            CellValue::ILong  { data, .. } => data.is_none(),
+            CellValue::Float  { data, .. } => data.is_none(),
+            CellValue::Bytes  { data, .. } => data.is_none(),
+        }
+    }
+
+    pub fn type_name(&self) -> &str {
+        match self {
+            CellValue::String { .. } => "String",
+            CellValue::Bool   { .. } => "Bool",
+            CellValue::UInt   { .. } => "UInt",
+            CellValue::ULong  { .. } => "ULong",
+            CellValue::IInt   { .. } => "IInt",
+            CellValue::ILong  { .. } => "ILong",
+            CellValue::Float  { .. } => "Float",
+            CellValue::Bytes  { .. } => "Bytes",
+        }
+    }
+
+    /* 
+     * order two cells by their data alone, None when either cell is null or they hold different types
+     */
+    pub fn compare_data(&self, other: &CellValue) -> Option<Ordering> {
+        match (self, other) {
+            (CellValue::String { data: Some(a), .. }, CellValue::String { data: Some(b), .. }) => Some(a.cmp(b)),
+            (CellValue::Bool   { data: Some(a), .. }, CellValue::Bool   { data: Some(b), .. }) => Some(a.cmp(b)),
+            (CellValue::UInt   { data: Some(a), .. }, CellValue::UInt   { data: Some(b), .. }) => Some(a.cmp(b)),
+            (CellValue::ULong  { data: Some(a), .. }, CellValue::ULong  { data: Some(b), .. }) => Some(a.cmp(b)),
+            (CellValue::IInt   { data: Some(a), .. }, CellValue::IInt   { data: Some(b), .. }) => Some(a.cmp(b)),
+            (CellValue::ILong  { data: Some(a), .. }, CellValue::ILong  { data: Some(b), .. }) => Some(a.cmp(b)),
+            (CellValue::Float  { data: Some(a), .. }, CellValue::Float  { data: Some(b), .. }) => a.partial_cmp(b),
+            (CellValue::Bytes  { data: Some(a), .. }, CellValue::Bytes  { data: Some(b), .. }) => Some(a.cmp(b)),
+            _ => None
+        }
+    }
+
+    /* 
+     * total order of two non null cells of the same type, floats use their total order so NaN sorts last,
+     * nulls and cells of different types are left to the caller and compare as equal here
+     */
+    pub fn order_data(&self, other: &CellValue) -> Ordering {
+        match (self, other) {
+            (CellValue::Float { data: Some(a), .. }, CellValue::Float { data: Some(b), .. }) => a.total_cmp(b),
+            _ => self.compare_data(other).unwrap_or(Ordering::Equal)
+        }
+    }
+
     pub fn comp_name(self, comp_name: &String) -> bool {
         match self {
             CellValue::String { name, .. } => return name.eq(comp_name).clone(),
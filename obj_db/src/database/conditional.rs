@@ -1,4 +1,8 @@
+use regex::Regex;
+use serde_json::{json, Value};
+
 use super::cell::CellValue;
+use super::record::Record;
 
 
 #[derive(Debug, Clone)]
@@ -17,22 +21,76 @@ pub enum Conditional {
     Smaller,
     EqualGreater,
     EqualSmaller,
+    In(Vec<CellValue>),
+    Between(CellValue, CellValue),
+    Like(Regex),
+    Regex(Regex),
+    IsNull,
+    IsNotNull,
     All,
 }
 
 impl Conditional {
+    /* 
+     * comparisons that take a single value, the list, range and pattern conditionals are built with their operands
+     */
     pub fn parse(a: String) -> Result<Self, String> {
-        match &a[..] {
+        match &a.to_uppercase()[..] {
             "!=" => Ok(Conditional::NotEqual),
             "==" => Ok(Conditional::Equal),
-            ">=" => Ok(Conditional::Greater),
-            "<=" => Ok(Conditional::Smaller),
-            ">"  => Ok(Conditional::EqualGreater),
-            "<"  => Ok(Conditional::EqualSmaller),
+            ">"  => Ok(Conditional::Greater),
+            "<"  => Ok(Conditional::Smaller),
+            ">=" => Ok(Conditional::EqualGreater),
+            "<=" => Ok(Conditional::EqualSmaller),
+            "IS NULL" => Ok(Conditional::IsNull),
+            "IS NOT NULL" => Ok(Conditional::IsNotNull),
             "*"  => Ok(Conditional::All),
             _ => Err("condition pattern not recognised".to_owned())
         }
     }
+
+    /* 
+     * an sql LIKE pattern, % matches any run of characters and _ matches exactly one, everything else is literal
+     */
+    pub fn like(pattern: &str) -> Result<Self, String> {
+        let translated = pattern.chars().map(|c| match c {
+            '%' => ".*".to_owned(),
+            '_' => ".".to_owned(),
+            c => regex::escape(&c.to_string())
+        }).collect::<String>();
+        match Regex::new(&["^(?s:", &translated, ")$"].concat()) {
+            Ok(like) => Ok(Conditional::Like(like)),
+            Err(e) => Err(["like pattern ", pattern, " could not be compiled ", &e.to_string()].concat())
+        }
+    }
+
+    /* 
+     * the operator as a request would spell it
+     */
+    pub fn symbol(&self) -> &str {
+        match self {
+            Conditional::NotEqual => "!=",
+            Conditional::Equal => "==",
+            Conditional::Greater => ">",
+            Conditional::Smaller => "<",
+            Conditional::EqualGreater => ">=",
+            Conditional::EqualSmaller => "<=",
+            Conditional::In(_) => "IN",
+            Conditional::Between(_, _) => "BETWEEN",
+            Conditional::Like(_) => "LIKE",
+            Conditional::Regex(_) => "REGEX",
+            Conditional::IsNull => "IS NULL",
+            Conditional::IsNotNull => "IS NOT NULL",
+            Conditional::All => "*",
+        }
+    }
+
+    pub fn regex(pattern: &str) -> Result<Self, String> {
+        match Regex::new(pattern) {
+            Ok(regex) => Ok(Conditional::Regex(regex)),
+            Err(e) => Err(["regex ", pattern, " could not be compiled ", &e.to_string()].concat())
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -48,7 +106,195 @@ impl Relation {
             "AND"|"&&" => Ok(Relation::AND),
             "OR"|"||" => Ok(Relation::OR),
             "NOT"|"!!" => Ok(Relation::NOT),
-            _ => Err("".to_owned()),
+            _ => Err("relation pattern not recognised".to_owned()),
+        }
+    }
+}
+
+/* 
+ * MARK: Filter
+ * a tree of conditions joined by AND, OR and NOT, groups nest as their own AND or OR nodes
+ */
+#[derive(Debug, Clone)]
+pub enum Filter {
+    Condition(Condition),
+    And(Vec<Filter>),
+    Or(Vec<Filter>),
+    Not(Box<Filter>),
+}
+
+/* 
+ * one element of a flat sequence of filters, either an operand or the relation joining it to the next operand
+ */
+#[derive(Debug, Clone)]
+pub enum FilterToken {
+    Operand(Filter),
+    Relation(Relation),
+}
+
+impl Filter {
+    /* 
+     * MARK: from sequence
+     * fold a flat sequence into a tree, NOT binds tighter than AND which binds tighter than OR,
+     * operands with no relation between them are joined by AND and a trailing relation is ignored
+     */
+    pub fn from_sequence(tokens: Vec<FilterToken>) -> Result<Self, String> {
+        let mut any: Vec<Filter> = vec![];
+        let mut all: Vec<Filter> = vec![];
+        let mut negate = false;
+        for token in tokens {
+            match token {
+                FilterToken::Operand(filter) => {
+                    all.push(match negate { true => Filter::Not(Box::new(filter)), false => filter });
+                    negate = false;
+                },
+                FilterToken::Relation(Relation::AND) => {},
+                FilterToken::Relation(Relation::NOT) => negate = !negate,
+                FilterToken::Relation(Relation::OR) => match all.is_empty() {
+                    true => return Err("OR has no condition before it".to_owned()),
+                    false => any.push(Filter::all_of(std::mem::take(&mut all)))
+                }
+            }
+        }
+        if !all.is_empty() {
+            any.push(Filter::all_of(all));
+        }
+        Ok(match any.len() {
+            1 => any.pop().unwrap(),
+            _ => Filter::Or(any)
+        })
+    }
+
+    fn all_of(mut filters: Vec<Filter>) -> Self {
+        match filters.len() {
+            1 => filters.pop().unwrap(),
+            _ => Filter::And(filters)
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        match self {
+            Filter::And(filters) | Filter::Or(filters) => filters.is_empty(),
+            _ => false
+        }
+    }
+
+    /* 
+     * MARK: evaluate
+     * an empty AND matches every record and an empty OR matches none
+     */
+    pub fn evaluate(&self, record: &Record) -> bool {
+        match self {
+            Filter::Condition(condition) => record.query_check(condition),
+            Filter::And(filters) => filters.iter().all(|filter| filter.evaluate(record)),
+            Filter::Or(filters) => filters.iter().any(|filter| filter.evaluate(record)),
+            Filter::Not(filter) => !filter.evaluate(record),
         }
     }
-}
\ No newline at end of file
+
+    /* 
+     * the filter in the json form conditions are submitted in, LIKE patterns are shown as the regex they were compiled to
+     */
+    pub fn to_json(&self) -> Value {
+        match self {
+            Filter::Condition(condition) => match &condition.conditional {
+                Conditional::All => json!(["*"]),
+                Conditional::IsNull | Conditional::IsNotNull => json!([condition.target_column, condition.conditional.symbol()]),
+                Conditional::In(values) => json!([condition.target_column, "IN", values.iter().map(|value| value.to_json()).collect::<Vec<Value>>()]),
+                Conditional::Between(low, high) => json!([condition.target_column, "BETWEEN", [low.to_json(), high.to_json()]]),
+                Conditional::Like(pattern) | Conditional::Regex(pattern) => json!([condition.target_column, condition.conditional.symbol(), pattern.as_str()]),
+                conditional => json!([condition.target_column, conditional.symbol(), condition.value.to_json()])
+            },
+            Filter::And(filters) => json!({ "and": filters.iter().map(|filter| filter.to_json()).collect::<Vec<Value>>() }),
+            Filter::Or(filters) => json!({ "or": filters.iter().map(|filter| filter.to_json()).collect::<Vec<Value>>() }),
+            Filter::Not(filter) => json!({ "not": filter.to_json() }),
+        }
+    }
+
+    /* 
+     * conditions every matching record has to satisfy, used to pick an index without evaluating the whole tree
+     */
+    pub fn conjuncts(&self) -> Vec<Condition> {
+        match self {
+            Filter::Condition(condition) => vec![condition.clone()],
+            Filter::And(filters) => filters.iter().flat_map(|filter| filter.conjuncts()).collect::<Vec<Condition>>(),
+            Filter::Or(filters) if filters.len() == 1 => filters[0].conjuncts(),
+            _ => vec![]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(value: i128, text: &str) -> Record {
+        Record::new(vec![CellValue::ILong { name: "n".to_owned(), data: Some(value) }, CellValue::String { name: "s".to_owned(), data: Some(text.to_owned()) }])
+    }
+
+    fn condition(column: &str, conditional: Conditional, value: CellValue) -> Filter {
+        Filter::Condition(Condition { target_column: column.to_owned(), conditional, value, relational: None })
+    }
+
+    fn compared(symbol: &str, value: i128) -> Filter {
+        condition("n", Conditional::parse(symbol.to_owned()).unwrap(), CellValue::ILong { name: "n".to_owned(), data: Some(value) })
+    }
+
+    fn like(pattern: &str) -> Filter {
+        condition("s", Conditional::like(pattern).unwrap(), CellValue::String { name: "s".to_owned(), data: None })
+    }
+
+    #[test]
+    fn comparisons_include_equality_only_when_they_say_so() {
+        let matches = |symbol: &str| [4, 5, 6].iter().filter(|n| compared(symbol, 5).evaluate(&record(**n, ""))).copied().collect::<Vec<i128>>();
+        assert_eq!(matches(">="), vec![5, 6]);
+        assert_eq!(matches(">"), vec![6]);
+        assert_eq!(matches("<="), vec![4, 5]);
+        assert_eq!(matches("<"), vec![4]);
+        assert_eq!(matches("!="), vec![4, 6]);
+    }
+
+    #[test]
+    fn like_wildcards_are_the_only_special_characters() {
+        assert!(like("a.c").evaluate(&record(0, "a.c")));
+        assert!(!like("a.c").evaluate(&record(0, "abc")));
+        assert!(like("(x)%").evaluate(&record(0, "(x) and\nmore")));
+        assert!(like("a_c").evaluate(&record(0, "abc")));
+        assert!(!like("a_c").evaluate(&record(0, "abbc")));
+        assert!(!like("b%").evaluate(&record(0, "abc")));
+        assert!(like("[a]*+?$^").evaluate(&record(0, "[a]*+?$^")));
+    }
+
+    fn sequence(tokens: &[&str]) -> Filter {
+        Filter::from_sequence(tokens.iter().map(|token| match Relation::parse(token.to_string()) {
+            Ok(relation) => FilterToken::Relation(relation),
+            Err(_) => FilterToken::Operand(compared("==", token.parse().unwrap()))
+        }).collect()).unwrap()
+    }
+
+    fn matching(filter: &Filter) -> Vec<i128> {
+        (1..=3).filter(|n| filter.evaluate(&record(*n, ""))).collect()
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        // 1 OR 2 AND 3 is 1 OR (2 AND 3), nothing is both 2 and 3
+        assert_eq!(matching(&sequence(&["1", "OR", "2", "AND", "3"])), vec![1]);
+        assert_eq!(matching(&sequence(&["2", "AND", "3", "OR", "1"])), vec![1]);
+        assert!(matches!(sequence(&["1", "OR", "2", "AND", "3"]), Filter::Or(filters) if matches!(&filters[1], Filter::And(_))));
+    }
+
+    #[test]
+    fn not_binds_to_the_next_operand_only() {
+        assert_eq!(matching(&sequence(&["NOT", "1", "AND", "NOT", "2"])), vec![3]);
+        assert_eq!(matching(&sequence(&["NOT", "1", "OR", "1"])), vec![1, 2, 3]);
+        assert_eq!(matching(&sequence(&["NOT", "NOT", "2"])), vec![2]);
+    }
+
+    #[test]
+    fn operands_without_a_relation_are_joined_by_and() {
+        assert!(matching(&sequence(&["1", "2"])).is_empty());
+        assert_eq!(matching(&sequence(&["1", "OR"])), vec![1]);
+        assert!(Filter::from_sequence(vec![FilterToken::Relation(Relation::OR), FilterToken::Operand(compared("==", 1))]).is_err());
+    }
+}
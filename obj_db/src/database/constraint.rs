@@ -0,0 +1,66 @@
+use std::fmt::Display;
+
+use serde_json::json;
+
+/*
+ * MARK: ConstraintViolation
+ * reasons a record can be refused by a table, or a delete refused because other records
+ * still reference it, displayed as a json object naming the constraint, the column and the offending value
+ */
+#[derive(Debug, Clone)]
+pub enum ConstraintViolation {
+    NotNull { column: String },
+    Unique { column: String, value: String },
+    PrimaryKey { column: String, value: String },
+    ForeignKey { column: String, value: String, references: String },
+    Referenced { column: String, value: String, referenced_by: String },
+}
+
+impl ConstraintViolation {
+    pub fn constraint(&self) -> &str {
+        match self {
+            ConstraintViolation::NotNull { .. }    => "not_null",
+            ConstraintViolation::Unique { .. }     => "unique",
+            ConstraintViolation::PrimaryKey { .. } => "primary_key",
+            ConstraintViolation::ForeignKey { .. } => "foreign_key",
+            ConstraintViolation::Referenced { .. } => "referenced",
+        }
+    }
+
+    pub fn column(&self) -> &str {
+        match self {
+            ConstraintViolation::NotNull { column }       => column,
+            ConstraintViolation::Unique { column, .. }     => column,
+            ConstraintViolation::PrimaryKey { column, .. } => column,
+            ConstraintViolation::ForeignKey { column, .. } => column,
+            ConstraintViolation::Referenced { column, .. } => column,
+        }
+    }
+
+    pub fn value(&self) -> Option<&str> {
+        match self {
+            ConstraintViolation::NotNull { .. }           => None,
+            ConstraintViolation::Unique { value, .. }     => Some(value),
+            ConstraintViolation::PrimaryKey { value, .. } => Some(value),
+            ConstraintViolation::ForeignKey { value, .. } => Some(value),
+            ConstraintViolation::Referenced { value, .. } => Some(value),
+        }
+    }
+}
+
+impl Display for ConstraintViolation {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut violation = json!({
+            "error": "constraint violation",
+            "constraint": self.constraint(),
+            "column": self.column(),
+            "value": self.value(),
+        });
+        match self {
+            ConstraintViolation::ForeignKey { references, .. } => violation["references"] = json!(references),
+            ConstraintViolation::Referenced { referenced_by, .. } => violation["referenced_by"] = json!(referenced_by),
+            _ => {}
+        }
+        fmt.write_str(&violation.to_string())
+    }
+}
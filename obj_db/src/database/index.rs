@@ -0,0 +1,355 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::fs::File;
+use std::io::Read;
+use std::io::Write;
+use std::ops::Bound;
+use super::cell::CellValue;
+use super::conditional::{Condition, Conditional};
+use super::part::Part;
+use super::record::Record;
+
+/*
+ * MARK: PrimaryIndex
+ * maps every primary key of a table to the part holding it and its slot within that part,
+ * kept sorted in memory and written to disk as a sorted list of (key, part, slot) entries
+ */
+pub struct PrimaryIndex {
+    path: String,
+    entries: BTreeMap<u128, (u32, u32)>,
+}
+
+impl PrimaryIndex {
+    /*
+     * MARK: load
+     * read the index file, if it is missing, unreadable or does not agree with the parts it is rebuilt from them
+     */
+    pub fn load(path: String, parts: &[Part]) -> Self {
+        let mut buf = vec![];
+        let entries = match File::open(&path).and_then(|mut file| file.read_to_end(&mut buf)) {
+            Ok(_) => match bincode::deserialize::<Vec<(u128, u32, u32)>>(&buf) {
+                Ok(entries) => Some(entries.into_iter().map(|(key, part, slot)| (key, (part, slot))).collect::<BTreeMap<u128, (u32, u32)>>()),
+                Err(e) => {
+                    println!("index {} unreadable {}", path, e);
+                    None
+                }
+            },
+            Err(_) => None
+        };
+        match entries {
+            Some(entries) if PrimaryIndex::agrees(&entries, parts) => PrimaryIndex { path, entries },
+            _ => {
+                println!("rebuilding index {}", path);
+                let index = PrimaryIndex::build(path, parts);
+                if let Err(e) = index.save() {
+                    println!("{}", e);
+                }
+                index
+            }
+        }
+    }
+
+    pub fn build(path: String, parts: &[Part]) -> Self {
+        let mut index = PrimaryIndex { path, entries: BTreeMap::new() };
+        parts.iter().for_each(|part| index.reindex_part(part));
+        index
+    }
+
+    fn agrees(entries: &BTreeMap<u128, (u32, u32)>, parts: &[Part]) -> bool {
+        entries.len() == parts.iter().map(|part| part.key_range.iter().flatten().count()).sum::<usize>()
+            && entries.iter().all(|(key, (part, slot))| match parts.iter().find(|p| p.index == *part) {
+                Some(p) => p.key_range.get(*slot as usize) == Some(&Some(*key)),
+                None => false
+            })
+    }
+
+    /*
+     * MARK: reindex part
+     * drop every entry pointing into the part and add its current keys, a slot is the position of the record
+     * in part.records and records without a key are skipped
+     */
+    pub fn reindex_part(&mut self, part: &Part) {
+        self.entries.retain(|_, (index, _)| *index != part.index);
+        part.key_range.iter().enumerate().for_each(|(slot, key)| if let Some(key) = key {
+            self.entries.insert(*key, (part.index, slot as u32));
+        });
+    }
+
+    pub fn remove_part(&mut self, part: u32) {
+        self.entries.retain(|_, (index, _)| *index != part);
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let tmp_path = [&self.path, ".tmp"].concat();
+        let entries = self.entries.iter().map(|(key, (part, slot))| (*key, *part, *slot)).collect::<Vec<(u128, u32, u32)>>();
+        let buf = match bincode::serialize(&entries) {
+            Ok(buf) => buf,
+            Err(e) => return Err(["unable to serialise index\n".to_string(), e.to_string()].concat())
+        };
+        match File::create(&tmp_path) {
+            Ok(mut file) => match file.write_all(&buf).and_then(|_| file.sync_all()) {
+                Ok(_) => {},
+                Err(e) => return Err(["unable to write index file\n".to_string(), e.to_string()].concat())
+            },
+            Err(e) => return Err(["unable to create index file\n".to_string(), e.to_string()].concat())
+        };
+        match fs::rename(&tmp_path, &self.path) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(["unable to replace index file\n".to_string(), e.to_string()].concat())
+        }
+    }
+
+    /*
+     * MARK: lookup
+     * the slots of every key in the range, grouped by part
+     */
+    pub fn lookup(&self, range: (Bound<u128>, Bound<u128>)) -> BTreeMap<u32, Vec<u32>> {
+        let mut found: BTreeMap<u32, Vec<u32>> = BTreeMap::new();
+        if PrimaryIndex::is_empty_range(&range) {
+            return found;
+        }
+        self.entries.range(range).for_each(|(_, (part, slot))| found.entry(*part).or_default().push(*slot));
+        found
+    }
+
+    fn is_empty_range(range: &(Bound<u128>, Bound<u128>)) -> bool {
+        match range {
+            (Bound::Included(start), Bound::Included(end)) => start > end,
+            (Bound::Included(start), Bound::Excluded(end)) | (Bound::Excluded(start), Bound::Included(end)) => start >= end,
+            (Bound::Excluded(start), Bound::Excluded(end)) => start.checked_add(1).is_none_or(|next| next >= *end),
+            (Bound::Excluded(start), Bound::Unbounded) => *start == u128::MAX,
+            _ => false
+        }
+    }
+
+    /*
+     * MARK: key range
+     * the range of primary keys allowed by the conditions on the key column, None when no condition narrows it
+     */
+    pub fn key_range(key_column: &str, conditions: &[Condition]) -> Option<(Bound<u128>, Bound<u128>)> {
+        let mut range = (Bound::Unbounded, Bound::Unbounded);
+        let mut narrowed = false;
+        for condition in conditions.iter().filter(|condition| condition.target_column == key_column) {
+            if let Conditional::Between(CellValue::ULong { data: Some(low), .. }, CellValue::ULong { data: Some(high), .. }) = condition.conditional {
+                range = (PrimaryIndex::tighter(range.0, Bound::Included(low), true), PrimaryIndex::tighter(range.1, Bound::Included(high), false));
+                narrowed = true;
+                continue;
+            }
+            let key = match condition.value {
+                CellValue::ULong { data: Some(key), .. } => key,
+                _ => continue
+            };
+            let (start, end) = match condition.conditional {
+                Conditional::Equal        => (Bound::Included(key), Bound::Included(key)),
+                Conditional::Greater      => (Bound::Excluded(key), Bound::Unbounded),
+                Conditional::EqualGreater => (Bound::Included(key), Bound::Unbounded),
+                Conditional::Smaller      => (Bound::Unbounded, Bound::Excluded(key)),
+                Conditional::EqualSmaller => (Bound::Unbounded, Bound::Included(key)),
+                _ => continue
+            };
+            range = (PrimaryIndex::tighter(range.0, start, true), PrimaryIndex::tighter(range.1, end, false));
+            narrowed = true;
+        }
+        match narrowed {
+            true => Some(range),
+            false => None
+        }
+    }
+
+    fn tighter(a: Bound<u128>, b: Bound<u128>, lower: bool) -> Bound<u128> {
+        match (a, b) {
+            (Bound::Unbounded, other) | (other, Bound::Unbounded) => other,
+            (a, b) => {
+                let value = |bound: &Bound<u128>| match bound { Bound::Included(v) | Bound::Excluded(v) => *v, Bound::Unbounded => 0 };
+                match (value(&a).cmp(&value(&b)), lower) {
+                    (std::cmp::Ordering::Greater, true) | (std::cmp::Ordering::Less, false) => a,
+                    (std::cmp::Ordering::Less, true) | (std::cmp::Ordering::Greater, false) => b,
+                    (std::cmp::Ordering::Equal, _) => match a {
+                        Bound::Excluded(_) => a,
+                        _ => b
+                    }
+                }
+            }
+        }
+    }
+}
+
+/*
+ * MARK: SecondaryIndex
+ * maps the values of one or more non key columns to the part and slot of every record holding them,
+ * values are compared as their data strings so only equality conditions can use it
+ */
+pub struct SecondaryIndex {
+    pub name: String,
+    pub columns: Vec<String>,
+    path: String,
+    entries: BTreeMap<Vec<String>, Vec<(u32, u32)>>,
+}
+
+impl SecondaryIndex {
+    /*
+     * MARK: load
+     * read the index file, if it is missing, unreadable or does not agree with the parts it is rebuilt from them
+     */
+    pub fn load(name: String, columns: Vec<String>, path: String, parts: &[Part]) -> Self {
+        let mut buf = vec![];
+        let entries = match File::open(&path).and_then(|mut file| file.read_to_end(&mut buf)) {
+            Ok(_) => match bincode::deserialize::<Vec<(Vec<String>, Vec<(u32, u32)>)>>(&buf) {
+                Ok(entries) => Some(entries.into_iter().collect::<BTreeMap<Vec<String>, Vec<(u32, u32)>>>()),
+                Err(e) => {
+                    println!("index {} unreadable {}", path, e);
+                    None
+                }
+            },
+            Err(_) => None
+        };
+        let mut index = SecondaryIndex { name, columns, path, entries: BTreeMap::new() };
+        match entries {
+            Some(entries) if index.agrees(&entries, parts) => index.entries = entries,
+            _ => {
+                println!("rebuilding index {}", index.path);
+                parts.iter().for_each(|part| index.reindex_part(part));
+                if let Err(e) = index.save() {
+                    println!("{}", e);
+                }
+            }
+        }
+        index
+    }
+
+    pub fn build(name: String, columns: Vec<String>, path: String, parts: &[Part]) -> Self {
+        let mut index = SecondaryIndex { name, columns, path, entries: BTreeMap::new() };
+        parts.iter().for_each(|part| index.reindex_part(part));
+        index
+    }
+
+    fn values(&self, record: &Record) -> Vec<String> {
+        self.columns.iter().map(|column| match record.columns.iter().find(|col| col.name() == column) {
+            Some(col) => col.data_str(),
+            None => "null".to_owned()
+        }).collect::<Vec<String>>()
+    }
+
+    fn agrees(&self, entries: &BTreeMap<Vec<String>, Vec<(u32, u32)>>, parts: &[Part]) -> bool {
+        entries.values().map(|slots| slots.len()).sum::<usize>() == parts.iter().map(|part| part.records.len()).sum::<usize>()
+            && entries.iter().all(|(values, slots)| slots.iter().all(|(part, slot)| match parts.iter().find(|p| p.index == *part).and_then(|p| p.records.get(*slot as usize)) {
+                Some(record) => self.values(record) == *values,
+                None => false
+            }))
+    }
+
+    /*
+     * MARK: reindex part
+     * drop every entry pointing into the part and add the values of its current records
+     */
+    pub fn reindex_part(&mut self, part: &Part) {
+        self.remove_part(part.index);
+        part.records.iter().enumerate().for_each(|(slot, record)| {
+            let values = self.values(record);
+            self.entries.entry(values).or_default().push((part.index, slot as u32));
+        });
+    }
+
+    pub fn remove_part(&mut self, part: u32) {
+        self.entries.values_mut().for_each(|slots| slots.retain(|(index, _)| *index != part));
+        self.entries.retain(|_, slots| !slots.is_empty());
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let tmp_path = [&self.path, ".tmp"].concat();
+        let entries = self.entries.iter().map(|(values, slots)| (values.clone(), slots.clone())).collect::<Vec<(Vec<String>, Vec<(u32, u32)>)>>();
+        let buf = match bincode::serialize(&entries) {
+            Ok(buf) => buf,
+            Err(e) => return Err(["unable to serialise index\n".to_string(), e.to_string()].concat())
+        };
+        match File::create(&tmp_path) {
+            Ok(mut file) => match file.write_all(&buf).and_then(|_| file.sync_all()) {
+                Ok(_) => {},
+                Err(e) => return Err(["unable to write index file\n".to_string(), e.to_string()].concat())
+            },
+            Err(e) => return Err(["unable to create index file\n".to_string(), e.to_string()].concat())
+        };
+        match fs::rename(&tmp_path, &self.path) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(["unable to replace index file\n".to_string(), e.to_string()].concat())
+        }
+    }
+
+    pub fn delete(&self) -> Result<(), String> {
+        match fs::remove_file(&self.path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(["unable to delete index file\n".to_string(), e.to_string()].concat()),
+            _ => Ok(())
+        }
+    }
+
+    /*
+     * MARK: lookup
+     * the slots of every record matching the equality conditions grouped by part,
+     * None when some indexed column has no equality condition on a non null value
+     */
+    pub fn lookup(&self, conditions: &[Condition]) -> Option<BTreeMap<u32, Vec<u32>>> {
+        let values = self.columns.iter().map(|column| conditions.iter().find_map(|condition| match condition.conditional {
+            Conditional::Equal if condition.target_column == *column && !condition.value.is_null() => Some(condition.value.data_str()),
+            _ => None
+        })).collect::<Option<Vec<String>>>()?;
+        let mut found: BTreeMap<u32, Vec<u32>> = BTreeMap::new();
+        if let Some(slots) = self.entries.get(&values) {
+            slots.iter().for_each(|(part, slot)| found.entry(*part).or_default().push(*slot));
+        }
+        Some(found)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use super::*;
+    use crate::database::zone::ZoneMap;
+
+    fn part(index: u32, records: Vec<Record>) -> Part {
+        let mut part = Part {
+            index,
+            size: 4096,
+            full: false,
+            directory: "".to_owned(),
+            key_column: Some("id".to_owned()),
+            key_range: vec![],
+            zone_map: ZoneMap::default(),
+            unsaved: false,
+            records: Arc::new(records),
+            history: Arc::new(vec![]),
+        };
+        part.rebuild_key_range();
+        part
+    }
+
+    fn record(id: Option<u128>, name: &str) -> Record {
+        let name = CellValue::String { name: "name".to_owned(), data: Some(name.to_owned()) };
+        match id {
+            Some(id) => Record::new(vec![name, CellValue::ULong { name: "id".to_owned(), data: Some(id) }]),
+            None => Record::new(vec![name])
+        }
+    }
+
+    #[test]
+    fn slots_point_at_records_wherever_the_key_column_is() {
+        let parts = vec![part(0, vec![record(Some(5), "a"), record(None, "b"), record(Some(9), "c")]), part(1, vec![record(Some(2), "d")])];
+        let index = PrimaryIndex::build("".to_owned(), &parts);
+        assert!(PrimaryIndex::agrees(&index.entries, &parts));
+        let found = index.lookup((Bound::Included(5), Bound::Unbounded));
+        assert_eq!(found, BTreeMap::from([(0, vec![0, 2])]));
+        let names = found[&0].iter().map(|slot| parts[0].records[*slot as usize].columns[0].data_str()).collect::<Vec<String>>();
+        assert_eq!(names, vec!["a", "c"]);
+        assert_eq!(index.lookup((Bound::Included(2), Bound::Included(2))), BTreeMap::from([(1, vec![0])]));
+    }
+
+    #[test]
+    fn empty_ranges_find_nothing() {
+        let parts = vec![part(0, vec![record(Some(1), "a"), record(Some(2), "b")])];
+        let index = PrimaryIndex::build("".to_owned(), &parts);
+        assert!(index.lookup((Bound::Excluded(1), Bound::Excluded(2))).is_empty());
+        assert!(index.lookup((Bound::Included(2), Bound::Included(1))).is_empty());
+        assert!(index.lookup((Bound::Excluded(u128::MAX), Bound::Excluded(u128::MAX))).is_empty());
+        assert!(index.lookup((Bound::Excluded(u128::MAX), Bound::Unbounded)).is_empty());
+    }
+}
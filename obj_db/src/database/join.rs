@@ -0,0 +1,75 @@
+use std::collections::HashMap;
+use super::cell::CellValue;
+use super::record::Record;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JoinKind {
+    Inner,
+    Left,
+}
+
+impl JoinKind {
+    pub fn parse(a: &str) -> Result<Self, String> {
+        match &a.to_uppercase().replace('_', " ")[..] {
+            "INNER" => Ok(JoinKind::Inner),
+            "LEFT" | "LEFT OUTER" => Ok(JoinKind::Left),
+            _ => Err(["join type ", a, " not recognised"].concat())
+        }
+    }
+}
+
+/*
+ * MARK: Join
+ * one table joined onto the rows built so far, matched where the joined tables column equals
+ * an already qualified column of the rows
+ */
+#[derive(Debug, Clone)]
+pub struct Join {
+    pub kind: JoinKind,
+    pub table: String,
+    pub column: String,
+    pub outer: String,
+}
+
+pub fn qualified(table: &str, column: &str) -> String {
+    [table, ".", column].concat()
+}
+
+/*
+ * rename every column of a record to table.column so rows from several tables cannot clash
+ */
+pub fn qualify(table: &str, record: Record) -> Record {
+    Record::new(record.columns.iter().map(|col| col.renamed(&qualified(table, col.name()))).collect::<Vec<CellValue>>())
+}
+
+/*
+ * the row extended with each of its matches, a left join keeps an unmatched row padded with the null columns of the joined table
+ */
+pub fn matched(row: Record, matches: &[Record], kind: JoinKind, nulls: &Record) -> Vec<Record> {
+    match (matches.is_empty(), kind) {
+        (true, JoinKind::Inner) => vec![],
+        (true, JoinKind::Left) => vec![Record::new([row.columns, nulls.columns.clone()].concat())],
+        (false, _) => matches.iter().map(|right| Record::new([row.columns.clone(), right.columns.clone()].concat())).collect::<Vec<Record>>()
+    }
+}
+
+/*
+ * MARK: hash join
+ * the joined table is hashed on its join column once and every row probes it, null keys never match
+ */
+pub fn hash_join(rows: Vec<Record>, join: &Join, right: Vec<Record>, nulls: &Record) -> Vec<Record> {
+    let column = qualified(&join.table, &join.column);
+    let mut buckets: HashMap<String, Vec<Record>> = HashMap::new();
+    for record in right {
+        if let Some(cell) = record.columns.iter().find(|col| col.name() == column).filter(|cell| !cell.is_null()) {
+            buckets.entry(cell.data_str()).or_default().push(record);
+        }
+    }
+    rows.into_iter().flat_map(|row| {
+        let matches = match row.columns.iter().find(|col| col.name() == join.outer).filter(|cell| !cell.is_null()) {
+            Some(cell) => buckets.get(&cell.data_str()).map(|matches| matches.as_slice()).unwrap_or(&[]),
+            None => &[]
+        };
+        matched(row, matches, join.kind, nulls)
+    }).collect::<Vec<Record>>()
+}
@@ -0,0 +1,126 @@
+use std::fs;
+use std::fs::File;
+use std::io::Read;
+use std::io::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use super::cell::{CellValue, ColumnDef};
+
+/*
+ * MARK: Conversion
+ * what a retyped column does with a value that does not fit the new type, strict fails the whole
+ * migration and null leaves the cell empty
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Conversion {
+    Strict,
+    Null,
+}
+
+impl Conversion {
+    pub fn parse(a: &str) -> Result<Self, String> {
+        match &a.to_uppercase()[..] {
+            "STRICT" => Ok(Conversion::Strict),
+            "NULL" => Ok(Conversion::Null),
+            _ => Err(["conversion ", a, " not recognised, expected strict or null"].concat())
+        }
+    }
+
+    /*
+     * a cell converted to the type of the template through its json value, named after the template
+     */
+    pub fn convert(&self, cell: &CellValue, template: &CellValue) -> Result<CellValue, String> {
+        match (template.parse_json(&cell.to_json()), self) {
+            (Ok(converted), _) => Ok(converted),
+            (Err(_), Conversion::Null) => Ok(template.as_null()),
+            (Err(e), Conversion::Strict) => Err(e)
+        }
+    }
+}
+
+/*
+ * MARK: Migration
+ * one change to the columns of a table, an added column takes the same
+ * (name, type, default, nullable, unique, foreign key, on delete) definition as create_table
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Migration {
+    Add { column: ColumnDef },
+    Drop { column: String },
+    Rename { column: String, name: String },
+    Retype { column: String, dtype: String, conversion: Conversion },
+}
+
+impl Migration {
+    /*
+     * the existing column the migration changes, None when it adds one
+     */
+    pub fn column(&self) -> Option<&str> {
+        match self {
+            Migration::Add { .. } => None,
+            Migration::Drop { column } => Some(column),
+            Migration::Rename { column, .. } => Some(column),
+            Migration::Retype { column, .. } => Some(column),
+        }
+    }
+}
+
+/*
+ * MARK: MigrationEntry
+ * a migration as it was applied to a table, numbered from one in the order they ran
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MigrationEntry {
+    pub version: u32,
+    pub applied_at: u64,
+    pub migration: Migration,
+}
+
+/*
+ * MARK: history
+ * every migration applied to a table, kept as json next to the table definition so it can be audited,
+ * a table without the file has never been migrated
+ */
+pub fn history(path: &str) -> Result<Vec<MigrationEntry>, String> {
+    let mut buf = "".to_owned();
+    match File::open(path) {
+        Ok(mut file) => { file.read_to_string(&mut buf).map_err(|e| ["unable to read migration history ", path, " ", &e.to_string()].concat())?; },
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(["unable to open migration history ", path, " ", &e.to_string()].concat())
+    };
+    serde_json::from_str(&buf).map_err(|e| ["migration history ", path, " is corrupt: ", &e.to_string()].concat())
+}
+
+/*
+ * add a migration to the end of the history, returns the entry that was recorded
+ */
+pub fn record(path: &str, migration: &Migration) -> Result<MigrationEntry, String> {
+    let mut entries = history(path)?;
+    let entry = MigrationEntry {
+        version: entries.len() as u32 + 1,
+        applied_at: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+        migration: migration.clone(),
+    };
+    entries.push(entry.clone());
+    let buf = match serde_json::to_string_pretty(&entries) {
+        Ok(buf) => buf,
+        Err(e) => return Err(["unable to serialise migration history\n".to_string(), e.to_string()].concat())
+    };
+    let tmp_path = [path, ".tmp"].concat();
+    match File::create(&tmp_path) {
+        Ok(mut file) => match file.write_all(buf.as_bytes()).and_then(|_| file.sync_all()) {
+            Ok(_) => {},
+            Err(e) => return Err(["unable to write migration history\n".to_string(), e.to_string()].concat())
+        },
+        Err(e) => return Err(["unable to create migration history\n".to_string(), e.to_string()].concat())
+    };
+    match fs::rename(&tmp_path, path) {
+        Ok(_) => Ok(entry),
+        Err(e) => Err(["unable to replace migration history\n".to_string(), e.to_string()].concat())
+    }
+}
+
+pub fn to_json(entries: &[MigrationEntry]) -> Value {
+    serde_json::to_value(entries).unwrap_or(Value::Null)
+}
@@ -5,31 +5,76 @@ use std::io::BufReader;
 use std::io::Read;
 use std::io::Seek;
 use std::io::Write;
+use std::path::Path;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::fmt::Display;
 use super::cell;
 use super::cell::Cell;
 use super::conditional;
 use super::record;
 use super::record::Record;
+use super::wal;
+use super::zone::ZoneMap;
+
+const PART_MAGIC: &[u8; 4] = b"ODBP";
+const PART_VERSION: u16 = 1;
+const PART_HEADER_LEN: usize = 18;
+
+/* 
+ * MARK: PartLoadError
+ * a part file whose bytes fail their checks is corrupt and can be quarantined, a part that could not be read
+ * or was written by a newer format is left where it is for the table to fail on
+ */
+#[derive(Debug, Clone)]
+pub enum PartLoadError {
+    Corrupt(String),
+    Unreadable(String),
+}
+
+impl Display for PartLoadError {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PartLoadError::Corrupt(e) | PartLoadError::Unreadable(e) => fmt.write_str(e)
+        }
+    }
+}
+
+impl From<PartLoadError> for String {
+    fn from(e: PartLoadError) -> String {
+        e.to_string()
+    }
+}
 
 pub struct Part {
     pub index: u32,
     pub size: u16,
     pub full: bool, 
     pub directory: String,
-    pub key_range: Vec<u128>,
-    pub records: Vec<record::Record>,
+    pub key_column: Option<String>,
+    pub key_range: Vec<Option<u128>>,
+    pub zone_map: ZoneMap,
+    pub unsaved: bool,
+    pub records: Arc<Vec<record::Record>>,
+    pub history: Arc<Vec<record::Record>>,
 }
 
 impl Part {
     // MARK: new
-    pub fn new(directory: &str, index: usize, size: u16) -> Self {
+    pub fn new(directory: &str, index: usize, size: u16, key_column: Option<String>) -> Self {
         let new_part = Part {
             index: index as u32,
             size: size,
             full: false,
             directory: format!("{}/p{:X}", directory, index).to_owned(),
+            key_column,
             key_range: vec![],
-            records: vec![],
+            zone_map: ZoneMap::default(),
+            unsaved: false,
+            records: Arc::new(vec![]),
+            history: Arc::new(vec![]),
         };
         match new_part.init_dir() {
             Ok(y) => y,
@@ -45,83 +90,180 @@ impl Part {
         Ok(self)
     }
 
-    pub fn load_from_dir(path: String, size: u16, index: u32) -> Result<Self, String> {
+    pub fn load_from_dir(path: String, size: u16, index: u32, key_column: Option<String>) -> Result<Self, PartLoadError> {
         println!("build part from file {}", &path.clone());
-        let records: Vec<record::Record> = match File::open(path.clone()) {
-            Ok(mut e)  => {
-                let mut buf = vec![];
-                e.read_to_end(&mut buf).unwrap();
-                match buf.is_empty() {
-                    true => vec![],
-                    false => {
-                        let rs: Vec<record::Record> = bincode::deserialize(&buf).unwrap();
-                        rs
-                    }
-                }
-            },
-            Err(e) => return Err([e.to_string(), "unable to find part".to_string()].concat()),
-        };
-
-        Ok(Part {
+        let records = Part::read_records(&path)?;
+        let mut part = Part {
             index: index,
             size: size,
             full: false,
             directory: path.clone(),
-            key_range: records.iter().map(|r| match r.columns.first() {
-                Some(e) => match e {
-                    cell::CellValue::ULong { data, .. } => match data {
-                        Some(e) => e.to_string(),
-                        None => "".to_owned()
-                    },
-                    _ => "".to_owned()
-                },
-                None => "".to_owned()
-            }).filter(|b| !b.is_empty()).map(|c| c.parse::<u128>().unwrap()).collect::<Vec<u128>>(),
-            records: records,
-        })
+            key_column,
+            key_range: vec![],
+            zone_map: ZoneMap::load(&path, &records, Part::checksum(&records)),
+            unsaved: false,
+            records: Arc::new(records),
+            history: Arc::new(vec![]),
+        };
+        part.rebuild_key_range();
+        Ok(part)
     }
 
+    /* 
+     * a part holding writes of a committing transaction is already newer than its file and is kept as it is
+     */
     pub fn reload(&mut self) -> Result<String, String> {
+        if self.unsaved {
+            return Ok("part has unsaved writes and was not reloaded".to_owned());
+        }
         println!("reloading {:?}\n\n", self.records);
-        match fs::File::open(self.directory.clone()) {
-            Ok(mut p) => {
-                let mut buf = vec![];
-                p.read_to_end(&mut buf).unwrap();
-                match buf.is_empty() {
-                    true => { self.records = vec![] },
-                    false => { self.records = bincode::deserialize(&buf).unwrap(); }
-                };
-                self.key_range = self.records.iter().map(|r| match r.columns.first() {
-                    Some(e) => match e {
-                        cell::CellValue::ULong { data, .. } => match data {
-                            Some(e) => e.to_string(),
-                            None => "".to_owned()
-                        },
-                        _ => "".to_owned()
-                    },
-                    None => "".to_owned()
-                }).filter(|b| !b.is_empty()).map(|c| c.parse::<u128>().unwrap()).collect::<Vec<u128>>()
-            },
-            Err(_) => return Err("couldnt open file".to_owned())
-        };
+        self.records = Arc::new(Part::read_records(&self.directory)?);
+        self.rebuild_key_range();
+        self.zone_map = ZoneMap::build(&self.records, Part::checksum(&self.records));
         println!("reloading {:?}\n\n", self.records);
         Ok("reloaded successully".to_owned())
     }
 
+    /* 
+     * the primary key of the record in each slot, found by the name of the key column so a record keeps its slot
+     * wherever the key sits among its columns, a record without a key leaves its slot empty
+     */
+    pub fn rebuild_key_range(&mut self) {
+        let key_column = self.key_column.as_deref();
+        self.key_range = self.records.iter().map(|r| match r.columns.iter().find(|col| Some(col.name()) == key_column) {
+            Some(cell::CellValue::ULong { data: Some(e), .. }) => Some(*e),
+            _ => None
+        }).collect::<Vec<Option<u128>>>();
+    }
+
     /* 
      * MARK: save records to disc
-     * (overwrite contents)
+     * (overwrite contents) the zone map is rebuilt and written after the records, a crash between the two
+     * leaves a zone map with the checksum of the old records which is rebuilt when the part is next loaded
     */
     pub fn save(&mut self) -> Result<String, String> {
-        let _ = match fs::OpenOptions::new().write(true).truncate(true).open(self.directory.clone()) {
-            Ok(mut p) => {
-                let mut writebuf: Vec<u8> = vec![];
-                writebuf.extend(bincode::serialize(&self.records).unwrap());
-                p.write(&writebuf)
+        let checksum = Part::write_records(&self.directory, &self.records)?;
+        self.zone_map = ZoneMap::build(&self.records, checksum);
+        self.zone_map.save(&self.directory)?;
+        self.unsaved = false;
+        Ok("records successully saved to disk".to_owned())
+    }
+
+    /* 
+     * MARK: part file format
+     * magic, format version, crc32 of the payload and payload length followed by the bincode payload,
+     * written to a temporary file that is fsynced and renamed over the part so a crash never leaves a torn part
+     */
+    pub fn write_records(path: &str, records: &[Record]) -> Result<u32, String> {
+        let payload = bincode::serialize(records).map_err(|e| e.to_string())?;
+        let checksum = crc32fast::hash(&payload);
+        let mut writebuf: Vec<u8> = Vec::with_capacity(PART_HEADER_LEN + payload.len());
+        writebuf.extend(PART_MAGIC);
+        writebuf.extend(PART_VERSION.to_le_bytes());
+        writebuf.extend(checksum.to_le_bytes());
+        writebuf.extend((payload.len() as u64).to_le_bytes());
+        writebuf.extend(payload);
+
+        let tmp_path = [path, ".tmp"].concat();
+        match File::create(&tmp_path) {
+            Ok(mut p) => match p.write_all(&writebuf).and_then(|_| p.sync_all()) {
+                Ok(_) => {},
+                Err(e) => return Err(["couldnt write temporary part file ", &e.to_string()].concat())
             },
-            Err(_) => return Err("couldnt open file".to_owned())
+            Err(e) => return Err(["couldnt create temporary part file ", &e.to_string()].concat())
         };
-        Ok("records successully saved to disk".to_owned())
+        match fs::rename(&tmp_path, path) {
+            Ok(_) => {
+                if let Some(Ok(dir)) = Path::new(path).parent().map(File::open) {
+                    let _ = dir.sync_all();
+                }
+                Ok(checksum)
+            },
+            Err(e) => Err(["couldnt replace part file ", &e.to_string()].concat())
+        }
+    }
+
+    /* 
+     * the checksum the part file holds for the records, bincode is deterministic so it can be recomputed after a read
+     */
+    pub fn checksum(records: &[Record]) -> u32 {
+        bincode::serialize(records).map(|payload| crc32fast::hash(&payload)).unwrap_or(0)
+    }
+
+    /* 
+     * an empty file is an empty part, a file without the magic number is read as a headerless part from before the format was versioned
+     */
+    pub fn read_records(path: &str) -> Result<Vec<Record>, PartLoadError> {
+        let mut buf = vec![];
+        match File::open(path) {
+            Ok(mut p) => { p.read_to_end(&mut buf).map_err(|e| PartLoadError::Unreadable(["couldnt read part file ", path, " ", &e.to_string()].concat()))?; },
+            Err(e) => return Err(PartLoadError::Unreadable([e.to_string(), " unable to find part ".to_string(), path.to_owned()].concat()))
+        };
+        if buf.is_empty() {
+            return Ok(vec![]);
+        }
+        let payload = match buf.starts_with(PART_MAGIC) {
+            true => {
+                if buf.len() < PART_HEADER_LEN {
+                    return Err(PartLoadError::Corrupt(["part file ", path, " is corrupt: header is truncated"].concat()));
+                }
+                let version = u16::from_le_bytes([buf[4], buf[5]]);
+                let checksum = u32::from_le_bytes([buf[6], buf[7], buf[8], buf[9]]);
+                let len = u64::from_le_bytes(buf[10..18].try_into().unwrap()) as usize;
+                let payload = &buf[PART_HEADER_LEN..];
+                if version > PART_VERSION {
+                    return Err(PartLoadError::Unreadable(["part file ", path, " has unsupported format version ", &version.to_string()].concat()));
+                }
+                if payload.len() != len {
+                    return Err(PartLoadError::Corrupt(["part file ", path, " is corrupt: expected ", &len.to_string(), " payload bytes but found ", &payload.len().to_string()].concat()));
+                }
+                if crc32fast::hash(payload) != checksum {
+                    return Err(PartLoadError::Corrupt(["part file ", path, " is corrupt: checksum mismatch"].concat()));
+                }
+                payload
+            },
+            false => &buf[..]
+        };
+        bincode::deserialize(payload).map_err(|e| PartLoadError::Corrupt(["part file ", path, " is corrupt: ", &e.to_string()].concat()))
+    }
+
+    /* 
+     * MARK: quarantine part file
+     * move a corrupt part out of the way so the rest of the table can still load
+     */
+    pub fn quarantine(path: &str) -> Result<String, String> {
+        let stamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        let quarantine_path = [path, ".", &stamp.to_string(), ".corrupt"].concat();
+        match fs::rename(path, &quarantine_path) {
+            Ok(_) => Ok(quarantine_path),
+            Err(e) => Err(["could not quarantine part file ", path, " ", &e.to_string()].concat())
+        }
+    }
+
+    /* 
+     * MARK: save records through the log
+     * the new image of the part is appended to the database log before the part file is rewritten,
+     * while the log is deferring writes the image is handed to it and the part is left unsaved
+     */
+    pub fn save_logged(&mut self, wal: Option<&Arc<Mutex<wal::WriteAheadLog>>>, operation: wal::LogOperation, records: Vec<Record>) -> Result<String, String> {
+        match wal {
+            Some(wal) => match wal.lock() {
+                Ok(mut wal) if wal.is_deferring() => {
+                    wal.push_deferred(wal::LogWrite { part: self.directory.clone(), operation, records, image: self.records.to_vec() });
+                    self.zone_map = ZoneMap::build(&self.records, self.zone_map.checksum);
+                    self.unsaved = true;
+                    Ok("part write deferred until the transaction commits".to_owned())
+                },
+                Ok(mut wal) => {
+                    let sequence = wal.log_writes(vec![wal::LogWrite { part: self.directory.clone(), operation, records, image: self.records.to_vec() }])?;
+                    let res = self.save()?;
+                    wal.log_applied(sequence)?;
+                    Ok(res)
+                },
+                Err(e) => Err(e.to_string())
+            },
+            None => self.save()
+        }
     }
 
     /* 
@@ -129,37 +271,53 @@ impl Part {
      * permanent
     */
     pub fn delete(&mut self, index: u32) -> Result<String, String> {
-        match fs::remove_file(self.directory.clone()) {
+        match fs::remove_file(self.directory.clone()).map_err(|e| e.to_string()).and_then(|_| ZoneMap::delete(&self.directory)) {
             Ok(_) => return Ok("deletion successful".to_owned()),
-            Err(e) => return Err(["could not delete part file", &e.to_string()].concat())
+            Err(e) => return Err(["could not delete part file", &e].concat())
         };
     }
 
     pub fn empty(&mut self) -> Result<String, String> {
-        self.records = vec![];
+        self.records = Arc::new(vec![]);
+        self.history = Arc::new(vec![]);
+        self.zone_map = ZoneMap::default();
         Ok("records successully cleared from in memory part".to_owned())
     }
 
     /* 
      * MARK: Query search in columns
+     * matching records are copied with only the named columns, or whole when no columns are given
      */
-    pub fn query_search_columns(&self, conditions: &Vec<conditional::Condition>) -> Result<String, String> {
+    pub fn query_search_columns(&self, filter: &conditional::Filter, columns: Option<&[String]>) -> Vec<Record> {
         println!("{:?}", self.records);
-        let matching_records = self.records.iter().filter(|r| conditions.iter().all(|condition| r.query_check(condition)) ).map(|b| b.to_owned()).collect::<record::RecordCollection>().get_vec();
+        let matching_records = self.records.iter().filter(|r| filter.evaluate(r)).map(|b| match columns { Some(columns) => b.select(columns), None => b.to_owned() }).collect::<record::RecordCollection>().get_vec();
         println!("{:?}", matching_records);
-        Ok(matching_records.iter().map(|a| a.to_string()).collect::<Vec<String>>().join(", "))
+        matching_records
+    }
+
+    /* 
+     * MARK: Query search slots
+     * only the records at the given slots are checked, used when the table index already located them
+     */
+    pub fn query_search_slots(&self, slots: &[u32], filter: &conditional::Filter, columns: Option<&[String]>) -> Vec<Record> {
+        slots.iter()
+            .filter_map(|slot| self.records.get(*slot as usize))
+            .filter(|r| filter.evaluate(r))
+            .map(|r| match columns { Some(columns) => r.select(columns), None => r.to_owned() })
+            .collect::<Vec<Record>>()
     }
 
     /* 
      * MARK: Query create columns
+     * the record is stamped with the write creating it
      */
-    pub fn query_create_record(&mut self, record: Record, table_indexer: Cell) -> Result<String, String> {
+    pub fn query_create_record(&mut self, mut record: Record, table_indexer: Cell, write: u64, wal: Option<&Arc<Mutex<wal::WriteAheadLog>>>) -> Result<String, String> {
         print!("part {} adding record {:?}", self.index, record);
         let res = match table_indexer {
             Cell::CellDef { ctype, .. } => match record.columns.iter().find(|rcol| rcol.name() == ctype.name()) {
                 Some(e) => match e {
                     cell::CellValue::ULong { data, .. } => match data {
-                        Some(e)=> Ok(self.key_range.push(e.clone())),
+                        Some(e)=> Ok(*e),
                         None => Err("submitted table index is None")
                     }
                     _ => Err("table indexer waas not correct type of ULong")
@@ -168,28 +326,119 @@ impl Part {
             },
             _ => Err("table indexer was not correct type")
         };
+        // the record is part of the image that is saved, it is taken back out and its key left off the range when the save fails
         match res {
-            Ok(_) => {
-                self.records.push(record);
-                match self.save() {
-                    Ok(_) => Ok("record created".to_owned()),
-                    Err(e) => Err(e)
+            Ok(key) => {
+                record.created = write;
+                Arc::make_mut(&mut self.records).push(record.clone());
+                match self.save_logged(wal, wal::LogOperation::Create, vec![record]) {
+                    Ok(_) => {
+                        self.key_range.push(Some(key));
+                        Ok("record created".to_owned())
+                    },
+                    Err(e) => {
+                        Arc::make_mut(&mut self.records).pop();
+                        Err(e)
+                    }
                 }
             },
             Err(e) => Err(e.to_owned())
         }
     }
 
+    /* 
+     * MARK: Query update records
+     * overwrite the assigned columns on every matching record, returns how many records were changed,
+     * the version each update replaces is kept in the history stamped with the write until it is collected
+     */
+    pub fn query_update_records(&mut self, matches: &dyn Fn(&Record) -> bool, assignments: &Record, write: u64, wal: Option<&Arc<Mutex<wal::WriteAheadLog>>>) -> Result<usize, String> {
+        // the records may still be shared with a snapshot, so they are only copied when something will change
+        if !self.records.iter().any(matches) {
+            return Ok(0);
+        }
+        let mut updated = vec![];
+        let mut replaced = vec![];
+        Arc::make_mut(&mut self.records).iter_mut().filter(|r| matches(r)).for_each(|r| {
+            replaced.push(Record { deleted: Some(write), ..r.clone() });
+            r.assign(assignments);
+            r.created = write;
+            updated.push(r.clone());
+        });
+        Arc::make_mut(&mut self.history).extend(replaced);
+        let count = updated.len();
+        self.rebuild_key_range();
+        self.save_logged(wal, wal::LogOperation::Update, updated).map(|_| count)
+    }
+
     /* 
      * MARK: Query delete in columns
+     * remove every matching record, returns how many records were deleted,
+     * the deleted versions are kept in the history stamped with the write until it is collected
      */
-    pub fn query_delete_records(&mut self, conditions: &Vec<conditional::Condition>) -> Result<String, String> {
-        let records_len = self.records.len();
-        self.records.retain(|r| !conditions.iter().all(|condition| r.query_check(condition)));
-        if records_len == self.records.len() {
-            Ok("no matching records found or deleted".to_owned())
-        } else {
-            Ok("matching records found and deleted successfully".to_owned())
+    pub fn query_delete_records(&mut self, matches: &dyn Fn(&Record) -> bool, write: u64, wal: Option<&Arc<Mutex<wal::WriteAheadLog>>>) -> Result<usize, String> {
+        if !self.records.iter().any(matches) {
+            return Ok(0);
         }
+        let (deleted, kept): (Vec<Record>, Vec<Record>) = Arc::unwrap_or_clone(std::mem::take(&mut self.records)).into_iter().partition(|r| matches(r));
+        self.records = Arc::new(kept);
+        Arc::make_mut(&mut self.history).extend(deleted.iter().map(|r| Record { deleted: Some(write), ..r.clone() }));
+        let count = deleted.len();
+        self.rebuild_key_range();
+        self.save_logged(wal, wal::LogOperation::Delete, deleted).map(|_| count)
     }
-}
\ No newline at end of file
+
+    /* 
+     * MARK: Collect history
+     * drop the replaced versions no new snapshot can see, snapshots taken before they were replaced
+     * hold their own copy of the history and keep those versions alive until they are dropped
+     */
+    pub fn collect_history(&mut self, committed: u64) {
+        if self.history.iter().any(|r| r.deleted.is_some_and(|deleted| deleted <= committed)) {
+            Arc::make_mut(&mut self.history).retain(|r| r.deleted.is_some_and(|deleted| deleted > committed));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn written(name: &str) -> String {
+        crate::test_dir();
+        let path = name.to_owned();
+        Part::write_records(&path, &[Record::new(vec![cell::CellValue::ULong { name: "id".to_owned(), data: Some(1) }])]).unwrap();
+        path
+    }
+
+    // at counts back from the end of the file when negative
+    fn rewrite(path: &str, at: isize, byte: impl Fn(u8) -> u8) {
+        let mut buf = fs::read(path).unwrap();
+        let at = if at < 0 { buf.len() - at.unsigned_abs() } else { at as usize };
+        buf[at] = byte(buf[at]);
+        fs::write(path, buf).unwrap();
+    }
+
+    #[test]
+    fn parts_read_back_what_was_written() {
+        let path = written("part_round_trip");
+        assert_eq!(Part::read_records(&path).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn a_changed_payload_fails_its_checksum_and_is_corrupt() {
+        let path = written("part_checksum");
+        rewrite(&path, -1, |byte| byte ^ 0xFF);
+        match Part::read_records(&path) {
+            Err(PartLoadError::Corrupt(e)) => assert!(e.contains("checksum mismatch")),
+            other => panic!("expected a corrupt part, got {:?}", other.map(|records| records.len()))
+        }
+    }
+
+    #[test]
+    fn a_newer_format_or_a_missing_file_is_not_corrupt() {
+        let path = written("part_version");
+        rewrite(&path, 4, |_| (PART_VERSION + 1) as u8);
+        assert!(matches!(Part::read_records(&path), Err(PartLoadError::Unreadable(_))));
+        assert!(matches!(Part::read_records("part_missing"), Err(PartLoadError::Unreadable(_))));
+    }
+}
@@ -0,0 +1,163 @@
+use std::{collections::BTreeMap, ops::Bound};
+use serde_json::{json, Value};
+use super::{conditional::Filter, read::ReadOptions};
+
+/*
+ * MARK: Access
+ * how a read reaches its records, a key range scan reads every part whose keys overlap the range,
+ * the index lookups only check the slots the index points at
+ */
+#[derive(Debug, Clone)]
+pub enum Access {
+    FullScan,
+    KeyRangeScan((Bound<u128>, Bound<u128>)),
+    PrimaryIndex((Bound<u128>, Bound<u128>)),
+    SecondaryIndex(String),
+}
+
+impl Access {
+    pub fn to_json(&self) -> Value {
+        match self {
+            Access::FullScan => json!({ "type": "full_scan" }),
+            Access::KeyRangeScan(range) => json!({ "type": "key_range_scan", "range": range_json(range) }),
+            Access::PrimaryIndex(range) => json!({ "type": "index_lookup", "index": "primary", "range": range_json(range) }),
+            Access::SecondaryIndex(name) => json!({ "type": "index_lookup", "index": name }),
+        }
+    }
+}
+
+fn range_json(range: &(Bound<u128>, Bound<u128>)) -> Value {
+    let bound = |bound: &Bound<u128>, inclusive: &str, exclusive: &str| match bound {
+        Bound::Included(key) => json!([inclusive, key.to_string()]),
+        Bound::Excluded(key) => json!([exclusive, key.to_string()]),
+        Bound::Unbounded => Value::Null
+    };
+    json!({ "from": bound(&range.0, ">=", ">"), "to": bound(&range.1, "<=", "<") })
+}
+
+/*
+ * MARK: Plan
+ * the physical side of a read, every part to reload mapped to the slots to check in it or None to check
+ * the whole part, the estimate is the number of records that will be checked against the filter,
+ * skipped holds the parts whose zone maps rule out a match and are never reloaded
+ */
+#[derive(Debug, Clone)]
+pub struct Plan {
+    pub access: Access,
+    pub parts: BTreeMap<u32, Option<Vec<u32>>>,
+    pub estimated_rows: usize,
+    pub skipped: Vec<u32>,
+}
+
+impl Plan {
+    pub fn lookup(access: Access, slots: BTreeMap<u32, Vec<u32>>) -> Self {
+        let estimated_rows = slots.values().map(|slots| slots.len()).sum::<usize>();
+        Plan { access, parts: slots.into_iter().map(|(part, slots)| (part, Some(slots))).collect::<BTreeMap<u32, Option<Vec<u32>>>>(), estimated_rows, skipped: vec![] }
+    }
+
+    /*
+     * the candidate checking the fewest records, earlier candidates win ties so the order they are costed in decides between equals
+     */
+    pub fn cheapest(candidates: Vec<Plan>) -> Plan {
+        candidates.into_iter().reduce(|best, candidate| match candidate.estimated_rows < best.estimated_rows { true => candidate, false => best }).unwrap_or(Plan { access: Access::FullScan, parts: BTreeMap::new(), estimated_rows: 0, skipped: vec![] })
+    }
+
+    /*
+     * MARK: explain
+     * the logical plan of the read next to the chosen physical plan, estimated rows is an upper bound on
+     * the records returned as every checked record is assumed to match
+     */
+    pub fn explain(&self, table_name: &str, filter: &Filter, options: &ReadOptions) -> Value {
+        let returned = self.estimated_rows.saturating_sub(options.offset).min(options.limit.unwrap_or(usize::MAX));
+        json!({ "plan": {
+            "table": table_name,
+            "logical": {
+                "filter": filter.to_json(),
+                "order_by": options.order_by.iter().map(|order| json!([order.column, match order.descending { true => "desc", false => "asc" }, match order.nulls_first { true => "nulls_first", false => "nulls_last" }])).collect::<Vec<Value>>(),
+                "offset": options.offset,
+                "limit": options.limit,
+                "columns": options.projection.as_ref().map(|projection| projection.iter().map(|(column, alias)| json!([column, alias])).collect::<Vec<Value>>()),
+                "paged": options.paged,
+            },
+            "physical": {
+                "access": self.access.to_json(),
+                "parts": self.parts.keys().collect::<Vec<&u32>>(),
+                "skipped_parts": self.skipped,
+                "checked_rows": self.estimated_rows,
+            },
+            "estimated_rows": returned,
+        }})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use super::*;
+    use super::super::{cell::{CellValue, ReferentialAction}, conditional::{Condition, Conditional}, record::Record, table::Table};
+
+    // three people in one part, with an index on their city
+    fn people(table_name: &str) -> Arc<Mutex<Table>> {
+        crate::test_dir();
+        let columns = vec![
+            ("id".to_owned(), "ULong".to_owned(), None, false, false, None, ReferentialAction::Restrict),
+            ("city".to_owned(), "String".to_owned(), None, true, false, None, ReferentialAction::Restrict),
+        ];
+        let table = Arc::new(Mutex::new(Table::new("plans".to_owned(), table_name.to_owned(), columns, true)));
+        let tables = vec![Arc::clone(&table)];
+        let row = |city: &str| Record::new(vec![CellValue::ULong { name: "id".to_owned(), data: None }, CellValue::String { name: "city".to_owned(), data: Some(city.to_owned()) }]);
+        let mut people = table.lock().unwrap();
+        people.query_create(vec![row("leeds"), row("york"), row("hull")], &tables).unwrap();
+        people.create_index("by_city".to_owned(), vec!["city".to_owned()]).unwrap();
+        drop(people);
+        table
+    }
+
+    fn key(conditional: Conditional, key: u128) -> Filter {
+        Filter::Condition(Condition { target_column: "id".to_owned(), conditional, value: CellValue::ULong { name: "id".to_owned(), data: Some(key) }, relational: None })
+    }
+
+    fn city(name: &str) -> Filter {
+        Filter::Condition(Condition { target_column: "city".to_owned(), conditional: Conditional::Equal, value: CellValue::String { name: "city".to_owned(), data: Some(name.to_owned()) }, relational: None })
+    }
+
+    #[test]
+    fn each_filter_is_read_by_the_access_path_checking_the_fewest_records() {
+        let people = people("access");
+        let planned = |filter: Filter| people.lock().unwrap().plan(&filter);
+
+        let equal = planned(key(Conditional::Equal, 2));
+        assert!(matches!(equal.access, Access::PrimaryIndex((Bound::Included(2), Bound::Included(2)))));
+        assert_eq!(equal.estimated_rows, 1);
+
+        let range = planned(key(Conditional::EqualGreater, 1));
+        assert!(matches!(range.access, Access::KeyRangeScan((Bound::Included(1), Bound::Unbounded))));
+        assert_eq!(range.estimated_rows, 3);
+
+        let indexed = planned(city("york"));
+        assert!(matches!(&indexed.access, Access::SecondaryIndex(name) if name == "by_city"));
+        assert_eq!(indexed.estimated_rows, 1);
+
+        let either = planned(Filter::Or(vec![key(Conditional::Equal, 1), city("york")]));
+        assert!(matches!(either.access, Access::FullScan));
+        assert_eq!(either.estimated_rows, 3);
+    }
+
+    #[test]
+    fn explain_returns_the_logical_and_physical_plan_instead_of_records() {
+        let people = people("explained");
+        let options = ReadOptions { explain: true, limit: Some(1), ..ReadOptions::default() };
+        let explained = serde_json::from_str::<Value>(&people.lock().unwrap().query_search_columns(&key(Conditional::Equal, 2), &options).unwrap()).unwrap();
+        let plan = &explained["plan"];
+        assert_eq!(plan["table"], json!("explained"));
+        assert_eq!(plan["logical"]["limit"], json!(1));
+        assert_eq!(plan["logical"]["offset"], json!(0));
+        assert_eq!(plan["logical"]["filter"], json!(["id", "==", 2]));
+        assert_eq!(plan["physical"]["access"], json!({ "type": "index_lookup", "index": "primary", "range": { "from": [">=", "2"], "to": ["<=", "2"] } }));
+        assert_eq!(plan["physical"]["parts"], json!([0]));
+        assert_eq!(plan["physical"]["skipped_parts"], json!([]));
+        assert_eq!(plan["physical"]["checked_rows"], json!(1));
+        assert_eq!(plan["estimated_rows"], json!(1));
+        assert!(explained.get("records").is_none());
+    }
+}
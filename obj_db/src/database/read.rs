@@ -0,0 +1,140 @@
+use std::cmp::Ordering;
+use super::record::Record;
+
+/*
+ * MARK: OrderBy
+ * one column of an order by, nulls are placed before or after every value regardless of direction
+ */
+#[derive(Debug, Clone)]
+pub struct OrderBy {
+    pub column: String,
+    pub descending: bool,
+    pub nulls_first: bool,
+}
+
+impl OrderBy {
+    /*
+     * parse the direction and null placement words that follow the column name,
+     * ascending puts nulls last and descending puts them first unless told otherwise
+     */
+    pub fn parse(column: String, modifiers: &[&str]) -> Result<Self, String> {
+        let mut descending = false;
+        let mut nulls = None;
+        for modifier in modifiers {
+            match &modifier.to_uppercase().replace('_', " ")[..] {
+                "ASC" => descending = false,
+                "DESC" => descending = true,
+                "NULLS FIRST" => nulls = Some(true),
+                "NULLS LAST" => nulls = Some(false),
+                _ => return Err(["order modifier ", modifier, " not recognised"].concat())
+            }
+        }
+        Ok(OrderBy { column, descending, nulls_first: nulls.unwrap_or(descending) })
+    }
+
+    pub fn compare(&self, a: &Record, b: &Record) -> Ordering {
+        let a = a.columns.iter().find(|col| col.name() == self.column);
+        let b = b.columns.iter().find(|col| col.name() == self.column);
+        let (a_null, b_null) = (a.is_none_or(|a| a.is_null()), b.is_none_or(|b| b.is_null()));
+        match (a_null, b_null) {
+            (true, true) => Ordering::Equal,
+            (true, false) => match self.nulls_first { true => Ordering::Less, false => Ordering::Greater },
+            (false, true) => match self.nulls_first { true => Ordering::Greater, false => Ordering::Less },
+            (false, false) => {
+                let ordering = a.unwrap().order_data(b.unwrap());
+                match self.descending { true => ordering.reverse(), false => ordering }
+            }
+        }
+    }
+}
+
+/*
+ * MARK: Cursor
+ * where a paged read stopped, the part it was scanning and the primary key of the last record returned,
+ * handed to clients as an opaque hex token
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cursor {
+    pub part: u32,
+    pub key: u128,
+}
+
+impl Cursor {
+    pub fn encode(&self) -> String {
+        format!("{:08X}{:032X}", self.part, self.key)
+    }
+
+    pub fn decode(token: &str) -> Result<Self, String> {
+        let invalid = || ["cursor ", token, " is not valid"].concat();
+        match (token.len(), token.is_ascii()) {
+            (40, true) => Ok(Cursor {
+                part: u32::from_str_radix(&token[..8], 16).map_err(|_| invalid())?,
+                key: u128::from_str_radix(&token[8..], 16).map_err(|_| invalid())?,
+            }),
+            _ => Err(invalid())
+        }
+    }
+}
+
+/*
+ * MARK: ReadOptions
+ * everything about a read besides its filter, projection holds (column, output name) pairs
+ * and every column is returned when it is None, a paged read resumes after the cursor when one is given
+ * and an explained read returns its plan instead of running
+ */
+#[derive(Debug, Clone, Default)]
+pub struct ReadOptions {
+    pub projection: Option<Vec<(String, String)>>,
+    pub order_by: Vec<OrderBy>,
+    pub limit: Option<usize>,
+    pub offset: usize,
+    pub paged: bool,
+    pub after: Option<Cursor>,
+    pub explain: bool,
+}
+
+impl ReadOptions {
+    /*
+     * the columns parts have to copy out of matching records, the projected ones and any needed for ordering
+     */
+    pub fn columns(&self) -> Option<Vec<String>> {
+        self.projection.as_ref().map(|projection| {
+            let mut columns = projection.iter().map(|(column, _)| column.clone()).collect::<Vec<String>>();
+            for order in self.order_by.iter() {
+                if !columns.contains(&order.column) {
+                    columns.push(order.column.clone());
+                }
+            }
+            columns
+        })
+    }
+
+    pub fn compare(&self, a: &Record, b: &Record) -> Ordering {
+        self.order_by.iter().map(|order| order.compare(a, b)).find(|ordering| *ordering != Ordering::Equal).unwrap_or(Ordering::Equal)
+    }
+
+    /*
+     * MARK: apply
+     * sort, skip the offset, cut at the limit and project what is left
+     */
+    pub fn apply(&self, mut records: Vec<Record>) -> Vec<Record> {
+        if !self.order_by.is_empty() {
+            records.sort_by(|a, b| self.compare(a, b));
+        }
+        records.into_iter()
+            .skip(self.offset)
+            .take(self.limit.unwrap_or(usize::MAX))
+            .map(|record| match &self.projection { Some(projection) => record.project(projection), None => record })
+            .collect::<Vec<Record>>()
+    }
+
+    /*
+     * without an order the scan can stop once this many records have matched
+     */
+    pub fn scan_limit(&self) -> Option<usize> {
+        match self.order_by.is_empty() {
+            true => self.limit.map(|limit| limit + self.offset),
+            false => None
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use std::fmt::Display;
+use std::{cmp::Ordering, fmt::Display};
 
 use super::cell;
 use super::cell::CellValue;
@@ -6,23 +6,82 @@ use super::conditional;
 use serde::{Deserialize, Serialize};
 use get_size::GetSize;
 
+/* 
+ * created and deleted are the ids of the table writes that made and replaced this version of the record,
+ * snapshots only live as long as the table is in memory so neither is written to the part file
+ */
 #[derive(Clone, Debug, Default, GetSize, Serialize, Deserialize)]
 pub struct Record {
-    pub columns: Vec<cell::CellValue>
+    pub columns: Vec<cell::CellValue>,
+    #[serde(skip)]
+    pub created: u64,
+    #[serde(skip)]
+    pub deleted: Option<u64>,
 }
 
 impl Record {
+    pub fn new(columns: Vec<cell::CellValue>) -> Record {
+        Record { columns, created: 0, deleted: None }
+    }
+
+    /* 
+     * whether a snapshot of the given version sees this version of the record
+     */
+    pub fn visible(&self, version: u64) -> bool {
+        self.created <= version && self.deleted.is_none_or(|deleted| deleted > version)
+    }
+
+    /* 
+     * comparisons look only at the data of the cell, a null cell never compares as equal, greater or smaller than anything
+     * so it only matches IS NULL, LIKE and regex patterns are matched against the data as text
+     */
     pub fn query_check(&self, condition: &conditional::Condition) -> bool {
-        match condition.conditional {
-            conditional::Conditional::NotEqual     => match self.columns.iter().find(|col| col.name() == condition.target_column) { Some(cell) => *cell != condition.value, None => false },
-            conditional::Conditional::Equal        => match self.columns.iter().find(|col| col.name() == condition.target_column) { Some(cell) => *cell == condition.value, None => false },
-            conditional::Conditional::EqualGreater => match self.columns.iter().find(|col| col.name() == condition.target_column) { Some(cell) => *cell >= condition.value, None => false },
-            conditional::Conditional::EqualSmaller => match self.columns.iter().find(|col| col.name() == condition.target_column) { Some(cell) => *cell <= condition.value, None => false },
-            conditional::Conditional::Greater      => match self.columns.iter().find(|col| col.name() == condition.target_column) { Some(cell) => *cell >  condition.value, None => false },
-            conditional::Conditional::Smaller      => match self.columns.iter().find(|col| col.name() == condition.target_column) { Some(cell) => *cell <  condition.value, None => false },
+        let cell = match condition.conditional {
+            conditional::Conditional::All => return true,
+            _ => match self.columns.iter().find(|col| col.name() == condition.target_column) {
+                Some(cell) => cell,
+                None => return false
+            }
+        };
+        match &condition.conditional {
+            conditional::Conditional::NotEqual     => matches!(cell.compare_data(&condition.value), Some(Ordering::Less | Ordering::Greater)),
+            conditional::Conditional::Equal        => matches!(cell.compare_data(&condition.value), Some(Ordering::Equal)),
+            conditional::Conditional::EqualGreater => matches!(cell.compare_data(&condition.value), Some(Ordering::Equal | Ordering::Greater)),
+            conditional::Conditional::EqualSmaller => matches!(cell.compare_data(&condition.value), Some(Ordering::Equal | Ordering::Less)),
+            conditional::Conditional::Greater      => matches!(cell.compare_data(&condition.value), Some(Ordering::Greater)),
+            conditional::Conditional::Smaller      => matches!(cell.compare_data(&condition.value), Some(Ordering::Less)),
+            conditional::Conditional::In(values)   => values.iter().any(|value| matches!(cell.compare_data(value), Some(Ordering::Equal))),
+            conditional::Conditional::Between(low, high) => matches!(cell.compare_data(low), Some(Ordering::Equal | Ordering::Greater)) && matches!(cell.compare_data(high), Some(Ordering::Equal | Ordering::Less)),
+            conditional::Conditional::Like(pattern) | conditional::Conditional::Regex(pattern) => !cell.is_null() && pattern.is_match(&cell.data_str()),
+            conditional::Conditional::IsNull       => cell.is_null(),
+            conditional::Conditional::IsNotNull    => !cell.is_null(),
             conditional::Conditional::All          => true,
         }
     }
+
+    /* 
+     * overwrite the assigned columns, adding any the record does not have yet
+     */
+    pub fn assign(&mut self, assignments: &Record) {
+        assignments.columns.iter().for_each(|assignment| match self.columns.iter_mut().find(|col| col.name() == assignment.name()) {
+            Some(col) => *col = assignment.clone(),
+            None => self.columns.push(assignment.clone())
+        });
+    }
+
+    /* 
+     * copy only the named columns, keeping their names
+     */
+    pub fn select(&self, columns: &[String]) -> Record {
+        Record::new(self.columns.iter().filter(|col| columns.iter().any(|column| column == col.name())).cloned().collect::<Vec<CellValue>>())
+    }
+
+    /* 
+     * copy only the requested columns in the requested order, each renamed to its output name
+     */
+    pub fn project(&self, projection: &[(String, String)]) -> Record {
+        Record::new(projection.iter().filter_map(|(column, alias)| self.columns.iter().find(|col| col.name() == column).map(|col| col.renamed(alias))).collect::<Vec<CellValue>>())
+    }
 }
 
 impl Display for Record {
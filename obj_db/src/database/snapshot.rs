@@ -0,0 +1,134 @@
+use std::sync::Arc;
+use serde_json::json;
+use super::aggregate::{Aggregate, Grouping};
+use super::cell::CellValue;
+use super::conditional::Filter;
+use super::plan::Plan;
+use super::read::{Cursor, ReadOptions};
+use super::record::Record;
+
+/*
+ * MARK: PartSnapshot
+ * the records of a part as they were when the snapshot was taken, shared with the part until it is next
+ * written, history holds the versions a committing transaction replaced but has not yet published
+ */
+pub struct PartSnapshot {
+    pub index: u32,
+    pub records: Arc<Vec<Record>>,
+    pub history: Arc<Vec<Record>>,
+}
+
+/*
+ * MARK: Snapshot
+ * a read of a table taken under its lock and scanned after the lock is released, every version created
+ * after the snapshot version or deleted at or before it is left out so the scan sees one committed state
+ */
+pub struct Snapshot {
+    pub version: u64,
+    pub plan: Plan,
+    pub parts: Vec<PartSnapshot>,
+}
+
+impl Snapshot {
+    /*
+     * MARK: read
+     * hand on the visible matching records of each part of the plan in turn, stopping early when told to
+     */
+    pub fn read(&self, filter: &Filter, columns: Option<&[String]>, mut each: impl FnMut(Vec<Record>) -> bool) {
+        for part in self.parts.iter() {
+            let records = self.matching(part, filter).into_iter()
+                .map(|record| match columns { Some(columns) => record.select(columns), None => record.clone() })
+                .collect::<Vec<Record>>();
+            if !each(records) {
+                break;
+            }
+        }
+    }
+
+    /*
+     * the visible records of the part that match the filter, the slots of an index lookup point into the
+     * records and the history is always checked whole
+     */
+    fn matching<'s>(&self, part: &'s PartSnapshot, filter: &Filter) -> Vec<&'s Record> {
+        let live = match self.plan.parts.get(&part.index) {
+            Some(Some(slots)) => slots.iter().filter_map(|slot| part.records.get(*slot as usize)).collect::<Vec<&Record>>(),
+            _ => part.records.iter().collect::<Vec<&Record>>()
+        };
+        live.into_iter()
+            .chain(part.history.iter())
+            .filter(|record| record.visible(self.version) && filter.evaluate(record))
+            .collect::<Vec<&Record>>()
+    }
+
+    /*
+     * MARK: Query search in columns
+     * the unpaged read of Table::query_search_columns over the snapshot, the columns are already checked
+     */
+    pub fn query_search_columns(&self, filter: &Filter, options: &ReadOptions) -> String {
+        let columns = options.columns();
+        let scan_limit = options.scan_limit().unwrap_or(usize::MAX);
+        let mut res = vec![];
+        self.read(filter, columns.as_deref(), |records| {
+            res.extend(records);
+            res.len() < scan_limit
+        });
+        format!("{{{}}}", options.apply(res).iter().map(|record| record.to_string()).collect::<Vec<String>>().join(", "))
+    }
+
+    /*
+     * MARK: Query search page
+     * the paged read of Table::query_search_page, parts in index order and the records of each in key order,
+     * resuming just after the cursor key so a cursor whose record has since been deleted still resumes
+     * after every smaller key of its part
+     */
+    pub fn query_search_page(&self, filter: &Filter, options: &ReadOptions, columns: Option<&[String]>, key_column: &str, limit: usize) -> String {
+        let key_of = |record: &Record| match record.columns.iter().find(|col| col.name() == key_column) {
+            Some(CellValue::ULong { data: Some(key), .. }) => Some(*key),
+            _ => None
+        };
+        let mut parts = self.parts.iter().filter(|part| options.after.is_none_or(|after| part.index >= after.part)).collect::<Vec<&PartSnapshot>>();
+        parts.sort_by_key(|part| part.index);
+
+        let mut page: Vec<Record> = vec![];
+        let mut last: Option<Cursor> = None;
+        let mut more = false;
+        for part in parts {
+            let mut records = self.matching(part, filter).into_iter()
+                .filter_map(|record| key_of(record).map(|key| (key, record)))
+                .filter(|(key, _)| options.after.is_none_or(|after| after.part != part.index || *key > after.key))
+                .collect::<Vec<(u128, &Record)>>();
+            records.sort_by_key(|(key, _)| *key);
+            for (key, record) in records {
+                if page.len() == limit {
+                    more = true;
+                    break;
+                }
+                page.push(match columns { Some(columns) => record.select(columns), None => record.clone() });
+                last = Some(Cursor { part: part.index, key });
+            }
+            if more {
+                break;
+            }
+        }
+
+        let records = options.apply(page).iter().map(|record| record.to_string()).collect::<Vec<String>>().join(", ");
+        let cursor = match (more, last) {
+            (true, Some(last)) => json!(last.encode()),
+            _ => serde_json::Value::Null
+        };
+        format!("{{\"records\": [{}], \"cursor\": {}}}", records, cursor)
+    }
+
+    /*
+     * MARK: Query aggregate
+     * fold the visible matching records into their groups, columns are the ones Table::aggregate_columns checked
+     */
+    pub fn query_aggregate(&self, filter: &Filter, columns: &[String], group_by: &[String], aggregates: &[Aggregate]) -> String {
+        let mut grouping = Grouping::new(group_by, aggregates);
+        self.read(filter, Some(columns), |records| {
+            records.iter().for_each(|record| grouping.add(record));
+            true
+        });
+        json!({ "rows": grouping.rows() }).to_string()
+    }
+}
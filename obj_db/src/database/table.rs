@@ -1,7 +1,8 @@
-use std::{collections::HashMap, env, fs::{self, File}, io::{Read, Write}, iter::Map, ops::Deref, path::Path, ptr::null};
+use std::{collections::{BTreeMap, HashMap, HashSet}, env, fs::{self, File}, io::{Read, Write}, iter::Map, ops::{Deref, RangeBounds}, path::Path, ptr::null, sync::{Arc, Mutex, MutexGuard, TryLockError}};
+use bincode::Options;
 use crate::database::part::Part;
 
-use super::{cell::{self, Cell, CellValue}, conditional, part, record};
+use super::{aggregate::{Aggregate, AggregateFn, Grouping}, cell::{self, Cell, CellValue}, conditional, constraint::ConstraintViolation, index::{PrimaryIndex, SecondaryIndex}, join, migration::{self, Migration}, part, plan::{Access, Plan}, read::ReadOptions, record, snapshot::{PartSnapshot, Snapshot}, wal};
 use get_size::GetSize;
 use serde_json::{Value, json};
 
@@ -9,10 +10,21 @@ pub struct Table {
     pub name: String,
     pub directory: String,
     pub auto_increment: bool,
+    pub next_key: u128,
     pub column_definition: Vec<cell::Cell>,
     pub records: Vec<part::Part>,
+    pub primary_index: Option<PrimaryIndex>,
+    pub secondary_indexes: Vec<SecondaryIndex>,
+    pub wal: Option<Arc<Mutex<wal::WriteAheadLog>>>,
+    pub version: u64,
+    pub committed: u64,
 }
 
+/* 
+ * what the definition file holds, the auto increment flag, the key counter, the columns and each secondary index
+ */
+type Definition = (bool, u128, Vec<cell::Cell>, Vec<(String, Vec<String>)>);
+
 /* 
  * some of the code used here was copied from the hex crate which was published under the MIT licence
 Copyright (c) 2013-2014 The Rust Project Developers.
@@ -39,28 +51,22 @@ SOFTWARE.
 impl Table {
     /* 
      * MARK: new
-     *                                                            cname   dtype   default value   nullable unique foreign key
+     *                                                            cname   dtype   default value   nullable unique foreign key               on delete
      */
-    pub fn new(db_name: String, table_name: String, columns: Vec<(String, String, Option<String>, bool, bool, Option<(String, String)>)>, ai: bool) -> Self {
+    pub fn new(db_name: String, table_name: String, columns: Vec<(String, String, Option<String>, bool, bool, Option<(String, String)>, cell::ReferentialAction)>, ai: bool) -> Self {
         println!("build new table {table_name}");
         let new_table = Table {
             name: table_name,
             directory: "".to_owned(),
             auto_increment: ai,
-            column_definition: columns.into_iter().enumerate().map(|(i, (col_name, data_type, default, nullable, unique, pkey))| {
-                    match &data_type[..] {
-                        "String" => cell::Cell::CellDef { name: col_name.to_string(), index: i as u16, ctype: cell::CellValue::String { name: col_name.to_owned(), data: match &default { Some(val) => Some(val.to_owned()), None => None } },                                                                                                                                                                                                                                                   default: match default { Some(_) => true, None => false }, not_null: nullable, unique: unique, primary_key: match i { 0 => true, _ => false }, foreign_key: match &pkey { Some(_) => pkey, None => None } },
-                        "Bool" =>   cell::Cell::CellDef { name: col_name.to_string(), index: i as u16, ctype: cell::CellValue::Bool   { name: col_name.to_owned(), data: match &default { Some(val) => match val.parse::<bool>() { Ok(bool) => Some(bool),  _ => None }, None => None } },                                                                                                                                                                                                 default: match default { Some(_) => true, None => false }, not_null: nullable, unique: unique, primary_key: match i { 0 => true, _ => false }, foreign_key: match &pkey { Some(_) => pkey, None => None } },
-                        "UInt" =>   cell::Cell::CellDef { name: col_name.to_string(), index: i as u16, ctype: cell::CellValue::UInt   { name: col_name.to_owned(), data: match &default { Some(val) => match val.parse::<u32>()  { Ok(int) =>   Some(int),   _ => None }, None => None } },                                                                                                                                                                                                 default: match default { Some(_) => true, None => false }, not_null: nullable, unique: unique, primary_key: match i { 0 => true, _ => false }, foreign_key: match &pkey { Some(_) => pkey, None => None } },
-                        "ULong" =>  cell::Cell::CellDef { name: col_name.to_string(), index: i as u16, ctype: cell::CellValue::ULong  { name: col_name.to_owned(), data: match &default { Some(val) => match val.parse::<u128>() { Ok(int) =>  Some(int),   _ => None }, None => None } },                                                                                                                                                                                                 default: match default { Some(_) => true, None => false }, not_null: nullable, unique: unique, primary_key: match i { 0 => true, _ => false }, foreign_key: match &pkey { Some(_) => pkey, None => None } },
-                        "IInt" =>   cell::Cell::CellDef { name: col_name.to_string(), index: i as u16, ctype: cell::CellValue::IInt   { name: col_name.to_owned(), data: match &default { Some(val) => match val.parse::<i32>()  { Ok(int) =>   Some(int),   _ => None }, None => None } },                                                                                                                                                                                                 default: match default { Some(_) => true, None => false }, not_null: nullable, unique: unique, primary_key: match i { 0 => true, _ => false }, foreign_key: match &pkey { Some(_) => pkey, None => None } },
-                        "ILong" =>  cell::Cell::CellDef { name: col_name.to_string(), index: i as u16, ctype: cell::CellValue::ILong  { name: col_name.to_owned(), data: match &default { Some(val) => match val.parse::<i128>() { Ok(int) =>  Some(int),   _ => None }, None => None } },                                                                                                                                                                                                 default: match default { Some(_) => true, None => false }, not_null: nullable, unique: unique, primary_key: match i { 0 => true, _ => false }, foreign_key: match &pkey { Some(_) => pkey, None => None } },
-                        "Float" =>  cell::Cell::CellDef { name: col_name.to_string(), index: i as u16, ctype: cell::CellValue::Float  { name: col_name.to_owned(), data: match &default { Some(val) => match val.parse::<f64>()  { Ok(float) => Some(float), _ => None }, None => None } },                                                                                                                                                                                                 default: match default { Some(_) => true, None => false }, not_null: nullable, unique: unique, primary_key: match i { 0 => true, _ => false }, foreign_key: match &pkey { Some(_) => pkey, None => None } },
-                        "Bytes" =>  cell::Cell::CellDef { name: col_name.to_string(), index: i as u16, ctype: cell::CellValue::Bytes  { name: col_name.to_owned(), data: match &default { Some(val) => Some(<String as AsRef<[u8]>>::as_ref(&val).chunks(2).map(|pair| match pair[0] { b'A'..=b'F' => pair[0]-b'A'+10, _ => pair[0] } << 4 |  match pair[1] { b'A'..=b'F' => pair[1]-b'A'+10, _ => pair[1] }).map(|a| a as u8).collect::<Vec<u8>>()), _ => None } }, default: match default { Some(_) => true, None => false }, not_null: nullable, unique: unique, primary_key: match i { 0 => true, _ => false }, foreign_key: match &pkey { Some(_) => pkey, None => None } },
-                        _ =>        cell::Cell::CellDef { name: col_name.to_string(), index: i as u16, ctype: cell::CellValue::String { name: col_name.to_owned(), data: match &default { Some(val) => Some(val.to_owned()), None => None } },                                                                                                                                                                                                                                                   default: match default { Some(_) => true, None => false }, not_null: nullable, unique: unique, primary_key: match i { 0 => true, _ => false }, foreign_key: match &pkey { Some(_) => pkey, None => None } },
-                    }
-                }).collect::<Vec<cell::Cell>>(), 
+            next_key: 1,
+            column_definition: columns.into_iter().enumerate().map(|(i, column)| Table::column_def(i, column)).collect::<Vec<cell::Cell>>(),
             records: vec![],
+            primary_index: None,
+            secondary_indexes: vec![],
+            wal: None,
+            version: 0,
+            committed: 0,
         };
         match new_table.init_dir(db_name) {
             Ok(y) => y,
@@ -68,58 +74,101 @@ impl Table {
         }
     }
 
+    /* 
+     * the name of the primary key column when it is a ULong that parts and the primary index can key by
+     */
+    pub fn key_column(column_definition: &[Cell]) -> Option<String> {
+        match column_definition.first() {
+            Some(Cell::CellDef { name, ctype: CellValue::ULong { .. }, .. }) => Some(name.clone()),
+            _ => None
+        }
+    }
+
+    /* 
+     * MARK: column definition
+     * the definition of the column at index i, the first column is the primary key
+     */
+    pub fn column_def(i: usize, (col_name, data_type, default, nullable, unique, pkey, on_delete): cell::ColumnDef) -> cell::Cell {
+        match &data_type[..] {
+            "String" => cell::Cell::CellDef { name: col_name.to_string(), index: i as u16, ctype: cell::CellValue::String { name: col_name.to_owned(), data: match &default { Some(val) => Some(val.to_owned()), None => None } },                                                                                                                                                                                                                                                   default: match default { Some(_) => true, None => false }, not_null: !nullable, unique: unique, primary_key: match i { 0 => true, _ => false }, foreign_key: match &pkey { Some(_) => pkey, None => None }, on_delete: on_delete },
+            "Bool" =>   cell::Cell::CellDef { name: col_name.to_string(), index: i as u16, ctype: cell::CellValue::Bool   { name: col_name.to_owned(), data: match &default { Some(val) => match val.parse::<bool>() { Ok(bool) => Some(bool),  _ => None }, None => None } },                                                                                                                                                                                                 default: match default { Some(_) => true, None => false }, not_null: !nullable, unique: unique, primary_key: match i { 0 => true, _ => false }, foreign_key: match &pkey { Some(_) => pkey, None => None }, on_delete: on_delete },
+            "UInt" =>   cell::Cell::CellDef { name: col_name.to_string(), index: i as u16, ctype: cell::CellValue::UInt   { name: col_name.to_owned(), data: match &default { Some(val) => match val.parse::<u32>()  { Ok(int) =>   Some(int),   _ => None }, None => None } },                                                                                                                                                                                                 default: match default { Some(_) => true, None => false }, not_null: !nullable, unique: unique, primary_key: match i { 0 => true, _ => false }, foreign_key: match &pkey { Some(_) => pkey, None => None }, on_delete: on_delete },
+            "ULong" =>  cell::Cell::CellDef { name: col_name.to_string(), index: i as u16, ctype: cell::CellValue::ULong  { name: col_name.to_owned(), data: match &default { Some(val) => match val.parse::<u128>() { Ok(int) =>  Some(int),   _ => None }, None => None } },                                                                                                                                                                                                 default: match default { Some(_) => true, None => false }, not_null: !nullable, unique: unique, primary_key: match i { 0 => true, _ => false }, foreign_key: match &pkey { Some(_) => pkey, None => None }, on_delete: on_delete },
+            "IInt" =>   cell::Cell::CellDef { name: col_name.to_string(), index: i as u16, ctype: cell::CellValue::IInt   { name: col_name.to_owned(), data: match &default { Some(val) => match val.parse::<i32>()  { Ok(int) =>   Some(int),   _ => None }, None => None } },                                                                                                                                                                                                 default: match default { Some(_) => true, None => false }, not_null: !nullable, unique: unique, primary_key: match i { 0 => true, _ => false }, foreign_key: match &pkey { Some(_) => pkey, None => None }, on_delete: on_delete },
+            "ILong" =>  cell::Cell::CellDef { name: col_name.to_string(), index: i as u16, ctype: cell::CellValue::ILong  { name: col_name.to_owned(), data: match &default { Some(val) => match val.parse::<i128>() { Ok(int) =>  Some(int),   _ => None }, None => None } },                                                                                                                                                                                                 default: match default { Some(_) => true, None => false }, not_null: !nullable, unique: unique, primary_key: match i { 0 => true, _ => false }, foreign_key: match &pkey { Some(_) => pkey, None => None }, on_delete: on_delete },
+            "Float" =>  cell::Cell::CellDef { name: col_name.to_string(), index: i as u16, ctype: cell::CellValue::Float  { name: col_name.to_owned(), data: match &default { Some(val) => match val.parse::<f64>()  { Ok(float) => Some(float), _ => None }, None => None } },                                                                                                                                                                                                 default: match default { Some(_) => true, None => false }, not_null: !nullable, unique: unique, primary_key: match i { 0 => true, _ => false }, foreign_key: match &pkey { Some(_) => pkey, None => None }, on_delete: on_delete },
+            "Bytes" =>  cell::Cell::CellDef { name: col_name.to_string(), index: i as u16, ctype: cell::CellValue::Bytes  { name: col_name.to_owned(), data: match &default { Some(val) => Some(<String as AsRef<[u8]>>::as_ref(&val).chunks(2).map(|pair| match pair[0] { b'A'..=b'F' => pair[0]-b'A'+10, _ => pair[0] } << 4 |  match pair[1] { b'A'..=b'F' => pair[1]-b'A'+10, _ => pair[1] }).map(|a| a as u8).collect::<Vec<u8>>()), _ => None } }, default: match default { Some(_) => true, None => false }, not_null: !nullable, unique: unique, primary_key: match i { 0 => true, _ => false }, foreign_key: match &pkey { Some(_) => pkey, None => None }, on_delete: on_delete },
+            _ =>        cell::Cell::CellDef { name: col_name.to_string(), index: i as u16, ctype: cell::CellValue::String { name: col_name.to_owned(), data: match &default { Some(val) => Some(val.to_owned()), None => None } },                                                                                                                                                                                                                                                   default: match default { Some(_) => true, None => false }, not_null: !nullable, unique: unique, primary_key: match i { 0 => true, _ => false }, foreign_key: match &pkey { Some(_) => pkey, None => None }, on_delete: on_delete },
+        }
+    }
+
     /* 
      * MARK: build from directory
+     * corrupt parts are quarantined and the reasons returned alongside the table, a part that could not be
+     * read for any other reason fails the whole table
      */
-    pub fn build_from_dir(table_dir: String) -> Result<Self, String> {
+    pub fn build_from_dir(table_dir: String) -> Result<(Self, Vec<String>), String> {
         // println!("build table from dir {}", &table_dir.clone());
-        let parts = match fs::read_dir(&table_dir.clone()) {
+        let mut parts = match fs::read_dir(&table_dir.clone()) {
             Ok(e)  => e.into_iter()
                                 .map(|a| match a {
-                                    Ok(e) => e.file_name().into_string().unwrap(),
+                                    Ok(e) => e.file_name().into_string().unwrap_or_default(),
                                     Err(e) => "".to_string(),
                                 })
-                                .filter(|b| !b.is_empty())
-                                .filter(|c| c.starts_with("p"))
-                                .collect::<Vec<String>>(),
+                                .filter_map(|b| match b.strip_prefix('p').map(|index| u32::from_str_radix(index, 16)) {
+                                    Some(Ok(index)) => Some((index, b)),
+                                    _ => None
+                                })
+                                .collect::<Vec<(u32, String)>>(),
             Err(e) => return Err([e.to_string(), "unable to find database directory".to_string()].concat()),
         };
+        parts.sort_by_key(|(index, _)| *index);
         println!("build table from dir {}", &table_dir.clone());
 
-        let (ai, coldefs): (bool, Vec<Cell>) = match File::open([&table_dir.clone(), ".def"].join("\\")) {
+        let (ai, next_key, coldefs, indexes) = match File::open([&table_dir.clone(), ".def"].join("\\")) {
             Ok(mut e) => {
                 let mut buf: Vec<u8> = vec![];
-                e.read_to_end(&mut buf).unwrap();
-                match bincode::deserialize(&buf) {
-                    Ok(e) => e,
-                    Err(e) => {
-                        println!("{}", e.to_string());
-                        (false, vec![])
-                    }
-                }
+                e.read_to_end(&mut buf).map_err(|e| ["unable to read table definition file\n".to_string(), e.to_string()].concat())?;
+                Table::decode_def(&buf).map_err(|e| ["table definition in ", &table_dir, " is not in any known layout: ", &e].concat())?
             },
-            Err(e) => (false, vec![])
+            Err(e) => (false, 1, vec![], vec![])
         };
 
-        Ok(Table {
+        let mut errors = vec![];
+        let key_column = Table::key_column(&coldefs);
+        let mut records = vec![];
+        for (i, a) in parts {
+            let part_path = [&table_dir[..], &a[..]].join("/");
+            match part::Part::load_from_dir(part_path.clone(), 4096, i, key_column.clone()) {
+                Ok(b) => records.push(b),
+                Err(part::PartLoadError::Corrupt(e)) => match part::Part::quarantine(&part_path) {
+                    Ok(quarantine_path) => errors.push([e, " (quarantined to ".to_owned(), quarantine_path, ")".to_owned()].concat()),
+                    Err(qe) => errors.push([e, " (".to_owned(), qe, ")".to_owned()].concat())
+                },
+                Err(part::PartLoadError::Unreadable(e)) => return Err(e)
+            }
+        }
+        let mut table = Table {
             name: match &table_dir.rsplit("\\").next() { Some(e) => e.to_string(), None => table_dir.split("\\").last().unwrap().to_owned() },
             directory: table_dir.clone(),
             auto_increment: ai,
+            next_key,
             column_definition: coldefs,
-            records: parts.into_iter()
-                            .enumerate()
-                            .map(|(i, a)| match part::Part::load_from_dir([&table_dir[..], &a[..]].join("\\"), 4096, i as u32 ) {
-                                Ok(b) => b, 
-                                Err(_) => part::Part { 
-                                    index: i as u32,
-                                    size: 4096,
-                                    full: false,
-                                    directory: "".to_owned(),
-                                    key_range: vec![],
-                                    records: vec![],
-                            }}).filter(|c| !c.directory.is_empty())
-                            .collect::<Vec<part::Part>>(),
-        })
+            records,
+            primary_index: None,
+            secondary_indexes: vec![],
+            wal: None,
+            version: 0,
+            committed: 0,
+        };
+        // a crash between saving a part and saving the definition can leave the counter behind the stored keys
+        table.next_key = table.records.iter().flat_map(|part| part.key_range.iter().flatten()).map(|key| key.saturating_add(1)).fold(table.next_key, u128::max);
+        table.primary_index = Some(PrimaryIndex::load([&table_dir, ".pk"].join("\\"), &table.records));
+        table.secondary_indexes = indexes.into_iter().map(|(name, columns)| {
+            let path = table.secondary_index_path(&name);
+            SecondaryIndex::load(name, columns, path, &table.records)
+        }).collect::<Vec<SecondaryIndex>>();
+        Ok((table, errors))
     }
 
     /* 
@@ -140,47 +189,965 @@ impl Table {
             Ok(d) => self.directory = table_dir.clone(),
             Err(e) => return Err((self, ["unable to create table directory\n".to_string(), e.to_string()].concat()))
         }
-        match File::create([&table_dir, ".def"].join("\\")) {
-            Ok(mut e) => match e.write_all(&bincode::serialize(&(&self.auto_increment, &self.column_definition)).unwrap()) {
+        self.directory = table_dir.clone();
+        if let Err(e) = self.save_def() {
+            return Err((self, e));
+        }
+        self.records.push(Part::new(&table_dir, 0, 4096, Table::key_column(&self.column_definition)));
+        let primary_index = PrimaryIndex::build([&table_dir, ".pk"].join("\\"), &self.records);
+        if let Err(e) = primary_index.save() {
+            return Err((self, e));
+        }
+        self.primary_index = Some(primary_index);
+        Ok(self)
+    }
+
+    /* 
+     * MARK: Decode definition
+     * older definitions hold only the auto increment flag and the columns, or those and the key counter, and the
+     * oldest have columns without an on delete action, every layout has to use up the whole file to be taken
+     */
+    fn decode_def(buf: &[u8]) -> Result<Definition, String> {
+        let options = bincode::DefaultOptions::new().with_fixint_encoding();
+        options.deserialize::<Definition>(buf)
+            .or_else(|_| options.deserialize::<(bool, u128, Vec<Cell>)>(buf).map(|(ai, next_key, coldefs)| (ai, next_key, coldefs, vec![])))
+            .or_else(|_| options.deserialize::<(bool, Vec<Cell>)>(buf).map(|(ai, coldefs)| (ai, 1, coldefs, vec![])))
+            .or_else(|_| options.deserialize::<(bool, Vec<cell::LegacyCell>)>(buf).map(|(ai, coldefs)| (ai, 1, coldefs.into_iter().map(Cell::from).collect(), vec![])))
+            .map_err(|e| e.to_string())
+    }
+
+    /* 
+     * MARK: Save definition
+     * the definition is written to a temporary file and renamed over the old one so the key counter is never torn
+     */
+    pub fn save_def(&self) -> Result<(), String> {
+        let def_path = [&self.directory, ".def"].join("\\");
+        let tmp_path = [&def_path, ".tmp"].concat();
+        let indexes = self.secondary_indexes.iter().map(|index| (index.name.clone(), index.columns.clone())).collect::<Vec<(String, Vec<String>)>>();
+        let def = match bincode::serialize(&(&self.auto_increment, &self.next_key, &self.column_definition, &indexes)) {
+            Ok(def) => def,
+            Err(e) => return Err(["unable to serialise table definition\n".to_string(), e.to_string()].concat())
+        };
+        match File::create(&tmp_path) {
+            Ok(mut e) => match e.write_all(&def).and_then(|_| e.sync_all()) {
                 Ok(_) => {}
-                Err(e) => return Err((self, ["unable to write def to def file\n".to_string(), e.to_string()].concat())),
+                Err(e) => return Err(["unable to write def to def file\n".to_string(), e.to_string()].concat()),
             },
-            Err(e) => return Err((self, ["unable to create table definition file\n".to_string(), e.to_string()].concat()))
+            Err(e) => return Err(["unable to create table definition file\n".to_string(), e.to_string()].concat())
         };
-        self.records.push(Part::new(&table_dir, 0, 4096));
-        self.directory = table_dir;
-        Ok(self)
+        match fs::rename(&tmp_path, &def_path) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(["unable to replace table definition file\n".to_string(), e.to_string()].concat())
+        }
+    }
+
+    /* 
+     * MARK: Assign keys
+     * records without a primary key take the next value of the counter, supplied keys push the counter past them,
+     * the counter is saved before any record is written so a restart never hands out a key twice,
+     * a key with nothing after it for the counter to move on to is refused and the counter left as it was
+     */
+    fn assign_keys(&mut self, records: &mut [record::Record]) -> Result<Vec<String>, String> {
+        let key_name = match self.column_definition.first() {
+            Some(Cell::CellDef { name, ctype: CellValue::ULong { .. }, .. }) => name.clone(),
+            _ => return Ok(records.iter().map(|record| record.columns.first().map(|col| col.data_str()).unwrap_or_default()).collect())
+        };
+        let start = self.next_key;
+        let mut keys = vec![];
+        for record in records.iter_mut() {
+            let assigned = match record.columns.iter_mut().find(|col| col.name() == key_name) {
+                Some(CellValue::ULong { data: Some(key), .. }) => self.key_after(*key).map(|after| self.next_key = self.next_key.max(after)),
+                Some(CellValue::ULong { data, .. }) if self.auto_increment => self.key_after(self.next_key).map(|after| {
+                    *data = Some(self.next_key);
+                    self.next_key = after;
+                }),
+                None if self.auto_increment => self.key_after(self.next_key).map(|after| {
+                    record.columns.insert(0, CellValue::ULong { name: key_name.clone(), data: Some(self.next_key) });
+                    self.next_key = after;
+                }),
+                _ => Ok(())
+            };
+            if let Err(e) = assigned {
+                self.next_key = start;
+                return Err(e);
+            }
+            keys.push(record.columns.iter().find(|col| col.name() == key_name).map(|col| col.data_str()).unwrap_or_default());
+        }
+        if self.next_key != start {
+            self.save_def()?;
+        }
+        Ok(keys)
+    }
+
+    fn key_after(&self, key: u128) -> Result<u128, String> {
+        key.checked_add(1).ok_or_else(|| ["key ", &key.to_string(), " is the largest a ULong can hold and leaves table ", &self.name, " no key to hand out after it"].concat())
     }
 
     /* 
      * MARK: Query search in columns
+     * parts only copy out the columns the options need, the matches are then ordered, paged and projected,
+     * without an order the scan stops as soon as enough records have matched to fill the page
+     */
+    pub fn query_search_columns(&mut self, filter: &conditional::Filter, options: &ReadOptions) -> Result<String, String> {
+        let columns = self.read_columns(options)?;
+        let plan = self.plan(filter);
+        if options.explain {
+            return Ok(plan.explain(&self.name, filter, options).to_string());
+        }
+        if options.paged {
+            return self.query_search_page(filter, options, columns);
+        }
+        let scan_limit = options.scan_limit().unwrap_or(usize::MAX);
+        let mut res = vec![];
+        self.read_plan(&plan, filter, columns.as_deref(), |records| {
+            res.extend(records);
+            res.len() < scan_limit
+        })?;
+        Ok(format!("{{{}}}", options.apply(res).iter().map(|record| record.to_string()).collect::<Vec<String>>().join(", ")))
+    }
+
+    /* 
+     * the columns a read copies out of each part, every one of them and of the ordering columns has to exist on the table
+     */
+    pub fn read_columns(&self, options: &ReadOptions) -> Result<Option<Vec<String>>, String> {
+        let columns = options.columns();
+        if let Some(column) = columns.iter().flatten().chain(options.order_by.iter().map(|order| &order.column)).find(|column| !self.column_definition.iter().any(|celldef| match celldef { Cell::CellDef { name, .. } => name == *column, _ => false })) {
+            return Err(["column ", column, " does not exist on table ", &self.name].concat());
+        }
+        Ok(columns)
+    }
+
+    /* 
+     * MARK: Snapshot
+     * the plan for the filter and the records of its parts at the last published write, cheap to take as the
+     * parts share their records with it, the table lock only has to be held while it is taken and not while it is read
+     */
+    pub fn snapshot(&self, filter: &conditional::Filter) -> Snapshot {
+        let plan = self.plan(filter);
+        let parts = self.records.iter()
+            .filter(|part| plan.parts.contains_key(&part.index))
+            .map(|part| PartSnapshot { index: part.index, records: Arc::clone(&part.records), history: Arc::clone(&part.history) })
+            .collect::<Vec<PartSnapshot>>();
+        Snapshot { version: self.committed, plan, parts }
+    }
+
+    /* 
+     * MARK: Write ids
+     * each write stamps the versions it creates and replaces with a new id which is published to snapshots once
+     * the write is done, while the log defers the writes of a committing transaction they share one id that is
+     * only published when the transaction is
+     */
+    fn write_id(&mut self) -> u64 {
+        match self.deferring() {
+            true if self.version > self.committed => {},
+            true => self.version += 1,
+            false => {
+                self.publish();
+                self.version += 1;
+            }
+        }
+        self.version
+    }
+
+    fn deferring(&self) -> bool {
+        self.wal.as_ref().is_some_and(|wal| match wal.lock() {
+            Ok(wal) => wal.is_deferring(),
+            Err(e) => panic!("{}", ["shits fucked ".to_owned(), e.to_string()].concat())
+        })
+    }
+
+    fn publish_written(&mut self) {
+        if !self.deferring() {
+            self.publish();
+        }
+    }
+
+    /* 
+     * MARK: Publish
+     * make every write so far visible to new snapshots and collect the versions they replaced, after a
+     * transaction is reverted the reloaded parts no longer hold its writes so publishing its id shows nothing
+     */
+    pub fn publish(&mut self) {
+        self.committed = self.version;
+        let committed = self.committed;
+        self.records.iter_mut().for_each(|part| part.collect_history(committed));
+    }
+
+    /* 
+     * MARK: Plan
+     * every access path the filter allows is costed by the records it would check and the cheapest is chosen,
+     * a scan of only the parts whose keys fall in the key range first so a range reaching every record of its parts
+     * is read part by part rather than slot by slot, then the primary and secondary indexes, and a scan of every part
+     * when nothing narrows the filter, parts whose zone maps rule out a match are left out of every candidate
+     */
+    pub fn plan(&self, filter: &conditional::Filter) -> Plan {
+        let conditions = filter.conjuncts();
+        let key_range = match self.column_definition.first() {
+            Some(Cell::CellDef { name, ctype: CellValue::ULong { .. }, .. }) => PrimaryIndex::key_range(name, &conditions),
+            _ => None
+        };
+        let skipped = self.records.iter().filter(|part| !part.zone_map.may_match(filter)).map(|part| part.index).collect::<Vec<u32>>();
+        let scanned = self.records.iter().filter(|part| !skipped.contains(&part.index)).collect::<Vec<&Part>>();
+        let unskipped = |slots: BTreeMap<u32, Vec<u32>>| slots.into_iter().filter(|(part, _)| !skipped.contains(part)).collect::<BTreeMap<u32, Vec<u32>>>();
+        let mut candidates = vec![];
+        if let Some(range) = key_range {
+            let parts = scanned.iter().filter(|part| part.key_range.iter().flatten().any(|key| range.contains(key))).collect::<Vec<&&Part>>();
+            candidates.push(Plan { access: Access::KeyRangeScan(range), parts: parts.iter().map(|part| (part.index, None)).collect(), estimated_rows: parts.iter().map(|part| part.records.len()).sum(), skipped: vec![] });
+        }
+        if let (Some(range), Some(primary_index)) = (key_range, self.primary_index.as_ref()) {
+            candidates.push(Plan::lookup(Access::PrimaryIndex(range), unskipped(primary_index.lookup(range))));
+        }
+        for index in self.secondary_indexes.iter() {
+            if let Some(slots) = index.lookup(&conditions) {
+                candidates.push(Plan::lookup(Access::SecondaryIndex(index.name.clone()), unskipped(slots)));
+            }
+        }
+        candidates.push(Plan { access: Access::FullScan, parts: scanned.iter().map(|part| (part.index, None)).collect(), estimated_rows: scanned.iter().map(|part| part.records.len()).sum(), skipped: vec![] });
+        Plan { skipped, ..Plan::cheapest(candidates) }
+    }
+
+    /* 
+     * MARK: Read plan
+     * reload each part of the plan in turn and hand its matching records on, stopping early when told to,
+     * a part that fails to reload is an error rather than a silently shorter result
+     */
+    fn read_plan(&mut self, plan: &Plan, filter: &conditional::Filter, columns: Option<&[String]>, mut each: impl FnMut(Vec<record::Record>) -> bool) -> Result<(), String> {
+        for (part_index, slots) in plan.parts.iter() {
+            let part = match self.records.iter_mut().find(|part| part.index == *part_index) {
+                Some(part) => part,
+                None => continue
+            };
+            part.reload()?;
+            let records = match slots {
+                Some(slots) => part.query_search_slots(slots, filter, columns),
+                None => part.query_search_columns(filter, columns)
+            };
+            if !each(records) {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /* 
+     * MARK: Qualified columns
+     * the column templates named table.column, used to check and parse the conditions of a join
+     */
+    pub fn qualified_columns(&self) -> Vec<(String, CellValue)> {
+        self.column_definition.iter().filter_map(|celldef| match celldef {
+            Cell::CellDef { name, ctype, .. } => Some((join::qualified(&self.name, name), ctype.renamed(&join::qualified(&self.name, name)).as_null())),
+            _ => None
+        }).collect::<Vec<(String, CellValue)>>()
+    }
+
+    /* 
+     * whether an equality on the column can be answered from the primary index or a single column index
+     */
+    pub fn indexed(&self, column: &str) -> bool {
+        let primary = matches!(self.column_definition.first(), Some(Cell::CellDef { name, ctype: CellValue::ULong { .. }, .. }) if name == column) && self.primary_index.is_some();
+        primary || self.secondary_indexes.iter().any(|index| index.columns.len() == 1 && index.columns[0] == column)
+    }
+
+    /* 
+     * MARK: Query qualified
+     * every record of the table with its columns named table.column, the build side of a hash join
+     */
+    pub fn query_qualified(&mut self) -> Result<Vec<record::Record>, String> {
+        let mut res = vec![];
+        for part in self.records.iter_mut() {
+            part.reload()?;
+            res.extend(part.records.iter().map(|record| join::qualify(&self.name, record.clone())));
+        }
+        Ok(res)
+    }
+
+    /* 
+     * the records whose column equals the value found through an index, columns named table.column
      */
-    pub fn query_search_columns(&mut self, conditions: &Vec<conditional::Condition>) -> Result<String, String> {
+    pub fn query_qualified_equal(&mut self, column: &str, value: &CellValue) -> Result<Vec<record::Record>, String> {
+        let filter = conditional::Filter::Condition(conditional::Condition { target_column: column.to_owned(), conditional: conditional::Conditional::Equal, value: value.renamed(column), relational: None });
+        let plan = self.plan(&filter);
         let mut res = vec![];
-        let _ = &self.records.iter_mut().for_each(|part| {
-            match part.reload() { 
-                Ok(_) => for record in part.query_search_columns(conditions) {
-                    res.push(record.clone());
-                }, 
-                Err(_) => {} 
+        self.read_plan(&plan, &filter, None, |records| {
+            res.extend(records);
+            true
+        })?;
+        Ok(res.into_iter().map(|record| join::qualify(&self.name, record)).collect::<Vec<record::Record>>())
+    }
+
+    /* 
+     * MARK: Query aggregate
+     * matching records are folded into their group one part at a time, only the grouped and aggregated
+     * columns are copied out of each part, the response holds one row per group
+     */
+    pub fn query_aggregate(&mut self, filter: &conditional::Filter, group_by: &[String], aggregates: &[Aggregate], explain: bool) -> Result<String, String> {
+        let columns = self.aggregate_columns(group_by, aggregates)?;
+        let plan = self.plan(filter);
+        if explain {
+            let mut explained = plan.explain(&self.name, filter, &ReadOptions::default());
+            explained["plan"]["logical"]["group_by"] = json!(group_by);
+            explained["plan"]["logical"]["aggregates"] = json!(aggregates.iter().map(|aggregate| aggregate.alias.clone()).collect::<Vec<String>>());
+            explained["plan"]["estimated_rows"] = json!(match group_by.is_empty() { true => 1, false => plan.estimated_rows.max(1) });
+            return Ok(explained.to_string());
+        }
+        let mut grouping = Grouping::new(group_by, aggregates);
+        self.read_plan(&plan, filter, Some(&columns), |records| {
+            records.iter().for_each(|record| grouping.add(record));
+            true
+        })?;
+        Ok(json!({ "rows": grouping.rows() }).to_string())
+    }
+
+    /* 
+     * the grouped and aggregated columns to copy out of each part, sums and averages need a numeric column
+     */
+    pub fn aggregate_columns(&self, group_by: &[String], aggregates: &[Aggregate]) -> Result<Vec<String>, String> {
+        let mut columns: Vec<String> = group_by.to_vec();
+        for aggregate in aggregates {
+            let column = match &aggregate.column {
+                Some(column) => column,
+                None => continue
+            };
+            match self.column_definition.iter().find_map(|celldef| match celldef { Cell::CellDef { name, ctype, .. } if name == column => Some(ctype), _ => None }) {
+                None => return Err(["column ", column, " does not exist on table ", &self.name].concat()),
+                Some(ctype) if matches!(aggregate.function, AggregateFn::Sum | AggregateFn::Avg) && !ctype.is_numeric() => return Err(["cannot take the ", &aggregate.alias, " of ", ctype.type_name(), " column ", column].concat()),
+                Some(_) => if !columns.contains(column) { columns.push(column.clone()) }
             }
+        }
+        if let Some(column) = group_by.iter().find(|column| !self.column_definition.iter().any(|celldef| match celldef { Cell::CellDef { name, .. } => name == *column, _ => false })) {
+            return Err(["column ", column, " does not exist on table ", &self.name].concat());
+        }
+        Ok(columns)
+    }
+
+    /* 
+     * MARK: Query search page
+     * a cursor read of a snapshot, records are paged by their primary key so records created while a client
+     * is paging land after its cursor instead of shifting the pages it has not read yet,
+     * the response holds the page and the cursor to pass back for the next one, null once the scan is finished
+     */
+    fn query_search_page(&mut self, filter: &conditional::Filter, options: &ReadOptions, columns: Option<Vec<String>>) -> Result<String, String> {
+        let (key_column, limit, columns) = self.page_bounds(options, columns)?;
+        Ok(self.snapshot(filter).query_search_page(filter, options, columns.as_deref(), &key_column, limit))
+    }
+
+    /* 
+     * the key column a cursor read pages by, how many records make a page and the columns copied out with the key among them
+     */
+    pub fn page_bounds(&self, options: &ReadOptions, columns: Option<Vec<String>>) -> Result<(String, usize, Option<Vec<String>>), String> {
+        let key_column = match self.column_definition.first() {
+            Some(Cell::CellDef { name, ctype: CellValue::ULong { .. }, .. }) => name.clone(),
+            _ => return Err(["table ", &self.name, " has no ULong primary key to page by"].concat())
+        };
+        let limit = match (options.limit, options.order_by.is_empty(), options.offset) {
+            (Some(limit), true, 0) if limit > 0 => limit,
+            (None, _, _) | (Some(0), _, _) => return Err("a cursor read needs a limit of at least one record".to_owned()),
+            _ => return Err("a cursor read follows the order records were written in and cannot use order_by or offset".to_owned())
+        };
+        let columns = columns.map(|mut columns| {
+            if !columns.contains(&key_column) {
+                columns.push(key_column.clone());
+            }
+            columns
         });
-        Ok(format!("{{{}}}", res.join(", ")))
+        Ok((key_column, limit, columns))
+    }
+
+    /* 
+     * reload only the parts the plan for the filter reads and return their indexes
+     */
+    fn reload_parts(&mut self, filter: &conditional::Filter) -> Result<Vec<u32>, String> {
+        let plan = self.plan(filter);
+        for part in self.records.iter_mut().filter(|part| plan.parts.contains_key(&part.index)) {
+            part.reload()?;
+        }
+        Ok(plan.parts.into_keys().collect::<Vec<u32>>())
+    }
+
+    /* 
+     * MARK: Save parts
+     * write out the parts holding writes of a committed transaction, the paths are the part files the log deferred
+     */
+    pub fn save_parts(&mut self, paths: &HashSet<String>) -> Result<(), String> {
+        for part in self.records.iter_mut().filter(|part| part.unsaved && paths.contains(&part.directory)) {
+            part.save()?;
+        }
+        Ok(())
+    }
+
+    /* 
+     * MARK: Revert parts
+     * drop the unsaved writes of a transaction that failed to commit by reloading the parts from their files
+     */
+    pub fn revert_parts(&mut self, paths: &HashSet<String>) -> Result<(), String> {
+        let mut reverted = vec![];
+        for part in self.records.iter_mut().filter(|part| paths.contains(&part.directory)) {
+            part.unsaved = false;
+            part.reload()?;
+            reverted.push(part.index);
+        }
+        self.reindex_parts(&reverted)
+    }
+
+    /* 
+     * bring the index entries of the given parts up to date and write the index back to disk
+     */
+    fn reindex_parts(&mut self, parts: &[u32]) -> Result<(), String> {
+        if parts.is_empty() {
+            return Ok(());
+        }
+        for part_index in parts {
+            let part = self.records.iter().find(|part| part.index == *part_index);
+            if let Some(primary_index) = self.primary_index.as_mut() {
+                match part {
+                    Some(part) => primary_index.reindex_part(part),
+                    None => primary_index.remove_part(*part_index)
+                }
+            }
+            for index in self.secondary_indexes.iter_mut() {
+                match part {
+                    Some(part) => index.reindex_part(part),
+                    None => index.remove_part(*part_index)
+                }
+            }
+        }
+        if let Some(primary_index) = self.primary_index.as_ref() {
+            primary_index.save()?;
+        }
+        self.secondary_indexes.iter().try_for_each(|index| index.save())
+    }
+
+    fn secondary_index_path(&self, name: &str) -> String {
+        [&self.directory[..], &[".ix_", name].concat()].join("\\")
+    }
+
+    /* 
+     * MARK: Create index
+     * build a secondary index over the named columns from every part and record it in the table definition
+     */
+    pub fn create_index(&mut self, name: String, columns: Vec<String>) -> Result<String, String> {
+        if self.secondary_indexes.iter().any(|index| index.name == name) {
+            return Err(["index ", &name, " already exists on table ", &self.name].concat());
+        }
+        if columns.is_empty() {
+            return Err("an index needs at least one column".to_owned());
+        }
+        if let Some(column) = columns.iter().find(|column| !self.column_definition.iter().any(|celldef| match celldef { Cell::CellDef { name, .. } => name == *column, _ => false })) {
+            return Err(["column ", column, " does not exist on table ", &self.name].concat());
+        }
+        for part in self.records.iter_mut() {
+            part.reload()?;
+        }
+        let index = SecondaryIndex::build(name.clone(), columns, self.secondary_index_path(&name), &self.records);
+        index.save()?;
+        self.secondary_indexes.push(index);
+        match self.save_def() {
+            Ok(_) => Ok(["index ", &name, " created"].concat()),
+            Err(e) => {
+                self.secondary_indexes.pop().map(|index| index.delete());
+                Err(e)
+            }
+        }
+    }
+
+    /* 
+     * MARK: Drop index
+     */
+    pub fn drop_index(&mut self, name: &str) -> Result<String, String> {
+        let position = match self.secondary_indexes.iter().position(|index| index.name == name) {
+            Some(position) => position,
+            None => return Err(["index ", name, " does not exist on table ", &self.name].concat())
+        };
+        let index = self.secondary_indexes.remove(position);
+        self.save_def()?;
+        index.delete()?;
+        Ok(["index ", name, " dropped"].concat())
+    }
+
+    fn migration_path(&self) -> String {
+        [&self.directory[..], ".migrations"].join("\\")
+    }
+
+    /* 
+     * MARK: Migrate
+     * change one column of the definition and of every record together, the rewritten parts are logged as one
+     * group before the definition is saved and the part files written, a column referenced by a foreign key
+     * keeps its name and type and the primary key column can only be renamed, once applied the migration is
+     * added to the history of the table, own is the entry of this table in tables
+     */
+    pub fn migrate(&mut self, own: &Arc<Mutex<Table>>, migration: &Migration, tables: &[Arc<Mutex<Table>>]) -> Result<String, String> {
+        let position = |column_definition: &[Cell], column: &str| column_definition.iter().position(|celldef| matches!(celldef, Cell::CellDef { name, .. } if name == column));
+        let known = |dtype: &str| matches!(dtype, "String" | "Bool" | "UInt" | "ULong" | "IInt" | "ILong" | "Float" | "Bytes");
+        let target = match migration.column() {
+            Some(column) => match position(&self.column_definition, column) {
+                Some(target) => Some(target),
+                None => return Err(["column ", column, " does not exist on table ", &self.name].concat())
+            },
+            None => None
+        };
+        if let Some(column) = migration.column() {
+            let mut referencing = self.referencing_columns(self).into_iter().map(|(child_column, parent_column, _)| (self.name.clone(), child_column, parent_column)).collect::<Vec<(String, String, String)>>();
+            for table in tables.iter().filter(|table| !Arc::ptr_eq(table, own)) {
+                let child = self.lock_other(table)?;
+                referencing.extend(self.referencing_columns(&child).into_iter().map(|(child_column, parent_column, _)| (child.name.clone(), child_column, parent_column)));
+            }
+            if let Some((child, child_column, _)) = referencing.iter().find(|(_, _, parent_column)| parent_column == column) {
+                return Err(["column ", column, " is referenced by ", child, ".", child_column, " and cannot be changed"].concat());
+            }
+        }
+
+        // the definition after the migration and the cell every record gets for the changed column
+        let mut column_definition = self.column_definition.clone();
+        let changed = match (migration, target) {
+            (Migration::Add { column }, _) => {
+                if position(&column_definition, &column.0).is_some() {
+                    return Err(["column ", &column.0, " already exists on table ", &self.name].concat());
+                }
+                if !known(&column.1) {
+                    return Err(["column type ", &column.1, " not recognised"].concat());
+                }
+                column_definition.push(Table::column_def(column_definition.len(), column.clone()));
+                Some(column_definition.len() - 1)
+            },
+            (Migration::Drop { column } | Migration::Retype { column, .. }, Some(0)) => return Err(["column ", column, " is the primary key of table ", &self.name, " and can only be renamed"].concat()),
+            (Migration::Drop { column }, Some(target)) => {
+                if let Some(index) = self.secondary_indexes.iter().find(|index| index.columns.contains(column)) {
+                    return Err(["column ", column, " is used by index ", &index.name, ", drop the index first"].concat());
+                }
+                column_definition.remove(target);
+                column_definition.iter_mut().enumerate().for_each(|(i, celldef)| if let Cell::CellDef { index, .. } = celldef { *index = i as u16 });
+                None
+            },
+            (Migration::Rename { name, .. }, Some(target)) => {
+                if position(&column_definition, name).is_some() {
+                    return Err(["column ", name, " already exists on table ", &self.name].concat());
+                }
+                if let Cell::CellDef { name: column_name, ctype, .. } = &mut column_definition[target] {
+                    *column_name = name.clone();
+                    *ctype = ctype.renamed(name);
+                }
+                None
+            },
+            (Migration::Retype { column, dtype, conversion }, Some(target)) => {
+                if !known(dtype) {
+                    return Err(["column type ", dtype, " not recognised"].concat());
+                }
+                column_definition[target] = match &column_definition[target] {
+                    Cell::CellDef { foreign_key: Some(_), .. } => return Err(["column ", column, " has a foreign key and cannot be retyped"].concat()),
+                    Cell::CellDef { name, ctype, default, not_null, unique, on_delete, .. } => match Table::column_def(target, (name.clone(), dtype.clone(), None, !not_null, *unique, None, *on_delete)) {
+                        // a default is converted the same way as the records
+                        Cell::CellDef { name, index, ctype: retyped, not_null, unique, primary_key, foreign_key, on_delete, .. } => {
+                            let ctype = match default { true => conversion.convert(ctype, &retyped)?, false => retyped };
+                            Cell::CellDef { name, index, default: !ctype.is_null(), ctype, not_null, unique, primary_key, foreign_key, on_delete }
+                        },
+                        celldef => celldef
+                    },
+                    celldef => celldef.clone()
+                };
+                Some(target)
+            },
+            (_, None) => return Err(["table ", &self.name, " has no column to migrate"].concat())
+        };
+        let template = match changed.map(|changed| &column_definition[changed]) {
+            Some(Cell::CellDef { ctype, .. }) => Some(ctype.clone()),
+            _ => None
+        };
+
+        let mut migrated = vec![];
+        for part in self.records.iter() {
+            migrated.push(part.records.iter().map(|record| {
+                let mut columns = record.columns.clone();
+                match (migration, &template) {
+                    (Migration::Add { .. }, Some(template)) => columns.push(template.clone()),
+                    (Migration::Drop { column }, _) => columns.retain(|col| col.name() != column),
+                    (Migration::Rename { column, name }, _) => columns.iter_mut().filter(|col| col.name() == column).for_each(|col| *col = col.renamed(name)),
+                    (Migration::Retype { column, conversion, .. }, Some(template)) => for col in columns.iter_mut().filter(|col| col.name() == column) {
+                        *col = conversion.convert(col, &template.as_null())?;
+                    },
+                    _ => {}
+                }
+                Ok(record::Record::new(columns))
+            }).collect::<Result<Vec<record::Record>, String>>()?);
+        }
+        if let Some(Cell::CellDef { name, not_null, unique, .. }) = changed.map(|changed| &column_definition[changed]) {
+            let mut taken = HashSet::new();
+            for col in migrated.iter().flatten().filter_map(|record| record.columns.iter().find(|col| col.name() == name)) {
+                if col.is_null() && *not_null {
+                    return Err(ConstraintViolation::NotNull { column: name.clone() }.to_string());
+                }
+                if !col.is_null() && *unique && !taken.insert(col.data_str()) {
+                    return Err(ConstraintViolation::Unique { column: name.clone(), value: col.data_str() }.to_string());
+                }
+            }
+        }
+
+        let previous = std::mem::replace(&mut self.column_definition, column_definition);
+        if let (Migration::Add { .. }, Some(template)) = (migration, &template) {
+            if let Err(violation) = self.check_foreign_keys(&[record::Record::new(vec![template.clone()])], tables) {
+                self.column_definition = previous;
+                return Err(violation.to_string());
+            }
+        }
+        if let Migration::Rename { column, name } = migration {
+            self.secondary_indexes.iter_mut().flat_map(|index| index.columns.iter_mut()).filter(|indexed| *indexed == column).for_each(|indexed| *indexed = name.clone());
+        }
+        // the new definition is on disk before any part is logged so a replayed migration never meets the definition from before it,
+        // a definition that could not be saved left the one on disk as it was
+        if let Err(e) = self.save_def() {
+            self.restore_definition(migration, previous);
+            return Err(["definition of table ", &self.name, " could not be saved and the migration was undone: ", &e].concat());
+        }
+
+        let write = self.write_id();
+        let rows = migrated.iter().map(|records| records.len()).sum::<usize>();
+        let originals = self.records.iter().map(|part| Arc::clone(&part.records)).collect::<Vec<Arc<Vec<record::Record>>>>();
+        let sequence = self.log_migrated(migrated, write);
+        let applied = match &sequence {
+            Ok(sequence) => self.apply_migrated(*sequence),
+            Err(e) => Err(e.clone())
+        };
+        if let Err(e) = applied {
+            // a logged group would replay the migrated parts, so the originals are written back and the group marked applied before the old definition returns
+            let key_column = Table::key_column(&previous);
+            for (part, records) in self.records.iter_mut().zip(originals) {
+                part.records = records;
+                part.key_column = key_column.clone();
+                part.rebuild_key_range();
+                part.save()?;
+            }
+            if let (Some(wal), Ok(Some(sequence))) = (self.wal.as_ref(), sequence) {
+                match wal.lock() {
+                    Ok(mut wal) => wal.log_applied(sequence)?,
+                    Err(e) => panic!("{}", ["shits fucked ".to_owned(), e.to_string()].concat())
+                }
+            }
+            self.restore_definition(migration, previous);
+            self.save_def()?;
+            return Err(["migration of table ", &self.name, " could not be written and was undone: ", &e].concat());
+        }
+        if let Migration::Retype { column, .. } = migration {
+            let rebuilt = self.secondary_indexes.iter().filter(|index| index.columns.contains(column)).map(|index| (index.name.clone(), index.columns.clone(), self.secondary_index_path(&index.name))).collect::<Vec<(String, Vec<String>, String)>>();
+            for (name, columns, path) in rebuilt {
+                let index = SecondaryIndex::build(name.clone(), columns, path, &self.records);
+                index.save()?;
+                if let Some(position) = self.secondary_indexes.iter().position(|index| index.name == name) {
+                    self.secondary_indexes[position] = index;
+                }
+            }
+        }
+        self.publish();
+        let entry = migration::record(&self.migration_path(), migration)?;
+        Ok(json!({ "table": self.name, "migration": entry, "rows": rows }).to_string())
+    }
+
+    /* 
+     * stage the migrated records in their parts and log every part as one group, with a log nothing is written to the part files yet
+     */
+    fn log_migrated(&mut self, migrated: Vec<Vec<record::Record>>, write: u64) -> Result<Option<u64>, String> {
+        if let Some(wal) = self.wal.as_ref() {
+            match wal.lock() {
+                Ok(mut wal) => wal.defer(),
+                Err(e) => panic!("{}", ["shits fucked ".to_owned(), e.to_string()].concat())
+            }
+        }
+        let key_column = Table::key_column(&self.column_definition);
+        let mut staged = Ok(());
+        for (part, records) in self.records.iter_mut().zip(migrated) {
+            part.records = Arc::new(records.into_iter().map(|record| record::Record { created: write, ..record }).collect::<Vec<record::Record>>());
+            part.key_column = key_column.clone();
+            part.rebuild_key_range();
+            staged = part.save_logged(self.wal.as_ref(), wal::LogOperation::Update, vec![]).map(|_| ());
+            if staged.is_err() {
+                break;
+            }
+        }
+        match self.wal.as_ref() {
+            Some(wal) => match wal.lock() {
+                Ok(mut wal) => {
+                    let writes = wal.take_deferred();
+                    staged.and_then(|_| wal.log_writes(writes)).map(Some)
+                },
+                Err(e) => panic!("{}", ["shits fucked ".to_owned(), e.to_string()].concat())
+            },
+            None => staged.map(|_| None)
+        }
+    }
+
+    /* 
+     * write the logged parts to their files and mark the group applied
+     */
+    fn apply_migrated(&mut self, sequence: Option<u64>) -> Result<(), String> {
+        for part in self.records.iter_mut().filter(|part| part.unsaved) {
+            part.save()?;
+        }
+        if let (Some(wal), Some(sequence)) = (self.wal.as_ref(), sequence) {
+            match wal.lock() {
+                Ok(mut wal) => wal.log_applied(sequence)?,
+                Err(e) => panic!("{}", ["shits fucked ".to_owned(), e.to_string()].concat())
+            }
+        }
+        Ok(())
+    }
+
+    /* 
+     * put the definition from before a migration back in memory
+     */
+    fn restore_definition(&mut self, migration: &Migration, previous: Vec<Cell>) {
+        self.column_definition = previous;
+        if let Migration::Rename { column, name } = migration {
+            self.secondary_indexes.iter_mut().flat_map(|index| index.columns.iter_mut()).filter(|indexed| *indexed == name).for_each(|indexed| *indexed = column.clone());
+        }
+    }
+
+    /* 
+     * MARK: Migration history
+     */
+    pub fn migration_history(&self) -> Result<String, String> {
+        Ok(json!({ "table": self.name, "migrations": migration::to_json(&migration::history(&self.migration_path())?) }).to_string())
     }
 
     /* 
      * MARK: Query delete records in columns
+     * tables are the tables of the owning database, searched for foreign keys referencing the deleted records,
+     * own is the entry of this table in them
      */
-    pub fn query_delete_records(&mut self, conditions: &Vec<conditional::Condition>) -> Result<String, String> {
-        let mut res = Ok("deletion successful".to_owned());
-        let _ = &self.records.iter_mut().for_each(|part| {
-            match part.reload() { 
-                Ok(_) => res = part.query_delete_records(conditions), 
-                Err(_) => res = Err("could not reload part".to_owned())
+    pub fn query_delete_records(&mut self, own: &Arc<Mutex<Table>>, filter: &conditional::Filter, tables: &[Arc<Mutex<Table>>]) -> Result<String, String> {
+        let parts = self.reload_parts(filter)?;
+        match self.delete_where(own, &|r: &record::Record| filter.evaluate(r), Some(&parts), tables, &[])? {
+            0 => Ok("no matching records found or deleted".to_owned()),
+            _ => Ok("matching records found and deleted successfully".to_owned())
+        }
+    }
+
+    /* 
+     * delete every matching record while honouring the on delete action of each foreign key referencing this table,
+     * restricting references are checked before anything is deleted and cascades or set nulls are applied afterwards,
+     * only the given parts are searched when the plan of a filter already ruled the others out, above holds the
+     * tables further up a cascade which are already locked, their restricting references are still checked
+     * but one that would cascade or set null back into them is refused
+     */
+    fn delete_where(&mut self, own: &Arc<Mutex<Table>>, matches: &dyn Fn(&record::Record) -> bool, parts: Option<&[u32]>, tables: &[Arc<Mutex<Table>>], above: &[(&Arc<Mutex<Table>>, &Table)]) -> Result<usize, String> {
+        let searched = |part: &&Part| parts.is_none_or(|parts| parts.contains(&part.index));
+        let deleted = self.records.iter().filter(searched).flat_map(|part| part.records.iter()).filter(|r| matches(r)).cloned().collect::<Vec<record::Record>>();
+        if deleted.is_empty() {
+            return Ok(0);
+        }
+
+        for table in tables.iter().filter(|table| !Arc::ptr_eq(table, own)) {
+            match above.iter().find(|(parent, _)| Arc::ptr_eq(parent, table)) {
+                Some((_, parent)) => {
+                    self.check_restrict(parent, &deleted, &|_| true)?;
+                    if let Some((child_column, ..)) = self.referencing_columns(parent).into_iter().find(|(_, _, action)| *action != cell::ReferentialAction::Restrict) {
+                        return Err(["deleting from ", &self.name, " would cascade back into ", &parent.name, ".", &child_column, " which is already being deleted from"].concat());
+                    }
+                },
+                None => {
+                    let child = self.lock_other(table)?;
+                    self.check_restrict(&child, &deleted, &|_| true)?;
+                }
             }
-            part.save();
-        });
-        res
+        }
+        self.check_restrict(self, &deleted, &|r| !matches(r))?;
+
+        let write = self.write_id();
+        let mut count = 0;
+        let mut touched = vec![];
+        for part in self.records.iter_mut().filter(|part| parts.is_none_or(|parts| parts.contains(&part.index))) {
+            match part.query_delete_records(matches, write, self.wal.as_ref())? {
+                0 => {},
+                deleted => {
+                    count += deleted;
+                    touched.push(part.index);
+                }
+            }
+        }
+        self.publish_written();
+        self.reindex_parts(&touched)?;
+
+        let chain = above.iter().copied().chain([(own, &*self)]).collect::<Vec<(&Arc<Mutex<Table>>, &Table)>>();
+        for table in tables.iter().filter(|table| !above.iter().any(|(parent, _)| Arc::ptr_eq(parent, table)) && !Arc::ptr_eq(table, own)) {
+            let mut child = self.lock_other(table)?;
+            for (child_column, parent_column, action) in self.referencing_columns(&child) {
+                child.apply_referential_action(table, &child_column, action, Table::column_values(deleted.iter(), &parent_column), tables, &chain)?;
+            }
+        }
+        for (child_column, parent_column, action) in self.referencing_columns(self) {
+            self.apply_referential_action(own, &child_column, action, Table::column_values(deleted.iter(), &parent_column), tables, above)?;
+        }
+        Ok(count)
+    }
+
+    /* 
+     * another table of the database, a table that is already locked is an error rather than skipped so no foreign key is missed
+     */
+    fn lock_other<'t>(&self, table: &'t Arc<Mutex<Table>>) -> Result<MutexGuard<'t, Table>, String> {
+        match table.try_lock() {
+            Ok(table) => Ok(table),
+            Err(TryLockError::WouldBlock) => Err(["another table of the database is in use so the foreign keys of ", &self.name, " could not be checked"].concat()),
+            Err(TryLockError::Poisoned(e)) => panic!("{}", ["shits fucked ".to_owned(), e.to_string()].concat())
+        }
+    }
+
+    /* 
+     * foreign key columns on child that reference a column of this table, as (child column, parent column, on delete action)
+     */
+    fn referencing_columns(&self, child: &Table) -> Vec<(String, String, cell::ReferentialAction)> {
+        child.column_definition.iter().filter_map(|celldef| match celldef {
+            Cell::CellDef { name, foreign_key: Some((table_name, column_name)), on_delete, .. } if *table_name == self.name => Some((name.clone(), column_name.clone(), *on_delete)),
+            _ => None
+        }).collect()
+    }
+
+    fn column_values<'r>(records: impl Iterator<Item = &'r record::Record>, column: &str) -> HashSet<String> {
+        records
+            .filter_map(|record| record.columns.iter().find(|col| col.name() == column))
+            .filter(|col| !col.is_null())
+            .map(|col| col.data_str())
+            .collect()
+    }
+
+    fn check_restrict(&self, child: &Table, deleted: &[record::Record], remaining: &dyn Fn(&record::Record) -> bool) -> Result<(), String> {
+        for (child_column, parent_column, action) in self.referencing_columns(child) {
+            if action != cell::ReferentialAction::Restrict {
+                continue;
+            }
+            let values = Table::column_values(deleted.iter(), &parent_column);
+            let referencing = child.records.iter()
+                .flat_map(|part| part.records.iter())
+                .filter(|r| remaining(r))
+                .filter_map(|r| r.columns.iter().find(|col| col.name() == child_column))
+                .find(|col| !col.is_null() && values.contains(&col.data_str()));
+            if let Some(col) = referencing {
+                return Err(ConstraintViolation::Referenced { column: parent_column, value: col.data_str(), referenced_by: [&child.name[..], ".", &child_column].concat() }.to_string());
+            }
+        }
+        Ok(())
+    }
+
+    fn apply_referential_action(&mut self, own: &Arc<Mutex<Table>>, column: &str, action: cell::ReferentialAction, values: HashSet<String>, tables: &[Arc<Mutex<Table>>], above: &[(&Arc<Mutex<Table>>, &Table)]) -> Result<(), String> {
+        let refers = |r: &record::Record| r.columns.iter().any(|col| col.name() == column && !col.is_null() && values.contains(&col.data_str()));
+        match action {
+            cell::ReferentialAction::Restrict => Ok(()),
+            cell::ReferentialAction::Cascade => self.delete_where(own, &refers, None, tables, above).map(|_| ()),
+            cell::ReferentialAction::SetNull => match self.column_definition.iter().find_map(|celldef| match celldef { Cell::CellDef { name, ctype, .. } if name == column => Some(ctype.as_null()), _ => None }) {
+                Some(null) => self.update_where(&refers, None, &record::Record::new(vec![null])).map(|_| ()),
+                None => Err(["column ", column, " does not exist on table ", &self.name].concat())
+            }
+        }
+    }
+
+    /* 
+     * MARK: check foreign keys
+     * every non null foreign key value has to exist in the referenced column, either on a sibling table
+     * of the owning database or, for self references, on this table or the records being inserted
+     */
+    pub fn check_foreign_keys(&self, records: &[record::Record], tables: &[Arc<Mutex<Table>>]) -> Result<(), ConstraintViolation> {
+        for celldef in self.column_definition.iter() {
+            if let Cell::CellDef { name, foreign_key: Some((table_name, column_name)), .. } = celldef {
+                let values = Table::column_values(records.iter(), name);
+                if values.is_empty() {
+                    continue;
+                }
+                let existing = match *table_name == self.name {
+                    true => Table::column_values(records.iter().chain(self.records.iter().flat_map(|part| part.records.iter())), column_name),
+                    false => tables.iter().find_map(|table| match table.try_lock() {
+                        Ok(table) if table.name == *table_name => Some(Table::column_values(table.records.iter().flat_map(|part| part.records.iter()), column_name)),
+                        _ => None
+                    }).unwrap_or_default()
+                };
+                if let Some(value) = values.into_iter().find(|value| !existing.contains(value)) {
+                    return Err(ConstraintViolation::ForeignKey { column: name.clone(), value, references: [&table_name[..], ".", column_name].concat() });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /* 
+     * MARK: Query update records in columns
+     * the updated images of the matching records are checked like inserted records before anything is written,
+     * uniqueness against every record that is not being replaced, primary keys are never assigned as records,
+     * the indexes and references to them all depend on their keys staying put
+     */
+    pub fn query_update_records(&mut self, filter: &conditional::Filter, assignments: &record::Record, tables: &[Arc<Mutex<Table>>]) -> Result<String, String> {
+        if let Some(Cell::CellDef { name, .. }) = self.column_definition.iter().find(|celldef| matches!(celldef, Cell::CellDef { name, primary_key: true, .. } if assignments.columns.iter().any(|col| col.name() == name))) {
+            return Err(["primary key column ", name, " of table ", &self.name, " cannot be updated"].concat());
+        }
+        let parts = self.reload_parts(filter)?;
+        let images = self.records.iter()
+            .filter(|part| parts.contains(&part.index))
+            .flat_map(|part| part.records.iter())
+            .filter(|record| filter.evaluate(record))
+            .map(|record| {
+                let mut image = record.clone();
+                image.assign(assignments);
+                image
+            })
+            .collect::<Vec<record::Record>>();
+        if let Err(violation) = self.check_constraints(&images, &|record| filter.evaluate(record)).and_then(|_| self.check_foreign_keys(&images, tables)) {
+            return Err(violation.to_string());
+        }
+        let updated = self.update_where(&|r: &record::Record| filter.evaluate(r), Some(&parts), assignments)?;
+        let total = updated.iter().map(|(_, count)| count).sum::<usize>();
+        Ok(json!({ "parts": updated.iter().map(|(part, count)| json!({ "part": part, "updated": count })).collect::<Vec<Value>>(), "total": total }).to_string())
+    }
+
+    /* 
+     * parts outside the given ones are reported with nothing updated without being searched
+     */
+    fn update_where(&mut self, matches: &dyn Fn(&record::Record) -> bool, parts: Option<&[u32]>, assignments: &record::Record) -> Result<Vec<(u32, usize)>, String> {
+        let write = self.write_id();
+        let mut updated = vec![];
+        for part in self.records.iter_mut() {
+            updated.push((part.index, match parts.is_none_or(|parts| parts.contains(&part.index)) {
+                true => part.query_update_records(matches, assignments, write, self.wal.as_ref())?,
+                false => 0
+            }));
+        }
+        self.publish_written();
+        self.reindex_parts(&updated.iter().filter(|(_, count)| *count > 0).map(|(part, _)| *part).collect::<Vec<u32>>())?;
+        Ok(updated)
+    }
+
+    /* 
+     * MARK: Describe
+     * the columns and secondary indexes of the table as json for introspection
+     */
+    pub fn describe(&self) -> Value {
+        json!({
+            "name": self.name,
+            "auto_increment": self.auto_increment,
+            "columns": self.column_definition.iter().map(|celldef| celldef.describe(self.auto_increment)).filter(|column| !column.is_null()).collect::<Vec<Value>>(),
+            "indexes": self.secondary_indexes.iter().map(|index| json!({ "name": index.name, "columns": index.columns })).collect::<Vec<Value>>(),
+        })
+    }
+
+    /* 
+     * MARK: Truncate
+     * delete every record without checking the foreign keys referencing them and start the key counter again,
+     * only used to reset a whole database so every referencing table is emptied along with this one
+     */
+    pub fn truncate(&mut self) -> Result<usize, String> {
+        let write = self.write_id();
+        let mut count = 0;
+        let mut touched = vec![];
+        for part in self.records.iter_mut() {
+            part.reload()?;
+            match part.query_delete_records(&|_| true, write, self.wal.as_ref())? {
+                0 => {},
+                deleted => {
+                    count += deleted;
+                    touched.push(part.index);
+                }
+            }
+        }
+        self.publish_written();
+        self.reindex_parts(&touched)?;
+        if self.auto_increment {
+            self.next_key = 1;
+            self.save_def()?;
+        }
+        Ok(count)
     }
 
     /* 
@@ -194,7 +1161,13 @@ impl Table {
             },
             false => {
                 match self.records.iter_mut().enumerate().map(|(i, part)| part.delete(i as u32)).all(|partres| partres.is_ok()) {
-                    true => match fs::remove_file([&self.directory, ".def"].join("\\")) {
+                    true => match fs::remove_file([&self.directory, ".def"].join("\\")).and_then(|_| match fs::remove_file([&self.directory, ".pk"].join("\\")) {
+                        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+                        _ => Ok(())
+                    }).and_then(|_| match fs::remove_file(self.migration_path()) {
+                        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+                        _ => Ok(())
+                    }).map_err(|e| e.to_string()).and_then(|_| self.secondary_indexes.iter().try_for_each(|index| index.delete())) {
                         Ok(_) => match fs::remove_dir(&self.directory) {
                             Ok(_) => Ok("table directory deleted".to_owned()),
                             Err(e) => Err(["could not delete directory ", &e.to_string()].concat())
@@ -207,39 +1180,514 @@ impl Table {
         }
     }
 
+    /* 
+     * MARK: check constraints
+     * every record is checked against the not null, unique and primary key flags of each column,
+     * uniqueness is checked across every part of the table and the other records being written,
+     * leaving out the records the written ones replace
+     */
+    pub fn check_constraints(&self, records: &[record::Record], replaced: &dyn Fn(&record::Record) -> bool) -> Result<(), ConstraintViolation> {
+        for celldef in self.column_definition.iter() {
+            if let Cell::CellDef { name, not_null, unique, primary_key, .. } = celldef {
+                let mut taken = match *unique || *primary_key {
+                    true => self.records.iter()
+                        .flat_map(|part| part.records.iter())
+                        .filter(|record| !replaced(record))
+                        .filter_map(|record| record.columns.iter().find(|col| col.name() == name))
+                        .filter(|col| !col.is_null())
+                        .map(|col| col.data_str())
+                        .collect::<HashSet<String>>(),
+                    false => HashSet::new()
+                };
+                for record in records {
+                    match record.columns.iter().find(|col| col.name() == name) {
+                        Some(value) if !value.is_null() => {
+                            if (*unique || *primary_key) && !taken.insert(value.data_str()) {
+                                return Err(match primary_key {
+                                    true => ConstraintViolation::PrimaryKey { column: name.clone(), value: value.data_str() },
+                                    false => ConstraintViolation::Unique { column: name.clone(), value: value.data_str() }
+                                });
+                            }
+                        },
+                        _ => if *not_null || *primary_key {
+                            return Err(ConstraintViolation::NotNull { column: name.clone() });
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
     /* 
      * MARK: Query add record
      * while there are records to be saved search for a part to save it in, if no part is available create a new part and save it in there
      */
-    pub fn query_create(&mut self, mut records: Vec<record::Record>) -> Result<String, String> {
+    pub fn query_create(&mut self, mut records: Vec<record::Record>, tables: &[Arc<Mutex<Table>>]) -> Result<String, String> {
         println!("table {} adding {:?}", self.name, records);
 
-        let mut res:  Result<String, String> = Ok("record creation successful".to_owned());
+        let keys = self.assign_keys(&mut records)?;
+        if let Err(violation) = self.check_constraints(&records, &|_| false).and_then(|_| self.check_foreign_keys(&records, tables)) {
+            return Err(violation.to_string());
+        }
 
-        records.retain_mut(|record| {
+        // records are written one at a time and the first that fails stops the rest, only the keys written before it are reported
+        let write = self.write_id();
+        let mut touched = vec![];
+        let mut created = vec![];
+        let mut failed = None;
+        for (record, key) in records.into_iter().zip(keys) {
             println!("moving a record {:?}", record);
-            match self.records.iter_mut().find(|part| !part.full) {
-                Some(part) => { 
+            let res = match self.records.iter_mut().find(|part| !part.full) {
+                Some(part) => {
                     println!("part found {:?}", record);
-                    res = part.query_create_record(std::mem::take(record), self.column_definition.first().unwrap().clone()) 
+                    touched.push(part.index);
+                    part.query_create_record(record, self.column_definition.first().unwrap().clone(), write, self.wal.as_ref())
                 }
                 None => {
                     println!("creating new part {:?}", record);
-                    let mut new_part = Part::new(&self.directory[..], self.records.len(), 4096);
-                    res = new_part.query_create_record(std::mem::take(record), self.column_definition.first().unwrap().clone());
+                    let mut new_part = Part::new(&self.directory[..], self.records.iter().map(|part| part.index as usize + 1).max().unwrap_or(0), 4096, Table::key_column(&self.column_definition));
+                    let res = new_part.query_create_record(record, self.column_definition.first().unwrap().clone(), write, self.wal.as_ref());
+                    touched.push(new_part.index);
                     self.records.push(new_part);
+                    res
+                }
+            };
+            match res {
+                Ok(_) => created.push(key),
+                Err(e) => {
+                    failed = Some(e);
+                    break;
                 }
             }
-            false
-        });
+        }
+
+        touched.dedup();
+        self.publish_written();
+        self.reindex_parts(&touched)?;
+
+        match failed {
+            None => Ok(json!({ "created": created.len(), "keys": created }).to_string()),
+            Some(e) => Err(json!({ "error": e, "created": created.len(), "keys": created }).to_string())
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cell::ReferentialAction;
+    use super::super::read::{Cursor, OrderBy};
+
+    fn table(db_name: &str, table_name: &str, columns: Vec<cell::ColumnDef>) -> Arc<Mutex<Table>> {
+        crate::test_dir();
+        Arc::new(Mutex::new(Table::new(db_name.to_owned(), table_name.to_owned(), columns, true)))
+    }
+
+    fn id() -> cell::ColumnDef {
+        ("id".to_owned(), "ULong".to_owned(), None, false, false, None, ReferentialAction::Restrict)
+    }
+
+    fn foreign_key(name: &str, table_name: &str, on_delete: ReferentialAction) -> cell::ColumnDef {
+        (name.to_owned(), "ULong".to_owned(), None, true, false, Some((table_name.to_owned(), "id".to_owned())), on_delete)
+    }
+
+    fn record(columns: &[(&str, Option<u128>)]) -> record::Record {
+        record::Record::new(columns.iter().map(|(name, data)| CellValue::ULong { name: name.to_string(), data: *data }).collect())
+    }
+
+    fn keyed(key: u128) -> conditional::Filter {
+        conditional::Filter::Condition(conditional::Condition { target_column: "id".to_owned(), conditional: conditional::Conditional::Equal, value: CellValue::ULong { name: "id".to_owned(), data: Some(key) }, relational: None })
+    }
+
+    fn count(table: &Arc<Mutex<Table>>) -> usize {
+        table.lock().unwrap().records.iter().map(|part| part.records.len()).sum()
+    }
+
+    fn delete(table: &Arc<Mutex<Table>>, key: u128, tables: &[Arc<Mutex<Table>>]) -> Result<String, String> {
+        table.lock().unwrap().query_delete_records(table, &keyed(key), tables)
+    }
+
+    #[test]
+    fn deletes_cascade_to_children_and_self_references() {
+        let parent = table("cascade", "parent", vec![id()]);
+        let child = table("cascade", "child", vec![id(), foreign_key("parent_id", "parent", ReferentialAction::Cascade)]);
+        let tables = vec![Arc::clone(&parent), Arc::clone(&child)];
+        parent.lock().unwrap().query_create(vec![record(&[("id", Some(1))]), record(&[("id", Some(2))])], &tables).unwrap();
+        child.lock().unwrap().query_create(vec![record(&[("id", Some(1)), ("parent_id", Some(1))]), record(&[("id", Some(2)), ("parent_id", Some(2))])], &tables).unwrap();
+        delete(&parent, 1, &tables).unwrap();
+        assert_eq!((count(&parent), count(&child)), (1, 1));
+
+        let tree = table("cascade", "tree", vec![id(), foreign_key("parent_id", "tree", ReferentialAction::Cascade)]);
+        let tables = vec![Arc::clone(&tree)];
+        tree.lock().unwrap().query_create(vec![record(&[("id", Some(1)), ("parent_id", None)]), record(&[("id", Some(2)), ("parent_id", Some(1))]), record(&[("id", Some(3)), ("parent_id", Some(2))]), record(&[("id", Some(4)), ("parent_id", None)])], &tables).unwrap();
+        delete(&tree, 1, &tables).unwrap();
+        assert_eq!(count(&tree), 1);
+    }
+
+    #[test]
+    fn references_from_tables_further_up_a_cascade_are_not_skipped() {
+        let a = table("cycle", "a", vec![id(), foreign_key("b_id", "b", ReferentialAction::Restrict)]);
+        let b = table("cycle", "b", vec![id(), foreign_key("a_id", "a", ReferentialAction::Cascade)]);
+        let tables = vec![Arc::clone(&a), Arc::clone(&b)];
+        a.lock().unwrap().query_create(vec![record(&[("id", Some(1)), ("b_id", None)]), record(&[("id", Some(2)), ("b_id", None)])], &tables).unwrap();
+        b.lock().unwrap().query_create(vec![record(&[("id", Some(1)), ("a_id", Some(1))])], &tables).unwrap();
+        a.lock().unwrap().update_where(&|r| r.columns[0].data_str() == "2", None, &record(&[("b_id", Some(1))])).unwrap();
+        assert!(delete(&a, 1, &tables).unwrap_err().contains("a.b_id"));
+
+        let c = table("cycle", "c", vec![id(), foreign_key("d_id", "d", ReferentialAction::Cascade)]);
+        let d = table("cycle", "d", vec![id(), foreign_key("c_id", "c", ReferentialAction::Cascade)]);
+        let tables = vec![Arc::clone(&c), Arc::clone(&d)];
+        c.lock().unwrap().query_create(vec![record(&[("id", Some(1)), ("d_id", None)])], &tables).unwrap();
+        d.lock().unwrap().query_create(vec![record(&[("id", Some(1)), ("c_id", Some(1))])], &tables).unwrap();
+        c.lock().unwrap().update_where(&|_| true, None, &record(&[("d_id", Some(1))])).unwrap();
+        assert!(delete(&c, 1, &tables).unwrap_err().contains("cascade back into c.d_id"));
+    }
+
+    #[test]
+    fn a_locked_table_is_an_error_rather_than_skipped() {
+        let parent = table("locked", "parent", vec![id()]);
+        let child = table("locked", "child", vec![id(), foreign_key("parent_id", "parent", ReferentialAction::Restrict)]);
+        let tables = vec![Arc::clone(&parent), Arc::clone(&child)];
+        parent.lock().unwrap().query_create(vec![record(&[("id", Some(1))])], &tables).unwrap();
+        child.lock().unwrap().query_create(vec![record(&[("id", Some(1)), ("parent_id", Some(1))])], &tables).unwrap();
+        let held = child.lock().unwrap();
+        assert!(delete(&parent, 1, &tables).unwrap_err().contains("could not be checked"));
+        drop(held);
+        assert!(delete(&parent, 1, &tables).unwrap_err().contains("referenced"));
+        assert_eq!(count(&parent), 1);
+    }
+
+    #[test]
+    fn the_largest_key_is_refused_and_the_counter_kept() {
+        let keys = table("keys", "keys", vec![id()]);
+        let mut keys = keys.lock().unwrap();
+        keys.next_key = u128::MAX - 1;
+        let mut records = vec![record(&[("id", None)]), record(&[("id", None)])];
+        assert!(keys.assign_keys(&mut records).unwrap_err().contains(&u128::MAX.to_string()));
+        assert_eq!(keys.next_key, u128::MAX - 1);
+        assert!(keys.assign_keys(&mut [record(&[("id", Some(u128::MAX))])]).is_err());
+        assert_eq!(keys.assign_keys(&mut [record(&[("id", None)])]).unwrap(), vec![(u128::MAX - 1).to_string()]);
+    }
+
+    #[test]
+    fn inserts_breaking_a_constraint_are_refused_whole() {
+        let text = |name: &str, data: Option<&str>| CellValue::String { name: name.to_owned(), data: data.map(|data| data.to_owned()) };
+        let row = |key: Option<u128>, email: Option<&str>, nickname: Option<&str>| record::Record::new(vec![CellValue::ULong { name: "id".to_owned(), data: key }, text("email", email), text("nickname", nickname)]);
+        let users = table("constraints", "users", vec![
+            id(),
+            ("email".to_owned(), "String".to_owned(), None, false, true, None, ReferentialAction::Restrict),
+            ("nickname".to_owned(), "String".to_owned(), None, true, true, None, ReferentialAction::Restrict)
+        ]);
+        let tables = vec![Arc::clone(&users)];
+        let create = |records: Vec<record::Record>| users.lock().unwrap().query_create(records, &tables);
+        create(vec![row(None, Some("a"), None), row(None, Some("b"), None)]).unwrap();
+
+        assert!(create(vec![row(None, Some("a"), Some("x"))]).unwrap_err().contains("\"constraint\":\"unique\""));
+        assert!(create(vec![row(None, None, Some("x"))]).unwrap_err().contains("\"constraint\":\"not_null\""));
+        assert!(create(vec![row(Some(1), Some("c"), None)]).unwrap_err().contains("\"constraint\":\"primary_key\""));
+        assert!(create(vec![row(None, Some("c"), None), row(None, Some("c"), None)]).unwrap_err().contains("\"value\":\"c\""));
+        assert_eq!(count(&users), 2);
+    }
+
+    #[test]
+    fn a_cursor_resumes_after_its_key_was_deleted() {
+        let pages = table("cursor", "pages", vec![id()]);
+        let tables = vec![Arc::clone(&pages)];
+        pages.lock().unwrap().query_create((0..5).map(|_| record(&[("id", None)])).collect(), &tables).unwrap();
+        let all = conditional::Filter::Condition(conditional::Condition { target_column: "id".to_owned(), conditional: conditional::Conditional::All, value: CellValue::ULong { name: "id".to_owned(), data: None }, relational: None });
+        let page = |after: &Value| {
+            let after = after.as_str().map(|token| Cursor::decode(token).unwrap());
+            let read: Value = serde_json::from_str(&pages.lock().unwrap().query_search_columns(&all, &ReadOptions { limit: Some(2), paged: true, after, ..ReadOptions::default() }).unwrap()).unwrap();
+            (read["records"].as_array().unwrap().iter().map(|record| record["id"].as_u64().unwrap()).collect::<Vec<u64>>(), read["cursor"].clone())
+        };
+
+        let (keys, cursor) = page(&Value::Null);
+        assert_eq!(keys, vec![1, 2]);
+        delete(&pages, 2, &tables).unwrap();
+        let (keys, cursor) = page(&cursor);
+        assert_eq!(keys, vec![3, 4]);
+        delete(&pages, 4, &tables).unwrap();
+        delete(&pages, 3, &tables).unwrap();
+        assert_eq!(page(&cursor), (vec![5], Value::Null));
+    }
+
+    #[test]
+    fn definitions_written_before_on_delete_still_load() {
+        let users = table("legacy", "users", vec![id()]);
+        let directory = users.lock().unwrap().directory.clone();
+        let legacy = vec![
+            cell::LegacyCell::CellDef { name: "id".to_owned(), index: 0, ctype: CellValue::ULong { name: "id".to_owned(), data: None }, default: false, not_null: true, unique: true, primary_key: true, foreign_key: None },
+            cell::LegacyCell::CellDef { name: "team_id".to_owned(), index: 1, ctype: CellValue::ULong { name: "team_id".to_owned(), data: None }, default: false, not_null: false, unique: false, primary_key: false, foreign_key: Some(("teams".to_owned(), "id".to_owned())) }
+        ];
+        fs::write([&directory, ".def"].join("\\"), bincode::serialize(&(true, legacy)).unwrap()).unwrap();
+        let (loaded, _) = Table::build_from_dir(directory.clone()).unwrap();
+        assert_eq!(Table::key_column(&loaded.column_definition), Some("id".to_owned()));
+        assert!(matches!(&loaded.column_definition[1], Cell::CellDef { name, foreign_key: Some(_), on_delete: ReferentialAction::Restrict, .. } if name == "team_id"));
+
+        fs::write([&directory, ".def"].join("\\"), [1, 2, 3]).unwrap();
+        assert!(Table::build_from_dir(directory).err().unwrap().contains("not in any known layout"));
+    }
+
+    fn text(name: &str, data: Option<&str>) -> CellValue {
+        CellValue::String { name: name.to_owned(), data: data.map(|data| data.to_owned()) }
+    }
+
+    fn people() -> (Arc<Mutex<Table>>, Vec<Arc<Mutex<Table>>>) {
+        let teams = table("updates", "teams", vec![id()]);
+        let people = table("updates", "people", vec![
+            id(),
+            ("email".to_owned(), "String".to_owned(), None, false, true, None, ReferentialAction::Restrict),
+            ("city".to_owned(), "String".to_owned(), None, true, false, None, ReferentialAction::Restrict),
+            foreign_key("team_id", "teams", ReferentialAction::Restrict)
+        ]);
+        let tables = vec![Arc::clone(&teams), Arc::clone(&people)];
+        teams.lock().unwrap().query_create(vec![record(&[("id", Some(1))])], &tables).unwrap();
+        let row = |email: &str, city: &str| record::Record::new(vec![CellValue::ULong { name: "id".to_owned(), data: None }, text("email", Some(email)), text("city", Some(city)), CellValue::ULong { name: "team_id".to_owned(), data: Some(1) }]);
+        people.lock().unwrap().query_create(vec![row("a", "leeds"), row("b", "york"), row("c", "leeds")], &tables).unwrap();
+        (people, tables)
+    }
+
+    fn update(table: &Arc<Mutex<Table>>, filter: &conditional::Filter, assignments: Vec<CellValue>, tables: &[Arc<Mutex<Table>>]) -> Result<String, String> {
+        table.lock().unwrap().query_update_records(filter, &record::Record::new(assignments), tables)
+    }
+
+    fn column(table: &Arc<Mutex<Table>>, name: &str) -> Vec<String> {
+        table.lock().unwrap().records.iter().flat_map(|part| part.records.iter()).filter_map(|record| record.columns.iter().find(|col| col.name() == name).map(|col| col.data_str())).collect()
+    }
+
+    #[test]
+    fn updates_change_only_the_assigned_columns_of_matching_records() {
+        let (people, tables) = people();
+        let leeds = conditional::Filter::Condition(conditional::Condition { target_column: "city".to_owned(), conditional: conditional::Conditional::Equal, value: text("city", Some("leeds")), relational: None });
+        assert!(update(&people, &leeds, vec![text("city", Some("hull"))], &tables).unwrap().contains("\"total\":2"));
+        assert_eq!(column(&people, "city"), vec!["hull", "york", "hull"]);
+        assert_eq!(column(&people, "email"), vec!["a", "b", "c"]);
+        // a unique value the record already holds is not taken by itself
+        update(&people, &keyed(1), vec![text("email", Some("a"))], &tables).unwrap();
+    }
+
+    #[test]
+    fn updates_breaking_a_constraint_change_nothing() {
+        let (people, tables) = people();
+        assert!(update(&people, &keyed(2), vec![text("email", Some("a"))], &tables).unwrap_err().contains("\"constraint\":\"unique\""));
+        assert!(update(&people, &keyed(2), vec![text("email", None)], &tables).unwrap_err().contains("\"constraint\":\"not_null\""));
+        assert!(update(&people, &keyed(2), vec![CellValue::ULong { name: "team_id".to_owned(), data: Some(9) }], &tables).unwrap_err().contains("\"constraint\":\"foreign_key\""));
+        assert_eq!(column(&people, "email"), vec!["a", "b", "c"]);
+        assert_eq!(column(&people, "team_id"), vec!["1", "1", "1"]);
+    }
+
+    fn numbers(table_name: &str) -> Arc<Mutex<Table>> {
+        let numbers = table("migrations", table_name, vec![id(), ("n".to_owned(), "String".to_owned(), None, true, false, None, ReferentialAction::Restrict)]);
+        let tables = vec![Arc::clone(&numbers)];
+        let row = |n: &str| record::Record::new(vec![CellValue::ULong { name: "id".to_owned(), data: None }, text("n", Some(n))]);
+        numbers.lock().unwrap().query_create(vec![row("1"), row("2")], &tables).unwrap();
+        numbers
+    }
+
+    fn migrate(table: &Arc<Mutex<Table>>, migration: Migration) -> Result<String, String> {
+        let tables = vec![Arc::clone(table)];
+        table.lock().unwrap().migrate(table, &migration, &tables)
+    }
+
+    // the table as it is built again from its directory
+    fn reloaded(table: &Arc<Mutex<Table>>) -> Arc<Mutex<Table>> {
+        let directory = table.lock().unwrap().directory.clone();
+        Arc::new(Mutex::new(Table::build_from_dir(directory).ok().unwrap().0))
+    }
+
+    fn columns(table: &Arc<Mutex<Table>>) -> Vec<(String, String)> {
+        table.lock().unwrap().column_definition.iter().filter_map(|celldef| match celldef {
+            Cell::CellDef { name, ctype, .. } => Some((name.clone(), ctype.type_name().to_owned())),
+            _ => None
+        }).collect()
+    }
+
+    #[test]
+    fn migrations_are_read_back_after_a_reload() {
+        let pair = |name: &str, dtype: &str| (name.to_owned(), dtype.to_owned());
+
+        let added = numbers("added");
+        migrate(&added, Migration::Add { column: ("flag".to_owned(), "Bool".to_owned(), Some("true".to_owned()), true, false, None, ReferentialAction::Restrict) }).unwrap();
+        let added = reloaded(&added);
+        assert_eq!(columns(&added), vec![pair("id", "ULong"), pair("n", "String"), pair("flag", "Bool")]);
+        assert_eq!(column(&added, "flag"), vec!["true", "true"]);
+
+        let dropped = numbers("dropped");
+        migrate(&dropped, Migration::Drop { column: "n".to_owned() }).unwrap();
+        let dropped = reloaded(&dropped);
+        assert_eq!(columns(&dropped), vec![pair("id", "ULong")]);
+        assert!(column(&dropped, "n").is_empty());
+
+        let renamed = numbers("renamed");
+        migrate(&renamed, Migration::Rename { column: "n".to_owned(), name: "m".to_owned() }).unwrap();
+        let renamed = reloaded(&renamed);
+        assert_eq!(columns(&renamed), vec![pair("id", "ULong"), pair("m", "String")]);
+        assert_eq!(column(&renamed, "m"), vec!["1", "2"]);
+
+        let retyped = numbers("retyped");
+        migrate(&retyped, Migration::Retype { column: "n".to_owned(), dtype: "ILong".to_owned(), conversion: migration::Conversion::Strict }).unwrap();
+        let retyped = reloaded(&retyped);
+        assert_eq!(columns(&retyped), vec![pair("id", "ULong"), pair("n", "ILong")]);
+        assert!(retyped.lock().unwrap().records.iter().flat_map(|part| part.records.iter()).all(|record| matches!(record.columns[1], CellValue::ILong { data: Some(_), .. })));
+    }
+
+    #[test]
+    fn a_migration_whose_definition_cannot_be_saved_changes_nothing() {
+        let unsaved = numbers("unsaved");
+        // a directory where the definition writes its temporary file makes saving it fail
+        let blocked = [&unsaved.lock().unwrap().directory[..], "\\.def.tmp"].concat();
+        fs::create_dir(&blocked).unwrap();
+        let failed = migrate(&unsaved, Migration::Rename { column: "n".to_owned(), name: "m".to_owned() }).unwrap_err();
+        fs::remove_dir(&blocked).unwrap();
+        assert!(failed.contains("migration was undone"));
+        assert_eq!(columns(&unsaved), vec![("id".to_owned(), "ULong".to_owned()), ("n".to_owned(), "String".to_owned())]);
+        assert_eq!(column(&reloaded(&unsaved), "n"), vec!["1", "2"]);
+    }
 
-        match records.is_empty() {
-            true => match res {
-                Ok(_) => res = Ok("records created".to_owned()),
-                Err(e) => res = Err(e)
-            } 
-            false => res = Err("records not successfully transferred".to_owned()),
+    #[test]
+    fn a_record_that_cannot_be_saved_stops_the_insert_and_is_left_out_of_its_part() {
+        let numbers = table("unsaved", "numbers", vec![id()]);
+        let tables = vec![Arc::clone(&numbers)];
+        numbers.lock().unwrap().query_create(vec![record(&[("id", None)])], &tables).unwrap();
+        // a directory where the part writes its temporary file makes every later save of the part fail
+        let blocked = [&numbers.lock().unwrap().records[0].directory[..], ".tmp"].concat();
+        fs::create_dir(&blocked).unwrap();
+        let failed = numbers.lock().unwrap().query_create(vec![record(&[("id", None)]), record(&[("id", None)])], &tables).unwrap_err();
+        fs::remove_dir(&blocked).unwrap();
+        assert!(failed.contains("\"created\":0") && failed.contains("\"keys\":[]"));
+        let numbers = numbers.lock().unwrap();
+        assert_eq!((numbers.records[0].records.len(), numbers.records[0].key_range.len()), (1, 1));
+    }
+
+    #[test]
+    fn a_snapshot_reads_what_it_was_taken_with_while_a_write_commits() {
+        let cities = table("snapshots", "cities", vec![id(), ("city".to_owned(), "String".to_owned(), None, true, false, None, ReferentialAction::Restrict)]);
+        let tables = vec![Arc::clone(&cities)];
+        let row = |city: &str| record::Record::new(vec![CellValue::ULong { name: "id".to_owned(), data: None }, text("city", Some(city))]);
+        cities.lock().unwrap().query_create(vec![row("leeds"), row("york")], &tables).unwrap();
+        let every = conditional::Filter::Condition(conditional::Condition { target_column: "id".to_owned(), conditional: conditional::Conditional::EqualGreater, value: CellValue::ULong { name: "id".to_owned(), data: Some(0) }, relational: None });
+        let read = |snapshot: &Snapshot| {
+            let mut seen = vec![];
+            snapshot.read(&every, None, |records| {
+                seen.extend(records.iter().map(|record| record.columns[1].data_str()));
+                true
+            });
+            seen.sort();
+            seen
         };
-        res
+        let before = cities.lock().unwrap().snapshot(&every);
+        let mut writer = cities.lock().unwrap();
+        writer.query_update_records(&keyed(1), &record::Record::new(vec![text("city", Some("hull"))]), &tables).unwrap();
+        writer.query_create(vec![row("bath")], &tables).unwrap();
+        // the writer still holds the table, the older snapshot is scanned without it
+        let seen = std::thread::scope(|scope| scope.spawn(|| read(&before)).join().unwrap());
+        assert_eq!(seen, vec!["leeds", "york"]);
+        assert_eq!(read(&writer.snapshot(&every)), vec!["bath", "hull", "york"]);
+    }
+
+    #[test]
+    fn updates_cannot_move_a_record_onto_another_key() {
+        let (people, tables) = people();
+        assert!(update(&people, &keyed(2), vec![CellValue::ULong { name: "id".to_owned(), data: Some(1) }], &tables).unwrap_err().contains("primary key column id"));
+        assert_eq!(column(&people, "id"), vec!["1", "2", "3"]);
+        let people = people.lock().unwrap();
+        let index = people.primary_index.as_ref().unwrap();
+        assert_eq!(index.lookup((std::ops::Bound::Included(1), std::ops::Bound::Included(2))).values().map(|slots| slots.len()).sum::<usize>(), 2);
+    }
+
+    fn readings() -> Arc<Mutex<Table>> {
+        let readings = table("reads", "readings", vec![
+            id(),
+            ("temp".to_owned(), "Float".to_owned(), None, true, false, None, ReferentialAction::Restrict),
+            ("label".to_owned(), "String".to_owned(), None, true, false, None, ReferentialAction::Restrict)
+        ]);
+        let tables = vec![Arc::clone(&readings)];
+        let row = |temp: Option<f64>, label: Option<&str>| record::Record::new(vec![CellValue::ULong { name: "id".to_owned(), data: None }, CellValue::Float { name: "temp".to_owned(), data: temp }, text("label", label)]);
+        readings.lock().unwrap().query_create(vec![row(Some(2.5), Some("b")), row(None, Some("a")), row(Some(-1.0), None), row(Some(10.0), Some("B"))], &tables).unwrap();
+        readings
+    }
+
+    // the keys of the records a read returns, in the order it returned them
+    fn read_keys(table: &Arc<Mutex<Table>>, options: ReadOptions) -> Result<Vec<u128>, String> {
+        let all = conditional::Filter::Condition(conditional::Condition { target_column: "id".to_owned(), conditional: conditional::Conditional::All, value: CellValue::ULong { name: "id".to_owned(), data: None }, relational: None });
+        read_keys_matching(table, &all, options)
+    }
+
+    fn read_keys_where(table: &Arc<Mutex<Table>>, filter: &conditional::Filter) -> Vec<u128> {
+        read_keys_matching(table, filter, ReadOptions::default()).unwrap()
+    }
+
+    fn read_keys_matching(table: &Arc<Mutex<Table>>, filter: &conditional::Filter, options: ReadOptions) -> Result<Vec<u128>, String> {
+        let read = table.lock().unwrap().query_search_columns(filter, &options)?;
+        Ok(read.split("\"id\": ").skip(1).map(|key| key.chars().take_while(|c| c.is_ascii_digit()).collect::<String>().parse().unwrap()).collect())
+    }
+
+    fn ordered(column: &str, modifiers: &[&str]) -> ReadOptions {
+        ReadOptions { order_by: vec![OrderBy::parse(column.to_owned(), modifiers).unwrap()], ..ReadOptions::default() }
+    }
+
+    #[test]
+    fn reads_are_ordered_then_offset_then_limited() {
+        let readings = readings();
+        assert_eq!(read_keys(&readings, ReadOptions::default()).unwrap(), vec![1, 2, 3, 4]);
+        assert_eq!(read_keys(&readings, ReadOptions { limit: Some(2), offset: 1, ..ReadOptions::default() }).unwrap(), vec![2, 3]);
+        assert_eq!(read_keys(&readings, ReadOptions { limit: Some(2), offset: 1, ..ordered("temp", &[]) }).unwrap(), vec![1, 4]);
+        assert_eq!(read_keys(&readings, ReadOptions { offset: 9, ..ordered("temp", &[]) }).unwrap(), Vec::<u128>::new());
+        assert_eq!(read_keys(&readings, ReadOptions { limit: Some(0), ..ReadOptions::default() }).unwrap(), Vec::<u128>::new());
+        assert!(read_keys(&readings, ordered("missing", &[])).unwrap_err().contains("column missing does not exist"));
+    }
+
+    #[test]
+    fn each_type_orders_by_its_value_with_nulls_placed_as_asked() {
+        let readings = readings();
+        // floats compare as numbers, not as the text they are written as
+        assert_eq!(read_keys(&readings, ordered("temp", &[])).unwrap(), vec![3, 1, 4, 2]);
+        assert_eq!(read_keys(&readings, ordered("temp", &["DESC"])).unwrap(), vec![2, 4, 1, 3]);
+        assert_eq!(read_keys(&readings, ordered("temp", &["NULLS_FIRST"])).unwrap(), vec![2, 3, 1, 4]);
+        assert_eq!(read_keys(&readings, ordered("temp", &["DESC", "NULLS_LAST"])).unwrap(), vec![4, 1, 3, 2]);
+        // strings compare byte by byte so upper case sorts before lower case
+        assert_eq!(read_keys(&readings, ordered("label", &[])).unwrap(), vec![4, 2, 1, 3]);
+        assert_eq!(read_keys(&readings, ordered("label", &["desc"])).unwrap(), vec![3, 1, 2, 4]);
+        assert!(OrderBy::parse("label".to_owned(), &["SIDEWAYS"]).unwrap_err().contains("SIDEWAYS"));
+    }
+
+    #[test]
+    fn a_cursor_read_refuses_an_order_or_an_offset() {
+        let readings = readings();
+        let paged = |options: ReadOptions| read_keys(&readings, ReadOptions { limit: Some(2), paged: true, ..options });
+        assert!(paged(ordered("temp", &[])).unwrap_err().contains("cannot use order_by or offset"));
+        assert!(paged(ReadOptions { offset: 1, ..ReadOptions::default() }).unwrap_err().contains("cannot use order_by or offset"));
+        assert!(read_keys(&readings, ReadOptions { paged: true, ..ReadOptions::default() }).unwrap_err().contains("needs a limit"));
+        assert_eq!(paged(ReadOptions::default()).unwrap(), vec![1, 2]);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn indexes_outlive_a_reload_and_serve_equal_reads() {
+        let cities = table("indexes", "cities", vec![id(), ("city".to_owned(), "String".to_owned(), None, true, false, None, ReferentialAction::Restrict)]);
+        let row = |city: &str| record::Record::new(vec![CellValue::ULong { name: "id".to_owned(), data: None }, text("city", Some(city))]);
+        cities.lock().unwrap().query_create(vec![row("leeds"), row("york"), row("leeds")], &[Arc::clone(&cities)]).unwrap();
+        let leeds = conditional::Filter::Condition(conditional::Condition { target_column: "city".to_owned(), conditional: conditional::Conditional::Equal, value: text("city", Some("leeds")), relational: None });
+        let mut created = cities.lock().unwrap();
+        assert!(created.create_index("by_city".to_owned(), vec!["missing".to_owned()]).unwrap_err().contains("column missing does not exist"));
+        created.create_index("by_city".to_owned(), vec!["city".to_owned()]).unwrap();
+        assert!(created.create_index("by_city".to_owned(), vec!["city".to_owned()]).unwrap_err().contains("already exists"));
+        let path = created.secondary_index_path("by_city");
+        assert!(fs::metadata(&path).is_ok());
+        drop(created);
+
+        let cities = reloaded(&cities);
+        let tables = vec![Arc::clone(&cities)];
+        cities.lock().unwrap().query_create(vec![row("leeds"), row("hull")], &tables).unwrap();
+        assert!(matches!(&cities.lock().unwrap().plan(&leeds).access, Access::SecondaryIndex(name) if name == "by_city"));
+        assert_eq!(read_keys_where(&cities, &leeds), vec![1, 3, 4]);
+
+        cities.lock().unwrap().drop_index("by_city").unwrap();
+        assert!(fs::metadata(&path).is_err());
+        let cities = reloaded(&cities);
+        assert!(cities.lock().unwrap().secondary_indexes.is_empty());
+        assert!(cities.lock().unwrap().drop_index("by_city").unwrap_err().contains("does not exist"));
+        assert!(!matches!(cities.lock().unwrap().plan(&leeds).access, Access::SecondaryIndex(_)));
+        assert_eq!(read_keys_where(&cities, &leeds), vec![1, 3, 4]);
+    }
+}
+
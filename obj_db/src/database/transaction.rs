@@ -0,0 +1,48 @@
+use serde_json::json;
+use super::conditional::Filter;
+use super::record::Record;
+
+/*
+ * MARK: TransactionWrite
+ * a parsed write waiting in a transaction, applied through the same table query it would have run
+ * as on its own once the transaction commits
+ */
+#[derive(Debug, Clone)]
+pub enum TransactionWrite {
+    Create { table: String, records: Vec<Record> },
+    Update { table: String, filter: Filter, assignments: Record },
+    Delete { table: String, filter: Filter },
+}
+
+impl TransactionWrite {
+    pub fn table(&self) -> &str {
+        match self {
+            TransactionWrite::Create { table, .. } => table,
+            TransactionWrite::Update { table, .. } => table,
+            TransactionWrite::Delete { table, .. } => table,
+        }
+    }
+}
+
+/*
+ * MARK: Transaction
+ * writes are only buffered here, nothing touches a table until the transaction commits, only the user
+ * who began it can add to it, commit it or roll it back
+ */
+#[derive(Debug, Clone)]
+pub struct Transaction {
+    pub id: u64,
+    pub owner: String,
+    pub writes: Vec<TransactionWrite>,
+}
+
+impl Transaction {
+    pub fn new(id: u64, owner: String) -> Self {
+        Transaction { id, owner, writes: vec![] }
+    }
+
+    pub fn buffer(&mut self, write: TransactionWrite) -> String {
+        self.writes.push(write);
+        json!({ "transaction": self.id, "buffered": self.writes.len() }).to_string()
+    }
+}
@@ -0,0 +1,229 @@
+use std::collections::HashSet;
+use std::fs;
+use std::fs::File;
+use std::io::Read;
+use std::io::Write;
+use serde::{Deserialize, Serialize};
+use super::part::Part;
+use super::record::Record;
+
+/*
+ * the operation that caused a part to be rewritten, kept alongside the image for auditing
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum LogOperation {
+    Create,
+    Update,
+    Delete,
+}
+
+/*
+ * a single part rewrite, records holds the records that were created, updated or deleted
+ * and image holds every record the part should contain once the operation has been applied
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogWrite {
+    pub part: String,
+    pub operation: LogOperation,
+    pub records: Vec<Record>,
+    pub image: Vec<Record>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum LogEntry {
+    Write { sequence: u64, write: LogWrite },
+    Commit { sequence: u64 },
+    Applied { sequence: u64 },
+}
+
+/*
+ * MARK: WriteAheadLog
+ * every part rewrite is appended here and fsynced before the part file is touched,
+ * a group of writes only counts once its commit entry is on disk and is replayed
+ * on startup if the matching applied entry never made it, while a transaction commits
+ * the rewrites are deferred so they can be logged as one group
+ */
+pub struct WriteAheadLog {
+    path: String,
+    sequence: u64,
+    outstanding: HashSet<u64>,
+    deferred: Option<Vec<LogWrite>>,
+}
+
+impl WriteAheadLog {
+    pub fn open(path: String) -> Result<Self, String> {
+        match fs::OpenOptions::new().create(true).append(true).open(&path) {
+            Ok(_) => Ok(WriteAheadLog { path, sequence: 0, outstanding: HashSet::new(), deferred: None }),
+            Err(e) => Err(["unable to open database log file\n".to_string(), e.to_string()].concat())
+        }
+    }
+
+    /*
+     * MARK: log writes
+     * append a group of part rewrites followed by its commit entry, returns the group sequence
+     */
+    pub fn log_writes(&mut self, writes: Vec<LogWrite>) -> Result<u64, String> {
+        self.sequence += 1;
+        let sequence = self.sequence;
+        let mut entries = writes.into_iter().map(|write| LogEntry::Write { sequence, write }).collect::<Vec<LogEntry>>();
+        entries.push(LogEntry::Commit { sequence });
+        self.append(&entries)?;
+        self.outstanding.insert(sequence);
+        Ok(sequence)
+    }
+
+    /*
+     * MARK: defer writes
+     * until the deferred writes are taken every part rewrite is held here instead of being logged and saved
+     */
+    pub fn defer(&mut self) {
+        self.deferred = Some(vec![]);
+    }
+
+    pub fn is_deferring(&self) -> bool {
+        self.deferred.is_some()
+    }
+
+    pub fn push_deferred(&mut self, write: LogWrite) {
+        if let Some(deferred) = self.deferred.as_mut() {
+            deferred.push(write);
+        }
+    }
+
+    pub fn take_deferred(&mut self) -> Vec<LogWrite> {
+        self.deferred.take().unwrap_or_default()
+    }
+
+    /*
+     * MARK: log applied
+     * mark a group as written to its part files, once nothing is outstanding the log is emptied
+     */
+    pub fn log_applied(&mut self, sequence: u64) -> Result<(), String> {
+        self.outstanding.remove(&sequence);
+        match self.outstanding.is_empty() {
+            true => self.checkpoint(),
+            false => self.append(&[LogEntry::Applied { sequence }])
+        }
+    }
+
+    /*
+     * MARK: replay
+     * rewrite every part whose committed group was never applied, then empty the log
+     */
+    pub fn replay(&mut self) -> Result<usize, String> {
+        let entries = self.read_entries()?;
+        let committed = entries.iter().filter_map(|entry| match entry { LogEntry::Commit { sequence } => Some(*sequence), _ => None }).collect::<HashSet<u64>>();
+        let applied = entries.iter().filter_map(|entry| match entry { LogEntry::Applied { sequence } => Some(*sequence), _ => None }).collect::<HashSet<u64>>();
+        let mut replayed = 0;
+        for entry in entries {
+            if let LogEntry::Write { sequence, write } = entry {
+                if committed.contains(&sequence) && !applied.contains(&sequence) {
+                    println!("replaying {:?} of part {}", write.operation, write.part);
+                    Part::write_records(&write.part, &write.image)?;
+                    replayed += 1;
+                }
+            }
+        }
+        self.checkpoint()?;
+        Ok(replayed)
+    }
+
+    fn checkpoint(&mut self) -> Result<(), String> {
+        match File::create(&self.path) {
+            Ok(file) => file.sync_all().map_err(|e| ["unable to sync database log file\n".to_string(), e.to_string()].concat()),
+            Err(e) => Err(["unable to truncate database log file\n".to_string(), e.to_string()].concat())
+        }
+    }
+
+    /*
+     * each entry is framed by its length so a torn append at the tail of the log can be detected and ignored
+     */
+    fn append(&mut self, entries: &[LogEntry]) -> Result<(), String> {
+        let mut writebuf: Vec<u8> = vec![];
+        for entry in entries {
+            let bytes = bincode::serialize(entry).map_err(|e| e.to_string())?;
+            writebuf.extend((bytes.len() as u64).to_le_bytes());
+            writebuf.extend(bytes);
+        }
+        match fs::OpenOptions::new().create(true).append(true).open(&self.path) {
+            Ok(mut file) => match file.write_all(&writebuf).and_then(|_| file.sync_all()) {
+                Ok(_) => Ok(()),
+                Err(e) => Err(["unable to append to database log file\n".to_string(), e.to_string()].concat())
+            },
+            Err(e) => Err(["unable to open database log file\n".to_string(), e.to_string()].concat())
+        }
+    }
+
+    fn read_entries(&self) -> Result<Vec<LogEntry>, String> {
+        let mut buf = vec![];
+        match File::open(&self.path) {
+            Ok(mut file) => { file.read_to_end(&mut buf).map_err(|e| e.to_string())?; },
+            Err(e) => return Err(["unable to open database log file\n".to_string(), e.to_string()].concat())
+        };
+        let mut entries = vec![];
+        let mut offset = 0;
+        while offset + 8 <= buf.len() {
+            let len = u64::from_le_bytes(buf[offset..offset + 8].try_into().unwrap()) as usize;
+            offset += 8;
+            if offset.checked_add(len).is_none_or(|end| end > buf.len()) {
+                println!("ignoring torn entry at the end of log {}", self.path);
+                break;
+            }
+            match bincode::deserialize::<LogEntry>(&buf[offset..offset + len]) {
+                Ok(entry) => entries.push(entry),
+                Err(e) => {
+                    println!("ignoring unreadable entry at the end of log {} {}", self.path, e);
+                    break;
+                }
+            }
+            offset += len;
+        }
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::cell::CellValue;
+
+    fn log(name: &str) -> WriteAheadLog {
+        crate::test_dir();
+        WriteAheadLog::open(name.to_owned()).unwrap()
+    }
+
+    fn write(part: &str, key: u128) -> LogWrite {
+        let record = Record::new(vec![CellValue::ULong { name: "id".to_owned(), data: Some(key) }]);
+        LogWrite { part: part.to_owned(), operation: LogOperation::Create, records: vec![record.clone()], image: vec![record] }
+    }
+
+    fn append_raw(path: &str, bytes: &[u8]) {
+        fs::OpenOptions::new().append(true).open(path).unwrap().write_all(bytes).unwrap();
+    }
+
+    #[test]
+    fn committed_groups_are_replayed_past_a_torn_tail() {
+        let mut wal = log("wal_torn_tail");
+        wal.log_writes(vec![write("wal_torn_tail_part", 1)]).unwrap();
+        // a length that runs past the end of the file
+        append_raw("wal_torn_tail", &[&16u64.to_le_bytes()[..], &[1, 2, 3]].concat());
+        assert_eq!(wal.replay().unwrap(), 1);
+        assert_eq!(Part::read_records("wal_torn_tail_part").unwrap().len(), 1);
+
+        // and one that would overflow the offset
+        wal.log_writes(vec![write("wal_torn_tail_part", 2)]).unwrap();
+        append_raw("wal_torn_tail", &u64::MAX.to_le_bytes());
+        assert_eq!(wal.replay().unwrap(), 1);
+        assert_eq!(wal.replay().unwrap(), 0);
+    }
+
+    #[test]
+    fn a_group_whose_commit_was_torn_off_is_not_replayed() {
+        let mut wal = log("wal_torn_commit");
+        wal.log_writes(vec![write("wal_torn_commit_part", 1)]).unwrap();
+        let buf = fs::read("wal_torn_commit").unwrap();
+        fs::write("wal_torn_commit", &buf[..buf.len() - 3]).unwrap();
+        assert_eq!(wal.replay().unwrap(), 0);
+        assert!(Part::read_records("wal_torn_commit_part").is_err());
+    }
+}
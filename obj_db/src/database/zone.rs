@@ -0,0 +1,271 @@
+use std::cmp::Ordering;
+use std::fs;
+use std::fs::File;
+use std::io::Read;
+use std::io::Write;
+use serde::{Deserialize, Serialize};
+use super::cell::CellValue;
+use super::conditional::{Condition, Conditional, Filter};
+use super::record::Record;
+
+/*
+ * MARK: ColumnZone
+ * the smallest and largest non null value of one column across a part and how many of its cells are null,
+ * min and max are None when every cell of the column is null
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ColumnZone {
+    pub column: String,
+    pub min: Option<CellValue>,
+    pub max: Option<CellValue>,
+    pub nulls: u32,
+    pub values: u32,
+}
+
+/*
+ * MARK: ZoneMap
+ * a summary of every column of a part, written next to the part file so a read can tell a part
+ * cannot hold a match without reloading it, a column no record of the part has is left out,
+ * the checksum is the one of the part file the zones were built from
+ */
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ZoneMap {
+    pub checksum: u32,
+    pub rows: u32,
+    pub columns: Vec<ColumnZone>,
+}
+
+impl ZoneMap {
+    pub fn path(part_path: &str) -> String {
+        [part_path, ".zone"].concat()
+    }
+
+    pub fn build(records: &[Record], checksum: u32) -> Self {
+        let mut zone_map = ZoneMap { checksum, ..ZoneMap::default() };
+        records.iter().for_each(|record| zone_map.add(record));
+        zone_map
+    }
+
+    /*
+     * widen the zones of the columns of the record, floats use their total order so NaN sits at the ends
+     */
+    pub fn add(&mut self, record: &Record) {
+        self.rows += 1;
+        for cell in record.columns.iter() {
+            let zone = match self.columns.iter().position(|zone| zone.column == cell.name()) {
+                Some(position) => &mut self.columns[position],
+                None => {
+                    self.columns.push(ColumnZone { column: cell.name().to_owned(), min: None, max: None, nulls: 0, values: 0 });
+                    self.columns.last_mut().unwrap()
+                }
+            };
+            if cell.is_null() {
+                zone.nulls += 1;
+                continue;
+            }
+            zone.values += 1;
+            if zone.min.as_ref().is_none_or(|min| cell.order_data(min) == Ordering::Less) {
+                zone.min = Some(cell.clone());
+            }
+            if zone.max.as_ref().is_none_or(|max| cell.order_data(max) == Ordering::Greater) {
+                zone.max = Some(cell.clone());
+            }
+        }
+    }
+
+    /*
+     * MARK: load
+     * read the zone file of a part, if it is missing, unreadable or was built from other records it is rebuilt from them
+     */
+    pub fn load(part_path: &str, records: &[Record], checksum: u32) -> Self {
+        let path = ZoneMap::path(part_path);
+        let mut buf = vec![];
+        let zone_map = match File::open(&path).and_then(|mut file| file.read_to_end(&mut buf)) {
+            Ok(_) => match bincode::deserialize::<ZoneMap>(&buf) {
+                Ok(zone_map) => Some(zone_map),
+                Err(e) => {
+                    println!("zone map {} unreadable {}", path, e);
+                    None
+                }
+            },
+            Err(_) => None
+        };
+        match zone_map {
+            Some(zone_map) if zone_map.checksum == checksum && zone_map.rows as usize == records.len() => zone_map,
+            _ => {
+                println!("rebuilding zone map {}", path);
+                let zone_map = ZoneMap::build(records, checksum);
+                if let Err(e) = zone_map.save(part_path) {
+                    println!("{}", e);
+                }
+                zone_map
+            }
+        }
+    }
+
+    pub fn save(&self, part_path: &str) -> Result<(), String> {
+        let path = ZoneMap::path(part_path);
+        let tmp_path = [&path, ".tmp"].concat();
+        let buf = match bincode::serialize(self) {
+            Ok(buf) => buf,
+            Err(e) => return Err(["unable to serialise zone map\n".to_string(), e.to_string()].concat())
+        };
+        match File::create(&tmp_path) {
+            Ok(mut file) => match file.write_all(&buf).and_then(|_| file.sync_all()) {
+                Ok(_) => {},
+                Err(e) => return Err(["unable to write zone map file\n".to_string(), e.to_string()].concat())
+            },
+            Err(e) => return Err(["unable to create zone map file\n".to_string(), e.to_string()].concat())
+        };
+        match fs::rename(&tmp_path, &path) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(["unable to replace zone map file\n".to_string(), e.to_string()].concat())
+        }
+    }
+
+    pub fn delete(part_path: &str) -> Result<(), String> {
+        match fs::remove_file(ZoneMap::path(part_path)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(["could not delete zone map file ", &e.to_string()].concat()),
+            _ => Ok(())
+        }
+    }
+
+    /*
+     * MARK: may match
+     * false only when no record of the part can satisfy the filter, anything the zones cannot decide,
+     * a NOT or a value of another type, is assumed to match
+     */
+    pub fn may_match(&self, filter: &Filter) -> bool {
+        match filter {
+            Filter::Condition(condition) => self.may_hold(condition),
+            Filter::And(filters) => filters.iter().all(|filter| self.may_match(filter)),
+            Filter::Or(filters) => filters.iter().any(|filter| self.may_match(filter)),
+            Filter::Not(_) => self.rows > 0,
+        }
+    }
+
+    fn may_hold(&self, condition: &Condition) -> bool {
+        if let Conditional::All = condition.conditional {
+            return self.rows > 0;
+        }
+        // a record without the column never matches a condition on it
+        let zone = match self.columns.iter().find(|zone| zone.column == condition.target_column) {
+            Some(zone) => zone,
+            None => return false
+        };
+        let (min, max) = match (&condition.conditional, &zone.min, &zone.max) {
+            (Conditional::IsNull, _, _) => return zone.nulls > 0,
+            (Conditional::IsNotNull | Conditional::Like(_) | Conditional::Regex(_), _, _) => return zone.values > 0,
+            (_, Some(min), Some(max)) => (min, max),
+            _ => return false
+        };
+        let holds = |cell: &CellValue, value: &CellValue, accepted: &[Ordering]| cell.compare_data(value).is_none_or(|ordering| accepted.contains(&ordering));
+        let within = |value: &CellValue| holds(min, value, &[Ordering::Less, Ordering::Equal]) && holds(max, value, &[Ordering::Greater, Ordering::Equal]);
+        match &condition.conditional {
+            Conditional::Equal => within(&condition.value),
+            Conditional::NotEqual => !(min.compare_data(&condition.value) == Some(Ordering::Equal) && max.compare_data(&condition.value) == Some(Ordering::Equal)),
+            Conditional::Greater => holds(max, &condition.value, &[Ordering::Greater]),
+            Conditional::EqualGreater => holds(max, &condition.value, &[Ordering::Greater, Ordering::Equal]),
+            Conditional::Smaller => holds(min, &condition.value, &[Ordering::Less]),
+            Conditional::EqualSmaller => holds(min, &condition.value, &[Ordering::Less, Ordering::Equal]),
+            Conditional::In(values) => values.iter().any(within),
+            Conditional::Between(low, high) => holds(max, low, &[Ordering::Greater, Ordering::Equal]) && holds(min, high, &[Ordering::Less, Ordering::Equal]),
+            _ => true
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use super::*;
+    use super::super::{cell::ReferentialAction, table::Table};
+
+    fn record(n: Option<i128>, s: Option<&str>) -> Record {
+        Record::new(vec![CellValue::ILong { name: "n".to_owned(), data: n }, CellValue::String { name: "s".to_owned(), data: s.map(|s| s.to_owned()) }])
+    }
+
+    fn number(n: i128) -> CellValue {
+        CellValue::ILong { name: "n".to_owned(), data: Some(n) }
+    }
+
+    fn condition(column: &str, conditional: Conditional, value: CellValue) -> Filter {
+        Filter::Condition(Condition { target_column: column.to_owned(), conditional, value, relational: None })
+    }
+
+    fn zone<'z>(zone_map: &'z ZoneMap, column: &str) -> &'z ColumnZone {
+        zone_map.columns.iter().find(|zone| zone.column == column).unwrap()
+    }
+
+    #[test]
+    fn zones_keep_the_bounds_and_null_count_of_each_column() {
+        let mut zone_map = ZoneMap::build(&[record(Some(3), Some("b")), record(None, Some("a")), record(Some(-1), None)], 7);
+        let n = zone(&zone_map, "n");
+        assert_eq!((n.min.clone(), n.max.clone(), n.nulls, n.values), (Some(number(-1)), Some(number(3)), 1, 2));
+        let s = zone(&zone_map, "s");
+        assert_eq!((s.min.as_ref().map(|min| min.data_str()), s.max.as_ref().map(|max| max.data_str()), s.nulls), (Some("a".to_owned()), Some("b".to_owned()), 1));
+
+        zone_map.add(&Record::new(vec![number(9), CellValue::Bool { name: "b".to_owned(), data: None }]));
+        assert_eq!((zone_map.rows, zone_map.checksum), (4, 7));
+        assert_eq!(zone(&zone_map, "n").max, Some(number(9)));
+        let b = zone(&zone_map, "b");
+        assert_eq!((b.min.is_none(), b.nulls, b.values), (true, 1, 0));
+    }
+
+    #[test]
+    fn a_zone_never_rules_out_a_record_that_matches() {
+        let records = [record(Some(3), Some("b")), record(None, Some("a")), record(Some(-1), None)];
+        let zone_map = ZoneMap::build(&records, 0);
+        let mut filters = vec![];
+        for value in -3..6 {
+            for conditional in [Conditional::Equal, Conditional::NotEqual, Conditional::Greater, Conditional::EqualGreater, Conditional::Smaller, Conditional::EqualSmaller] {
+                filters.push(condition("n", conditional, number(value)));
+            }
+            filters.push(condition("n", Conditional::In(vec![number(value), number(value + 2)]), number(0)));
+            filters.push(condition("n", Conditional::Between(number(value), number(value + 1)), number(0)));
+        }
+        for conditional in [Conditional::IsNull, Conditional::IsNotNull, Conditional::like("a%").unwrap()] {
+            filters.push(condition("n", conditional.clone(), number(0)));
+            filters.push(condition("s", conditional, CellValue::String { name: "s".to_owned(), data: None }));
+        }
+        filters.push(Filter::Not(Box::new(condition("n", Conditional::Equal, number(3)))));
+        for filter in filters {
+            if records.iter().any(|record| filter.evaluate(record)) {
+                assert!(zone_map.may_match(&filter), "{:?} matches a record but was ruled out", filter);
+            }
+        }
+        assert!(!zone_map.may_match(&condition("n", Conditional::Greater, number(3))));
+        assert!(!zone_map.may_match(&condition("n", Conditional::Between(number(4), number(8)), number(0))));
+        assert!(!zone_map.may_match(&condition("missing", Conditional::IsNull, number(0))));
+        assert!(!ZoneMap::build(&[record(Some(1), Some("a"))], 0).may_match(&condition("n", Conditional::IsNull, number(0))));
+    }
+
+    #[test]
+    fn a_part_the_zone_rules_out_is_skipped_and_writes_keep_its_zone_wide_enough() {
+        crate::test_dir();
+        let columns = vec![
+            ("id".to_owned(), "ULong".to_owned(), None, false, false, None, ReferentialAction::Restrict),
+            ("n".to_owned(), "ILong".to_owned(), None, true, false, None, ReferentialAction::Restrict),
+        ];
+        let table = Arc::new(Mutex::new(Table::new("zones".to_owned(), "numbers".to_owned(), columns, true)));
+        let tables = vec![Arc::clone(&table)];
+        let row = |n: Option<i128>| Record::new(vec![CellValue::ULong { name: "id".to_owned(), data: None }, CellValue::ILong { name: "n".to_owned(), data: n }]);
+        let equal = |n: i128| condition("n", Conditional::Equal, number(n));
+        let skipped = |filter: &Filter| table.lock().unwrap().plan(filter).skipped == vec![0];
+        table.lock().unwrap().query_create(vec![row(Some(1)), row(Some(2)), row(Some(3))], &tables).unwrap();
+
+        let above = condition("n", Conditional::Greater, number(10));
+        let plan = table.lock().unwrap().plan(&above);
+        assert_eq!((plan.skipped, plan.parts.len(), plan.estimated_rows), (vec![0], 0, 0));
+        assert!(!skipped(&equal(2)));
+
+        table.lock().unwrap().query_update_records(&equal(1), &Record::new(vec![number(50)]), &tables).unwrap();
+        assert!(!skipped(&equal(50)));
+        table.lock().unwrap().query_delete_records(&table, &equal(3), &tables).unwrap();
+        assert!(!skipped(&equal(2)) && !skipped(&equal(50)));
+        assert!(skipped(&condition("n", Conditional::IsNull, number(0))));
+        table.lock().unwrap().query_create(vec![row(None)], &tables).unwrap();
+        assert!(!skipped(&condition("n", Conditional::IsNull, number(0))));
+    }
+}
+
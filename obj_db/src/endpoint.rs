@@ -2,7 +2,7 @@ use std::sync::{Arc, Mutex, MutexGuard};
 
 use serde_json::Value;
 
-use crate::{database::{self, table, Database}, endpoint::query::QueryDatabaseCreateTable};
+use crate::{database::{self, access::{Permission, User}, table, Database}, endpoint::query::QueryDatabaseCreateTable, sql::Statement};
 
 pub mod runnable;
 pub mod query;
@@ -16,9 +16,16 @@ pub struct Endpoint<'a> {
 }
 
 impl<'a> Endpoint<'a> {
-    pub fn run(&mut self, mut database: Option<&mut MutexGuard<Database<'a>>>, body: Value, dir_override: Option<String>) {
+    pub fn run(&mut self, user: &User, mut database: Option<&mut MutexGuard<Database<'a>>>, body: Value, dir_override: Option<String>) {
+        if let Some(database) = database.as_mut() {
+            database.user = Some(user.name.clone());
+        }
+        let checked = self.check_role(user, database.as_ref().map(|database| &database.name[..]), &body);
         match self.runnable.try_lock() {
-            Ok(mut e) => e.run(Some(Arc::clone(&self.admin_db)), database, body, dir_override),
+            Ok(mut e) => match checked {
+                Ok(_) => e.run(Some(Arc::clone(&self.admin_db)), database, body, dir_override),
+                Err(reason) => e.refuse(reason)
+            },
             Err(e) => panic!("shits fucked")
         }
     }
@@ -37,8 +44,59 @@ impl<'a> Endpoint<'a> {
         }
     }
 
-    fn check_role(&self) {
-        todo!()
+    pub fn database(&self) -> Result<Arc<Mutex<Database<'a>>>, String> {
+        match self.runnable.try_lock(){
+            Ok(e) => e.database(),
+            Err(e) => Err(e.to_string())
+        }
+    }
+
+    /* 
+     * MARK: check role
+     * the role of the endpoint is the role its database was created for, users with that role or the admin role
+     * can run it and anyone else needs a grant for each table it touches, server endpoints without a database
+     * are checked against the database named in the body
+     */
+    fn check_role(&self, user: &User, database: Option<&str>, body: &Value) -> Result<(), String> {
+        let database = database.or(body["database_name"].as_str()).unwrap_or(database::access::ANY);
+        if !user.accesses(&self.role, database) {
+            return Err(["user ", &user.name, " has no access to database ", database].concat());
+        }
+        match self.permissions(body).into_iter().find(|(table, permission)| !user.allows(&self.role, database, table.as_deref(), *permission)) {
+            Some((table, permission)) => Err(["user ", &user.name, " is not granted ", permission.name(), " on ", database, &table.map(|table| [".", &table].concat()).unwrap_or_default()].concat()),
+            None => Ok(())
+        }
+    }
+
+    /* 
+     * what a run of the endpoint needs, as the table and permission of each thing it does, a table of None is the
+     * database as a whole, transactions only need access to the database as each write is checked when it is buffered
+     */
+    fn permissions(&self, body: &Value) -> Vec<(Option<String>, Permission)> {
+        let table = self.table().ok().and_then(|table| table.try_lock().ok().map(|table| table.name.clone()));
+        let named = body["table_name"].as_str().map(|table_name| table_name.to_owned());
+        match &self.name[..] {
+            "create_record" => vec![(table, Permission::Create)],
+            "read_record" => vec![(table, Permission::Read)],
+            "update_record" => vec![(table, Permission::Update)],
+            "delete_record" => vec![(table, Permission::Delete)],
+            "join" => std::iter::once(named)
+                .chain(body["joins"].as_array().into_iter().flatten().map(|join| join["table_name"].as_str().map(|table_name| table_name.to_owned())))
+                .map(|table| (table, Permission::Read))
+                .collect(),
+            "sql" => match Statement::parse(body.as_str().or(body["sql"].as_str()).unwrap_or_default()) {
+                Ok(Statement::CreateTable { table_name, .. }) | Ok(Statement::DropTable { table_name }) => vec![(Some(table_name), Permission::Schema)],
+                Ok(Statement::Insert { table_name, .. }) => vec![(Some(table_name), Permission::Create)],
+                Ok(Statement::Select { table_name, .. }) => vec![(Some(table_name), Permission::Read)],
+                Ok(Statement::Update { table_name, .. }) => vec![(Some(table_name), Permission::Update)],
+                Ok(Statement::Delete { table_name, .. }) => vec![(Some(table_name), Permission::Delete)],
+                Err(_) => vec![]
+            },
+            "update_table" if body["migration"].as_str() == Some("history") => vec![(named, Permission::Read)],
+            "create_table" | "update_table" | "delete_table" | "create_index" | "drop_index" => vec![(named, Permission::Schema)],
+            "begin" | "commit" | "rollback" => vec![],
+            _ => vec![(None, Permission::Schema)]
+        }
     }
 
     /* 
@@ -47,7 +105,12 @@ impl<'a> Endpoint<'a> {
      * generate admin endpoints that a new database server should have
      */
     pub fn new_server(admin_db: Arc<Mutex<Database<'a>>>, role: String) -> Vec<Self> {
-        vec![Endpoint { name: "create_database".to_owned(), role: role.clone(), admin_db: Arc::clone(&admin_db), runnable: Arc::new(Mutex::new(runnable::Runnable::Query(query::Query::QueryNewDatabase(query::QueryNewDatabase::new("create_database".to_owned()))))) }]
+        vec![
+            Endpoint { name: "create_database".to_owned(), role: role.clone(), admin_db: Arc::clone(&admin_db), runnable: Arc::new(Mutex::new(runnable::Runnable::Query(query::Query::QueryNewDatabase(query::QueryNewDatabase::new("create_database".to_owned()))))) },
+            Endpoint { name: "drop_database".to_owned(), role: role.clone(), admin_db: Arc::clone(&admin_db), runnable: Arc::new(Mutex::new(runnable::Runnable::Query(query::Query::QueryDropDatabase(query::QueryDropDatabase::new("drop_database".to_owned()))))) },
+            Endpoint { name: "rename_database".to_owned(), role: role.clone(), admin_db: Arc::clone(&admin_db), runnable: Arc::new(Mutex::new(runnable::Runnable::Query(query::Query::QueryRenameDatabase(query::QueryRenameDatabase::new("rename_database".to_owned()))))) },
+            Endpoint { name: "clone_database".to_owned(), role: role.clone(), admin_db: Arc::clone(&admin_db), runnable: Arc::new(Mutex::new(runnable::Runnable::Query(query::Query::QueryCloneDatabase(query::QueryCloneDatabase::new("clone_database".to_owned()))))) }
+        ]
     }
 
     /* 
@@ -60,6 +123,14 @@ impl<'a> Endpoint<'a> {
             Arc::new(Mutex::new(Endpoint { name: "create_table".to_owned(), role: role.clone(), admin_db: Arc::clone(&admin_db), runnable: Arc::new(Mutex::new(runnable::Runnable::Query(query::Query::QueryDatabase(query::QueryDatabase::QueryDatabaseCreateTable(query::QueryDatabaseCreateTable::new("create_table".to_owned() )))))) })),
             Arc::new(Mutex::new(Endpoint { name: "update_table".to_owned(), role: role.clone(), admin_db: Arc::clone(&admin_db), runnable: Arc::new(Mutex::new(runnable::Runnable::Query(query::Query::QueryDatabase(query::QueryDatabase::QueryDatabaseUpdateTable(query::QueryDatabaseUpdateTable::new("update_table".to_owned() )))))) })),
             Arc::new(Mutex::new(Endpoint { name: "delete_table".to_owned(), role: role.clone(), admin_db: Arc::clone(&admin_db), runnable: Arc::new(Mutex::new(runnable::Runnable::Query(query::Query::QueryDatabase(query::QueryDatabase::QueryDatabaseDeleteTable(query::QueryDatabaseDeleteTable::new("delete_table".to_owned() )))))) })),
+            Arc::new(Mutex::new(Endpoint { name: "create_index".to_owned(), role: role.clone(), admin_db: Arc::clone(&admin_db), runnable: Arc::new(Mutex::new(runnable::Runnable::Query(query::Query::QueryDatabase(query::QueryDatabase::QueryDatabaseCreateIndex(query::QueryDatabaseCreateIndex::new("create_index".to_owned() )))))) })),
+            Arc::new(Mutex::new(Endpoint { name: "drop_index".to_owned(), role: role.clone(), admin_db: Arc::clone(&admin_db), runnable: Arc::new(Mutex::new(runnable::Runnable::Query(query::Query::QueryDatabase(query::QueryDatabase::QueryDatabaseDropIndex(query::QueryDatabaseDropIndex::new("drop_index".to_owned() )))))) })),
+            Arc::new(Mutex::new(Endpoint { name: "join".to_owned(), role: role.clone(), admin_db: Arc::clone(&admin_db), runnable: Arc::new(Mutex::new(runnable::Runnable::Query(query::Query::QueryDatabase(query::QueryDatabase::QueryDatabaseJoin(query::QueryDatabaseJoin::new("join".to_owned() )))))) })),
+            Arc::new(Mutex::new(Endpoint { name: "sql".to_owned(), role: role.clone(), admin_db: Arc::clone(&admin_db), runnable: Arc::new(Mutex::new(runnable::Runnable::Query(query::Query::QueryDatabase(query::QueryDatabase::QueryDatabaseSql(query::QueryDatabaseSql::new("sql".to_owned() )))))) })),
+            Arc::new(Mutex::new(Endpoint { name: "begin".to_owned(), role: role.clone(), admin_db: Arc::clone(&admin_db), runnable: Arc::new(Mutex::new(runnable::Runnable::Query(query::Query::QueryDatabase(query::QueryDatabase::QueryDatabaseBegin(query::QueryDatabaseBegin::new("begin".to_owned() )))))) })),
+            Arc::new(Mutex::new(Endpoint { name: "commit".to_owned(), role: role.clone(), admin_db: Arc::clone(&admin_db), runnable: Arc::new(Mutex::new(runnable::Runnable::Query(query::Query::QueryDatabase(query::QueryDatabase::QueryDatabaseCommit(query::QueryDatabaseCommit::new("commit".to_owned() )))))) })),
+            Arc::new(Mutex::new(Endpoint { name: "rollback".to_owned(), role: role.clone(), admin_db: Arc::clone(&admin_db), runnable: Arc::new(Mutex::new(runnable::Runnable::Query(query::Query::QueryDatabase(query::QueryDatabase::QueryDatabaseRollback(query::QueryDatabaseRollback::new("rollback".to_owned() )))))) })),
+            Arc::new(Mutex::new(Endpoint { name: "reset".to_owned(), role: role.clone(), admin_db: Arc::clone(&admin_db), runnable: Arc::new(Mutex::new(runnable::Runnable::Query(query::Query::QueryDatabase(query::QueryDatabase::QueryDatabaseReset(query::QueryDatabaseReset::new("reset".to_owned() )))))) })),
             Arc::new(Mutex::new(Endpoint { name: "indev_toggle".to_owned(), role: role.clone(), admin_db: Arc::clone(&admin_db), runnable: Arc::new(Mutex::new(runnable::Runnable::Query(query::Query::QueryDatabase(query::QueryDatabase::QueryDatabaseInDevToggle(query::QueryDatabaseInDevToggle::new("indev_toggle".to_owned() )))))) }))
         ]
     }
@@ -74,6 +145,14 @@ impl<'a> Endpoint<'a> {
             Arc::new(Mutex::new(Endpoint { name: "create_table".to_owned(), role: role.clone(), admin_db: Arc::clone(&admin_db), runnable: Arc::new(Mutex::new(runnable::Runnable::Query(query::Query::QueryDatabase(query::QueryDatabase::QueryDatabaseCreateTable(query::QueryDatabaseCreateTable::new("create_table".to_owned() )))))) })),
             Arc::new(Mutex::new(Endpoint { name: "update_table".to_owned(), role: role.clone(), admin_db: Arc::clone(&admin_db), runnable: Arc::new(Mutex::new(runnable::Runnable::Query(query::Query::QueryDatabase(query::QueryDatabase::QueryDatabaseUpdateTable(query::QueryDatabaseUpdateTable::new("update_table".to_owned() )))))) })),
             Arc::new(Mutex::new(Endpoint { name: "delete_table".to_owned(), role: role.clone(), admin_db: Arc::clone(&admin_db), runnable: Arc::new(Mutex::new(runnable::Runnable::Query(query::Query::QueryDatabase(query::QueryDatabase::QueryDatabaseDeleteTable(query::QueryDatabaseDeleteTable::new("delete_table".to_owned() )))))) })),
+            Arc::new(Mutex::new(Endpoint { name: "create_index".to_owned(), role: role.clone(), admin_db: Arc::clone(&admin_db), runnable: Arc::new(Mutex::new(runnable::Runnable::Query(query::Query::QueryDatabase(query::QueryDatabase::QueryDatabaseCreateIndex(query::QueryDatabaseCreateIndex::new("create_index".to_owned() )))))) })),
+            Arc::new(Mutex::new(Endpoint { name: "drop_index".to_owned(), role: role.clone(), admin_db: Arc::clone(&admin_db), runnable: Arc::new(Mutex::new(runnable::Runnable::Query(query::Query::QueryDatabase(query::QueryDatabase::QueryDatabaseDropIndex(query::QueryDatabaseDropIndex::new("drop_index".to_owned() )))))) })),
+            Arc::new(Mutex::new(Endpoint { name: "join".to_owned(), role: role.clone(), admin_db: Arc::clone(&admin_db), runnable: Arc::new(Mutex::new(runnable::Runnable::Query(query::Query::QueryDatabase(query::QueryDatabase::QueryDatabaseJoin(query::QueryDatabaseJoin::new("join".to_owned() )))))) })),
+            Arc::new(Mutex::new(Endpoint { name: "sql".to_owned(), role: role.clone(), admin_db: Arc::clone(&admin_db), runnable: Arc::new(Mutex::new(runnable::Runnable::Query(query::Query::QueryDatabase(query::QueryDatabase::QueryDatabaseSql(query::QueryDatabaseSql::new("sql".to_owned() )))))) })),
+            Arc::new(Mutex::new(Endpoint { name: "begin".to_owned(), role: role.clone(), admin_db: Arc::clone(&admin_db), runnable: Arc::new(Mutex::new(runnable::Runnable::Query(query::Query::QueryDatabase(query::QueryDatabase::QueryDatabaseBegin(query::QueryDatabaseBegin::new("begin".to_owned() )))))) })),
+            Arc::new(Mutex::new(Endpoint { name: "commit".to_owned(), role: role.clone(), admin_db: Arc::clone(&admin_db), runnable: Arc::new(Mutex::new(runnable::Runnable::Query(query::Query::QueryDatabase(query::QueryDatabase::QueryDatabaseCommit(query::QueryDatabaseCommit::new("commit".to_owned() )))))) })),
+            Arc::new(Mutex::new(Endpoint { name: "rollback".to_owned(), role: role.clone(), admin_db: Arc::clone(&admin_db), runnable: Arc::new(Mutex::new(runnable::Runnable::Query(query::Query::QueryDatabase(query::QueryDatabase::QueryDatabaseRollback(query::QueryDatabaseRollback::new("rollback".to_owned() )))))) })),
+            Arc::new(Mutex::new(Endpoint { name: "reset".to_owned(), role: role.clone(), admin_db: Arc::clone(&admin_db), runnable: Arc::new(Mutex::new(runnable::Runnable::Query(query::Query::QueryDatabase(query::QueryDatabase::QueryDatabaseReset(query::QueryDatabaseReset::new("reset".to_owned() )))))) })),
             Arc::new(Mutex::new(Endpoint { name: "indev_toggle".to_owned(), role: role.clone(), admin_db: Arc::clone(&admin_db), runnable: Arc::new(Mutex::new(runnable::Runnable::Query(query::Query::QueryDatabase(query::QueryDatabase::QueryDatabaseInDevToggle(query::QueryDatabaseInDevToggle::new("indev_toggle".to_owned() )))))) }))
         ]
     }
@@ -88,7 +167,19 @@ impl<'a> Endpoint<'a> {
             Arc::new(Mutex::new(Endpoint { name: "create_table".to_owned(), role: role.clone(), admin_db: Arc::clone(&database), runnable: Arc::new(Mutex::new(runnable::Runnable::Query(query::Query::QueryDatabase(query::QueryDatabase::QueryDatabaseCreateTable(query::QueryDatabaseCreateTable::new("create_table".to_owned() )))))) })),
             Arc::new(Mutex::new(Endpoint { name: "update_table".to_owned(), role: role.clone(), admin_db: Arc::clone(&database), runnable: Arc::new(Mutex::new(runnable::Runnable::Query(query::Query::QueryDatabase(query::QueryDatabase::QueryDatabaseUpdateTable(query::QueryDatabaseUpdateTable::new("update_table".to_owned() )))))) })),
             Arc::new(Mutex::new(Endpoint { name: "delete_table".to_owned(), role: role.clone(), admin_db: Arc::clone(&database), runnable: Arc::new(Mutex::new(runnable::Runnable::Query(query::Query::QueryDatabase(query::QueryDatabase::QueryDatabaseDeleteTable(query::QueryDatabaseDeleteTable::new("delete_table".to_owned() )))))) })),
-            Arc::new(Mutex::new(Endpoint { name: "indev_toggle".to_owned(), role: role.clone(), admin_db: Arc::clone(&database), runnable: Arc::new(Mutex::new(runnable::Runnable::Query(query::Query::QueryDatabase(query::QueryDatabase::QueryDatabaseInDevToggle(query::QueryDatabaseInDevToggle::new("indev_toggle".to_owned() )))))) }))
+            Arc::new(Mutex::new(Endpoint { name: "create_index".to_owned(), role: role.clone(), admin_db: Arc::clone(&database), runnable: Arc::new(Mutex::new(runnable::Runnable::Query(query::Query::QueryDatabase(query::QueryDatabase::QueryDatabaseCreateIndex(query::QueryDatabaseCreateIndex::new("create_index".to_owned() )))))) })),
+            Arc::new(Mutex::new(Endpoint { name: "drop_index".to_owned(), role: role.clone(), admin_db: Arc::clone(&database), runnable: Arc::new(Mutex::new(runnable::Runnable::Query(query::Query::QueryDatabase(query::QueryDatabase::QueryDatabaseDropIndex(query::QueryDatabaseDropIndex::new("drop_index".to_owned() )))))) })),
+            Arc::new(Mutex::new(Endpoint { name: "join".to_owned(), role: role.clone(), admin_db: Arc::clone(&database), runnable: Arc::new(Mutex::new(runnable::Runnable::Query(query::Query::QueryDatabase(query::QueryDatabase::QueryDatabaseJoin(query::QueryDatabaseJoin::new("join".to_owned() )))))) })),
+            Arc::new(Mutex::new(Endpoint { name: "sql".to_owned(), role: role.clone(), admin_db: Arc::clone(&database), runnable: Arc::new(Mutex::new(runnable::Runnable::Query(query::Query::QueryDatabase(query::QueryDatabase::QueryDatabaseSql(query::QueryDatabaseSql::new("sql".to_owned() )))))) })),
+            Arc::new(Mutex::new(Endpoint { name: "begin".to_owned(), role: role.clone(), admin_db: Arc::clone(&database), runnable: Arc::new(Mutex::new(runnable::Runnable::Query(query::Query::QueryDatabase(query::QueryDatabase::QueryDatabaseBegin(query::QueryDatabaseBegin::new("begin".to_owned() )))))) })),
+            Arc::new(Mutex::new(Endpoint { name: "commit".to_owned(), role: role.clone(), admin_db: Arc::clone(&database), runnable: Arc::new(Mutex::new(runnable::Runnable::Query(query::Query::QueryDatabase(query::QueryDatabase::QueryDatabaseCommit(query::QueryDatabaseCommit::new("commit".to_owned() )))))) })),
+            Arc::new(Mutex::new(Endpoint { name: "rollback".to_owned(), role: role.clone(), admin_db: Arc::clone(&database), runnable: Arc::new(Mutex::new(runnable::Runnable::Query(query::Query::QueryDatabase(query::QueryDatabase::QueryDatabaseRollback(query::QueryDatabaseRollback::new("rollback".to_owned() )))))) })),
+            Arc::new(Mutex::new(Endpoint { name: "reset".to_owned(), role: role.clone(), admin_db: Arc::clone(&database), runnable: Arc::new(Mutex::new(runnable::Runnable::Query(query::Query::QueryDatabase(query::QueryDatabase::QueryDatabaseReset(query::QueryDatabaseReset::new("reset".to_owned() )))))) })),
+            Arc::new(Mutex::new(Endpoint { name: "indev_toggle".to_owned(), role: role.clone(), admin_db: Arc::clone(&database), runnable: Arc::new(Mutex::new(runnable::Runnable::Query(query::Query::QueryDatabase(query::QueryDatabase::QueryDatabaseInDevToggle(query::QueryDatabaseInDevToggle::new("indev_toggle".to_owned() )))))) })),
+            Arc::new(Mutex::new(Endpoint { name: "create_user".to_owned(), role: role.clone(), admin_db: Arc::clone(&database), runnable: Arc::new(Mutex::new(runnable::Runnable::Query(query::Query::QueryDatabase(query::QueryDatabase::QueryDatabaseCreateUser(query::QueryDatabaseCreateUser::new("create_user".to_owned() )))))) })),
+            Arc::new(Mutex::new(Endpoint { name: "drop_user".to_owned(), role: role.clone(), admin_db: Arc::clone(&database), runnable: Arc::new(Mutex::new(runnable::Runnable::Query(query::Query::QueryDatabase(query::QueryDatabase::QueryDatabaseDropUser(query::QueryDatabaseDropUser::new("drop_user".to_owned() )))))) })),
+            Arc::new(Mutex::new(Endpoint { name: "grant".to_owned(), role: role.clone(), admin_db: Arc::clone(&database), runnable: Arc::new(Mutex::new(runnable::Runnable::Query(query::Query::QueryDatabase(query::QueryDatabase::QueryDatabaseGrant(query::QueryDatabaseGrant::new("grant".to_owned() )))))) })),
+            Arc::new(Mutex::new(Endpoint { name: "revoke".to_owned(), role: role.clone(), admin_db: Arc::clone(&database), runnable: Arc::new(Mutex::new(runnable::Runnable::Query(query::Query::QueryDatabase(query::QueryDatabase::QueryDatabaseRevoke(query::QueryDatabaseRevoke::new("revoke".to_owned() )))))) }))
         ]
     }
 
@@ -2,9 +2,9 @@
 use std::{cell::Cell, default, fs::DirEntry, sync::{Arc, Mutex, MutexGuard}};
 
 use regex::Regex;
-use serde_json::Value;
+use serde_json::{json, Value};
 
-use crate::database::{self, cell::{self, CellValue}, conditional::{self, Condition, Conditional, Relation}, record::{self, Record}, table, Database};
+use crate::{sql::Statement, database::{self, access::{Grant, Permission}, aggregate::{Aggregate, AggregateFn}, cell::{self, CellValue}, join::{Join, JoinKind}, migration::{Conversion, Migration}, read::{Cursor, OrderBy, ReadOptions}, conditional::{self, Condition, Conditional, Filter, FilterToken, Relation}, record::{self, Record}, table, transaction::{Transaction, TransactionWrite}, Database}};
 
 /* 
  * MARK: Query
@@ -13,6 +13,9 @@ use crate::database::{self, cell::{self, CellValue}, conditional::{self, Conditi
  */
 pub enum Query<'a> {
     QueryNewDatabase(QueryNewDatabase<'a>),
+    QueryDropDatabase(QueryDropDatabase),
+    QueryRenameDatabase(QueryRenameDatabase<'a>),
+    QueryCloneDatabase(QueryCloneDatabase<'a>),
     QueryDatabase(QueryDatabase),
     QueryTable(QueryTable),
 }
@@ -21,26 +24,61 @@ impl<'a> Query<'a> {
     pub fn run(&mut self, admin_db: Option<Arc<Mutex<Database<'a>>>>, mut database: Option<&mut MutexGuard<Database<'a>>>, body: Value, dir_override: Option<String>) {
         match self {
             Query::QueryNewDatabase(qnd) => qnd.run(admin_db, body, dir_override),
+            Query::QueryDropDatabase(qdd) => match database { Some(db) => qdd.run(db), None => qdd.result = Err("no db pointer found".to_owned())},
+            Query::QueryRenameDatabase(qrd) => match database { Some(db) => qrd.parse(admin_db, db, body, dir_override), None => qrd.result = Err("no db pointer found".to_owned())},
+            Query::QueryCloneDatabase(qcd) => match database { Some(db) => qcd.parse(admin_db, db, body, dir_override), None => qcd.result = Err("no db pointer found".to_owned())},
             Query::QueryDatabase(qd) => match admin_db{ Some(admin_db) => qd.run(admin_db, database, body),  None => qd.set_result(Err("admin database not initialised or not attached".to_owned()))},
-            Query::QueryTable(qt) => qt.run(body),
+            Query::QueryTable(qt) => qt.run(database, body),
         }
     }
 
-    pub fn result(&self) -> Result<String, String> {
+    pub fn result(&mut self) -> Result<String, String> {
         match self {
             Query::QueryNewDatabase(qnd) => match &qnd.result.clone() { Ok(_) => Ok("database creation successful".to_owned()), Err(e) => Err(e.clone()) },
+            Query::QueryDropDatabase(qdd) => qdd.result.clone(),
+            Query::QueryRenameDatabase(qrd) => match &qrd.result { Ok(_) => Ok("database rename successful".to_owned()), Err(e) => Err(e.clone()) },
+            Query::QueryCloneDatabase(qcd) => match &qcd.result { Ok(_) => Ok("database clone successful".to_owned()), Err(e) => Err(e.clone()) },
             Query::QueryDatabase(qd) => qd.result(),
             Query::QueryTable(qt) => qt.result(),
         }
     }
 
+    /* 
+     * fail the query without running it, used when the user sending it is not allowed to
+     */
+    pub fn refuse(&mut self, reason: String) {
+        match self {
+            Query::QueryNewDatabase(qnd) => qnd.result = Err(reason),
+            Query::QueryDropDatabase(qdd) => qdd.result = Err(reason),
+            Query::QueryRenameDatabase(qrd) => qrd.result = Err(reason),
+            Query::QueryCloneDatabase(qcd) => qcd.result = Err(reason),
+            Query::QueryDatabase(qd) => qd.set_result(Err(reason)),
+            Query::QueryTable(qt) => qt.set_result(Err(reason)),
+        }
+    }
+
     pub fn table(&self) -> Result<Arc<Mutex<table::Table>>, String> {
         match self {
             Query::QueryNewDatabase(_) => Err("not a table query".to_owned()),
+            Query::QueryDropDatabase(_) => Err("not a table query".to_owned()),
+            Query::QueryRenameDatabase(_) => Err("not a table query".to_owned()),
+            Query::QueryCloneDatabase(_) => Err("not a table query".to_owned()),
             Query::QueryDatabase(_) => Err("not a table query".to_owned()),
             Query::QueryTable(qt) => qt.table(),
         }
     }
+
+    /* 
+     * the database a server query built, which takes the place of the one it was run on
+     */
+    pub fn database(&self) -> Result<Arc<Mutex<Database<'a>>>, String> {
+        match self {
+            Query::QueryNewDatabase(qnd) => qnd.result.clone(),
+            Query::QueryRenameDatabase(qrd) => qrd.result.clone(),
+            Query::QueryCloneDatabase(qcd) => qcd.result.clone(),
+            _ => Err("not a query that builds a database".to_owned()),
+        }
+    }
 }
 
 /* 
@@ -69,6 +107,364 @@ impl<'a> QueryNewDatabase<'a> {
     }
 }
 
+/* 
+ * MARK: QueryDropDatabase
+ * delete an indev database and everything in it, the server forgets it once the files are gone
+ */
+pub struct QueryDropDatabase { name: String, pub result: Result<String, String> }
+
+impl QueryDropDatabase {
+    pub fn new(qname: String) -> Self {
+        QueryDropDatabase { name: qname, result: Err("Query has not yet been run".to_owned()) }
+    }
+
+    pub fn run(&mut self, database: &mut MutexGuard<Database>) {
+        self.result = database.drop_dir()
+    }
+}
+
+/* 
+ * the name a database is renamed or cloned to, read from new_name in the body
+ */
+fn parse_new_name(body: &Value) -> Result<String, String> {
+    match body["new_name"].as_str() {
+        Some(new_name) => Ok(new_name.to_owned()),
+        None => Err("could not parse new_name".to_owned())
+    }
+}
+
+/* 
+ * MARK: QueryRenameDatabase
+ * move an indev database to a new name, the result is the database built again from its moved directory
+ */
+pub struct QueryRenameDatabase<'a> { name: String, pub result: Result<Arc<Mutex<Database<'a>>>, String> }
+
+impl<'a> QueryRenameDatabase<'a> {
+    pub fn new(qname: String) -> Self {
+        QueryRenameDatabase { name: qname, result: Err("Query has not yet been run".to_owned()) }
+    }
+
+    pub fn parse(&mut self, admin_db: Option<Arc<Mutex<Database<'a>>>>, database: &mut MutexGuard<Database<'a>>, body: Value, dir_override: Option<String>) {
+        match parse_new_name(&body) {
+            Ok(new_name) => self.run(admin_db, database, new_name, dir_override),
+            Err(e) => self.result = Err(e)
+        }
+    }
+
+    pub fn run(&mut self, admin_db: Option<Arc<Mutex<Database<'a>>>>, database: &mut MutexGuard<Database<'a>>, new_name: String, dir_override: Option<String>) {
+        self.result = database.rename_dir(&new_name, dir_override.clone()).and_then(|_| Database::build_from_dir(new_name, admin_db, dir_override))
+    }
+}
+
+/* 
+ * MARK: QueryCloneDatabase
+ * copy a database to a new name, the result is the copy built from its own directory
+ */
+pub struct QueryCloneDatabase<'a> { name: String, pub result: Result<Arc<Mutex<Database<'a>>>, String> }
+
+impl<'a> QueryCloneDatabase<'a> {
+    pub fn new(qname: String) -> Self {
+        QueryCloneDatabase { name: qname, result: Err("Query has not yet been run".to_owned()) }
+    }
+
+    pub fn parse(&mut self, admin_db: Option<Arc<Mutex<Database<'a>>>>, database: &mut MutexGuard<Database<'a>>, body: Value, dir_override: Option<String>) {
+        match parse_new_name(&body) {
+            Ok(new_name) => self.run(admin_db, database, new_name, dir_override),
+            Err(e) => self.result = Err(e)
+        }
+    }
+
+    pub fn run(&mut self, admin_db: Option<Arc<Mutex<Database<'a>>>>, database: &mut MutexGuard<Database<'a>>, new_name: String, dir_override: Option<String>) {
+        self.result = database.clone_dir(&new_name, dir_override.clone()).and_then(|_| Database::build_from_dir(new_name, admin_db, dir_override))
+    }
+}
+
+/* 
+ * MARK: table column definitions
+ * name and type template of every column on the table
+ */
+fn table_coldefs(table: &Arc<Mutex<table::Table>>) -> Vec<(String, CellValue)> {
+    match table.try_lock() {
+        Ok(table) => table.column_definition.iter().map(|celldef| match celldef { 
+            cell::Cell::CellDef { name, ctype, .. } => (name.clone(), ctype.clone()),
+            _ => ("".to_owned(), cell::CellValue::String{ name: "".to_owned(), data: None }) })
+            .filter(|celldef| !celldef.0.is_empty())
+            .collect::<Vec<(String, CellValue)>>(),
+        Err(e) =>panic!("{}", ["shits fucked ".to_owned(), e.to_string()].concat())
+    }
+}
+
+/* 
+ * MARK: parse condition
+ * a condition is either ["*"], [column, "IS NULL"], [column, "IS NOT NULL"] or [column, conditional, value]
+ * with an optional relation to the next condition as a fourth element, IN takes an array of values,
+ * BETWEEN an array of the low and high value and LIKE and REGEX a pattern string
+ */
+fn parse_condition(coldefs: &[(String, CellValue)], condition: &Value) -> Result<Condition, String> {
+    match condition.as_array() {
+        Some(conditionarr) => match conditionarr.len() {
+            1 => match conditionarr[0].as_str() {
+                Some("*") => Ok(Condition { target_column: "".to_owned(), conditional: Conditional::All, value: CellValue::Bool { name: "".to_owned(), data: None }, relational: None }),
+                _ => Err(["condition ", &condition.to_string(), " is not recognised"].concat())
+            },
+            2..=4 => {
+                let target_column = conditionarr[0].as_str().unwrap_or("");
+                let operator = conditionarr[1].as_str().unwrap_or("");
+                let operand = conditionarr.get(2).unwrap_or(&Value::Null);
+                match coldefs.iter().find(|celldef| celldef.0 == target_column) {
+                    Some(celldef) => {
+                        let (conditional, value) = match &operator.to_uppercase()[..] {
+                            "IN" => match operand.as_array() {
+                                Some(values) => (Conditional::In(values.iter().map(|value| celldef.1.parse_json(value)).collect::<Result<Vec<CellValue>, String>>()?), celldef.1.as_null()),
+                                None => return Err(["IN on ", target_column, " needs an array of values"].concat())
+                            },
+                            "BETWEEN" => match operand.as_array().map(|range| range.as_slice()) {
+                                Some([low, high]) => (Conditional::Between(celldef.1.parse_json(low)?, celldef.1.parse_json(high)?), celldef.1.as_null()),
+                                _ => return Err(["BETWEEN on ", target_column, " needs an array of the low and high value"].concat())
+                            },
+                            "LIKE" => match operand.as_str() {
+                                Some(pattern) => (Conditional::like(pattern)?, celldef.1.as_null()),
+                                None => return Err(["LIKE on ", target_column, " needs a pattern string"].concat())
+                            },
+                            "REGEX" | "~" => match operand.as_str() {
+                                Some(pattern) => (Conditional::regex(pattern)?, celldef.1.as_null()),
+                                None => return Err(["REGEX on ", target_column, " needs a pattern string"].concat())
+                            },
+                            _ => (Conditional::parse(operator.to_owned())?, celldef.1.parse_json(operand)?)
+                        };
+                        Ok(Condition { 
+                            target_column: target_column.to_owned(), 
+                            conditional, 
+                            value, 
+                            relational: match conditionarr.get(3) { Some(relation) => Some(Relation::parse(relation.as_str().unwrap_or("").to_owned())?), None => None } 
+                        })
+                    },
+                    None => Err(["target column \"", target_column, "\" does not exist on target table"].concat())
+                }
+            },
+            _ => Err(["condition ", &condition.to_string(), " has the wrong number of elements"].concat())
+        },
+        None => Err(["condition ", &condition.to_string(), " is not an array"].concat())
+    }
+}
+
+/* 
+ * MARK: parse filter
+ * a filter is a single condition, an object {"and": [..]}, {"or": [..]} or {"not": ..},
+ * or an array of filters and relation strings such as [["a", "==", 1], "OR", ["b", "==", 2]]
+ * where AND is assumed between filters with no relation and NOT binds tighter than AND which binds tighter than OR
+ */
+fn parse_filter(coldefs: &[(String, CellValue)], filter: &Value) -> Result<Filter, String> {
+    match filter {
+        Value::Object(group) => match group.iter().next() {
+            Some((relation, inner)) if group.len() == 1 => match &relation.to_uppercase()[..] {
+                "AND" => Ok(Filter::And(parse_filter_list(coldefs, inner)?)),
+                "OR"  => Ok(Filter::Or(parse_filter_list(coldefs, inner)?)),
+                "NOT" => Ok(Filter::Not(Box::new(parse_filter(coldefs, inner)?))),
+                _ => Err(["filter group ", relation, " is not one of and, or, not"].concat())
+            },
+            _ => Err(["filter group ", &filter.to_string(), " must have exactly one of and, or, not"].concat())
+        },
+        Value::Array(sequence) => match (sequence.first(), sequence.get(1)) {
+            (Some(Value::String(_)), Some(Value::String(_)) | None) => Ok(Filter::Condition(parse_condition(coldefs, filter)?)),
+            _ => {
+                let mut tokens = vec![];
+                for element in sequence {
+                    match element {
+                        Value::String(relation) => tokens.push(FilterToken::Relation(Relation::parse(relation.to_owned())?)),
+                        _ => match parse_filter(coldefs, element)? {
+                            Filter::Condition(condition) => {
+                                let relation = condition.relational.clone();
+                                tokens.push(FilterToken::Operand(Filter::Condition(condition)));
+                                if let Some(relation) = relation {
+                                    tokens.push(FilterToken::Relation(relation));
+                                }
+                            },
+                            filter => tokens.push(FilterToken::Operand(filter))
+                        }
+                    }
+                }
+                Filter::from_sequence(tokens)
+            }
+        },
+        _ => Err("conditions could not be formatted".to_owned())
+    }
+}
+
+fn parse_filter_list(coldefs: &[(String, CellValue)], filters: &Value) -> Result<Vec<Filter>, String> {
+    match filters.as_array() {
+        Some(filters) => filters.iter().map(|filter| parse_filter(coldefs, filter)).collect::<Result<Vec<Filter>, String>>(),
+        None => Err(["filter group ", &filters.to_string(), " is not an array"].concat())
+    }
+}
+
+/* 
+ * MARK: parse projection
+ * each column is either a column name or [column, alias], a missing or empty list projects every column
+ */
+fn parse_projection(coldefs: &[(String, CellValue)], columns: &Value) -> Result<Option<Vec<(String, String)>>, String> {
+    let columns = match columns {
+        Value::Null => return Ok(None),
+        Value::Array(columns) if columns.is_empty() => return Ok(None),
+        Value::Array(columns) => columns,
+        _ => return Err("columns is not an array".to_owned())
+    };
+    let mut projection: Vec<(String, String)> = vec![];
+    for column in columns {
+        let (column_name, alias) = match column {
+            Value::String(column_name) => (column_name.as_str(), column_name.as_str()),
+            Value::Array(pair) => match pair.as_slice() {
+                [Value::String(column_name), Value::String(alias)] => (column_name.as_str(), alias.as_str()),
+                _ => return Err(["column ", &column.to_string(), " is not a name or a [column, alias] pair"].concat())
+            },
+            _ => return Err(["column ", &column.to_string(), " is not a name or a [column, alias] pair"].concat())
+        };
+        if !coldefs.iter().any(|celldef| celldef.0 == column_name) {
+            return Err(["column \"", column_name, "\" does not exist on target table"].concat());
+        }
+        if projection.iter().any(|(_, existing)| existing == alias) {
+            return Err(["column name \"", alias, "\" is used more than once"].concat());
+        }
+        projection.push((column_name.to_owned(), alias.to_owned()));
+    }
+    Ok(Some(projection))
+}
+
+/* 
+ * MARK: parse order by
+ * each entry is either a column name or [column, modifiers..] with ASC or DESC and NULLS FIRST or NULLS LAST
+ */
+fn parse_order_by(coldefs: &[(String, CellValue)], order_by: &Value) -> Result<Vec<OrderBy>, String> {
+    let order_by = match order_by {
+        Value::Null => return Ok(vec![]),
+        Value::Array(order_by) => order_by,
+        _ => return Err("order_by is not an array".to_owned())
+    };
+    order_by.iter().map(|order| {
+        let (column_name, modifiers) = match order {
+            Value::String(column_name) => (column_name.as_str(), vec![]),
+            Value::Array(entry) => match entry.split_first() {
+                Some((Value::String(column_name), modifiers)) => (column_name.as_str(), modifiers.iter().map(|modifier| modifier.as_str().unwrap_or("")).collect::<Vec<&str>>()),
+                _ => return Err(["order ", &order.to_string(), " does not start with a column name"].concat())
+            },
+            _ => return Err(["order ", &order.to_string(), " is not a column name or an array"].concat())
+        };
+        match coldefs.iter().any(|celldef| celldef.0 == column_name) {
+            true => OrderBy::parse(column_name.to_owned(), &modifiers),
+            false => Err(["order column \"", column_name, "\" does not exist on target table"].concat())
+        }
+    }).collect::<Result<Vec<OrderBy>, String>>()
+}
+
+/* 
+ * MARK: parse read options
+ */
+fn parse_read_options(coldefs: &[(String, CellValue)], body: &Value) -> Result<ReadOptions, String> {
+    let limit = match &body["limit"] {
+        Value::Null => None,
+        limit => Some(limit.as_u64().ok_or("limit is not a positive whole number")? as usize)
+    };
+    let offset = match &body["offset"] {
+        Value::Null => 0,
+        offset => offset.as_u64().ok_or("offset is not a positive whole number")? as usize
+    };
+    // a cursor of true starts a paged read and a token returned by the previous page continues it
+    let (paged, after) = match &body["cursor"] {
+        Value::Null | Value::Bool(false) => (false, None),
+        Value::Bool(true) => (true, None),
+        Value::String(token) => (true, Some(Cursor::decode(token)?)),
+        _ => return Err("cursor is not true or a token".to_owned())
+    };
+    Ok(ReadOptions {
+        projection: parse_projection(coldefs, &body["columns"])?,
+        order_by: parse_order_by(coldefs, &body["order_by"])?,
+        limit,
+        offset,
+        paged,
+        after,
+        explain: parse_explain(body)?,
+    })
+}
+
+/* 
+ * an explained read returns the plan it would run as json instead of its records
+ */
+fn parse_explain(body: &Value) -> Result<bool, String> {
+    match &body["explain"] {
+        Value::Null => Ok(false),
+        Value::Bool(explain) => Ok(*explain),
+        _ => Err("explain is not true or false".to_owned())
+    }
+}
+
+/* 
+ * MARK: parse aggregates
+ * each aggregate is an object like {"fn":"sum","column":"amount","as":"total"}, count may leave out the column to count rows
+ */
+fn parse_aggregates(aggregates: &Value) -> Result<Vec<Aggregate>, String> {
+    let aggregates = match aggregates {
+        Value::Null => return Ok(vec![]),
+        Value::Array(aggregates) => aggregates,
+        _ => return Err("aggregates is not an array".to_owned())
+    };
+    let mut parsed: Vec<Aggregate> = vec![];
+    for aggregate in aggregates {
+        let function = match aggregate["fn"].as_str() {
+            Some(function) => AggregateFn::parse(function)?,
+            None => return Err(["aggregate ", &aggregate.to_string(), " has no fn"].concat())
+        };
+        let column = match &aggregate["column"] {
+            Value::Null => None,
+            Value::String(column) if column == "*" => None,
+            Value::String(column) => Some(column.clone()),
+            _ => return Err(["aggregate ", &aggregate.to_string(), " column is not a name"].concat())
+        };
+        let alias = match &aggregate["as"] {
+            Value::Null => None,
+            Value::String(alias) => Some(alias.clone()),
+            _ => return Err(["aggregate ", &aggregate.to_string(), " alias is not a name"].concat())
+        };
+        let aggregate = Aggregate::new(function, column, alias)?;
+        if parsed.iter().any(|existing| existing.alias == aggregate.alias) {
+            return Err(["column name \"", &aggregate.alias, "\" is used more than once"].concat());
+        }
+        parsed.push(aggregate);
+    }
+    Ok(parsed)
+}
+
+fn parse_group_by(group_by: &Value) -> Result<Vec<String>, String> {
+    match group_by {
+        Value::Null => Ok(vec![]),
+        Value::Array(group_by) => group_by.iter().map(|column| column.as_str().map(|column| column.to_owned()).ok_or(["group_by column ", &column.to_string(), " is not a name"].concat())).collect::<Result<Vec<String>, String>>(),
+        _ => Err("group_by is not an array".to_owned())
+    }
+}
+
+/* 
+ * MARK: parse column definition
+ * a column as [name, type, default, nullable, unique, "table.column" foreign key, on delete], the name and type are required
+ * and anything after them left off takes its default, an error when a column cannot be read
+ */
+fn parse_column_def(coldef: &Value) -> Result<cell::ColumnDef, String> {
+    match coldef.as_array() {
+        Some(coldefarr) => Ok((
+            coldefarr.first().and_then(|name| name.as_str()).filter(|name| !name.is_empty()).ok_or("column name could not be parsed".to_owned())?.to_owned(), 
+            coldefarr.get(1).and_then(|ctype| ctype.as_str()).ok_or("column type could not be parsed".to_owned())?.to_owned(), 
+            match coldefarr.get(2).and_then(|default| default.as_str()) { Some(val) => match val.is_empty() { true => None, false => Some(val.to_owned()) }, _ => None }, 
+            match coldefarr.get(3).and_then(|nullable| nullable.as_str()) { Some(str) => match str { "true"|"True"|"TRUE"|"1" => true, _ => false }, _ => false }, 
+            match coldefarr.get(4).and_then(|unique| unique.as_str()) { Some(str) => match str { "true"|"True"|"TRUE"|"1" => true, _ => false }, _ => false },
+            match coldefarr.get(5).and_then(|foreign_key| foreign_key.as_str()) { Some(str) => match Regex::new(r"(?P<table_name>[\w]*).(?P<column_name>[\w]*)").unwrap().captures(str) {
+                    Some(a) => Some((a["table_name"].to_owned(), a["column_name"].to_owned())),
+                    None => None
+                }, _ => None 
+            },
+            match coldefarr.get(6).and_then(|action| action.as_str()) { Some(action) => cell::ReferentialAction::parse(action)?, None => cell::ReferentialAction::Restrict }
+        )),
+        None => Err("column definition could not be parsed".to_owned())
+    }
+}
+
 /* 
  * MARK: Querytable
  * these queries should be limited to only reading, creating or 
@@ -82,24 +478,42 @@ pub enum QueryTable {
 }
 
 impl QueryTable {
-    pub fn run(&mut self, body: Value) {
-        match self {
-            QueryTable::TableQueryCreate(TQC) => TQC.parse(body),
-            QueryTable::TableQueryRead(TQR)     => TQR.parse(body),
-            QueryTable::TableQueryUpdate(TQU) => TQU.parse(body),
-            QueryTable::TableQueryDelete(TQD) => TQD.parse(body),
+    /* 
+     * the tables of the owning database are handed to queries that have to follow foreign keys,
+     * writes made inside a transaction are buffered in it while reads only see committed records
+     */
+    pub fn run<'a>(&mut self, database: Option<&mut MutexGuard<Database<'a>>>, body: Value) {
+        let tables = match &database { Some(db) => db.tables.clone(), None => vec![] };
+        let transaction = match database { Some(db) => db.active_transaction(), None => Ok(None) };
+        match (self, transaction) {
+            (QueryTable::TableQueryCreate(TQC), Ok(transaction)) => TQC.parse(body, &tables, transaction),
+            (QueryTable::TableQueryRead(TQR), _)                 => TQR.parse(body),
+            (QueryTable::TableQueryUpdate(TQU), Ok(transaction)) => TQU.parse(body, &tables, transaction),
+            (QueryTable::TableQueryDelete(TQD), Ok(transaction)) => TQD.parse(body, &tables, transaction),
+            (QueryTable::TableQueryCreate(TQC), Err(e)) => TQC.result = Err(e),
+            (QueryTable::TableQueryUpdate(TQU), Err(e)) => TQU.result = Err(e),
+            (QueryTable::TableQueryDelete(TQD), Err(e)) => TQD.result = Err(e),
         }
     }
 
-    pub fn result(&self) -> Result<String, String> {
+    pub fn result(&mut self) -> Result<String, String> {
         match self {
             QueryTable::TableQueryCreate(TQC) => TQC.result.clone(),
-            QueryTable::TableQueryRead(TQR)     => TQR.result.clone(),
+            QueryTable::TableQueryRead(TQR)     => TQR.finish(),
             QueryTable::TableQueryUpdate(TQU) => TQU.result.clone(),
             QueryTable::TableQueryDelete(TQD) => TQD.result.clone(),
         }
     }
 
+    pub fn set_result(&mut self, result: Result<String, String>) {
+        match self {
+            QueryTable::TableQueryCreate(TQC) => TQC.result = result,
+            QueryTable::TableQueryRead(TQR)     => TQR.result = result,
+            QueryTable::TableQueryUpdate(TQU) => TQU.result = result,
+            QueryTable::TableQueryDelete(TQD) => TQD.result = result,
+        }
+    }
+
     pub fn table(&self) -> Result<Arc<Mutex<table::Table>>, String> {
         match self {
             QueryTable::TableQueryCreate(TQC) => Ok(TQC.table.clone()),
@@ -120,7 +534,7 @@ impl TableQueryCreate {
         TableQueryCreate { table: table, name: qname, result: Err("Query has not yet been run".to_owned()) }
     }
 
-    pub fn parse(&mut self, body: Value) {
+    pub fn parse(&mut self, body: Value, tables: &[Arc<Mutex<table::Table>>], transaction: Option<&mut Transaction>) {
         println!("record creation query to parse {}", body);
 
         let coldefs = match self.table.try_lock() {
@@ -133,58 +547,39 @@ impl TableQueryCreate {
             Err(e) =>panic!("{}", ["shits fucked ".to_owned(), e.to_string()].concat())
         };
         println!("coldefs formatted {:?}", coldefs);
+        // values are parsed the same way as the assignments of an update, one that does not fit its column refuses the request
         let records = match body["records"].as_array() {
             Some(records) => {
                 println!("records as array {:?}", records);
-                let parsed = records.iter().map(|record| {
-                    println!("record as val {:?}", record);
-                    match record.as_object() {
-                        Some(record) => {
-                            println!("record as object {:?}", record);
-                            record.iter().map(|(record_name, record_value)| match coldefs.iter().find(|celldef| &celldef.0 == record_name) {
-                                Some((_, celldefvalue)) => {
-                                    let cdv = match celldefvalue {
-                                        cell::CellValue::String {..} => cell::CellValue::String { name: record_name.to_owned(), data: match record_value.as_str() { Some(val) => Some(val.to_owned()), None => None } },
-                                        cell::CellValue::Bool   {..} => cell::CellValue::Bool   { name: record_name.to_owned(), data: record_value.as_bool() },
-                                        cell::CellValue::UInt   {..} => cell::CellValue::UInt   { name: record_name.to_owned(), data: match record_value.as_str() { Some(val) => match val.parse::<u32>() { Ok(val) => Some(val), _ => None } , None => None } },
-                                        cell::CellValue::ULong  {..} => cell::CellValue::ULong  { name: record_name.to_owned(), data: match record_value.as_str() { Some(val) => match val.parse::<u128>() { Ok(val) => Some(val), _ => None } , None => None } },
-                                        cell::CellValue::IInt   {..} => cell::CellValue::IInt   { name: record_name.to_owned(), data: match record_value.as_str() { Some(val) => match val.parse::<i32>() { Ok(val) => Some(val), _ => None } , None => None } },
-                                        cell::CellValue::ILong  {..} => cell::CellValue::ILong  { name: record_name.to_owned(), data: match record_value.as_str() { Some(val) => match val.parse::<i128>() { Ok(val) => Some(val), _ => None } , None => None } },
-                                        cell::CellValue::Float  {..} => cell::CellValue::Float  { name: record_name.to_owned(), data: record_value.as_f64() },
-                                        cell::CellValue::Bytes  {..} => cell::CellValue::Bytes  { name: record_name.to_owned(), data: match record_value.as_str() { Some(val) => Some(<String as AsRef<[u8]>>::as_ref(&val.to_owned()).chunks(2).map(|pair| match pair[0] { b'A'..=b'F' => pair[0]-b'A'+10, _ => pair[0] } << 4 |  match pair[1] { b'A'..=b'F' => pair[1]-b'A'+10, _ => pair[1] }).map(|a| a as u8).collect::<Vec<u8>>()), None => None } },
-                                        _ => CellValue::String { name: "".to_owned(), data: Some("".to_owned()) }
-                                    };
-                                    cdv
-                                },
-                                None => CellValue::String { name: "".to_owned(), data: Some("".to_owned()) }
+                records.iter().map(|record| match record.as_object() {
+                        Some(record) => record.iter().map(|(record_name, record_value)| match coldefs.iter().find(|celldef| &celldef.0 == record_name) {
+                                Some((_, celldefvalue)) => celldefvalue.parse_json(record_value),
+                                None => Err(["column ", record_name, " does not exist on table"].concat())
                             })
-                            .filter(|a| !a.name().is_empty())
-                            .collect::<Vec<CellValue>>()
-                        },
-                        None => vec![]
-                    }
-                })
-                .filter(|a| !a.is_empty())
-                .map(|recordvec| Record { columns: recordvec })
-                .collect::<Vec<Record>>();
-                Some(parsed)
+                            .collect::<Result<Vec<CellValue>, String>>(),
+                        None => Err(["record ", &record.to_string(), " is not an object"].concat())
+                    })
+                    .filter(|record| !matches!(record, Ok(columns) if columns.is_empty()))
+                    .map(|record| record.map(Record::new))
+                    .collect::<Result<Vec<Record>, String>>()
             },
-            None => None
+            None => Err("no records submitted".to_owned())
         };
         match records {
-            Some(records) => self.run(records),
-            None => self.result = Err("no records submitted".to_owned())
+            Ok(records) => self.run(records, tables, transaction),
+            Err(e) => self.result = Err(e)
         }
     }
 
     /* 
      * loop over each record supplied in the request, for each record compare it against the table definition to find any columns where values were not supplied
-     * and add the default value to the record from the table definition if one exists
+     * and add the default value to the record from the table definition if one exists, otherwise leave it null for the table constraints to check,
+     * inside a transaction the filled records are buffered and their keys are only assigned when it commits
      */
-    pub fn run(&mut self, mut records: Vec<record::Record> ) {
+    pub fn run(&mut self, mut records: Vec<record::Record>, tables: &[Arc<Mutex<table::Table>>], transaction: Option<&mut Transaction>) {
         let mut full_records: Vec<record::Record> = vec![];
         records.iter().for_each(|record| {
-            let mut full_record = Record { columns: vec![] };
+            let mut full_record = Record::new(vec![]);
             match self.table.try_lock() {
                 Ok(table) => table.column_definition.iter().for_each(|cell_def|  match cell_def {
                     cell::Cell::CellDef { name, ctype, default, .. } => {
@@ -195,7 +590,7 @@ impl TableQueryCreate {
                             },
                             false => match record.columns.iter().find(|column| column.name() == name) {
                                 Some(matching_cell) => full_record.columns.push(matching_cell.clone()),
-                                None => self.result = Err(["no default value specified for column ", name].concat())
+                                None => full_record.columns.push(ctype.as_null())
                             }
                         }
                     },
@@ -208,14 +603,15 @@ impl TableQueryCreate {
 
         println!("query has filled records {:?}", full_records);
 
-        match self.table.try_lock() {
-            Ok(mut table) => {
-                match table.query_create(full_records) {
-                    Ok(_)  => self.result = Ok("records created successfully".to_owned()),
-                    Err(e) => self.result = Err(["error creating records ".to_owned(), e.clone()].concat()),
+        match (self.table.try_lock(), transaction) {
+            (Ok(table), Some(transaction)) => self.result = Ok(transaction.buffer(TransactionWrite::Create { table: table.name.clone(), records: full_records })),
+            (Ok(mut table), None) => {
+                match table.query_create(full_records, tables) {
+                    Ok(created) => self.result = Ok(created),
+                    Err(e) => self.result = Err(e),
                 }
             },
-            Err(e) => panic!("{}", ["shits fucked ".to_owned(), e.to_string()].concat())
+            (Err(e), _) => panic!("{}", ["shits fucked ".to_owned(), e.to_string()].concat())
         }
     }
 }
@@ -223,123 +619,114 @@ impl TableQueryCreate {
 /* 
  * MARK: TableQueryRead
  */
-pub struct TableQueryRead   { table: Arc<Mutex<table::Table>>, name: String, pub result: Result<String, String> }
+/* 
+ * a read of a snapshot waiting to be scanned, run when the result is asked for so whoever ran the query
+ * can let go of the database first
+ */
+pub type Scan = Box<dyn FnOnce() -> Result<String, String> + Send>;
+
+pub struct TableQueryRead   { table: Arc<Mutex<table::Table>>, name: String, scan: Option<Scan>, pub result: Result<String, String> }
 
 impl TableQueryRead {
     pub fn new(qname: String, table: Arc<Mutex<table::Table>>) -> Self {
-        TableQueryRead { table: table, name: qname, result: Err("Query has not yet been run".to_owned()) }
+        TableQueryRead { table: table, name: qname, scan: None, result: Err("Query has not yet been run".to_owned()) }
     }
 
     pub fn parse(&mut self, body: Value) {
-        let coldefs = match self.table.try_lock() {
-            Ok(table) => table.column_definition.iter().map(|celldef| match celldef { 
-                cell::Cell::CellDef { name, ctype, .. } => (name.clone(), ctype.clone()),
-                _ => ("".to_owned(), cell::CellValue::String{ name: "".to_owned(), data: None }) })
-                .filter(|celldef| !celldef.0.is_empty())
-                .collect::<Vec<(String, CellValue)>>(),
-            Err(e) =>panic!("{}", ["shits fucked ".to_owned(), e.to_string()].concat())
-        };
-        let conditions = match body["conditions"].as_array() {
-            Some(conditionsarr) => {
-                let conditions = conditionsarr.iter().map(|condition| match condition.as_array() {
-                    Some(conditionarr) => match conditionarr.len() {
-                        1 => {
-                            println!("{:?}", conditionarr);
-                            match conditionarr.first(){
-                                Some(arr) => {
-                                    println!("{:?}", arr);
-                                    match arr.as_str() {
-                                        Some(str) => match str {
-                                            "*" => {
-                                                let all = conditional::Condition { target_column: "".to_owned(), conditional: conditional::Conditional::All, value: CellValue::Bool { name: "".to_owned(), data: None }, relational: None };
-                                                Some(all)
-                                            },
-                                            _ => None
-                                        },
-                                        _ => None
-                                    }
-                                },
-                                None => None
-                            }
-                        },
-                        3|4 => {
-                            let conditions = match coldefs.iter().find(|celldef| celldef.0 == conditionsarr[0]) {
-                                Some(celldef) => match conditionsarr.len() {
-                                    3 => {
-                                        let condition = match &celldef.1 {
-                                            cell::CellValue::String { name, ..} => Some(conditional::Condition { target_column: conditionsarr[0].as_str().unwrap().to_owned(), conditional: Conditional::parse(conditionsarr[1].as_str().unwrap().to_owned()).unwrap(), value: cell::CellValue::String { name: name.to_owned(), data: match conditionsarr[3].as_str() { Some(val) => Some(val.to_owned()), None => None } }, relational: None }),
-                                            cell::CellValue::Bool   { name, ..} => Some(conditional::Condition { target_column: conditionsarr[0].as_str().unwrap().to_owned(), conditional: Conditional::parse(conditionsarr[1].as_str().unwrap().to_owned()).unwrap(), value: cell::CellValue::Bool   { name: name.to_owned(), data: conditionsarr[3].as_bool() }, relational: None }),
-                                            cell::CellValue::UInt   { name, ..} => Some(conditional::Condition { target_column: conditionsarr[0].as_str().unwrap().to_owned(), conditional: Conditional::parse(conditionsarr[1].as_str().unwrap().to_owned()).unwrap(), value: cell::CellValue::UInt   { name: name.to_owned(), data: match conditionsarr[3].as_str() { Some(val) => match val.parse::<u32>() { Ok(val) => Some(val), _ => None } , None => None } }, relational: None }),
-                                            cell::CellValue::ULong  { name, ..} => Some(conditional::Condition { target_column: conditionsarr[0].as_str().unwrap().to_owned(), conditional: Conditional::parse(conditionsarr[1].as_str().unwrap().to_owned()).unwrap(), value: cell::CellValue::ULong  { name: name.to_owned(), data: match conditionsarr[3].as_str() { Some(val) => match val.parse::<u128>() { Ok(val) => Some(val), _ => None } , None => None } }, relational: None }),
-                                            cell::CellValue::IInt   { name, ..} => Some(conditional::Condition { target_column: conditionsarr[0].as_str().unwrap().to_owned(), conditional: Conditional::parse(conditionsarr[1].as_str().unwrap().to_owned()).unwrap(), value: cell::CellValue::IInt   { name: name.to_owned(), data: match conditionsarr[3].as_str() { Some(val) => match val.parse::<i32>() { Ok(val) => Some(val), _ => None } , None => None } }, relational: None }),
-                                            cell::CellValue::ILong  { name, ..} => Some(conditional::Condition { target_column: conditionsarr[0].as_str().unwrap().to_owned(), conditional: Conditional::parse(conditionsarr[1].as_str().unwrap().to_owned()).unwrap(), value: cell::CellValue::ILong  { name: name.to_owned(), data: match conditionsarr[3].as_str() { Some(val) => match val.parse::<i128>() { Ok(val) => Some(val), _ => None } , None => None } }, relational: None }),
-                                            cell::CellValue::Float  { name, ..} => Some(conditional::Condition { target_column: conditionsarr[0].as_str().unwrap().to_owned(), conditional: Conditional::parse(conditionsarr[1].as_str().unwrap().to_owned()).unwrap(), value: cell::CellValue::Float  { name: name.to_owned(), data: conditionsarr[3].as_f64() }, relational: None }),
-                                            cell::CellValue::Bytes  { name, ..} => Some(conditional::Condition { target_column: conditionsarr[0].as_str().unwrap().to_owned(), conditional: Conditional::parse(conditionsarr[1].as_str().unwrap().to_owned()).unwrap(), value: cell::CellValue::Bytes  { name: name.to_owned(), data: match conditionsarr[3].as_str() { Some(val) => Some(<String as AsRef<[u8]>>::as_ref(&val.to_owned()).chunks(2).map(|pair| match pair[0] { b'A'..=b'F' => pair[0]-b'A'+10, _ => pair[0] } << 4 |  match pair[1] { b'A'..=b'F' => pair[1]-b'A'+10, _ => pair[1] }).map(|a| a as u8).collect::<Vec<u8>>()), None => None } }, relational: None }),
-                                            _ => None,
-                                        };
-                                        condition
-                                    },
-                                    4 => {
-                                        let condition = match &celldef.1 {
-                                            cell::CellValue::String { name, ..} => Some(conditional::Condition { target_column: conditionsarr[0].as_str().unwrap().to_owned(), conditional: Conditional::parse(conditionsarr[1].as_str().unwrap().to_owned()).unwrap(), value: cell::CellValue::String { name: name.to_owned(), data: match conditionsarr[3].as_str() { Some(val) => Some(val.to_owned()), None => None } }, relational: Some(Relation::parse(conditionsarr[4].as_str().unwrap().to_owned()).unwrap()) }),
-                                            cell::CellValue::Bool   { name, ..} => Some(conditional::Condition { target_column: conditionsarr[0].as_str().unwrap().to_owned(), conditional: Conditional::parse(conditionsarr[1].as_str().unwrap().to_owned()).unwrap(), value: cell::CellValue::Bool   { name: name.to_owned(), data: conditionsarr[3].as_bool() }, relational: Some(Relation::parse(conditionsarr[4].as_str().unwrap().to_owned()).unwrap()) }),
-                                            cell::CellValue::UInt   { name, ..} => Some(conditional::Condition { target_column: conditionsarr[0].as_str().unwrap().to_owned(), conditional: Conditional::parse(conditionsarr[1].as_str().unwrap().to_owned()).unwrap(), value: cell::CellValue::UInt   { name: name.to_owned(), data: match conditionsarr[3].as_str() { Some(val) => match val.parse::<u32>() { Ok(val) => Some(val), _ => None } , None => None } }, relational: Some(Relation::parse(conditionsarr[4].as_str().unwrap().to_owned()).unwrap()) }),
-                                            cell::CellValue::ULong  { name, ..} => Some(conditional::Condition { target_column: conditionsarr[0].as_str().unwrap().to_owned(), conditional: Conditional::parse(conditionsarr[1].as_str().unwrap().to_owned()).unwrap(), value: cell::CellValue::ULong  { name: name.to_owned(), data: match conditionsarr[3].as_str() { Some(val) => match val.parse::<u128>() { Ok(val) => Some(val), _ => None } , None => None } }, relational: Some(Relation::parse(conditionsarr[4].as_str().unwrap().to_owned()).unwrap()) }),
-                                            cell::CellValue::IInt   { name, ..} => Some(conditional::Condition { target_column: conditionsarr[0].as_str().unwrap().to_owned(), conditional: Conditional::parse(conditionsarr[1].as_str().unwrap().to_owned()).unwrap(), value: cell::CellValue::IInt   { name: name.to_owned(), data: match conditionsarr[3].as_str() { Some(val) => match val.parse::<i32>() { Ok(val) => Some(val), _ => None } , None => None } }, relational: Some(Relation::parse(conditionsarr[4].as_str().unwrap().to_owned()).unwrap()) }),
-                                            cell::CellValue::ILong  { name, ..} => Some(conditional::Condition { target_column: conditionsarr[0].as_str().unwrap().to_owned(), conditional: Conditional::parse(conditionsarr[1].as_str().unwrap().to_owned()).unwrap(), value: cell::CellValue::ILong  { name: name.to_owned(), data: match conditionsarr[3].as_str() { Some(val) => match val.parse::<i128>() { Ok(val) => Some(val), _ => None } , None => None } }, relational: Some(Relation::parse(conditionsarr[4].as_str().unwrap().to_owned()).unwrap()) }),
-                                            cell::CellValue::Float  { name, ..} => Some(conditional::Condition { target_column: conditionsarr[0].as_str().unwrap().to_owned(), conditional: Conditional::parse(conditionsarr[1].as_str().unwrap().to_owned()).unwrap(), value: cell::CellValue::Float  { name: name.to_owned(), data: conditionsarr[3].as_f64() }, relational: Some(Relation::parse(conditionsarr[4].as_str().unwrap().to_owned()).unwrap()) }),
-                                            cell::CellValue::Bytes  { name, ..} => Some(conditional::Condition { target_column: conditionsarr[0].as_str().unwrap().to_owned(), conditional: Conditional::parse(conditionsarr[1].as_str().unwrap().to_owned()).unwrap(), value: cell::CellValue::Bytes  { name: name.to_owned(), data: match conditionsarr[3].as_str() { Some(val) => Some(<String as AsRef<[u8]>>::as_ref(&val.to_owned()).chunks(2).map(|pair| match pair[0] { b'A'..=b'F' => pair[0]-b'A'+10, _ => pair[0] } << 4 |  match pair[1] { b'A'..=b'F' => pair[1]-b'A'+10, _ => pair[1] }).map(|a| a as u8).collect::<Vec<u8>>()), None => None } }, relational: Some(Relation::parse(conditionsarr[4].as_str().unwrap().to_owned()).unwrap()) }),
-                                            _ => None,
-                                        };
-                                        condition
-                                    },
-                                    _ => None
-                                },
-                                None => None
-                            };
-                            conditions
+        self.scan = None;
+        let coldefs = table_coldefs(&self.table);
+        let filter = parse_filter(&coldefs, &body["conditions"]);
+        // a body with aggregates or group_by columns is read as rows of totals instead of records
+        if !body["aggregates"].is_null() || !body["group_by"].is_null() {
+            match (filter, parse_group_by(&body["group_by"]), parse_aggregates(&body["aggregates"]), parse_explain(&body)) {
+                (Ok(filter), Ok(group_by), Ok(aggregates), Ok(explain)) => self.run_aggregate(filter, group_by, aggregates, explain),
+                (Err(e), _, _, _) | (_, Err(e), _, _) | (_, _, Err(e), _) | (_, _, _, Err(e)) => self.result = Err(e)
+            }
+            return;
+        }
+        let options = parse_read_options(&coldefs, &body);
+        println!("conditions filter {:?} options {:?}", filter, options);
+        match (filter, options) {
+            (Ok(filter), Ok(options)) => self.run(filter, options),
+            (Err(e), _) | (_, Err(e)) => self.result = Err(e)
+        }
+    }
+
+    /* 
+     * loop over each record in the table and check each matching column with the supplied conditions,
+     * the table is only locked while a snapshot is taken and the snapshot is scanned by finish once the lock is released,
+     * explains are answered under the lock
+     */
+    pub fn run(&mut self, filter: conditional::Filter, options: ReadOptions) {
+        println!("conditions filter {:?}", filter);
+        match filter.is_empty() {
+            false => {
+                let scan = match self.table.try_lock() {
+                    Ok(mut table) => match (options.explain, options.paged) {
+                        (true, _) => {
+                            self.result = table.query_search_columns(&filter, &options);
+                            return;
                         },
-                        _ => None
+                        (false, true) => table.read_columns(&options).and_then(|columns| table.page_bounds(&options, columns)).map(|(key_column, limit, columns)| {
+                            let snapshot = table.snapshot(&filter);
+                            Box::new(move || Ok(snapshot.query_search_page(&filter, &options, columns.as_deref(), &key_column, limit))) as Scan
+                        }),
+                        (false, false) => table.read_columns(&options).map(|_| {
+                            let snapshot = table.snapshot(&filter);
+                            Box::new(move || Ok(snapshot.query_search_columns(&filter, &options))) as Scan
+                        })
                     },
-                    None => None
-                })
-                .map(|a| match a { Some(a) => a, None => Condition { target_column: "".to_owned(), conditional: Conditional::parse("!=".to_owned()).unwrap(), value: CellValue::Bool { name: "".to_owned(), data: None }, relational: None } })
-                .filter(|conditional| match conditional {
-                    Condition { target_column, conditional, ..} => match target_column.is_empty() {
-                        true => match conditional {
-                            Conditional::All => true,
-                            _ => false
-                        },
-                        false => true
-                    }
-                })
-                .collect::<Vec<Condition>>();
-                Some(conditions)
+                    Err(e) => panic!("{}", ["shits fucked ".to_owned(), e.to_string()].concat())
+                };
+                match scan {
+                    Ok(scan) => self.scan = Some(scan),
+                    Err(e) => self.result = Err(e)
+                }
             },
-            None => None
-        };
-        println!("conditions vec {:?}", conditions);
-        match conditions {
-            Some(conditions) => self.run(conditions),
-            _ => self.result = Err("conditions could not be formatted".to_owned())
+            _ => self.result = Err("length of conditional list < 1".to_owned())
         }
     }
 
     /* 
-     * loop over each record in the table and check each matching column with the supplied conditions
+     * fold every record matching the conditions into its group, returning one row per group,
+     * read from a snapshot the same way as the records of run
      */
-    pub fn run(&mut self, conditions: Vec<conditional::Condition>) {
-        println!("conditions vec {:?}", conditions);
-        match conditions.len() {
-            1.. => {
-                match self.table.try_lock() {
-                    Ok(mut table) => self.result = table.query_search_columns(&conditions),
+    pub fn run_aggregate(&mut self, filter: conditional::Filter, group_by: Vec<String>, aggregates: Vec<Aggregate>, explain: bool) {
+        println!("aggregating conditions filter {:?} group by {:?}", filter, group_by);
+        match (filter.is_empty(), group_by.is_empty() && aggregates.is_empty()) {
+            (false, false) => {
+                let scan = match self.table.try_lock() {
+                    Ok(mut table) => match explain {
+                        true => {
+                            self.result = table.query_aggregate(&filter, &group_by, &aggregates, explain);
+                            return;
+                        },
+                        false => table.aggregate_columns(&group_by, &aggregates).map(|columns| {
+                            let snapshot = table.snapshot(&filter);
+                            Box::new(move || Ok(snapshot.query_aggregate(&filter, &columns, &group_by, &aggregates))) as Scan
+                        })
+                    },
                     Err(e) => panic!("{}", ["shits fucked ".to_owned(), e.to_string()].concat())
+                };
+                match scan {
+                    Ok(scan) => self.scan = Some(scan),
+                    Err(e) => self.result = Err(e)
                 }
             },
-            _ => self.result = Err("length of conditional list < 1".to_owned())
+            (true, _) => self.result = Err("length of conditional list < 1".to_owned()),
+            (_, true) => self.result = Err("no aggregates or group_by columns submitted".to_owned())
         }
     }
+
+    /* 
+     * scan the snapshot taken by run if it has not been yet and hand back the result
+     */
+    pub fn finish(&mut self) -> Result<String, String> {
+        if let Some(scan) = self.scan.take() {
+            self.result = scan();
+        }
+        self.result.clone()
+    }
 }
 
 /* 
@@ -352,12 +739,42 @@ impl TableQueryUpdate {
         TableQueryUpdate { table: table, name: qname, result: Err("Query has not yet been run or implemented".to_owned()) }
     }
 
-    pub fn parse(&mut self, body: Value) {
-        todo!()
+    pub fn parse(&mut self, body: Value, tables: &[Arc<Mutex<table::Table>>], transaction: Option<&mut Transaction>) {
+        let coldefs = table_coldefs(&self.table);
+        let filter = parse_filter(&coldefs, &body["conditions"]);
+        let assignments = match body["set"].as_object() {
+            Some(set) => set.iter().map(|(column_name, column_value)| match coldefs.iter().find(|celldef| &celldef.0 == column_name) {
+                    Some((_, celldefvalue)) => celldefvalue.parse_json(column_value),
+                    None => Err(["column ", column_name, " does not exist on table"].concat())
+                })
+                .collect::<Result<Vec<CellValue>, String>>(),
+            None => Err("no set object submitted".to_owned())
+        };
+        println!("conditions filter {:?} assignments {:?}", filter, assignments);
+        match (filter, assignments) {
+            (Ok(filter), Ok(assignments)) => match assignments.is_empty() {
+                true => self.result = Err("set object has no columns to update".to_owned()),
+                false => self.run(filter, Record::new(assignments), tables, transaction)
+            },
+            (Err(e), _) | (_, Err(e)) => self.result = Err(e)
+        }
     }
 
-    pub fn run(&mut self, conditions: Vec<conditional::Condition>, mut records: Vec<record::Record>) {
-        todo!();
+    /* 
+     * the assignments record only holds the columns being changed, every record matching the conditions has those columns overwritten
+     */
+    pub fn run(&mut self, filter: conditional::Filter, assignments: record::Record, tables: &[Arc<Mutex<table::Table>>], transaction: Option<&mut Transaction>) {
+        println!("updating records with conditions {:?}", filter);
+        match filter.is_empty() {
+            false => {
+                match (self.table.try_lock(), transaction) {
+                    (Ok(table), Some(transaction)) => self.result = Ok(transaction.buffer(TransactionWrite::Update { table: table.name.clone(), filter, assignments })),
+                    (Ok(mut table), None) => self.result = table.query_update_records(&filter, &assignments, tables),
+                    (Err(e), _) => panic!("{}", ["shits fucked ".to_owned(), e.to_string()].concat())
+                }
+            },
+            _ => self.result = Err("length of conditional list < 1".to_owned())
+        }
     }
 }
 
@@ -371,108 +788,24 @@ impl TableQueryDelete {
         TableQueryDelete { table: table, name: qname, result: Err("Query has not yet been run or implemented".to_owned()) }
     }
 
-    pub fn parse(&mut self, body: Value) {
-        let coldefs = match self.table.try_lock() {
-            Ok(table) => table.column_definition.iter().map(|celldef| match celldef { 
-                cell::Cell::CellDef { name, ctype, .. } => (name.clone(), ctype.clone()),
-                _ => ("".to_owned(), cell::CellValue::String{ name: "".to_owned(), data: None }) })
-                .filter(|celldef| !celldef.0.is_empty())
-                .collect::<Vec<(String, CellValue)>>(),
-            Err(e) =>panic!("{}", ["shits fucked ".to_owned(), e.to_string()].concat())
-        };
-        let conditions = match body["conditions"].as_array() {
-            Some(conditionsarr) => {
-                let conditions = conditionsarr.iter().map(|condition| match condition.as_array() {
-                    Some(conditionarr) => match conditionarr.len() {
-                        1 => {
-                            println!("{:?}", conditionarr);
-                            match conditionarr.first(){
-                                Some(arr) => {
-                                    println!("{:?}", arr);
-                                    match arr.as_str() {
-                                        Some(str) => match str {
-                                            "*" => {
-                                                let all = conditional::Condition { target_column: "".to_owned(), conditional: conditional::Conditional::All, value: CellValue::Bool { name: "".to_owned(), data: None }, relational: None };
-                                                Some(all)
-                                            },
-                                            _ => None
-                                        },
-                                        _ => None
-                                    }
-                                },
-                                None => None
-                            }
-                        },
-                        3|4 => {
-                            let conditions = match coldefs.iter().find(|celldef| celldef.0 == conditionsarr[0]) {
-                                Some(celldef) => match conditionsarr.len() {
-                                    3 => {
-                                        let condition = match &celldef.1 {
-                                            cell::CellValue::String { name, ..} => Some(conditional::Condition { target_column: conditionsarr[0].as_str().unwrap().to_owned(), conditional: Conditional::parse(conditionsarr[1].as_str().unwrap().to_owned()).unwrap(), value: cell::CellValue::String { name: name.to_owned(), data: match conditionsarr[3].as_str() { Some(val) => Some(val.to_owned()), None => None } }, relational: None }),
-                                            cell::CellValue::Bool   { name, ..} => Some(conditional::Condition { target_column: conditionsarr[0].as_str().unwrap().to_owned(), conditional: Conditional::parse(conditionsarr[1].as_str().unwrap().to_owned()).unwrap(), value: cell::CellValue::Bool   { name: name.to_owned(), data: conditionsarr[3].as_bool() }, relational: None }),
-                                            cell::CellValue::UInt   { name, ..} => Some(conditional::Condition { target_column: conditionsarr[0].as_str().unwrap().to_owned(), conditional: Conditional::parse(conditionsarr[1].as_str().unwrap().to_owned()).unwrap(), value: cell::CellValue::UInt   { name: name.to_owned(), data: match conditionsarr[3].as_str() { Some(val) => match val.parse::<u32>() { Ok(val) => Some(val), _ => None } , None => None } }, relational: None }),
-                                            cell::CellValue::ULong  { name, ..} => Some(conditional::Condition { target_column: conditionsarr[0].as_str().unwrap().to_owned(), conditional: Conditional::parse(conditionsarr[1].as_str().unwrap().to_owned()).unwrap(), value: cell::CellValue::ULong  { name: name.to_owned(), data: match conditionsarr[3].as_str() { Some(val) => match val.parse::<u128>() { Ok(val) => Some(val), _ => None } , None => None } }, relational: None }),
-                                            cell::CellValue::IInt   { name, ..} => Some(conditional::Condition { target_column: conditionsarr[0].as_str().unwrap().to_owned(), conditional: Conditional::parse(conditionsarr[1].as_str().unwrap().to_owned()).unwrap(), value: cell::CellValue::IInt   { name: name.to_owned(), data: match conditionsarr[3].as_str() { Some(val) => match val.parse::<i32>() { Ok(val) => Some(val), _ => None } , None => None } }, relational: None }),
-                                            cell::CellValue::ILong  { name, ..} => Some(conditional::Condition { target_column: conditionsarr[0].as_str().unwrap().to_owned(), conditional: Conditional::parse(conditionsarr[1].as_str().unwrap().to_owned()).unwrap(), value: cell::CellValue::ILong  { name: name.to_owned(), data: match conditionsarr[3].as_str() { Some(val) => match val.parse::<i128>() { Ok(val) => Some(val), _ => None } , None => None } }, relational: None }),
-                                            cell::CellValue::Float  { name, ..} => Some(conditional::Condition { target_column: conditionsarr[0].as_str().unwrap().to_owned(), conditional: Conditional::parse(conditionsarr[1].as_str().unwrap().to_owned()).unwrap(), value: cell::CellValue::Float  { name: name.to_owned(), data: conditionsarr[3].as_f64() }, relational: None }),
-                                            cell::CellValue::Bytes  { name, ..} => Some(conditional::Condition { target_column: conditionsarr[0].as_str().unwrap().to_owned(), conditional: Conditional::parse(conditionsarr[1].as_str().unwrap().to_owned()).unwrap(), value: cell::CellValue::Bytes  { name: name.to_owned(), data: match conditionsarr[3].as_str() { Some(val) => Some(<String as AsRef<[u8]>>::as_ref(&val.to_owned()).chunks(2).map(|pair| match pair[0] { b'A'..=b'F' => pair[0]-b'A'+10, _ => pair[0] } << 4 |  match pair[1] { b'A'..=b'F' => pair[1]-b'A'+10, _ => pair[1] }).map(|a| a as u8).collect::<Vec<u8>>()), None => None } }, relational: None }),
-                                            _ => None,
-                                        };
-                                        condition
-                                    },
-                                    4 => {
-                                        let condition = match &celldef.1 {
-                                            cell::CellValue::String { name, ..} => Some(conditional::Condition { target_column: conditionsarr[0].as_str().unwrap().to_owned(), conditional: Conditional::parse(conditionsarr[1].as_str().unwrap().to_owned()).unwrap(), value: cell::CellValue::String { name: name.to_owned(), data: match conditionsarr[3].as_str() { Some(val) => Some(val.to_owned()), None => None } }, relational: Some(Relation::parse(conditionsarr[4].as_str().unwrap().to_owned()).unwrap()) }),
-                                            cell::CellValue::Bool   { name, ..} => Some(conditional::Condition { target_column: conditionsarr[0].as_str().unwrap().to_owned(), conditional: Conditional::parse(conditionsarr[1].as_str().unwrap().to_owned()).unwrap(), value: cell::CellValue::Bool   { name: name.to_owned(), data: conditionsarr[3].as_bool() }, relational: Some(Relation::parse(conditionsarr[4].as_str().unwrap().to_owned()).unwrap()) }),
-                                            cell::CellValue::UInt   { name, ..} => Some(conditional::Condition { target_column: conditionsarr[0].as_str().unwrap().to_owned(), conditional: Conditional::parse(conditionsarr[1].as_str().unwrap().to_owned()).unwrap(), value: cell::CellValue::UInt   { name: name.to_owned(), data: match conditionsarr[3].as_str() { Some(val) => match val.parse::<u32>() { Ok(val) => Some(val), _ => None } , None => None } }, relational: Some(Relation::parse(conditionsarr[4].as_str().unwrap().to_owned()).unwrap()) }),
-                                            cell::CellValue::ULong  { name, ..} => Some(conditional::Condition { target_column: conditionsarr[0].as_str().unwrap().to_owned(), conditional: Conditional::parse(conditionsarr[1].as_str().unwrap().to_owned()).unwrap(), value: cell::CellValue::ULong  { name: name.to_owned(), data: match conditionsarr[3].as_str() { Some(val) => match val.parse::<u128>() { Ok(val) => Some(val), _ => None } , None => None } }, relational: Some(Relation::parse(conditionsarr[4].as_str().unwrap().to_owned()).unwrap()) }),
-                                            cell::CellValue::IInt   { name, ..} => Some(conditional::Condition { target_column: conditionsarr[0].as_str().unwrap().to_owned(), conditional: Conditional::parse(conditionsarr[1].as_str().unwrap().to_owned()).unwrap(), value: cell::CellValue::IInt   { name: name.to_owned(), data: match conditionsarr[3].as_str() { Some(val) => match val.parse::<i32>() { Ok(val) => Some(val), _ => None } , None => None } }, relational: Some(Relation::parse(conditionsarr[4].as_str().unwrap().to_owned()).unwrap()) }),
-                                            cell::CellValue::ILong  { name, ..} => Some(conditional::Condition { target_column: conditionsarr[0].as_str().unwrap().to_owned(), conditional: Conditional::parse(conditionsarr[1].as_str().unwrap().to_owned()).unwrap(), value: cell::CellValue::ILong  { name: name.to_owned(), data: match conditionsarr[3].as_str() { Some(val) => match val.parse::<i128>() { Ok(val) => Some(val), _ => None } , None => None } }, relational: Some(Relation::parse(conditionsarr[4].as_str().unwrap().to_owned()).unwrap()) }),
-                                            cell::CellValue::Float  { name, ..} => Some(conditional::Condition { target_column: conditionsarr[0].as_str().unwrap().to_owned(), conditional: Conditional::parse(conditionsarr[1].as_str().unwrap().to_owned()).unwrap(), value: cell::CellValue::Float  { name: name.to_owned(), data: conditionsarr[3].as_f64() }, relational: Some(Relation::parse(conditionsarr[4].as_str().unwrap().to_owned()).unwrap()) }),
-                                            cell::CellValue::Bytes  { name, ..} => Some(conditional::Condition { target_column: conditionsarr[0].as_str().unwrap().to_owned(), conditional: Conditional::parse(conditionsarr[1].as_str().unwrap().to_owned()).unwrap(), value: cell::CellValue::Bytes  { name: name.to_owned(), data: match conditionsarr[3].as_str() { Some(val) => Some(<String as AsRef<[u8]>>::as_ref(&val.to_owned()).chunks(2).map(|pair| match pair[0] { b'A'..=b'F' => pair[0]-b'A'+10, _ => pair[0] } << 4 |  match pair[1] { b'A'..=b'F' => pair[1]-b'A'+10, _ => pair[1] }).map(|a| a as u8).collect::<Vec<u8>>()), None => None } }, relational: Some(Relation::parse(conditionsarr[4].as_str().unwrap().to_owned()).unwrap()) }),
-                                            _ => None,
-                                        };
-                                        condition
-                                    },
-                                    _ => None
-                                },
-                                None => None
-                            };
-                            conditions
-                        },
-                        _ => None
-                    },
-                    None => None
-                })
-                .map(|a| match a { Some(a) => a, None => Condition { target_column: "".to_owned(), conditional: Conditional::parse("!=".to_owned()).unwrap(), value: CellValue::Bool { name: "".to_owned(), data: None }, relational: None } })
-                .filter(|conditional| match conditional {
-                    Condition { target_column, conditional, ..} => match target_column.is_empty() {
-                        true => match conditional {
-                            Conditional::All => true,
-                            _ => false
-                        },
-                        false => true
-                    }
-                })
-                .collect::<Vec<Condition>>();
-                Some(conditions)
-            },
-            None => None
-        };
-        println!("conditions vec {:?}", conditions);
-        match conditions {
-            Some(conditions) => self.run(conditions),
-            _ => self.result = Err("conditions could not be formatted".to_owned())
+    pub fn parse(&mut self, body: Value, tables: &[Arc<Mutex<table::Table>>], transaction: Option<&mut Transaction>) {
+        let coldefs = table_coldefs(&self.table);
+        let filter = parse_filter(&coldefs, &body["conditions"]);
+        println!("conditions filter {:?}", filter);
+        match filter {
+            Ok(filter) => self.run(filter, tables, transaction),
+            Err(e) => self.result = Err(e)
         }
     }
 
-    pub fn run(&mut self, conditions: Vec<conditional::Condition> ) {
-        println!("deteting records with conditions {:?}", conditions);
-        match conditions.len() {
-            1.. => {
-                match self.table.try_lock() {
-                    Ok(mut table) => self.result = table.query_delete_records(&conditions),
-                    Err(e) => panic!("{}", ["shits fucked ".to_owned(), e.to_string()].concat())
+    pub fn run(&mut self, filter: conditional::Filter, tables: &[Arc<Mutex<table::Table>>], transaction: Option<&mut Transaction>) {
+        println!("deteting records with conditions {:?}", filter);
+        match filter.is_empty() {
+            false => {
+                match (self.table.try_lock(), transaction) {
+                    (Ok(table), Some(transaction)) => self.result = Ok(transaction.buffer(TransactionWrite::Delete { table: table.name.clone(), filter })),
+                    (Ok(mut table), None) => self.result = table.query_delete_records(&self.table, &filter, tables),
+                    (Err(e), _) => panic!("{}", ["shits fucked ".to_owned(), e.to_string()].concat())
                 }
             },
             _ => self.result = Err("length of conditional list < 1".to_owned())
@@ -505,7 +838,19 @@ pub enum QueryDatabase {
     QueryDatabaseCreateTable(QueryDatabaseCreateTable),
     QueryDatabaseUpdateTable(QueryDatabaseUpdateTable),
     QueryDatabaseDeleteTable(QueryDatabaseDeleteTable),
+    QueryDatabaseCreateIndex(QueryDatabaseCreateIndex),
+    QueryDatabaseDropIndex(QueryDatabaseDropIndex),
+    QueryDatabaseJoin(QueryDatabaseJoin),
+    QueryDatabaseSql(QueryDatabaseSql),
+    QueryDatabaseBegin(QueryDatabaseBegin),
+    QueryDatabaseCommit(QueryDatabaseCommit),
+    QueryDatabaseRollback(QueryDatabaseRollback),
+    QueryDatabaseReset(QueryDatabaseReset),
     QueryDatabaseInDevToggle(QueryDatabaseInDevToggle),
+    QueryDatabaseCreateUser(QueryDatabaseCreateUser),
+    QueryDatabaseDropUser(QueryDatabaseDropUser),
+    QueryDatabaseGrant(QueryDatabaseGrant),
+    QueryDatabaseRevoke(QueryDatabaseRevoke),
 }
 
 impl QueryDatabase {
@@ -514,16 +859,40 @@ impl QueryDatabase {
             QueryDatabase::QueryDatabaseCreateTable(QDCT) => match database { Some(db) => QDCT.parse(admin_db, db, body), None => QDCT.result = Err("no db pointer found".to_owned())},
             QueryDatabase::QueryDatabaseUpdateTable(QDUT) => match database { Some(db) => QDUT.parse(admin_db, db, body), None => QDUT.result = Err("no db pointer found".to_owned())},
             QueryDatabase::QueryDatabaseDeleteTable(QDDT) => match database { Some(db) => QDDT.parse(db, body), None => QDDT.result = Err("no db pointer found".to_owned())},
+            QueryDatabase::QueryDatabaseCreateIndex(QDCI) => match database { Some(db) => QDCI.parse(db, body), None => QDCI.result = Err("no db pointer found".to_owned())},
+            QueryDatabase::QueryDatabaseDropIndex(QDDI) => match database { Some(db) => QDDI.parse(db, body), None => QDDI.result = Err("no db pointer found".to_owned())},
+            QueryDatabase::QueryDatabaseJoin(QDJ) => match database { Some(db) => QDJ.parse(db, body), None => QDJ.result = Err("no db pointer found".to_owned())},
+            QueryDatabase::QueryDatabaseSql(QDS) => match database { Some(db) => QDS.parse(admin_db, db, body), None => QDS.result = Err("no db pointer found".to_owned())},
+            QueryDatabase::QueryDatabaseBegin(QDB) => match database { Some(db) => QDB.run(db), None => QDB.result = Err("no db pointer found".to_owned())},
+            QueryDatabase::QueryDatabaseCommit(QDC) => match database { Some(db) => QDC.run(db), None => QDC.result = Err("no db pointer found".to_owned())},
+            QueryDatabase::QueryDatabaseRollback(QDR) => match database { Some(db) => QDR.run(db), None => QDR.result = Err("no db pointer found".to_owned())},
+            QueryDatabase::QueryDatabaseReset(QDRS) => match database { Some(db) => QDRS.parse(db, body), None => QDRS.result = Err("no db pointer found".to_owned())},
             QueryDatabase::QueryDatabaseInDevToggle(QDIDT) => match database { Some(db) => QDIDT.run(db), None => QDIDT.result = Err("no db pointer found".to_owned())},
+            QueryDatabase::QueryDatabaseCreateUser(QDCU) => match database { Some(db) => QDCU.parse(db, body), None => QDCU.result = Err("no db pointer found".to_owned())},
+            QueryDatabase::QueryDatabaseDropUser(QDDU) => match database { Some(db) => QDDU.parse(db, body), None => QDDU.result = Err("no db pointer found".to_owned())},
+            QueryDatabase::QueryDatabaseGrant(QDG) => match database { Some(db) => QDG.parse(db, body), None => QDG.result = Err("no db pointer found".to_owned())},
+            QueryDatabase::QueryDatabaseRevoke(QDRV) => match database { Some(db) => QDRV.parse(db, body), None => QDRV.result = Err("no db pointer found".to_owned())},
         }
     }
 
-    pub fn result(&self) -> Result<String, String> {
+    pub fn result(&mut self) -> Result<String, String> {
         match self {
             QueryDatabase::QueryDatabaseCreateTable(QDCT) => QDCT.result.clone(), 
             QueryDatabase::QueryDatabaseUpdateTable(QDUT) => QDUT.result.clone(), 
             QueryDatabase::QueryDatabaseDeleteTable(QDDT) => QDDT.result.clone(), 
+            QueryDatabase::QueryDatabaseCreateIndex(QDCI) => QDCI.result.clone(), 
+            QueryDatabase::QueryDatabaseDropIndex(QDDI) => QDDI.result.clone(), 
+            QueryDatabase::QueryDatabaseJoin(QDJ) => QDJ.result.clone(), 
+            QueryDatabase::QueryDatabaseSql(QDS) => QDS.finish(), 
+            QueryDatabase::QueryDatabaseBegin(QDB) => QDB.result.clone(), 
+            QueryDatabase::QueryDatabaseCommit(QDC) => QDC.result.clone(), 
+            QueryDatabase::QueryDatabaseRollback(QDR) => QDR.result.clone(), 
+            QueryDatabase::QueryDatabaseReset(QDRS) => QDRS.result.clone(), 
             QueryDatabase::QueryDatabaseInDevToggle(QDIDT) => QDIDT.result.clone(), 
+            QueryDatabase::QueryDatabaseCreateUser(QDCU) => QDCU.result.clone(), 
+            QueryDatabase::QueryDatabaseDropUser(QDDU) => QDDU.result.clone(), 
+            QueryDatabase::QueryDatabaseGrant(QDG) => QDG.result.clone(), 
+            QueryDatabase::QueryDatabaseRevoke(QDRV) => QDRV.result.clone(), 
         }
     }
 
@@ -532,7 +901,19 @@ impl QueryDatabase {
             QueryDatabase::QueryDatabaseCreateTable(QDCT) => QDCT.result = result, 
             QueryDatabase::QueryDatabaseUpdateTable(QDUT) => QDUT.result = result, 
             QueryDatabase::QueryDatabaseDeleteTable(QDDT) => QDDT.result = result, 
+            QueryDatabase::QueryDatabaseCreateIndex(QDCI) => QDCI.result = result, 
+            QueryDatabase::QueryDatabaseDropIndex(QDDI) => QDDI.result = result, 
+            QueryDatabase::QueryDatabaseJoin(QDJ) => QDJ.result = result, 
+            QueryDatabase::QueryDatabaseSql(QDS) => QDS.result = result, 
+            QueryDatabase::QueryDatabaseBegin(QDB) => QDB.result = result, 
+            QueryDatabase::QueryDatabaseCommit(QDC) => QDC.result = result, 
+            QueryDatabase::QueryDatabaseRollback(QDR) => QDR.result = result, 
+            QueryDatabase::QueryDatabaseReset(QDRS) => QDRS.result = result, 
             QueryDatabase::QueryDatabaseInDevToggle(QDIDT) => QDIDT.result = result, 
+            QueryDatabase::QueryDatabaseCreateUser(QDCU) => QDCU.result = result, 
+            QueryDatabase::QueryDatabaseDropUser(QDDU) => QDDU.result = result, 
+            QueryDatabase::QueryDatabaseGrant(QDG) => QDG.result = result, 
+            QueryDatabase::QueryDatabaseRevoke(QDRV) => QDRV.result = result, 
         }
     }
 }
@@ -550,39 +931,23 @@ impl QueryDatabaseCreateTable {
     pub fn parse<'a>(&mut self, admin_db: Arc<Mutex<Database<'a>>>, database: &mut MutexGuard<Database<'a>>, body: Value) {
         println!("build new table query parse");
         let column_defs = match body["columns"].as_array() {
-            Some(column_def_arr) => Some(column_def_arr.iter().map(|coldef| match coldef.as_array() {
-                    Some(coldefarr) => Some((
-                        coldefarr[0].as_str()?.to_owned(), 
-                        coldefarr[1].as_str()?.to_owned(), 
-                        match coldefarr[2].as_str() { Some(val) => match val.is_empty() { true => None, false => Some(val.to_owned()) }, _ => None }, 
-                        match coldefarr[3].as_str() { Some(str) => match str { "true"|"True"|"TRUE"|"1" => true, _ => false }, _ => false }, 
-                        match coldefarr[4].as_str() { Some(str) => match str { "true"|"True"|"TRUE"|"1" => true, _ => false }, _ => false },
-                        match coldefarr[5].as_str() { Some(str) => match Regex::new(r"(?P<table_name>[\w]*).(?P<column_name>[\w]*)").unwrap().captures(str) {
-                                Some(a) => Some((a["table_name"].to_owned(), a["column_name"].to_owned())),
-                                None => None
-                            }, _ => None 
-                        }
-                    )),
-                    None => None
-                }).map(|a| match a {
-                    Some(a) => a,
-                    None => ("".to_owned(), "".to_owned(), None, false, false, None)
-                }).filter(|b| !b.0.is_empty())
-                .collect::<Vec<(String, String, Option<String>, bool, bool, Option<(String, String)>)>>()
-            ),
-            None => None
+            Some(column_def_arr) => column_def_arr.iter().map(parse_column_def).collect::<Result<Vec<cell::ColumnDef>, String>>(),
+            None => Err("column definitions could not be parsed".to_owned())
         };
         match body["table_name"].as_str() {
             Some(table_name) => match column_defs {
-                Some(column_defs) => self.run(admin_db, database, table_name.to_owned(), column_defs),
-                None => self.result = Err("column definitions could not be parsed".to_owned())
+                Ok(column_defs) => self.run(admin_db, database, table_name.to_owned(), column_defs),
+                Err(e) => self.result = Err(e)
             },
             None => self.result = Err("table name could not be parsed".to_owned())
         }
     }
 
-    pub fn run<'a>(&mut self, admin_db: Arc<Mutex<Database<'a>>>, database: &mut MutexGuard<Database<'a>>, table_name: String, columns: Vec<(String, String, Option<String>, bool, bool, Option<(String, String)>)>) {
+    pub fn run<'a>(&mut self, admin_db: Arc<Mutex<Database<'a>>>, database: &mut MutexGuard<Database<'a>>, table_name: String, columns: Vec<(String, String, Option<String>, bool, bool, Option<(String, String)>, cell::ReferentialAction)>) {
         println!("build new table query run db");
+        if let Err(e) = database.check_indev("create_table") {
+            return self.result = Err(e);
+        }
         match database.tables.iter().any(|table| match table.try_lock() { Ok(table) => table.name == table_name, Err(e) => panic!("{}", ["shits fucked ".to_owned(), e.to_string()].concat())}) {
             true => {
                 println!("table does exist err");
@@ -612,14 +977,47 @@ impl QueryDatabaseUpdateTable {
         QueryDatabaseUpdateTable { name , result: Err("query has not yet been run".to_owned()) }
     }
 
+    /* 
+     * the body names the table and one migration:
+     * {"migration": "add_column", "column": [name, type, default, nullable, unique, foreign key, on delete]},
+     * {"migration": "drop_column", "column": name}, {"migration": "rename_column", "column": name, "name": new name},
+     * {"migration": "retype_column", "column": name, "type": type, "conversion": "strict"|"null"}
+     * or {"migration": "history"} for the migrations already applied to the table
+     */
     pub fn parse(&mut self, admin_db: Arc<Mutex<Database>>, database: &mut MutexGuard<Database>, body: Value) {
         println!("parsing");
-        self.run(database);
+        let table_name = match body["table_name"].as_str() {
+            Some(table_name) => table_name,
+            None => return self.result = Err("table name could not be parsed".to_owned())
+        };
+        let column = |body: &Value| body["column"].as_str().map(|column| column.to_owned()).ok_or("column name could not be parsed".to_owned());
+        let migration = match body["migration"].as_str() {
+            Some("history") => return self.result = database.migration_history(table_name),
+            Some("add_column") => parse_column_def(&body["column"]).map(|column| Migration::Add { column }),
+            Some("drop_column") => column(&body).map(|column| Migration::Drop { column }),
+            Some("rename_column") => match (column(&body), body["name"].as_str()) {
+                (Ok(column), Some(name)) if !name.is_empty() => Ok(Migration::Rename { column, name: name.to_owned() }),
+                (Err(e), _) => Err(e),
+                (_, _) => Err("new column name could not be parsed".to_owned())
+            },
+            Some("retype_column") => match (column(&body), body["type"].as_str(), body["conversion"].as_str()) {
+                (Ok(column), Some(dtype), Some(conversion)) => Conversion::parse(conversion).map(|conversion| Migration::Retype { column, dtype: dtype.to_owned(), conversion }),
+                (Err(e), _, _) => Err(e),
+                (_, None, _) => Err("column type could not be parsed".to_owned()),
+                (_, _, None) => Err("a retyped column needs an explicit conversion of strict or null".to_owned())
+            },
+            Some(migration) => Err(["migration ", migration, " not recognised"].concat()),
+            None => Err("migration could not be parsed".to_owned())
+        };
+        match migration {
+            Ok(migration) => self.run(database, table_name, migration),
+            Err(e) => self.result = Err(e)
+        }
     }
 
-    pub fn run(&mut self, database: &mut MutexGuard<Database>) {
-        println!("running");
-        self.result = Ok("update query has been run".to_owned());
+    pub fn run(&mut self, database: &mut MutexGuard<Database>, table_name: &str, migration: Migration) {
+        println!("running migration {:?} on {}", migration, table_name);
+        self.result = database.update_table(table_name, &migration);
     }
 }
 
@@ -649,6 +1047,383 @@ impl QueryDatabaseDeleteTable {
     }
 }
 
+/* 
+ * MARK: QueryDatabaseCreateIndex
+ */
+pub struct QueryDatabaseCreateIndex { name: String, pub result: Result<String, String> }
+
+impl QueryDatabaseCreateIndex {
+    pub fn new(name: String) -> Self {
+        QueryDatabaseCreateIndex { name, result: Err("query has not yet been run".to_owned()) }
+    }
+
+    pub fn parse(&mut self, database: &mut MutexGuard<Database>, body: Value) {
+        let columns = match body["columns"].as_array() {
+            Some(columns) => columns.iter().map(|column| column.as_str().map(|column| column.to_owned())).collect::<Option<Vec<String>>>(),
+            None => None
+        };
+        match (body["table_name"].as_str(), body["index_name"].as_str(), columns) {
+            (Some(table_name), Some(index_name), Some(columns)) => self.run(database, table_name.to_owned(), index_name.to_owned(), columns),
+            (None, _, _) => self.result = Err("table name could not be parsed".to_owned()),
+            (_, None, _) => self.result = Err("index name could not be parsed".to_owned()),
+            (_, _, None) => self.result = Err("index columns could not be parsed".to_owned())
+        }
+    }
+
+    pub fn run(&mut self, database: &mut MutexGuard<Database>, table_name: String, index_name: String, columns: Vec<String>) {
+        self.result = database.create_index(table_name, index_name, columns);
+    }
+}
+
+/* 
+ * MARK: QueryDatabaseDropIndex
+ */
+pub struct QueryDatabaseDropIndex { name: String, pub result: Result<String, String> }
+
+impl QueryDatabaseDropIndex {
+    pub fn new(name: String) -> Self {
+        QueryDatabaseDropIndex { name, result: Err("query has not yet been run".to_owned()) }
+    }
+
+    pub fn parse(&mut self, database: &mut MutexGuard<Database>, body: Value) {
+        match (body["table_name"].as_str(), body["index_name"].as_str()) {
+            (Some(table_name), Some(index_name)) => self.run(database, table_name.to_owned(), index_name.to_owned()),
+            (None, _) => self.result = Err("table name could not be parsed".to_owned()),
+            (_, None) => self.result = Err("index name could not be parsed".to_owned())
+        }
+    }
+
+    pub fn run(&mut self, database: &mut MutexGuard<Database>, table_name: String, index_name: String) {
+        self.result = database.drop_index(table_name, index_name);
+    }
+}
+
+/* 
+ * MARK: QueryDatabaseJoin
+ * joins are given in order as {"table_name": .., "type": "inner"|"left", "on": [column, column]} with one column
+ * of the pair on the joined table and the other on a table joined before it, every column is named table.column
+ */
+pub struct QueryDatabaseJoin { name: String, pub result: Result<String, String> }
+
+impl QueryDatabaseJoin {
+    pub fn new(name: String) -> Self {
+        QueryDatabaseJoin { name, result: Err("query has not yet been run".to_owned()) }
+    }
+
+    pub fn parse(&mut self, database: &mut MutexGuard<Database>, body: Value) {
+        let table_name = match body["table_name"].as_str() {
+            Some(table_name) => table_name,
+            None => return self.result = Err("table name could not be parsed".to_owned())
+        };
+        let mut coldefs = match database.table(table_name) {
+            Some(table) => match table.try_lock() {
+                Ok(table) => table.qualified_columns(),
+                Err(e) => panic!("{}", ["shits fucked ".to_owned(), e.to_string()].concat())
+            },
+            None => return self.result = Err(["table ", table_name, " does not exist in database"].concat())
+        };
+        let mut tables = vec![table_name.to_owned()];
+        let mut joins = vec![];
+        for join in body["joins"].as_array().unwrap_or(&vec![]) {
+            match self.parse_join(database, &mut coldefs, &mut tables, join) {
+                Ok(join) => joins.push(join),
+                Err(e) => return self.result = Err(e)
+            }
+        }
+        if joins.is_empty() {
+            return self.result = Err("no joins submitted".to_owned());
+        }
+        let filter = match &body["conditions"] {
+            Value::Null => Ok(Filter::Condition(Condition { target_column: "".to_owned(), conditional: Conditional::All, value: CellValue::Bool { name: "".to_owned(), data: None }, relational: None })),
+            conditions => parse_filter(&coldefs, conditions)
+        };
+        match (filter, parse_projection(&coldefs, &body["columns"]), parse_explain(&body)) {
+            (Ok(filter), Ok(projection), Ok(true)) => self.result = Ok(database.explain_join(table_name, &joins, &filter, projection.as_deref()).to_string()),
+            (Ok(filter), Ok(projection), Ok(false)) => self.run(database, table_name, joins, filter, projection),
+            (Err(e), _, _) | (_, Err(e), _) | (_, _, Err(e)) => self.result = Err(e)
+        }
+    }
+
+    /* 
+     * check one join against the tables already joined, adding the joined tables columns for the clauses after it
+     */
+    fn parse_join(&self, database: &MutexGuard<Database>, coldefs: &mut Vec<(String, CellValue)>, tables: &mut Vec<String>, join: &Value) -> Result<Join, String> {
+        let table_name = join["table_name"].as_str().ok_or(["join ", &join.to_string(), " has no table_name"].concat())?;
+        if tables.iter().any(|table| table == table_name) {
+            return Err(["table ", table_name, " is joined more than once"].concat());
+        }
+        let kind = match join["type"].as_str() {
+            Some(kind) => JoinKind::parse(kind)?,
+            None => JoinKind::Inner
+        };
+        let joined = match database.table(table_name) {
+            Some(table) => match table.try_lock() {
+                Ok(table) => table.qualified_columns(),
+                Err(e) => panic!("{}", ["shits fucked ".to_owned(), e.to_string()].concat())
+            },
+            None => return Err(["table ", table_name, " does not exist in database"].concat())
+        };
+        let (inner, outer) = match join["on"].as_array().map(|on| on.as_slice()) {
+            Some([Value::String(a), Value::String(b)]) => match (joined.iter().find(|celldef| &celldef.0 == a), joined.iter().find(|celldef| &celldef.0 == b)) {
+                (Some(inner), None) => (inner, b),
+                (None, Some(inner)) => (inner, a),
+                _ => return Err(["join on ", table_name, " must compare one of its columns with a column joined before it"].concat())
+            },
+            _ => return Err(["join on ", table_name, " needs an on pair of table.column names"].concat())
+        };
+        let outer = match coldefs.iter().find(|celldef| &celldef.0 == outer) {
+            Some(outer) => outer,
+            None => return Err(["column ", outer, " does not exist on a table joined before ", table_name].concat())
+        };
+        if inner.1.type_name() != outer.1.type_name() {
+            return Err(["cannot join ", &inner.0, " of type ", inner.1.type_name(), " on ", &outer.0, " of type ", outer.1.type_name()].concat());
+        }
+        let join = Join { kind, table: table_name.to_owned(), column: inner.0[table_name.len() + 1..].to_owned(), outer: outer.0.clone() };
+        tables.push(table_name.to_owned());
+        coldefs.extend(joined);
+        Ok(join)
+    }
+
+    pub fn run(&mut self, database: &mut MutexGuard<Database>, table_name: &str, joins: Vec<Join>, filter: conditional::Filter, projection: Option<Vec<(String, String)>>) {
+        println!("joining {} with {:?} conditions filter {:?}", table_name, joins, filter);
+        self.result = database.join(table_name, &joins, &filter, projection.as_deref());
+    }
+}
+
+/* 
+ * MARK: QueryDatabaseSql
+ * the body is the statement as a json string or {"sql": statement}, once parsed it is run by
+ * the same query the equivalent json request would use
+ */
+pub struct QueryDatabaseSql { name: String, scan: Option<Scan>, pub result: Result<String, String> }
+
+impl QueryDatabaseSql {
+    pub fn new(name: String) -> Self {
+        QueryDatabaseSql { name, scan: None, result: Err("query has not yet been run".to_owned()) }
+    }
+
+    pub fn parse<'a>(&mut self, admin_db: Arc<Mutex<Database<'a>>>, database: &mut MutexGuard<Database<'a>>, body: Value) {
+        self.scan = None;
+        let sql = match (body.as_str(), body["sql"].as_str()) {
+            (Some(sql), _) | (_, Some(sql)) => sql.to_owned(),
+            _ => return self.result = Err("no sql statement submitted".to_owned())
+        };
+        println!("sql statement {}", sql);
+        match Statement::parse(&sql) {
+            Ok(statement) => self.run(admin_db, database, statement),
+            Err(e) => self.result = Err(e)
+        }
+    }
+
+    pub fn run<'a>(&mut self, admin_db: Arc<Mutex<Database<'a>>>, database: &mut MutexGuard<Database<'a>>, statement: Statement) {
+        if let Err(e) = database.check_access_table(statement.table_name()) {
+            return self.result = Err(e);
+        }
+        let table = |table_name: &str| database.table(table_name).ok_or(["table ", table_name, " does not exist in database"].concat());
+        self.result = match statement {
+            Statement::CreateTable { table_name, columns } => {
+                let mut query = QueryDatabaseCreateTable::new(self.name.clone());
+                query.run(admin_db, database, table_name, columns);
+                query.result
+            },
+            Statement::DropTable { table_name } => {
+                let mut query = QueryDatabaseDeleteTable::new(self.name.clone());
+                query.run(database, table_name);
+                query.result
+            },
+            Statement::Insert { table_name, columns, rows } => match table(&table_name) {
+                Ok(table) => match Statement::records(&columns, &rows, &table_coldefs(&table)) {
+                    Ok(records) => {
+                        let tables = database.tables.clone();
+                        let mut query = TableQueryCreate::new(self.name.clone(), table);
+                        match database.active_transaction() {
+                            Ok(transaction) => query.run(records, &tables, transaction),
+                            Err(e) => query.result = Err(e)
+                        }
+                        query.result
+                    },
+                    Err(e) => Err(e)
+                },
+                Err(e) => Err(e)
+            },
+            Statement::Select { table_name, columns, condition, order_by, limit, offset, explain } => match table(&table_name) {
+                Ok(table) => match condition.filter(&table_coldefs(&table)) {
+                    Ok(filter) => {
+                        let mut query = TableQueryRead::new(self.name.clone(), table);
+                        query.run(filter, ReadOptions { projection: columns, order_by, limit, offset, explain, ..ReadOptions::default() });
+                        self.scan = query.scan.take();
+                        query.result
+                    },
+                    Err(e) => Err(e)
+                },
+                Err(e) => Err(e)
+            },
+            Statement::Update { table_name, set, condition } => match table(&table_name) {
+                Ok(table) => {
+                    let coldefs = table_coldefs(&table);
+                    match (condition.filter(&coldefs), Statement::assignments(&set, &coldefs)) {
+                        (Ok(filter), Ok(assignments)) => {
+                            let tables = database.tables.clone();
+                            let mut query = TableQueryUpdate::new(self.name.clone(), table);
+                            match database.active_transaction() {
+                                Ok(transaction) => query.run(filter, assignments, &tables, transaction),
+                                Err(e) => query.result = Err(e)
+                            }
+                            query.result
+                        },
+                        (Err(e), _) | (_, Err(e)) => Err(e)
+                    }
+                },
+                Err(e) => Err(e)
+            },
+            Statement::Delete { table_name, condition } => match table(&table_name) {
+                Ok(table) => match condition.filter(&table_coldefs(&table)) {
+                    Ok(filter) => {
+                        let tables = database.tables.clone();
+                        let mut query = TableQueryDelete::new(self.name.clone(), table);
+                        match database.active_transaction() {
+                            Ok(transaction) => query.run(filter, &tables, transaction),
+                            Err(e) => query.result = Err(e)
+                        }
+                        query.result
+                    },
+                    Err(e) => Err(e)
+                },
+                Err(e) => Err(e)
+            },
+        }
+    }
+
+    /* 
+     * a SELECT scans its snapshot here, after the database has been let go of
+     */
+    pub fn finish(&mut self) -> Result<String, String> {
+        if let Some(scan) = self.scan.take() {
+            self.result = scan();
+        }
+        self.result.clone()
+    }
+}
+
+/* 
+ * MARK: QueryDatabaseBegin
+ * start a transaction, the id returned is sent back in the Transaction-Id header of the writes made in it
+ */
+pub struct QueryDatabaseBegin { name: String, pub result: Result<String, String> }
+
+impl QueryDatabaseBegin {
+    pub fn new(name: String) -> Self {
+        QueryDatabaseBegin { name, result: Err("query has not yet been run".to_owned()) }
+    }
+
+    pub fn run(&mut self, database: &mut MutexGuard<Database>) {
+        self.result = match database.transaction {
+            Some(id) => Err(["request is already part of transaction ", &id.to_string()].concat()),
+            None => Ok(json!({ "transaction": database.begin_transaction() }).to_string())
+        }
+    }
+}
+
+/* 
+ * MARK: QueryDatabaseCommit
+ * apply every write buffered in the transaction named by the Transaction-Id header, all of them or none
+ */
+pub struct QueryDatabaseCommit { name: String, pub result: Result<String, String> }
+
+impl QueryDatabaseCommit {
+    pub fn new(name: String) -> Self {
+        QueryDatabaseCommit { name, result: Err("query has not yet been run".to_owned()) }
+    }
+
+    pub fn run(&mut self, database: &mut MutexGuard<Database>) {
+        self.result = match database.transaction {
+            Some(id) => database.commit_transaction(id),
+            None => Err("no transaction id header submitted".to_owned())
+        }
+    }
+}
+
+/* 
+ * MARK: QueryDatabaseRollback
+ * discard the transaction named by the Transaction-Id header and everything buffered in it
+ */
+pub struct QueryDatabaseRollback { name: String, pub result: Result<String, String> }
+
+impl QueryDatabaseRollback {
+    pub fn new(name: String) -> Self {
+        QueryDatabaseRollback { name, result: Err("query has not yet been run".to_owned()) }
+    }
+
+    pub fn run(&mut self, database: &mut MutexGuard<Database>) {
+        self.result = match database.transaction {
+            Some(id) => database.rollback_transaction(id),
+            None => Err("no transaction id header submitted".to_owned())
+        }
+    }
+}
+
+/* 
+ * MARK: QueryDatabaseReset
+ * empty every table of an indev database and seed it again, the seed lists
+ * the records of each table in the order they are created so referenced rows can come first
+ */
+pub struct QueryDatabaseReset { name: String, pub result: Result<String, String> }
+
+impl QueryDatabaseReset {
+    pub fn new(name: String) -> Self {
+        QueryDatabaseReset { name, result: Err("query has not yet been run".to_owned()) }
+    }
+
+    /* 
+     * the body optionally holds {"seed": [{"table_name": name, "records": [...]}, ...]} with records
+     * in the same form create_record takes them, every seeded table is checked before anything is emptied
+     */
+    pub fn parse(&mut self, database: &mut MutexGuard<Database>, body: Value) {
+        let seed = match &body["seed"] {
+            Value::Null => Ok(vec![]),
+            Value::Array(seed) => seed.iter().map(|entry| match (entry["table_name"].as_str(), entry["records"].is_array()) {
+                    (Some(table_name), true) => match database.table(table_name) {
+                        Some(table) => Ok((table_name.to_owned(), table, json!({ "records": entry["records"] }))),
+                        None => Err(["table ", table_name, " does not exist in database"].concat())
+                    },
+                    (None, _) => Err("seed table name could not be parsed".to_owned()),
+                    (Some(table_name), false) => Err(["seed records for ", table_name, " could not be parsed"].concat())
+                })
+                .collect::<Result<Vec<(String, Arc<Mutex<table::Table>>, Value)>, String>>(),
+            _ => Err("seed could not be parsed".to_owned())
+        };
+        match seed {
+            Ok(seed) => self.run(database, seed),
+            Err(e) => self.result = Err(e)
+        }
+    }
+
+    pub fn run(&mut self, database: &mut MutexGuard<Database>, seed: Vec<(String, Arc<Mutex<table::Table>>, Value)>) {
+        if let Some(id) = database.transaction {
+            return self.result = Err(["reset can not run inside transaction ", &id.to_string()].concat());
+        }
+        let truncated = match database.reset_tables() {
+            Ok(truncated) => truncated,
+            Err(e) => return self.result = Err(e)
+        };
+        let tables = database.tables.clone();
+        let mut seeded = serde_json::Map::new();
+        for (table_name, table, records) in seed {
+            let mut query = TableQueryCreate::new(self.name.clone(), table);
+            query.parse(records, &tables, None);
+            match query.result {
+                Ok(created) => seeded.insert(table_name, serde_json::from_str::<Value>(&created).map(|created| created["created"].clone()).unwrap_or(Value::Null)),
+                Err(e) => return self.result = Err(["database was reset but seeding ", &table_name, " failed: ", &e].concat())
+            };
+        }
+        self.result = Ok(json!({ "truncated": truncated, "seeded": seeded }).to_string())
+    }
+}
+
+/* 
+ * MARK: QueryDatabaseInDevToggle
+ * the flag is saved in the database definition so a reloaded database keeps it
+ */
 pub struct QueryDatabaseInDevToggle { name: String, pub result: Result<String, String> }
 
 impl QueryDatabaseInDevToggle {
@@ -657,7 +1432,237 @@ impl QueryDatabaseInDevToggle {
     }
 
     pub fn run(&mut self, database: &mut MutexGuard<Database>) {
-        database.indev = !database.indev;
-        self.result = Ok("databse indev status successfuly toggled".to_owned())
+        let indev = !database.indev;
+        self.result = database.set_indev(indev)
+    }
+}
+/* 
+ * MARK: QueryDatabaseCreateUser
+ * add a user to the admin database, the body holds their username, password and role
+ */
+pub struct QueryDatabaseCreateUser { name: String, pub result: Result<String, String> }
+
+impl QueryDatabaseCreateUser {
+    pub fn new(name: String) -> Self {
+        QueryDatabaseCreateUser { name, result: Err("query has not yet been run".to_owned()) }
+    }
+
+    pub fn parse(&mut self, database: &mut MutexGuard<Database>, body: Value) {
+        match (body["username"].as_str(), body["password"].as_str(), body["role"].as_str()) {
+            (Some(username), Some(password), Some(role)) => self.run(database, username, password, role),
+            _ => self.result = Err("username, password and role could not be parsed".to_owned())
+        }
+    }
+
+    pub fn run(&mut self, database: &mut MutexGuard<Database>, username: &str, password: &str, role: &str) {
+        self.result = database.create_user(username, password, role)
+    }
+}
+
+/* 
+ * MARK: QueryDatabaseDropUser
+ */
+pub struct QueryDatabaseDropUser { name: String, pub result: Result<String, String> }
+
+impl QueryDatabaseDropUser {
+    pub fn new(name: String) -> Self {
+        QueryDatabaseDropUser { name, result: Err("query has not yet been run".to_owned()) }
+    }
+
+    pub fn parse(&mut self, database: &mut MutexGuard<Database>, body: Value) {
+        match body["username"].as_str() {
+            Some(username) => self.result = database.drop_user(username),
+            None => self.result = Err("username could not be parsed".to_owned())
+        }
+    }
+}
+
+/* 
+ * MARK: parse grants
+ * {"role": role, "database": database or *, "table": table or * when left out, "permissions": ["create", "read", ...]}
+ */
+fn parse_grants(body: &Value) -> Result<Vec<Grant>, String> {
+    let (role, database) = match (body["role"].as_str(), body["database"].as_str()) {
+        (Some(role), Some(database)) => (role, database),
+        _ => return Err("role and database could not be parsed".to_owned())
+    };
+    let table = body["table"].as_str().unwrap_or(database::access::ANY);
+    match body["permissions"].as_array() {
+        Some(permissions) if !permissions.is_empty() => permissions.iter()
+            .map(|permission| match permission.as_str() {
+                Some(permission) => Permission::parse(permission).map(|permission| Grant { role: role.to_owned(), database: database.to_owned(), table: table.to_owned(), permission }),
+                None => Err(["permission ", &permission.to_string(), " is not a string"].concat())
+            })
+            .collect::<Result<Vec<Grant>, String>>(),
+        _ => Err("permissions could not be parsed".to_owned())
+    }
+}
+
+/* 
+ * MARK: QueryDatabaseGrant
+ */
+pub struct QueryDatabaseGrant { name: String, pub result: Result<String, String> }
+
+impl QueryDatabaseGrant {
+    pub fn new(name: String) -> Self {
+        QueryDatabaseGrant { name, result: Err("query has not yet been run".to_owned()) }
     }
-}
\ No newline at end of file
+
+    pub fn parse(&mut self, database: &mut MutexGuard<Database>, body: Value) {
+        self.result = parse_grants(&body).and_then(|grants| database.grant(&grants))
+    }
+}
+
+/* 
+ * MARK: QueryDatabaseRevoke
+ */
+pub struct QueryDatabaseRevoke { name: String, pub result: Result<String, String> }
+
+impl QueryDatabaseRevoke {
+    pub fn new(name: String) -> Self {
+        QueryDatabaseRevoke { name, result: Err("query has not yet been run".to_owned()) }
+    }
+
+    pub fn parse(&mut self, database: &mut MutexGuard<Database>, body: Value) {
+        self.result = parse_grants(&body).and_then(|grants| database.revoke(&grants))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn column_defs_parse_their_on_delete_action() {
+        let column = parse_column_def(&json!(["owner", "ULong", "", "true", "false", "users.id", "set_null"])).unwrap();
+        assert_eq!(column.5, Some(("users".to_owned(), "id".to_owned())));
+        assert_eq!(column.6, cell::ReferentialAction::SetNull);
+        assert_eq!(parse_column_def(&json!(["owner", "ULong", "", "true", "false", ""])).unwrap().6, cell::ReferentialAction::Restrict);
+    }
+
+    #[test]
+    fn created_values_are_parsed_for_their_column_or_refused() {
+        crate::test_dir();
+        let columns = vec![
+            ("id".to_owned(), "ULong".to_owned(), None, false, false, None, cell::ReferentialAction::Restrict),
+            ("n".to_owned(), "ILong".to_owned(), None, true, false, None, cell::ReferentialAction::Restrict),
+        ];
+        let table = Arc::new(Mutex::new(table::Table::new("create_values".to_owned(), "numbers".to_owned(), columns, true)));
+        let tables = vec![Arc::clone(&table)];
+        let create = |body: Value| {
+            let mut query = TableQueryCreate::new("create".to_owned(), Arc::clone(&table));
+            query.parse(body, &tables, None);
+            query.result
+        };
+        assert!(create(json!({ "records": [{ "id": 7, "n": -3 }] })).unwrap().contains("\"7\""));
+        assert!(create(json!({ "records": [{ "id": "8", "n": "-4" }] })).unwrap().contains("\"8\""));
+        assert!(create(json!({ "records": [{ "id": "nine" }] })).unwrap_err().contains("not valid for column id"));
+        assert!(create(json!({ "records": [{ "n": 1.5 }] })).unwrap_err().contains("not valid for column n"));
+        assert!(create(json!({ "records": [{ "missing": 1 }] })).unwrap_err().contains("does not exist"));
+        assert_eq!(table.lock().unwrap().records.iter().map(|part| part.records.len()).sum::<usize>(), 2);
+    }
+
+    #[test]
+    fn bad_column_defs_are_errors() {
+        assert_eq!(parse_column_def(&json!(["owner", "ULong", "", "true", "false", "users.id", "explode"])), Err("referential action explode not recognised".to_owned()));
+        assert!(parse_column_def(&json!(["", "ULong", "", "true", "false", ""])).is_err());
+        assert!(parse_column_def(&json!("owner")).is_err());
+        assert_eq!(parse_column_def(&json!([])), Err("column name could not be parsed".to_owned()));
+        assert_eq!(parse_column_def(&json!(["owner"])), Err("column type could not be parsed".to_owned()));
+    }
+
+    #[test]
+    fn column_defs_left_short_take_their_defaults() {
+        assert_eq!(parse_column_def(&json!(["owner", "ULong"])).unwrap(), ("owner".to_owned(), "ULong".to_owned(), None, false, false, None, cell::ReferentialAction::Restrict));
+        assert!(parse_column_def(&json!(["owner", "ULong", "7", "true"])).unwrap().3);
+    }
+
+    fn people(table_name: &str) -> Arc<Mutex<table::Table>> {
+        crate::test_dir();
+        let columns = vec![
+            ("id".to_owned(), "ULong".to_owned(), None, false, false, None, cell::ReferentialAction::Restrict),
+            ("name".to_owned(), "String".to_owned(), None, true, false, None, cell::ReferentialAction::Restrict),
+            ("city".to_owned(), "String".to_owned(), None, true, false, None, cell::ReferentialAction::Restrict),
+        ];
+        let table = Arc::new(Mutex::new(table::Table::new("reads".to_owned(), table_name.to_owned(), columns, true)));
+        let mut query = TableQueryCreate::new("create".to_owned(), Arc::clone(&table));
+        query.parse(json!({ "records": [{ "name": "ann", "city": "york" }, { "name": "bob", "city": "leeds" }] }), &[Arc::clone(&table)], None);
+        query.result.unwrap();
+        table
+    }
+
+    fn read(table: &Arc<Mutex<table::Table>>, body: Value) -> Result<String, String> {
+        let mut query = TableQueryRead::new("read".to_owned(), Arc::clone(table));
+        query.parse(body);
+        query.finish()
+    }
+
+    #[test]
+    fn reads_return_only_the_projected_columns_under_their_aliases() {
+        let people = people("projected");
+        let records = read(&people, json!({ "conditions": ["*"], "columns": ["name", ["city", "town"]], "order_by": [["city", "DESC"]] })).unwrap();
+        assert_eq!(records, "{{ \"name\": \"ann\", \"town\": \"york\" }, { \"name\": \"bob\", \"town\": \"leeds\" }}");
+        // a column only ordered by is left out of the records
+        let records = read(&people, json!({ "conditions": ["*"], "columns": [["name", "who"]], "order_by": ["city"] })).unwrap();
+        assert_eq!(records, "{{ \"who\": \"bob\" }, { \"who\": \"ann\" }}");
+        assert!(read(&people, json!({ "conditions": ["*"], "columns": [] })).unwrap().contains("\"id\": 1"));
+    }
+
+    #[test]
+    fn projections_naming_unknown_or_repeated_columns_are_errors() {
+        let people = people("unprojected");
+        let projected = |columns: Value| read(&people, json!({ "conditions": ["*"], "columns": columns })).unwrap_err();
+        assert_eq!(projected(json!(["missing"])), "column \"missing\" does not exist on target table");
+        assert_eq!(projected(json!([["missing", "name"]])), "column \"missing\" does not exist on target table");
+        assert_eq!(projected(json!(["name", ["city", "name"]])), "column name \"name\" is used more than once");
+        assert!(projected(json!([["name"]])).contains("is not a name or a [column, alias] pair"));
+        assert_eq!(projected(json!("name")), "columns is not an array");
+    }
+
+    fn sales(table_name: &str) -> Arc<Mutex<table::Table>> {
+        crate::test_dir();
+        let columns = vec![
+            ("id".to_owned(), "ULong".to_owned(), None, false, false, None, cell::ReferentialAction::Restrict),
+            ("region".to_owned(), "String".to_owned(), None, true, false, None, cell::ReferentialAction::Restrict),
+            ("amount".to_owned(), "ILong".to_owned(), None, true, false, None, cell::ReferentialAction::Restrict),
+        ];
+        let table = Arc::new(Mutex::new(table::Table::new("aggregates".to_owned(), table_name.to_owned(), columns, true)));
+        let mut query = TableQueryCreate::new("create".to_owned(), Arc::clone(&table));
+        let records = json!([{ "region": "north", "amount": 5 }, { "region": "north", "amount": -3 }, { "region": "north" }, { "region": "south" }, { "amount": 7 }]);
+        query.parse(json!({ "records": records }), &[Arc::clone(&table)], None);
+        query.result.unwrap();
+        table
+    }
+
+    fn aggregated(table: &Arc<Mutex<table::Table>>, body: Value) -> Result<Value, String> {
+        read(table, body).map(|rows| serde_json::from_str::<Value>(&rows).unwrap()["rows"].clone())
+    }
+
+    #[test]
+    fn aggregates_total_each_group_and_skip_nulls() {
+        let sales = sales("grouped");
+        let every = json!([{ "fn": "count" }, { "fn": "count", "column": "amount" }, { "fn": "sum", "column": "amount", "as": "total" }, { "fn": "avg", "column": "amount" }, { "fn": "min", "column": "amount" }, { "fn": "max", "column": "amount" }]);
+        // records with a null region make a group of their own and a group with only null amounts totals to null
+        assert_eq!(aggregated(&sales, json!({ "conditions": ["*"], "group_by": ["region"], "aggregates": every })).unwrap(), json!([
+            { "region": null, "count(*)": 1, "count(amount)": 1, "total": 7, "avg(amount)": 7.0, "min(amount)": 7, "max(amount)": 7 },
+            { "region": "north", "count(*)": 3, "count(amount)": 2, "total": 2, "avg(amount)": 1.0, "min(amount)": -3, "max(amount)": 5 },
+            { "region": "south", "count(*)": 1, "count(amount)": 0, "total": null, "avg(amount)": null, "min(amount)": null, "max(amount)": null }
+        ]));
+        assert_eq!(aggregated(&sales, json!({ "conditions": ["*"], "aggregates": every })).unwrap(), json!([
+            { "count(*)": 5, "count(amount)": 3, "total": 9, "avg(amount)": 3.0, "min(amount)": -3, "max(amount)": 7 }
+        ]));
+    }
+
+    #[test]
+    fn aggregates_over_nothing_return_one_empty_row_unless_grouped() {
+        let sales = sales("unmatched");
+        let none = json!(["amount", ">", 100]);
+        let aggregates = json!([{ "fn": "count" }, { "fn": "sum", "column": "amount" }]);
+        assert_eq!(aggregated(&sales, json!({ "conditions": none, "aggregates": aggregates })).unwrap(), json!([{ "count(*)": 0, "sum(amount)": null }]));
+        assert_eq!(aggregated(&sales, json!({ "conditions": none, "group_by": ["region"], "aggregates": aggregates })).unwrap(), json!([]));
+        assert_eq!(aggregated(&sales, json!({ "conditions": ["*"], "aggregates": [{ "fn": "sum", "column": "region" }] })).unwrap_err(), "cannot take the sum(region) of String column region");
+        assert_eq!(aggregated(&sales, json!({ "conditions": ["*"], "aggregates": [{ "fn": "median", "column": "amount" }] })).unwrap_err(), "aggregate function median not recognised");
+        assert_eq!(aggregated(&sales, json!({ "conditions": ["*"], "aggregates": [{ "fn": "max" }] })).unwrap_err(), "aggregate max needs a column");
+    }
+}
+
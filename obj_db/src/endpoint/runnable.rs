@@ -18,6 +18,13 @@ impl<'a> Runnable<'a> {
         }
     }
 
+    pub fn refuse(&mut self, reason: String) {
+        match self {
+            Runnable::Query(q) => q.refuse(reason),
+            Runnable::Script(q) => {}
+        }
+    }
+
     pub fn result(&mut self) -> Result<String, String> {
         match self {
             Runnable::Query(q) => q.result(),
@@ -31,4 +38,11 @@ impl<'a> Runnable<'a> {
             Runnable::Script(q) => Err("not a query".to_owned()),
         }
     }
+
+    pub fn database(&self) -> Result<Arc<Mutex<Database<'a>>>, String> {
+        match self {
+            Runnable::Query(q) => q.database(),
+            Runnable::Script(q) => Err("not a query".to_owned()),
+        }
+    }
 }
\ No newline at end of file
@@ -21,7 +21,29 @@ use std::{
 
 pub mod database;
 pub mod endpoint;
+pub mod sql;
 
 fn main() {
     todo!()
-}
\ No newline at end of file
+}
+
+/* 
+ * MARK: test directory
+ * tables are created under the working directory so every test that writes to disk works from one directory
+ * in the temp dir, anything a previous run left behind is removed first
+ */
+#[cfg(test)]
+pub(crate) fn test_dir() {
+    static INIT: std::sync::Once = std::sync::Once::new();
+    INIT.call_once(|| {
+        let temp = env::temp_dir();
+        if let Ok(entries) = fs::read_dir(&temp) {
+            entries.flatten()
+                .filter(|entry| entry.file_name().to_string_lossy().starts_with("obj_db_tests"))
+                .for_each(|entry| { let _ = fs::remove_dir_all(entry.path()).or_else(|_| fs::remove_file(entry.path())); });
+        }
+        let dir = temp.join("obj_db_tests");
+        fs::create_dir_all(&dir).unwrap();
+        env::set_current_dir(&dir).unwrap();
+    });
+}
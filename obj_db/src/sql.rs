@@ -0,0 +1,733 @@
+use serde_json::Value;
+
+use crate::database::{cell::{CellValue, ColumnDef, ReferentialAction}, conditional::{Condition, Conditional, Filter}, read::OrderBy, record::Record};
+
+/*
+ * MARK: sql
+ * a small subset of sql, CREATE TABLE, DROP TABLE, INSERT, SELECT, UPDATE and DELETE on a single table,
+ * with EXPLAIN before a SELECT returning its plan, parsed into the column definitions, conditions and
+ * records the json queries already build
+ */
+
+const RESERVED: [&str; 29] = [
+    "EXPLAIN", "SELECT", "FROM", "WHERE", "INSERT", "INTO", "VALUES", "UPDATE", "SET", "DELETE", "CREATE", "DROP", "TABLE", "AND", "OR",
+    "NOT", "IN", "BETWEEN", "LIKE", "IS", "NULL", "ORDER", "BY", "LIMIT", "OFFSET", "AS", "TRUE", "FALSE", "REGEXP"
+];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Identifier(String),
+    Text(String),
+    Number(String),
+    Symbol(String),
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match self {
+            Token::Word(word) | Token::Number(word) | Token::Symbol(word) => word.clone(),
+            Token::Identifier(identifier) => ["\"", identifier, "\""].concat(),
+            Token::Text(text) => ["'", text, "'"].concat(),
+        }
+    }
+}
+
+/*
+ * MARK: tokenize
+ * bare words are matched against keywords case insensitively, "double" or `back` quoted identifiers never are,
+ * strings are single quoted with '' for a quote inside them and -- starts a comment to the end of the line
+ */
+fn tokenize(sql: &str) -> Result<Vec<Token>, String> {
+    let chars = sql.chars().collect::<Vec<char>>();
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            c if c.is_whitespace() => i += 1,
+            '-' if chars.get(i + 1) == Some(&'-') => while i < chars.len() && chars[i] != '\n' { i += 1 },
+            '\'' => {
+                let mut text = String::new();
+                i += 1;
+                loop {
+                    match (chars.get(i), chars.get(i + 1)) {
+                        (Some('\''), Some('\'')) => { text.push('\''); i += 2 },
+                        (Some('\''), _) => { i += 1; break },
+                        (Some(c), _) => { text.push(*c); i += 1 },
+                        (None, _) => return Err("string literal is never closed".to_owned())
+                    }
+                }
+                tokens.push(Token::Text(text));
+            },
+            '"' | '`' => match chars[i + 1..].iter().position(|end| *end == c) {
+                Some(length) => {
+                    tokens.push(Token::Identifier(chars[i + 1..i + 1 + length].iter().collect::<String>()));
+                    i += length + 2;
+                },
+                None => return Err("quoted identifier is never closed".to_owned())
+            },
+            c if c.is_ascii_digit() || (c == '-' && chars.get(i + 1).is_some_and(|next| next.is_ascii_digit() || *next == '.')) || (c == '.' && chars.get(i + 1).is_some_and(|next| next.is_ascii_digit())) => {
+                let start = i;
+                i += 1;
+                while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.' || ((chars[i] == 'e' || chars[i] == 'E') && chars.get(i + 1).is_some_and(|next| next.is_ascii_digit() || *next == '-' || *next == '+'))) {
+                    i += match chars[i] { 'e' | 'E' => 2, _ => 1 };
+                }
+                tokens.push(Token::Number(chars[start..i].iter().collect::<String>()));
+            },
+            c if c.is_alphabetic() || c == '_' => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == '.') { i += 1 }
+                tokens.push(Token::Word(chars[start..i].iter().collect::<String>()));
+            },
+            _ => {
+                let pair = chars[i..chars.len().min(i + 2)].iter().collect::<String>();
+                match &pair[..] {
+                    "<=" | ">=" | "!=" | "<>" | "==" => { tokens.push(Token::Symbol(pair)); i += 2 },
+                    _ => match c {
+                        '(' | ')' | ',' | ';' | '*' | '=' | '<' | '>' | '~' => { tokens.push(Token::Symbol(c.to_string())); i += 1 },
+                        _ => return Err(["unexpected character ", &c.to_string(), " in statement"].concat())
+                    }
+                }
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/*
+ * MARK: Expression
+ * a where clause before it is checked against a table, literals are kept as json values
+ * so they can be parsed by the column they are compared with
+ */
+#[derive(Debug, Clone)]
+pub enum Expression {
+    All,
+    Compare(String, String, Value),
+    In(String, Vec<Value>),
+    Between(String, Value, Value),
+    Like(String, String),
+    Regex(String, String),
+    IsNull(String),
+    IsNotNull(String),
+    And(Vec<Expression>),
+    Or(Vec<Expression>),
+    Not(Box<Expression>),
+}
+
+impl Expression {
+    /*
+     * build the filter the table queries run, every column is looked up on the table and every value parsed as that columns type
+     */
+    pub fn filter(&self, coldefs: &[(String, CellValue)]) -> Result<Filter, String> {
+        let column = |target_column: &str| match coldefs.iter().find(|celldef| celldef.0 == target_column) {
+            Some(celldef) => Ok(celldef.1.clone()),
+            None => Err(["target column \"", target_column, "\" does not exist on target table"].concat())
+        };
+        let condition = |target_column: &str, conditional: Conditional, value: CellValue| Filter::Condition(Condition { target_column: target_column.to_owned(), conditional, value, relational: None });
+        Ok(match self {
+            Expression::All => condition("", Conditional::All, CellValue::Bool { name: "".to_owned(), data: None }),
+            Expression::Compare(target_column, operator, value) => {
+                let celldef = column(target_column)?;
+                condition(target_column, Conditional::parse(operator.to_owned())?, celldef.parse_json(value)?)
+            },
+            Expression::In(target_column, values) => {
+                let celldef = column(target_column)?;
+                condition(target_column, Conditional::In(values.iter().map(|value| celldef.parse_json(value)).collect::<Result<Vec<CellValue>, String>>()?), celldef.as_null())
+            },
+            Expression::Between(target_column, low, high) => {
+                let celldef = column(target_column)?;
+                condition(target_column, Conditional::Between(celldef.parse_json(low)?, celldef.parse_json(high)?), celldef.as_null())
+            },
+            Expression::Like(target_column, pattern) => condition(target_column, Conditional::like(pattern)?, column(target_column)?.as_null()),
+            Expression::Regex(target_column, pattern) => condition(target_column, Conditional::regex(pattern)?, column(target_column)?.as_null()),
+            Expression::IsNull(target_column) => condition(target_column, Conditional::IsNull, column(target_column)?.as_null()),
+            Expression::IsNotNull(target_column) => condition(target_column, Conditional::IsNotNull, column(target_column)?.as_null()),
+            Expression::And(expressions) => Filter::And(expressions.iter().map(|expression| expression.filter(coldefs)).collect::<Result<Vec<Filter>, String>>()?),
+            Expression::Or(expressions) => Filter::Or(expressions.iter().map(|expression| expression.filter(coldefs)).collect::<Result<Vec<Filter>, String>>()?),
+            Expression::Not(expression) => Filter::Not(Box::new(expression.filter(coldefs)?)),
+        })
+    }
+}
+
+/*
+ * MARK: Statement
+ */
+#[derive(Debug, Clone)]
+pub enum Statement {
+    CreateTable { table_name: String, columns: Vec<ColumnDef> },
+    DropTable { table_name: String },
+    Insert { table_name: String, columns: Option<Vec<String>>, rows: Vec<Vec<Value>> },
+    Select { table_name: String, columns: Option<Vec<(String, String)>>, condition: Expression, order_by: Vec<OrderBy>, limit: Option<usize>, offset: usize, explain: bool },
+    Update { table_name: String, set: Vec<(String, Value)>, condition: Expression },
+    Delete { table_name: String, condition: Expression },
+}
+
+impl Statement {
+    pub fn parse(sql: &str) -> Result<Self, String> {
+        let mut parser = Parser { tokens: tokenize(sql)?, position: 0 };
+        let statement = parser.statement()?;
+        parser.symbol(";");
+        match parser.peek() {
+            None => Ok(statement),
+            Some(token) => Err(["unexpected ", &token.describe(), " after the end of the statement"].concat())
+        }
+    }
+
+    /*
+     * the table the statement runs against
+     */
+    pub fn table_name(&self) -> &str {
+        match self {
+            Statement::CreateTable { table_name, .. } | Statement::DropTable { table_name } | Statement::Insert { table_name, .. }
+            | Statement::Select { table_name, .. } | Statement::Update { table_name, .. } | Statement::Delete { table_name, .. } => table_name
+        }
+    }
+
+    /*
+     * the records of an insert, each value parsed as the type of the column it is inserted into
+     */
+    pub fn records(columns: &Option<Vec<String>>, rows: &[Vec<Value>], coldefs: &[(String, CellValue)]) -> Result<Vec<Record>, String> {
+        let columns = match columns {
+            Some(columns) => columns.clone(),
+            None => coldefs.iter().map(|celldef| celldef.0.clone()).collect::<Vec<String>>()
+        };
+        rows.iter().map(|row| match row.len() == columns.len() {
+            true => Ok(Record::new(columns.iter().zip(row.iter()).map(|(column_name, value)| match coldefs.iter().find(|celldef| &celldef.0 == column_name) {
+                    Some((_, celldef)) => celldef.parse_json(value),
+                    None => Err(["column ", column_name, " does not exist on table"].concat())
+                }).collect::<Result<Vec<CellValue>, String>>()?)),
+            false => Err(["row has ", &row.len().to_string(), " values for ", &columns.len().to_string(), " columns"].concat())
+        }).collect::<Result<Vec<Record>, String>>()
+    }
+
+    /*
+     * the assignments record of an update, holding only the columns being set
+     */
+    pub fn assignments(set: &[(String, Value)], coldefs: &[(String, CellValue)]) -> Result<Record, String> {
+        Ok(Record::new(set.iter().map(|(column_name, value)| match coldefs.iter().find(|celldef| &celldef.0 == column_name) {
+            Some((_, celldef)) => celldef.parse_json(value),
+            None => Err(["column ", column_name, " does not exist on table"].concat())
+        }).collect::<Result<Vec<CellValue>, String>>()?))
+    }
+}
+
+/*
+ * MARK: Parser
+ * recursive descent over the tokens, NOT binds tighter than AND which binds tighter than OR
+ */
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn found(&self) -> String {
+        match self.peek() {
+            Some(token) => token.describe(),
+            None => "the end of the statement".to_owned()
+        }
+    }
+
+    fn keyword(&mut self, keyword: &str) -> bool {
+        match self.peek() {
+            Some(Token::Word(word)) if word.eq_ignore_ascii_case(keyword) => { self.position += 1; true },
+            _ => false
+        }
+    }
+
+    fn keywords(&mut self, keywords: &[&str]) -> bool {
+        let start = self.position;
+        match keywords.iter().all(|keyword| self.keyword(keyword)) {
+            true => true,
+            false => { self.position = start; false }
+        }
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), String> {
+        match self.keyword(keyword) {
+            true => Ok(()),
+            false => Err(["expected ", keyword, " but found ", &self.found()].concat())
+        }
+    }
+
+    fn symbol(&mut self, symbol: &str) -> bool {
+        match self.peek() {
+            Some(Token::Symbol(found)) if found == symbol => { self.position += 1; true },
+            _ => false
+        }
+    }
+
+    fn expect_symbol(&mut self, symbol: &str) -> Result<(), String> {
+        match self.symbol(symbol) {
+            true => Ok(()),
+            false => Err(["expected ", symbol, " but found ", &self.found()].concat())
+        }
+    }
+
+    fn identifier(&mut self) -> Result<String, String> {
+        match self.peek().cloned() {
+            Some(Token::Identifier(identifier)) => { self.position += 1; Ok(identifier) },
+            Some(Token::Word(word)) if !RESERVED.contains(&&word.to_uppercase()[..]) => { self.position += 1; Ok(word) },
+            _ => Err(["expected a name but found ", &self.found()].concat())
+        }
+    }
+
+    fn literal(&mut self) -> Result<Value, String> {
+        let value = match self.peek().cloned() {
+            Some(Token::Text(text)) => Value::String(text),
+            Some(Token::Number(number)) => match (number.parse::<i64>(), number.parse::<u64>(), number.parse::<f64>()) {
+                (Ok(int), _, _) => Value::from(int),
+                (_, Ok(uint), _) => Value::from(uint),
+                // integers too wide for a json number are handed on as text for the 128 bit columns to parse
+                _ if number.chars().all(|c| c.is_ascii_digit() || c == '-') => Value::String(number),
+                (_, _, Ok(float)) => Value::from(float),
+                _ => return Err(["number ", &number, " could not be parsed"].concat())
+            },
+            Some(Token::Word(word)) => match &word.to_uppercase()[..] {
+                "NULL" => Value::Null,
+                "TRUE" => Value::Bool(true),
+                "FALSE" => Value::Bool(false),
+                _ => return Err(["expected a value but found ", &word].concat())
+            },
+            _ => return Err(["expected a value but found ", &self.found()].concat())
+        };
+        self.position += 1;
+        Ok(value)
+    }
+
+    fn count(&mut self) -> Result<usize, String> {
+        match self.peek().cloned() {
+            Some(Token::Number(number)) => match number.parse::<usize>() {
+                Ok(count) => { self.position += 1; Ok(count) },
+                Err(_) => Err(["expected a positive whole number but found ", &number].concat())
+            },
+            _ => Err(["expected a positive whole number but found ", &self.found()].concat())
+        }
+    }
+
+    fn list<T>(&mut self, mut item: impl FnMut(&mut Self) -> Result<T, String>) -> Result<Vec<T>, String> {
+        let mut items = vec![item(self)?];
+        while self.symbol(",") {
+            items.push(item(self)?);
+        }
+        Ok(items)
+    }
+
+    fn statement(&mut self) -> Result<Statement, String> {
+        match self.peek() {
+            Some(Token::Word(word)) => match &word.to_uppercase()[..] {
+                "CREATE" => self.create_table(),
+                "DROP" => {
+                    self.position += 1;
+                    self.expect_keyword("TABLE")?;
+                    Ok(Statement::DropTable { table_name: self.identifier()? })
+                },
+                "INSERT" => self.insert(),
+                "SELECT" | "EXPLAIN" => self.select(),
+                "UPDATE" => self.update(),
+                "DELETE" => {
+                    self.position += 1;
+                    self.expect_keyword("FROM")?;
+                    let table_name = self.identifier()?;
+                    Ok(Statement::Delete { table_name, condition: self.where_clause()? })
+                },
+                _ => Err(["statement ", word, " is not supported"].concat())
+            },
+            _ => Err(["expected a statement but found ", &self.found()].concat())
+        }
+    }
+
+    /*
+     * MARK: create table
+     * column types are the table types or their common sql names, the first column is the primary key,
+     * columns are nullable unless NOT NULL and REFERENCES table(column) adds a foreign key with an optional ON DELETE action
+     */
+    fn create_table(&mut self) -> Result<Statement, String> {
+        self.expect_keyword("CREATE")?;
+        self.expect_keyword("TABLE")?;
+        let table_name = self.identifier()?;
+        self.expect_symbol("(")?;
+        let mut columns: Vec<ColumnDef> = vec![];
+        for (column, primary_key) in self.list(|parser| parser.column_definition())? {
+            if columns.iter().any(|existing| existing.0 == column.0) {
+                return Err(["column ", &column.0, " is defined more than once"].concat());
+            }
+            if primary_key && !columns.is_empty() {
+                return Err(["column ", &column.0, " cannot be the primary key, the primary key is always the first column"].concat());
+            }
+            columns.push(column);
+        }
+        self.expect_symbol(")")?;
+        Ok(Statement::CreateTable { table_name, columns })
+    }
+
+    fn column_definition(&mut self) -> Result<(ColumnDef, bool), String> {
+        let column_name = self.identifier()?;
+        let dtype = match self.peek().cloned() {
+            Some(Token::Word(word)) => match &word.to_uppercase()[..] {
+                "STRING" | "TEXT" | "VARCHAR" | "CHAR" => "String",
+                "BOOL" | "BOOLEAN" => "Bool",
+                "UINT" => "UInt",
+                "ULONG" => "ULong",
+                "IINT" | "INT" | "INTEGER" => "IInt",
+                "ILONG" | "BIGINT" | "LONG" => "ILong",
+                "FLOAT" | "DOUBLE" | "REAL" => "Float",
+                "BYTES" | "BLOB" => "Bytes",
+                _ => return Err(["column type ", &word, " not recognised"].concat())
+            },
+            _ => return Err(["expected a column type for ", &column_name, " but found ", &self.found()].concat())
+        };
+        self.position += 1;
+        // a length such as VARCHAR(255) is accepted and ignored
+        if self.symbol("(") {
+            self.count()?;
+            self.expect_symbol(")")?;
+        }
+        let (mut default, mut nullable, mut unique, mut primary_key, mut foreign_key, mut on_delete) = (None, true, false, false, None, ReferentialAction::Restrict);
+        loop {
+            if self.keywords(&["NOT", "NULL"]) {
+                nullable = false;
+            } else if self.keyword("NULL") {
+                nullable = true;
+            } else if self.keyword("UNIQUE") {
+                unique = true;
+            } else if self.keywords(&["PRIMARY", "KEY"]) {
+                primary_key = true;
+                unique = true;
+            } else if self.keyword("DEFAULT") {
+                default = match self.literal()? {
+                    Value::Null => None,
+                    Value::String(text) => Some(text),
+                    value => Some(value.to_string())
+                };
+            } else if self.keyword("REFERENCES") {
+                let reference = self.identifier()?;
+                foreign_key = Some(match self.symbol("(") {
+                    true => {
+                        let column = self.identifier()?;
+                        self.expect_symbol(")")?;
+                        (reference, column)
+                    },
+                    false => match reference.split_once('.') {
+                        Some((table, column)) => (table.to_owned(), column.to_owned()),
+                        None => return Err(["foreign key ", &reference, " should be table(column) or table.column"].concat())
+                    }
+                });
+                if self.keywords(&["ON", "DELETE"]) {
+                    let mut action = vec![];
+                    while let Some(Token::Word(word)) = self.peek().cloned() {
+                        match &word.to_uppercase()[..] {
+                            "CASCADE" | "RESTRICT" | "NO" | "ACTION" | "SET" | "NULL" => { action.push(word); self.position += 1 },
+                            _ => break
+                        }
+                    }
+                    on_delete = ReferentialAction::parse(&action.join(" "))?;
+                }
+            } else {
+                break;
+            }
+        }
+        Ok(((column_name, dtype.to_owned(), default, nullable, unique, foreign_key, on_delete), primary_key))
+    }
+
+    /*
+     * MARK: insert
+     */
+    fn insert(&mut self) -> Result<Statement, String> {
+        self.expect_keyword("INSERT")?;
+        self.expect_keyword("INTO")?;
+        let table_name = self.identifier()?;
+        let columns = match self.symbol("(") {
+            true => {
+                let columns = self.list(|parser| parser.identifier())?;
+                self.expect_symbol(")")?;
+                Some(columns)
+            },
+            false => None
+        };
+        self.expect_keyword("VALUES")?;
+        let rows = self.list(|parser| {
+            parser.expect_symbol("(")?;
+            let row = parser.list(|parser| parser.literal())?;
+            parser.expect_symbol(")")?;
+            Ok(row)
+        })?;
+        Ok(Statement::Insert { table_name, columns, rows })
+    }
+
+    /*
+     * MARK: select
+     */
+    fn select(&mut self) -> Result<Statement, String> {
+        let explain = self.keyword("EXPLAIN");
+        self.expect_keyword("SELECT")?;
+        let columns = match self.symbol("*") {
+            true => None,
+            false => Some(self.list(|parser| {
+                let column = parser.identifier()?;
+                match parser.keyword("AS") {
+                    true => Ok((column, parser.identifier()?)),
+                    false => Ok((column.clone(), column))
+                }
+            })?)
+        };
+        self.expect_keyword("FROM")?;
+        let table_name = self.identifier()?;
+        let condition = self.where_clause()?;
+        let order_by = match self.keywords(&["ORDER", "BY"]) {
+            true => self.list(|parser| {
+                let column = parser.identifier()?;
+                let mut modifiers = vec![];
+                loop {
+                    if parser.keyword("ASC") {
+                        modifiers.push("ASC");
+                    } else if parser.keyword("DESC") {
+                        modifiers.push("DESC");
+                    } else if parser.keywords(&["NULLS", "FIRST"]) {
+                        modifiers.push("NULLS FIRST");
+                    } else if parser.keywords(&["NULLS", "LAST"]) {
+                        modifiers.push("NULLS LAST");
+                    } else {
+                        break;
+                    }
+                }
+                OrderBy::parse(column, &modifiers)
+            })?,
+            false => vec![]
+        };
+        let limit = match self.keyword("LIMIT") {
+            true => Some(self.count()?),
+            false => None
+        };
+        let offset = match self.keyword("OFFSET") {
+            true => self.count()?,
+            false => 0
+        };
+        Ok(Statement::Select { table_name, columns, condition, order_by, limit, offset, explain })
+    }
+
+    /*
+     * MARK: update
+     */
+    fn update(&mut self) -> Result<Statement, String> {
+        self.expect_keyword("UPDATE")?;
+        let table_name = self.identifier()?;
+        self.expect_keyword("SET")?;
+        let set = self.list(|parser| {
+            let column = parser.identifier()?;
+            parser.expect_symbol("=")?;
+            Ok((column, parser.literal()?))
+        })?;
+        Ok(Statement::Update { table_name, set, condition: self.where_clause()? })
+    }
+
+    /*
+     * MARK: where
+     * a statement without a where clause applies to every record
+     */
+    fn where_clause(&mut self) -> Result<Expression, String> {
+        match self.keyword("WHERE") {
+            true => self.or_expression(),
+            false => Ok(Expression::All)
+        }
+    }
+
+    fn or_expression(&mut self) -> Result<Expression, String> {
+        let mut operands = vec![self.and_expression()?];
+        while self.keyword("OR") {
+            operands.push(self.and_expression()?);
+        }
+        Ok(match operands.len() { 1 => operands.remove(0), _ => Expression::Or(operands) })
+    }
+
+    fn and_expression(&mut self) -> Result<Expression, String> {
+        let mut operands = vec![self.not_expression()?];
+        while self.keyword("AND") {
+            operands.push(self.not_expression()?);
+        }
+        Ok(match operands.len() { 1 => operands.remove(0), _ => Expression::And(operands) })
+    }
+
+    fn not_expression(&mut self) -> Result<Expression, String> {
+        match self.keyword("NOT") {
+            true => Ok(Expression::Not(Box::new(self.not_expression()?))),
+            false => self.predicate()
+        }
+    }
+
+    fn predicate(&mut self) -> Result<Expression, String> {
+        if self.symbol("(") {
+            let expression = self.or_expression()?;
+            self.expect_symbol(")")?;
+            return Ok(expression);
+        }
+        let column = self.identifier()?;
+        if self.keyword("IS") {
+            let negated = self.keyword("NOT");
+            self.expect_keyword("NULL")?;
+            return Ok(match negated { true => Expression::IsNotNull(column), false => Expression::IsNull(column) });
+        }
+        let negated = self.keyword("NOT");
+        let expression = if self.keyword("IN") {
+            self.expect_symbol("(")?;
+            let values = self.list(|parser| parser.literal())?;
+            self.expect_symbol(")")?;
+            Expression::In(column, values)
+        } else if self.keyword("BETWEEN") {
+            let low = self.literal()?;
+            self.expect_keyword("AND")?;
+            Expression::Between(column, low, self.literal()?)
+        } else if self.keyword("LIKE") {
+            match self.literal()? {
+                Value::String(pattern) => Expression::Like(column, pattern),
+                _ => return Err(["LIKE on ", &column, " needs a pattern string"].concat())
+            }
+        } else if self.keyword("REGEXP") || self.symbol("~") {
+            match self.literal()? {
+                Value::String(pattern) => Expression::Regex(column, pattern),
+                _ => return Err(["REGEXP on ", &column, " needs a pattern string"].concat())
+            }
+        } else if negated {
+            return Err(["expected IN, BETWEEN, LIKE or REGEXP after NOT but found ", &self.found()].concat());
+        } else {
+            let operator = match self.peek() {
+                Some(Token::Symbol(symbol)) => match &symbol[..] {
+                    "=" | "==" => "==",
+                    "!=" | "<>" => "!=",
+                    ">" => ">",
+                    "<" => "<",
+                    ">=" => ">=",
+                    "<=" => "<=",
+                    _ => return Err(["expected a comparison after ", &column, " but found ", symbol].concat())
+                },
+                _ => return Err(["expected a comparison after ", &column, " but found ", &self.found()].concat())
+            };
+            self.position += 1;
+            Expression::Compare(column, operator.to_owned(), self.literal()?)
+        };
+        Ok(match negated { true => Expression::Not(Box::new(expression)), false => expression })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn parsed(sql: &str) -> Statement {
+        Statement::parse(sql).unwrap()
+    }
+
+    fn compare(column: &str, value: Value) -> impl Fn(&Expression) -> bool + '_ {
+        move |expression| matches!(expression, Expression::Compare(found, operator, found_value) if found == column && operator == "==" && *found_value == value)
+    }
+
+    #[test]
+    fn select_where_binds_not_then_and_then_or() {
+        match parsed("SELECT a AS b, c FROM t WHERE a = 1 OR NOT b > 2 AND c IS NULL ORDER BY a DESC LIMIT 5 OFFSET 2") {
+            Statement::Select { table_name, columns, condition, order_by, limit, offset, explain } => {
+                assert_eq!(table_name, "t");
+                assert_eq!(columns, Some(vec![("a".to_owned(), "b".to_owned()), ("c".to_owned(), "c".to_owned())]));
+                assert!(matches!(&condition, Expression::Or(operands) if compare("a", json!(1))(&operands[0])
+                    && matches!(&operands[1], Expression::And(operands) if matches!(&operands[..], [Expression::Not(_), Expression::IsNull(_)]))));
+                assert_eq!((order_by.len(), order_by[0].descending, limit, offset, explain), (1, true, Some(5), 2, false));
+            },
+            other => panic!("expected a select, got {:?}", other)
+        }
+        match parsed("EXPLAIN SELECT * FROM t WHERE (a = 1 OR a = 2) AND b = 3;") {
+            Statement::Select { columns: None, condition: Expression::And(operands), explain: true, .. } => {
+                assert!(matches!(&operands[0], Expression::Or(operands) if operands.len() == 2));
+                assert!(compare("b", json!(3))(&operands[1]));
+            },
+            other => panic!("expected an explained select, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn insert_keeps_its_columns_and_rows_of_values() {
+        match parsed("INSERT INTO t (a, b) VALUES (1, 'x'), (-2, NULL)") {
+            Statement::Insert { table_name, columns, rows } => {
+                assert_eq!(table_name, "t");
+                assert_eq!(columns, Some(vec!["a".to_owned(), "b".to_owned()]));
+                assert_eq!(rows, vec![vec![json!(1), json!("x")], vec![json!(-2), Value::Null]]);
+            },
+            other => panic!("expected an insert, got {:?}", other)
+        }
+        assert!(matches!(parsed("insert into t values (true, 1.5)"), Statement::Insert { columns: None, rows, .. } if rows == vec![vec![json!(true), json!(1.5)]]));
+    }
+
+    #[test]
+    fn update_sets_each_assignment_where_its_condition_holds() {
+        match parsed("UPDATE t SET a = 1, b = 'y' WHERE id = 3") {
+            Statement::Update { table_name, set, condition } => {
+                assert_eq!(table_name, "t");
+                assert_eq!(set, vec![("a".to_owned(), json!(1)), ("b".to_owned(), json!("y"))]);
+                assert!(compare("id", json!(3))(&condition));
+            },
+            other => panic!("expected an update, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn delete_without_a_where_clause_applies_to_every_record() {
+        assert!(matches!(parsed("DELETE FROM t WHERE id IN (1, 2)"), Statement::Delete { condition: Expression::In(column, values), .. } if column == "id" && values == vec![json!(1), json!(2)]));
+        assert!(matches!(parsed("DELETE FROM t"), Statement::Delete { condition: Expression::All, .. }));
+        assert!(matches!(parsed("DROP TABLE t"), Statement::DropTable { table_name } if table_name == "t"));
+    }
+
+    #[test]
+    fn create_table_reads_types_constraints_and_foreign_keys() {
+        match parsed("CREATE TABLE t (id ULONG PRIMARY KEY, name VARCHAR(20) NOT NULL UNIQUE DEFAULT 'x', owner ULONG REFERENCES users(id) ON DELETE SET NULL, team BIGINT REFERENCES teams.id)") {
+            Statement::CreateTable { table_name, columns } => {
+                assert_eq!(table_name, "t");
+                assert_eq!(columns, vec![
+                    ("id".to_owned(), "ULong".to_owned(), None, true, true, None, ReferentialAction::Restrict),
+                    ("name".to_owned(), "String".to_owned(), Some("x".to_owned()), false, true, None, ReferentialAction::Restrict),
+                    ("owner".to_owned(), "ULong".to_owned(), None, true, false, Some(("users".to_owned(), "id".to_owned())), ReferentialAction::SetNull),
+                    ("team".to_owned(), "ILong".to_owned(), None, true, false, Some(("teams".to_owned(), "id".to_owned())), ReferentialAction::Restrict),
+                ]);
+            },
+            other => panic!("expected a create table, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn quoted_names_and_strings_keep_what_is_inside_them() {
+        match parsed("SELECT * FROM \"select\" WHERE `from` = 'it''s -- not a comment' -- but this is") {
+            Statement::Select { table_name, condition, .. } => {
+                assert_eq!(table_name, "select");
+                assert!(compare("from", json!("it's -- not a comment"))(&condition));
+            },
+            other => panic!("expected a select, got {:?}", other)
+        }
+        assert!(matches!(parsed("SELECT * FROM t WHERE n = 340282366920938463463374607431768211455"), Statement::Select { condition: Expression::Compare(_, _, Value::String(wide)), .. } if wide == u128::MAX.to_string()));
+    }
+
+    #[test]
+    fn malformed_statements_are_errors() {
+        for sql in [
+            "SELECT FROM t",
+            "SELECT * FROM t WHERE a = 'never closed",
+            "SELECT * FROM \"never closed",
+            "SELECT * FROM t WHERE a",
+            "SELECT * FROM t WHERE NOT a = 1 AND",
+            "SELECT * FROM t LIMIT -1",
+            "SELECT * FROM t; SELECT * FROM u",
+            "DELETE t",
+            "UPDATE t SET a = 1 b = 2",
+            "INSERT INTO t VALUES (1",
+            "CREATE TABLE t (a WIBBLE)",
+            "CREATE TABLE t (a ULONG, b ULONG PRIMARY KEY)",
+            "CREATE TABLE t (a ULONG, a STRING)",
+            "TRUNCATE t",
+            "SELECT * FROM t WHERE a = 1 & b = 2",
+        ] {
+            assert!(Statement::parse(sql).is_err(), "{} should not parse", sql);
+        }
+    }
+}